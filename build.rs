@@ -0,0 +1,51 @@
+use chrono::{SecondsFormat, Utc};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    let git_sha = Command::new("git")
+        .args(&["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_owned())
+        // Docker builds usually lack .git, so the sha can be passed in
+        .or_else(|| env::var("SEEDY_GIT_SHA").ok())
+        .unwrap_or_else(|| "unknown".to_owned());
+    let mut features: Vec<String> = env::vars()
+        .map(|(key, _)| key)
+        .filter(|key| key.starts_with("CARGO_FEATURE_"))
+        .map(|key| {
+            key["CARGO_FEATURE_".len()..]
+                .to_lowercase()
+                .replace('_', "-")
+        })
+        .collect();
+    features.sort();
+    if features.is_empty() {
+        features.push("none".to_owned());
+    }
+    println!("cargo:rustc-env=SEEDY_GIT_SHA={}", git_sha);
+    println!(
+        "cargo:rustc-env=SEEDY_BUILD_TIMESTAMP={}",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+    println!("cargo:rustc-env=SEEDY_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    // HEAD names the branch, so commits only change the branch ref
+    if let Ok(head) = fs::read_to_string(".git/HEAD") {
+        if head.starts_with("ref: ") {
+            println!(
+                "cargo:rerun-if-changed=.git/{}",
+                head["ref: ".len()..].trim()
+            );
+        }
+    }
+    if Path::new(".git/packed-refs").exists() {
+        println!("cargo:rerun-if-changed=.git/packed-refs");
+    }
+    println!("cargo:rerun-if-env-changed=SEEDY_GIT_SHA");
+}
@@ -0,0 +1,224 @@
+use crate::events::Event;
+use crate::reference::ImageRef;
+use crate::{
+    aws, azure, docker_config, ecr_tokens, gcp, github, registry, secrets, vault, Opt, Result,
+    SeedyError,
+};
+use bollard::auth::DockerCredentials;
+use rusoto_core::Region;
+use std::str::FromStr;
+
+/// Credentials fetched from registries and secret stores, and the clients
+/// used to fetch them, kept on the deployer from one event to the next.
+#[derive(Default)]
+pub struct Cache {
+    pub ecr_clients: aws::EcrClients,
+    pub ecr_tokens: ecr_tokens::Tokens,
+    pub secrets: secrets::Logins,
+    pub vault: vault::Login,
+    pub github: github::InstallationToken,
+}
+
+/// Supplies credentials for pulling from the registries it recognizes by
+/// host. Supporting a new registry means adding a provider to `PROVIDERS`.
+pub trait RegistryAuthProvider {
+    fn handles(&self, registry: &str) -> bool;
+    fn credentials(
+        &self,
+        registry: &str,
+        cache: &mut Cache,
+        opt: &Opt,
+    ) -> Result<Option<DockerCredentials>>;
+    /// Forget cached credentials for the registry after it refused them
+    fn evict(&self, _registry: &str, _cache: &mut Cache) {}
+}
+
+/// ECR Public, which serves pulls to anyone.
+pub const ECR_PUBLIC: &str = "public.ecr.aws";
+
+/// Registries that only host public images, pulled without credentials.
+pub struct Anonymous;
+
+impl RegistryAuthProvider for Anonymous {
+    fn handles(&self, registry: &str) -> bool {
+        registry == ECR_PUBLIC
+    }
+
+    fn credentials(
+        &self,
+        _registry: &str,
+        _cache: &mut Cache,
+        _opt: &Opt,
+    ) -> Result<Option<DockerCredentials>> {
+        Ok(None)
+    }
+}
+
+/// Private ECR registries, with tokens from GetAuthorizationToken.
+pub struct Ecr;
+
+/// Account and region of an ECR registry host,
+/// <account>.dkr.ecr.<region>.amazonaws.com or amazonaws.com.cn in China.
+pub fn ecr_registry(registry: &str) -> Option<(&str, Region)> {
+    match registry.split('.').collect::<Vec<&str>>().as_slice() {
+        [account_id, "dkr", "ecr", region, "amazonaws", "com"]
+        | [account_id, "dkr", "ecr", region, "amazonaws", "com", "cn"] => {
+            Some((account_id, Region::from_str(region).ok()?))
+        }
+        _ => None,
+    }
+}
+
+impl RegistryAuthProvider for Ecr {
+    fn handles(&self, registry: &str) -> bool {
+        ecr_registry(registry).is_some()
+    }
+
+    fn credentials(
+        &self,
+        registry: &str,
+        cache: &mut Cache,
+        opt: &Opt,
+    ) -> Result<Option<DockerCredentials>> {
+        match ecr_registry(registry) {
+            Some((account_id, region)) => {
+                ecr_tokens::credentials(cache, opt, aws::ecr_region(opt, region), account_id)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn evict(&self, registry: &str, cache: &mut Cache) {
+        if let Some((account_id, _)) = ecr_registry(registry) {
+            cache.ecr_tokens.evict(account_id);
+        }
+    }
+}
+
+pub struct Ghcr;
+
+impl RegistryAuthProvider for Ghcr {
+    fn handles(&self, registry: &str) -> bool {
+        registry == github::GHCR
+    }
+
+    fn credentials(
+        &self,
+        _registry: &str,
+        cache: &mut Cache,
+        opt: &Opt,
+    ) -> Result<Option<DockerCredentials>> {
+        github::ghcr_credentials(&mut cache.github, opt)
+    }
+
+    fn evict(&self, _registry: &str, cache: &mut Cache) {
+        cache.github.clear();
+    }
+}
+
+pub struct Acr;
+
+impl RegistryAuthProvider for Acr {
+    fn handles(&self, registry: &str) -> bool {
+        azure::is_acr(registry)
+    }
+
+    fn credentials(
+        &self,
+        registry: &str,
+        _cache: &mut Cache,
+        opt: &Opt,
+    ) -> Result<Option<DockerCredentials>> {
+        azure::acr_credentials(registry, opt)
+    }
+}
+
+pub struct Gcp;
+
+impl RegistryAuthProvider for Gcp {
+    fn handles(&self, registry: &str) -> bool {
+        gcp::is_gcp_registry(registry)
+    }
+
+    fn credentials(
+        &self,
+        registry: &str,
+        _cache: &mut Cache,
+        opt: &Opt,
+    ) -> Result<Option<DockerCredentials>> {
+        gcp::registry_credentials(registry, opt)
+    }
+}
+
+/// Any other registry, with the login given on the command line or in the
+/// Docker config file, if any.
+pub struct Generic;
+
+impl RegistryAuthProvider for Generic {
+    fn handles(&self, _registry: &str) -> bool {
+        true
+    }
+
+    fn credentials(
+        &self,
+        registry: &str,
+        cache: &mut Cache,
+        opt: &Opt,
+    ) -> Result<Option<DockerCredentials>> {
+        if let Some(credentials) = registry::static_credentials(registry, cache, opt)? {
+            return Ok(Some(credentials));
+        }
+        docker_config::registry_credentials(registry, opt)
+    }
+
+    fn evict(&self, _registry: &str, cache: &mut Cache) {
+        cache.secrets.clear();
+        cache.vault.clear();
+    }
+}
+
+/// Providers in the order they are asked whether they handle a registry.
+const PROVIDERS: &[&dyn RegistryAuthProvider] = &[&Anonymous, &Ecr, &Ghcr, &Acr, &Gcp, &Generic];
+
+/// The provider for a registry host.
+pub fn provider(registry: &str) -> &'static dyn RegistryAuthProvider {
+    PROVIDERS
+        .iter()
+        .find(|provider| provider.handles(registry))
+        .copied()
+        .unwrap_or(&Generic)
+}
+
+/// Whether the event's repository was declared public with
+/// --public-repository, however the repository is written there.
+pub fn is_public(event: &Event, opt: &Opt) -> bool {
+    let repository = event.repository();
+    opt.public_repositories
+        .iter()
+        .filter_map(|public| ImageRef::parse(public))
+        .any(|public| public.name() == repository)
+}
+
+/// Credentials for pulling the pushed image. Without credentials, images are
+/// pulled with whatever login the nodes have.
+pub fn event_credentials(
+    event: &Event,
+    cache: &mut Cache,
+    opt: &Opt,
+) -> Result<Option<DockerCredentials>> {
+    if is_public(event, opt) {
+        return Ok(None);
+    }
+    let registry = event.registry_host();
+    provider(&registry).credentials(&registry, cache, opt)
+}
+
+/// Pass on the result of a registry request, first forgetting the cached
+/// credentials for the registry if it refused them, so that a retry of the
+/// event fetches new ones.
+pub fn evict_refused<T>(registry: &str, result: Result<T>, cache: &mut Cache) -> Result<T> {
+    if let Err(SeedyError::RegistryRefused { .. }) = &result {
+        provider(registry).evict(registry, cache);
+    }
+    result
+}
@@ -0,0 +1,408 @@
+use crate::sso::SsoProvider;
+use crate::{
+    AwsCredentialProvider, CredentialSourceFormat, HttpClientTls, MissingCredentialOption, Opt,
+    Result, SeedyError,
+};
+use chrono::{DateTime, Utc};
+use futures01::future::{self, FutureResult};
+use reqwest::blocking::Client;
+use rusoto_core::{HttpClient, Region};
+use rusoto_credential::{
+    AutoRefreshingProvider, AwsCredentials, ContainerProvider, CredentialsError,
+    DefaultCredentialsProvider, EnvironmentProvider, InstanceMetadataProvider, ProfileProvider,
+    ProvideAwsCredentials, StaticProvider,
+};
+use rusoto_ecr::EcrClient;
+use rusoto_logs::CloudWatchLogsClient;
+use rusoto_s3::S3Client;
+use rusoto_secretsmanager::SecretsManagerClient;
+use rusoto_sns::SnsClient;
+use rusoto_sqs::SqsClient;
+use rusoto_stepfunctions::StepFunctionsClient;
+use rusoto_sts::{StsAssumeRoleSessionCredentialsProvider, StsClient};
+use snafu::{OptionExt, ResultExt};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CredentialSource {
+    Default,
+    Environment,
+    Profile,
+    InstanceMetadata,
+    Container,
+    WebIdentity,
+    Static,
+    Sso,
+}
+
+impl FromStr for CredentialSource {
+    type Err = SeedyError;
+
+    fn from_str(input: &str) -> Result<CredentialSource> {
+        match input {
+            "default" => Ok(CredentialSource::Default),
+            "environment" => Ok(CredentialSource::Environment),
+            "profile" => Ok(CredentialSource::Profile),
+            "instance-metadata" => Ok(CredentialSource::InstanceMetadata),
+            "container" => Ok(CredentialSource::Container),
+            "web-identity" => Ok(CredentialSource::WebIdentity),
+            "static" => Ok(CredentialSource::Static),
+            "sso" => Ok(CredentialSource::Sso),
+            _ => CredentialSourceFormat {
+                value: input.to_owned(),
+            }
+            .fail(),
+        }
+    }
+}
+
+/// Rusoto clients are not generic over their credentials provider, but
+/// their constructors are.
+pub trait FromProvider: Sized {
+    /// Service name as used in --aws-service-endpoints
+    const SERVICE: &'static str;
+
+    fn from_provider<P>(dispatcher: HttpClient, provider: P, region: Region) -> Self
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+        P::Future: Send;
+}
+
+impl FromProvider for SqsClient {
+    const SERVICE: &'static str = "sqs";
+
+    fn from_provider<P>(dispatcher: HttpClient, provider: P, region: Region) -> Self
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+        P::Future: Send,
+    {
+        SqsClient::new_with(dispatcher, provider, region)
+    }
+}
+
+impl FromProvider for EcrClient {
+    const SERVICE: &'static str = "ecr";
+
+    fn from_provider<P>(dispatcher: HttpClient, provider: P, region: Region) -> Self
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+        P::Future: Send,
+    {
+        EcrClient::new_with(dispatcher, provider, region)
+    }
+}
+
+impl FromProvider for S3Client {
+    const SERVICE: &'static str = "s3";
+
+    fn from_provider<P>(dispatcher: HttpClient, provider: P, region: Region) -> Self
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+        P::Future: Send,
+    {
+        S3Client::new_with(dispatcher, provider, region)
+    }
+}
+
+impl FromProvider for StsClient {
+    const SERVICE: &'static str = "sts";
+
+    fn from_provider<P>(dispatcher: HttpClient, provider: P, region: Region) -> Self
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+        P::Future: Send,
+    {
+        StsClient::new_with(dispatcher, provider, region)
+    }
+}
+
+impl FromProvider for CloudWatchLogsClient {
+    const SERVICE: &'static str = "logs";
+
+    fn from_provider<P>(dispatcher: HttpClient, provider: P, region: Region) -> Self
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+        P::Future: Send,
+    {
+        CloudWatchLogsClient::new_with(dispatcher, provider, region)
+    }
+}
+
+impl FromProvider for StepFunctionsClient {
+    const SERVICE: &'static str = "states";
+
+    fn from_provider<P>(dispatcher: HttpClient, provider: P, region: Region) -> Self
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+        P::Future: Send,
+    {
+        StepFunctionsClient::new_with(dispatcher, provider, region)
+    }
+}
+
+impl FromProvider for SnsClient {
+    const SERVICE: &'static str = "sns";
+
+    fn from_provider<P>(dispatcher: HttpClient, provider: P, region: Region) -> Self
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+        P::Future: Send,
+    {
+        SnsClient::new_with(dispatcher, provider, region)
+    }
+}
+
+impl FromProvider for SecretsManagerClient {
+    const SERVICE: &'static str = "secretsmanager";
+
+    fn from_provider<P>(dispatcher: HttpClient, provider: P, region: Region) -> Self
+    where
+        P: ProvideAwsCredentials + Send + Sync + 'static,
+        P::Future: Send,
+    {
+        SecretsManagerClient::new_with(dispatcher, provider, region)
+    }
+}
+
+fn xml_value<'a>(body: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let start = body.find(&open)? + open.len();
+    let end = start + body[start..].find(&format!("</{}>", tag))?;
+    Some(&body[start..end])
+}
+
+pub fn parse_assume_role_response(body: &str) -> Option<AwsCredentials> {
+    let expires_at = xml_value(body, "Expiration")?
+        .parse::<DateTime<Utc>>()
+        .ok()?;
+    Some(AwsCredentials::new(
+        xml_value(body, "AccessKeyId")?,
+        xml_value(body, "SecretAccessKey")?,
+        Some(xml_value(body, "SessionToken")?.to_owned()),
+        Some(expires_at),
+    ))
+}
+
+/// Exchanges a web identity token for role credentials. The token file is
+/// re-read on every refresh since orchestrators rotate it.
+pub struct WebIdentityProvider {
+    token_file: PathBuf,
+    role_arn: String,
+    region: Region,
+}
+
+impl WebIdentityProvider {
+    fn assume_role(&self) -> std::result::Result<AwsCredentials, CredentialsError> {
+        let token = fs::read_to_string(&self.token_file).map_err(|err| {
+            CredentialsError::new(format!(
+                "Could not read web identity token {}: {}",
+                self.token_file.display(),
+                err
+            ))
+        })?;
+        let body = Client::new()
+            .get(&format!(
+                "https://sts.{}.amazonaws.com/",
+                self.region.name()
+            ))
+            .query(&[
+                ("Action", "AssumeRoleWithWebIdentity"),
+                ("Version", "2011-06-15"),
+                ("RoleArn", &self.role_arn),
+                ("RoleSessionName", "swarm-ecr-deployer"),
+                ("WebIdentityToken", token.trim()),
+            ])
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|err| {
+                CredentialsError::new(format!("AssumeRoleWithWebIdentity failed: {}", err))
+            })?;
+        parse_assume_role_response(&body).ok_or_else(|| {
+            CredentialsError::new("AssumeRoleWithWebIdentity returned no credentials")
+        })
+    }
+}
+
+impl ProvideAwsCredentials for WebIdentityProvider {
+    type Future = FutureResult<AwsCredentials, CredentialsError>;
+
+    fn credentials(&self) -> Self::Future {
+        future::result(self.assume_role())
+    }
+}
+
+fn build<C, P>(provider: P, region: Region) -> Result<C>
+where
+    C: FromProvider,
+    P: ProvideAwsCredentials + Send + Sync + 'static,
+    P::Future: Send,
+{
+    let dispatcher = HttpClient::new().with_context(|| HttpClientTls)?;
+    Ok(C::from_provider(dispatcher, provider, region))
+}
+
+/// Point the client at an endpoint given on the command line, if any, such
+/// as a VPC endpoint or LocalStack. Signing still uses the region's name.
+pub fn endpoint_region<C: FromProvider>(opt: &Opt, region: Region) -> Region {
+    let endpoint = opt
+        .aws_service_endpoints
+        .iter()
+        .find(|(service, _)| service == C::SERVICE)
+        .map(|(_, endpoint)| endpoint)
+        .or_else(|| opt.aws_endpoint_url.as_ref());
+    match endpoint {
+        Some(endpoint) => Region::Custom {
+            name: region.name().to_owned(),
+            endpoint: endpoint.clone(),
+        },
+        None => region,
+    }
+}
+
+/// Region to make ECR API calls in for an image in the given region, unless
+/// overridden on the command line.
+pub fn ecr_region(opt: &Opt, region: Region) -> Region {
+    opt.ecr_region_override.clone().unwrap_or(region)
+}
+
+/// Role to assume for ECR calls concerning an account's registries, if any.
+pub fn ecr_role<'a>(opt: &'a Opt, account_id: &str) -> Option<&'a str> {
+    opt.ecr_account_roles
+        .iter()
+        .find(|(account, _)| account == account_id)
+        .map(|(_, role_arn)| role_arn.as_str())
+        .or_else(|| opt.ecr_role_arn.as_deref())
+}
+
+/// ECR clients per region and assumed role, so that their connections and
+/// credential chains are reused from one event to the next.
+#[derive(Default)]
+pub struct EcrClients {
+    clients: HashMap<(String, Option<String>), EcrClient>,
+}
+
+impl EcrClients {
+    /// An ECR client for the region acting as the role, built on first use.
+    pub fn get(&mut self, opt: &Opt, region: Region, role_arn: Option<&str>) -> Result<EcrClient> {
+        let key = (region.name().to_owned(), role_arn.map(str::to_owned));
+        if let Some(ecr) = self.clients.get(&key) {
+            return Ok(ecr.clone());
+        }
+        let ecr: EcrClient = match role_arn {
+            Some(role_arn) => {
+                assumed_role_client(opt, role_arn, opt.ecr_external_id.clone(), region)?
+            }
+            None => client(opt, region)?,
+        };
+        self.clients.insert(key, ecr.clone());
+        Ok(ecr)
+    }
+}
+
+/// Build a client using the credential source selected on the command line.
+pub fn client<C: FromProvider>(opt: &Opt, region: Region) -> Result<C> {
+    let region = endpoint_region::<C>(opt, region);
+    match opt.aws_credential_source {
+        CredentialSource::Default => build(
+            DefaultCredentialsProvider::new().with_context(|| AwsCredentialProvider)?,
+            region,
+        ),
+        CredentialSource::Environment => build(EnvironmentProvider::default(), region),
+        CredentialSource::Profile => build(
+            ProfileProvider::new().with_context(|| AwsCredentialProvider)?,
+            region,
+        ),
+        CredentialSource::InstanceMetadata => build(
+            AutoRefreshingProvider::new(InstanceMetadataProvider::new())
+                .with_context(|| AwsCredentialProvider)?,
+            region,
+        ),
+        CredentialSource::Container => build(
+            AutoRefreshingProvider::new(ContainerProvider::new())
+                .with_context(|| AwsCredentialProvider)?,
+            region,
+        ),
+        CredentialSource::Static => {
+            let access_key_id =
+                opt.aws_access_key_id
+                    .clone()
+                    .with_context(|| MissingCredentialOption {
+                        option: "--aws-access-key-id",
+                    })?;
+            let secret_access_key =
+                opt.aws_secret_access_key
+                    .clone()
+                    .with_context(|| MissingCredentialOption {
+                        option: "--aws-secret-access-key",
+                    })?;
+            build(
+                StaticProvider::new_minimal(access_key_id, secret_access_key),
+                region,
+            )
+        }
+        CredentialSource::WebIdentity => {
+            let token_file = opt.aws_web_identity_token_file.clone().with_context(|| {
+                MissingCredentialOption {
+                    option: "--aws-web-identity-token-file",
+                }
+            })?;
+            let role_arn = opt
+                .aws_role_arn
+                .clone()
+                .with_context(|| MissingCredentialOption {
+                    option: "--aws-role-arn",
+                })?;
+            let provider = WebIdentityProvider {
+                token_file,
+                role_arn,
+                region: Region::default(),
+            };
+            build(
+                AutoRefreshingProvider::new(provider).with_context(|| AwsCredentialProvider)?,
+                region,
+            )
+        }
+        CredentialSource::Sso => {
+            let aws_dir = dirs::home_dir()
+                .map(|home| home.join(".aws"))
+                .with_context(|| MissingCredentialOption {
+                    option: "a home directory",
+                })?;
+            let profile = opt.aws_profile.as_deref().unwrap_or("default");
+            build(
+                AutoRefreshingProvider::new(SsoProvider::new(&aws_dir, profile))
+                    .with_context(|| AwsCredentialProvider)?,
+                region,
+            )
+        }
+    }
+}
+
+/// Build a client acting as a role, typically in another account, assumed
+/// with the credentials selected on the command line.
+pub fn assumed_role_client<C: FromProvider>(
+    opt: &Opt,
+    role_arn: &str,
+    external_id: Option<String>,
+    region: Region,
+) -> Result<C> {
+    let sts: StsClient = client(opt, region.clone())?;
+    let region = endpoint_region::<C>(opt, region);
+    let provider = StsAssumeRoleSessionCredentialsProvider::new(
+        sts,
+        role_arn.to_owned(),
+        "swarm-ecr-deployer".to_owned(),
+        external_id,
+        None,
+        None,
+        None,
+    );
+    build(
+        AutoRefreshingProvider::new(provider).with_context(|| AwsCredentialProvider)?,
+        region,
+    )
+}
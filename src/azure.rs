@@ -0,0 +1,28 @@
+use crate::{MissingCredentialOption, Opt, Result};
+use bollard::auth::DockerCredentials;
+use snafu::OptionExt;
+
+pub const ACR_SUFFIX: &str = ".azurecr.io";
+
+pub fn is_acr(registry: &str) -> bool {
+    registry.ends_with(ACR_SUFFIX)
+}
+
+/// Credentials for an Azure Container Registry from a repository-scoped
+/// token, a service principal or the admin user, all of which log in with
+/// name and password.
+pub fn acr_credentials(registry: &str, opt: &Opt) -> Result<Option<DockerCredentials>> {
+    let password = match &opt.acr_password {
+        Some(password) => password,
+        None => return Ok(None),
+    };
+    let username = opt.acr_username.as_ref().context(MissingCredentialOption {
+        option: "--acr-username",
+    })?;
+    Ok(Some(DockerCredentials {
+        username: Some(username.clone()),
+        password: Some(password.clone()),
+        serveraddress: Some(registry.to_owned()),
+        ..Default::default()
+    }))
+}
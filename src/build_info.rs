@@ -0,0 +1,22 @@
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_SHA: &str = env!("SEEDY_GIT_SHA");
+pub const BUILD_TIMESTAMP: &str = env!("SEEDY_BUILD_TIMESTAMP");
+pub const FEATURES: &str = env!("SEEDY_FEATURES");
+
+pub const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("SEEDY_GIT_SHA"),
+    ", built ",
+    env!("SEEDY_BUILD_TIMESTAMP"),
+    ", features: ",
+    env!("SEEDY_FEATURES"),
+    ")"
+);
+
+pub fn banner() -> String {
+    format!(
+        "swarm-ecr-deployer {} ({}, built {}, features: {})",
+        VERSION, GIT_SHA, BUILD_TIMESTAMP, FEATURES
+    )
+}
@@ -0,0 +1,58 @@
+use crate::auth::Cache;
+use crate::events::Event;
+use crate::{watch, Opt, Result};
+use log::warn;
+use serde_json::Value;
+
+/// Image references exported as environment variables by a successful
+/// CodeBuild build, as announced by a "CodeBuild Build State Change" event.
+/// A variable may list several images separated by commas or whitespace.
+pub fn image_references(event_str: &str, variables: &[String]) -> Vec<String> {
+    let parsed: Value = match serde_json::from_str(event_str) {
+        Ok(parsed) => parsed,
+        Err(_) => return Vec::new(),
+    };
+    let detail = match parsed.get("detail") {
+        Some(detail)
+            if parsed.get("source").and_then(Value::as_str) == Some("aws.codebuild")
+                && detail.get("build-status").and_then(Value::as_str) == Some("SUCCEEDED") =>
+        {
+            detail
+        }
+        _ => return Vec::new(),
+    };
+    detail
+        .get("additional-information")
+        .and_then(|info| info.get("exported-environment-variables"))
+        .and_then(Value::as_array)
+        .map(|exported| {
+            exported
+                .iter()
+                .filter(|variable| {
+                    variable
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .map_or(false, |name| variables.iter().any(|v| v == name))
+                })
+                .filter_map(|variable| variable.get("value").and_then(Value::as_str))
+                .flat_map(|value| value.split(|c: char| c == ',' || c.is_whitespace()))
+                .filter(|image| !image.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn build_events(event_str: &str, cache: &mut Cache, opt: &Opt) -> Result<Vec<Event>> {
+    let events = image_references(event_str, &opt.codebuild_image_variables)
+        .iter()
+        .filter_map(|image| {
+            let event = watch::event_for_image(image);
+            if event.is_none() {
+                warn!("Ignoring CodeBuild image {} without a tag", image);
+            }
+            event
+        })
+        .collect();
+    watch::resolve_digests(events, cache, opt)
+}
@@ -0,0 +1,138 @@
+use crate::reference::ImageRef;
+use crate::{ContainerListing, Opt, RecreatingContainer, Result};
+use bollard::auth::DockerCredentials;
+use bollard::container::{
+    APIContainers, CreateContainerOptions, InspectContainerOptions, ListContainersOptions,
+    NetworkingConfig, RemoveContainerOptions, RenameContainerOptions, StartContainerOptions,
+    StopContainerOptions,
+};
+use bollard::image::CreateImageOptions;
+use bollard::Docker;
+use futures::stream::TryStreamExt;
+use log::warn;
+use snafu::ResultExt;
+use tokio::runtime::Runtime;
+
+/// Added to the name of a replacement container until the container it
+/// replaces has been removed.
+const REPLACEMENT_SUFFIX: &str = "seedy-next";
+
+/// Whether the container runs the tag of the image, whatever the digest.
+pub fn runs_image(container_image: &str, image: &ImageRef) -> bool {
+    ImageRef::parse(container_image).map_or(false, |running| running.same_tag(image))
+}
+
+pub fn is_tracked(container: &APIContainers, opt: &Opt) -> bool {
+    match &opt.filter_label {
+        Some((key, value)) => container.labels.get(key) == Some(value),
+        None => true,
+    }
+}
+
+/// The container name as Docker reports it has a leading slash.
+pub fn container_name(container: &APIContainers) -> Option<&str> {
+    container
+        .names
+        .first()
+        .map(|name| name.trim_start_matches('/'))
+}
+
+/// Running containers on this node whose image matches, ignoring digests.
+pub fn matching(
+    docker: &Docker,
+    rt: &mut Runtime,
+    image: &str,
+    opt: &Opt,
+) -> Result<Vec<APIContainers>> {
+    let image = match ImageRef::parse(image) {
+        Some(image) => image,
+        None => return Ok(Vec::new()),
+    };
+    let containers = rt
+        .block_on(
+            docker.list_containers::<String>(Some(ListContainersOptions {
+                all: false,
+                ..Default::default()
+            })),
+        )
+        .with_context(|| ContainerListing)?;
+    Ok(containers
+        .into_iter()
+        .filter(|container| runs_image(&container.image, &image))
+        .filter(|container| is_tracked(container, opt))
+        .collect())
+}
+
+/// Pull the new image and replace the container with one that has the same
+/// name, configuration and networks. The replacement is created before the
+/// old container is stopped, and the old container is started again if the
+/// replacement fails to start.
+pub fn recreate(
+    docker: &Docker,
+    rt: &mut Runtime,
+    container: &APIContainers,
+    pinned_image: &str,
+    credentials: Option<DockerCredentials>,
+) -> Result<()> {
+    let context = || RecreatingContainer {
+        container_id: container.id.clone(),
+    };
+    let name = container_name(container)
+        .unwrap_or(&container.id)
+        .to_owned();
+    rt.block_on(
+        docker
+            .create_image(
+                Some(CreateImageOptions {
+                    from_image: pinned_image,
+                    ..Default::default()
+                }),
+                None,
+                credentials,
+            )
+            .try_collect::<Vec<_>>(),
+    )
+    .with_context(context)?;
+    let details = rt
+        .block_on(docker.inspect_container(&container.id, None::<InspectContainerOptions>))
+        .with_context(context)?;
+    let mut config = details.config;
+    config.image = Some(pinned_image.to_owned());
+    config.host_config = Some(details.host_config);
+    config.networking_config = Some(NetworkingConfig {
+        endpoints_config: details.network_settings.networks,
+    });
+    let replacement = format!("{}-{}", name, REPLACEMENT_SUFFIX);
+    rt.block_on(docker.create_container(
+        Some(CreateContainerOptions {
+            name: replacement.as_str(),
+        }),
+        config,
+    ))
+    .with_context(context)?;
+    rt.block_on(docker.stop_container(&container.id, None::<StopContainerOptions>))
+        .with_context(context)?;
+    let started =
+        rt.block_on(docker.start_container(&replacement, None::<StartContainerOptions<String>>));
+    if let Err(err) = started {
+        warn!(
+            "Replacement for container {} failed to start, restarting it: {}",
+            &name, err
+        );
+        rt.block_on(docker.remove_container(&replacement, None::<RemoveContainerOptions>))
+            .with_context(context)?;
+        rt.block_on(docker.start_container(&container.id, None::<StartContainerOptions<String>>))
+            .with_context(context)?;
+        return Err(err).with_context(context);
+    }
+    rt.block_on(docker.remove_container(&container.id, None::<RemoveContainerOptions>))
+        .with_context(context)?;
+    rt.block_on(docker.rename_container(
+        &replacement,
+        RenameContainerOptions {
+            name: name.as_str(),
+        },
+    ))
+    .with_context(context)?;
+    Ok(())
+}
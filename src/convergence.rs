@@ -0,0 +1,197 @@
+use crate::managers::Managers;
+use crate::{current_image, DurationFormat, Opt, Result, ServiceListing, UpdatingService};
+use bollard::service::{ListServicesOptions, Service, UpdateServiceOptions};
+use log::{error, info, warn};
+use snafu::{OptionExt, ResultExt};
+use std::collections::HashMap;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+pub const DEADLINE_LABEL: &str = "seedy.deadline";
+pub const MIN_HEALTHY_LABEL: &str = "seedy.min-healthy-seconds";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, PartialEq)]
+pub struct Deadline {
+    pub deadline: Duration,
+    pub min_healthy: Duration,
+}
+
+/// Parse durations on the form 90, 90s, 10m or 2h.
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let (digits, multiplier) = match input.chars().last() {
+        Some('s') => (&input[..input.len() - 1], 1),
+        Some('m') => (&input[..input.len() - 1], 60),
+        Some('h') => (&input[..input.len() - 1], 3600),
+        _ => (input, 1),
+    };
+    let seconds = digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(multiplier))
+        .context(DurationFormat {
+            value: input.to_owned(),
+        })?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Labels on the service take precedence over the deployer-wide flags. No
+/// deadline means the deployer does not wait for the rollout.
+pub fn deadline_for(service: &Service<String>, opt: &Opt) -> Option<Deadline> {
+    let labels = &service.spec.labels;
+    let deadline = match labels.get(DEADLINE_LABEL) {
+        Some(value) => match parse_duration(value) {
+            Ok(deadline) => Some(deadline),
+            Err(err) => {
+                warn!(
+                    "Ignoring {} on service {}: {}",
+                    DEADLINE_LABEL, &service.id, err
+                );
+                opt.deadline
+            }
+        },
+        None => opt.deadline,
+    }?;
+    let min_healthy = labels
+        .get(MIN_HEALTHY_LABEL)
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(opt.min_healthy_seconds);
+    Some(Deadline {
+        deadline,
+        min_healthy: Duration::from_secs(min_healthy),
+    })
+}
+
+fn update_state(service: &Service<String>) -> Option<&str> {
+    service
+        .update_status
+        .as_ref()
+        .and_then(|status| status.state.as_ref())
+        .map(String::as_str)
+}
+
+/// Whether the manager has begun rolling out the update. Until it has, the
+/// service still shows the status of its previous update, if any.
+fn update_begun(service: &Service<String>, previous: &Service<String>) -> bool {
+    let started_at = |service: &Service<String>| {
+        service
+            .update_status
+            .as_ref()
+            .map(|status| status.started_at.clone())
+    };
+    started_at(service) != started_at(previous)
+}
+
+pub fn fetch_service(
+    managers: &mut Managers,
+    rt: &mut Runtime,
+    service_id: &str,
+) -> Result<Option<Service<String>>> {
+    managers.run(|docker| {
+        let mut filters = HashMap::new();
+        filters.insert("id".to_owned(), vec![service_id.to_owned()]);
+        let options = ListServicesOptions { filters };
+        rt.block_on(docker.list_services(Some(options)))
+            .with_context(|| ServiceListing)
+            .map(|services| {
+                services
+                    .into_iter()
+                    .find(|service| service.id == service_id)
+            })
+    })
+}
+
+/// Wait for a service update to complete and stay healthy. Returns the
+/// reason if it does not.
+fn await_rollout(
+    managers: &mut Managers,
+    rt: &mut Runtime,
+    previous: &Service<String>,
+    image: &str,
+    deadline: &Deadline,
+) -> Result<Option<String>> {
+    let service_id = &previous.id;
+    let started = Instant::now();
+    loop {
+        let service = match fetch_service(managers, rt, service_id)? {
+            Some(service) => service,
+            None => return Ok(Some("service disappeared".to_owned())),
+        };
+        if update_begun(&service, previous) {
+            match update_state(&service) {
+                Some("completed") | None => break,
+                Some("updating") => (),
+                Some(state) => return Ok(Some(format!("update ended in state {}", state))),
+            }
+        }
+        if started.elapsed() >= deadline.deadline {
+            return Ok(Some(format!(
+                "update did not complete within {}s",
+                deadline.deadline.as_secs()
+            )));
+        }
+        sleep(POLL_INTERVAL);
+    }
+    sleep(deadline.min_healthy);
+    let service = match fetch_service(managers, rt, service_id)? {
+        Some(service) => service,
+        None => return Ok(Some("service disappeared".to_owned())),
+    };
+    if current_image(&service).map(String::as_str) != Some(image) {
+        return Ok(Some("service no longer runs the deployed image".to_owned()));
+    }
+    match update_state(&service) {
+        Some("completed") | None => Ok(None),
+        Some(state) => Ok(Some(format!(
+            "service went into state {} within {}s",
+            state,
+            deadline.min_healthy.as_secs()
+        ))),
+    }
+}
+
+fn roll_back(managers: &mut Managers, rt: &mut Runtime, previous: &Service<String>) -> Result<()> {
+    let version = match fetch_service(managers, rt, &previous.id)? {
+        Some(service) => service.version.index,
+        None => return Ok(()),
+    };
+    managers.run(|docker| {
+        let options = UpdateServiceOptions {
+            version,
+            ..Default::default()
+        };
+        rt.block_on(docker.update_service(&previous.id, previous.spec.clone(), options, None))
+            .with_context(|| UpdatingService {
+                service_id: previous.id.clone(),
+            })
+    })?;
+    Ok(())
+}
+
+/// Follow the rollout of an update, rolling back to the previous spec if the
+/// service does not become healthy before its deadline. Returns whether the
+/// update converged.
+pub fn verify(
+    managers: &mut Managers,
+    rt: &mut Runtime,
+    previous: &Service<String>,
+    image: &str,
+    deadline: &Deadline,
+) -> Result<bool> {
+    match await_rollout(managers, rt, previous, image, deadline)? {
+        None => {
+            info!("Service {} converged on {}", &previous.id, image);
+            Ok(true)
+        }
+        Some(reason) => {
+            error!(
+                "Update of service {} to {} failed: {}; rolling back",
+                &previous.id, image, reason
+            );
+            roll_back(managers, rt, previous)?;
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::VecDeque;
+
+/// Bounded record of recently deployed digests. EventBridge fan-out and SQS
+/// redelivery both produce duplicate push events; these are skipped rather
+/// than redeploying the same digest again. Entries are per tagged image, so
+/// a push of several tags still updates services tracking each of them.
+/// The least recently seen digest is forgotten first.
+pub struct Dedupe {
+    capacity: usize,
+    entries: VecDeque<(String, String, String)>,
+}
+
+impl Dedupe {
+    pub fn new(capacity: usize) -> Dedupe {
+        Dedupe {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Id of the message which most recently deployed this digest, if any.
+    pub fn seen(&mut self, image: &str, digest: &str) -> Option<String> {
+        let pos = self
+            .entries
+            .iter()
+            .position(|(i, d, _)| i == image && d == digest)?;
+        let entry = self.entries.remove(pos)?;
+        let message_id = entry.2.clone();
+        self.entries.push_back(entry);
+        Some(message_id)
+    }
+
+    pub fn record(&mut self, image: &str, digest: &str, message_id: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|(i, d, _)| i != image || d != digest);
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries
+            .push_back((image.to_owned(), digest.to_owned(), message_id.to_owned()));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::events::Event;
+use crate::{
+    running_image, DeleteActionFormat, Deployer, Opt, Result, SeedyError, UpdatingService,
+};
+use bollard::service::{Service, ServiceSpecMode, ServiceSpecModeReplicated, UpdateServiceOptions};
+use log::{info, warn};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Services carrying this label are left alone by the deployer. Its value
+/// is the digest that was deleted from the registry.
+pub const PINNED_LABEL: &str = "seedy.pinned";
+
+/// What to do with a service whose running image was deleted from ECR, as
+/// new tasks of the service will fail to pull it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeleteAction {
+    Warn,
+    ScaleToZero,
+    /// Label the service so that later pushes are not deployed to it
+    Pin,
+}
+
+impl FromStr for DeleteAction {
+    type Err = SeedyError;
+
+    fn from_str(input: &str) -> Result<DeleteAction> {
+        match input {
+            "warn" => Ok(DeleteAction::Warn),
+            "scale-to-zero" => Ok(DeleteAction::ScaleToZero),
+            "pin" => Ok(DeleteAction::Pin),
+            _ => DeleteActionFormat { value: input }.fail(),
+        }
+    }
+}
+
+pub fn is_pinned(service: &Service<String>) -> bool {
+    service.spec.labels.contains_key(PINNED_LABEL)
+}
+
+/// Global services run one task per node and cannot be scaled.
+pub fn is_global(service: &Service<String>) -> bool {
+    service
+        .spec
+        .mode
+        .as_ref()
+        .map_or(false, |mode| mode.global.is_some())
+}
+
+/// Services running exactly the deleted digest from the same repository.
+/// Deletions of digests no service runs, e.g. by lifecycle policies, concern
+/// no one, and neither do deletions of a digest promoted to another repository.
+pub fn affected<'a>(
+    deleted: &Event,
+    services_by_image: &'a HashMap<String, Service<String>>,
+) -> Vec<&'a Service<String>> {
+    let repository = deleted.repository();
+    services_by_image
+        .values()
+        .filter(|service| {
+            running_image(service).map_or(false, |image| {
+                image.name() == repository && image.digest.as_ref() == Some(&deleted.image_digest)
+            })
+        })
+        .collect()
+}
+
+pub fn process_deletion(
+    deleted: &Event,
+    services_by_image: &HashMap<String, Service<String>>,
+    deployer: &mut Deployer,
+    opt: &Opt,
+) -> Result<()> {
+    let Deployer { managers, rt, .. } = deployer;
+    for service in affected(deleted, services_by_image) {
+        warn!(
+            "Service {} runs {}@{} which was deleted from the registry",
+            &service.spec.name, &deleted.repository_name, &deleted.image_digest
+        );
+        let mut spec = service.spec.clone();
+        match opt.on_delete {
+            DeleteAction::Warn => continue,
+            DeleteAction::ScaleToZero if is_global(service) => {
+                warn!("Not scaling global service {} to zero", &service.spec.name);
+                continue;
+            }
+            DeleteAction::ScaleToZero => {
+                spec.mode = Some(ServiceSpecMode {
+                    replicated: Some(ServiceSpecModeReplicated { replicas: Some(0) }),
+                    ..Default::default()
+                });
+            }
+            DeleteAction::Pin if is_pinned(service) => continue,
+            DeleteAction::Pin => {
+                spec.labels
+                    .insert(PINNED_LABEL.to_owned(), deleted.image_digest.clone());
+            }
+        }
+        managers.run(|docker| {
+            let options = UpdateServiceOptions {
+                version: service.version.index,
+                ..Default::default()
+            };
+            rt.block_on(docker.update_service(&service.id, spec.clone(), options, None))
+                .with_context(|| UpdatingService {
+                    service_id: service.id.clone(),
+                })
+        })?;
+        info!(
+            "Service {} {} after deletion of its image",
+            &service.spec.name,
+            match opt.on_delete {
+                DeleteAction::ScaleToZero => "scaled to zero",
+                _ => "pinned",
+            }
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use crate::events::DOCKER_HUB;
+use crate::{CredentialHelper, DockerConfigFormat, DockerConfigIo, Opt, Result};
+use bollard::auth::DockerCredentials;
+use serde_json::Value;
+use snafu::ResultExt;
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Keys the Docker CLI files Docker Hub logins under.
+const DOCKER_HUB_KEYS: &[&str] = &["index.docker.io", "registry-1.docker.io"];
+
+/// Server URL credential helpers know Docker Hub logins by.
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+
+/// Username credential helpers return for identity tokens.
+const TOKEN_USERNAME: &str = "<token>";
+
+/// How long a credential helper may take, e.g. waiting on a keychain, and
+/// how often to check whether it has exited.
+const HELPER_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The Docker CLI config file, from --docker-config, $DOCKER_CONFIG or the
+/// home directory, in that order.
+pub fn config_path(opt: &Opt) -> PathBuf {
+    opt.docker_config.clone().unwrap_or_else(|| {
+        env::var("DOCKER_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| dirs::home_dir().unwrap_or_default().join(".docker"))
+            .join("config.json")
+    })
+}
+
+/// Read the config file. A missing file is only an error if it was given
+/// on the command line.
+pub fn load(opt: &Opt) -> Result<Option<Value>> {
+    let path = config_path(opt);
+    if opt.docker_config.is_none() && !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).context(DockerConfigIo { path: &path })?;
+    let config = serde_json::from_str(&content).context(DockerConfigFormat { path: &path })?;
+    Ok(Some(config))
+}
+
+/// The registry host an auths key refers to. Keys may be URLs, as for
+/// Docker Hub's https://index.docker.io/v1/.
+fn registry_of(key: &str) -> &str {
+    let host = key
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let host = &host[..host.find('/').unwrap_or(host.len())];
+    if DOCKER_HUB_KEYS.contains(&host) {
+        DOCKER_HUB
+    } else {
+        host
+    }
+}
+
+/// Credentials stored for the registry under "auths", either as a base64
+/// encoded user:password pair, as separate fields or as an identity token.
+pub fn auth_credentials(config: &Value, registry: &str) -> Option<DockerCredentials> {
+    let auths = config.get("auths")?.as_object()?;
+    let (_, entry) = auths.iter().find(|(key, _)| registry_of(key) == registry)?;
+    let field = |name: &str| entry.get(name).and_then(Value::as_str).map(str::to_owned);
+    let (username, password) = match field("auth") {
+        Some(auth) => {
+            let decoded = String::from_utf8(base64::decode(&auth).ok()?).ok()?;
+            let colon_pos = decoded.find(':')?;
+            (
+                Some(decoded[..colon_pos].to_owned()),
+                Some(decoded[colon_pos + 1..].to_owned()),
+            )
+        }
+        None => (field("username"), field("password")),
+    };
+    let identitytoken = field("identitytoken");
+    if password.is_none() && identitytoken.is_none() {
+        return None;
+    }
+    Some(DockerCredentials {
+        username,
+        password,
+        identitytoken,
+        serveraddress: Some(registry.to_owned()),
+        ..Default::default()
+    })
+}
+
+/// The credential helper configured for the registry under "credHelpers",
+/// or else the one configured for all registries under "credsStore".
+pub fn helper_for<'a>(config: &'a Value, registry: &str) -> Option<&'a str> {
+    config
+        .get("credHelpers")
+        .and_then(Value::as_object)
+        .and_then(|helpers| {
+            helpers
+                .iter()
+                .find(|(key, _)| registry_of(key) == registry)
+                .and_then(|(_, helper)| helper.as_str())
+        })
+        .or_else(|| config.get("credsStore").and_then(Value::as_str))
+}
+
+/// Read the response of a credential helper's get command.
+pub fn parse_helper_output(output: &str, registry: &str) -> Option<DockerCredentials> {
+    let response: Value = serde_json::from_str(output).ok()?;
+    let username = response.get("Username")?.as_str()?.to_owned();
+    let secret = response.get("Secret")?.as_str()?.to_owned();
+    let (username, password, identitytoken) = if username == TOKEN_USERNAME {
+        (None, None, Some(secret))
+    } else {
+        (Some(username), Some(secret), None)
+    };
+    Some(DockerCredentials {
+        username,
+        password,
+        identitytoken,
+        serveraddress: Some(registry.to_owned()),
+        ..Default::default()
+    })
+}
+
+/// Read a pipe of a child from a thread, so that the child can be waited
+/// for without either side blocking on a full pipe.
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+/// Ask docker-credential-<helper> for the registry's credentials, as the
+/// Docker CLI does. Registries the helper has no login for yield None.
+fn helper_credentials(helper: &str, registry: &str) -> Result<Option<DockerCredentials>> {
+    let program = format!("docker-credential-{}", helper);
+    let failed = |message: String| CredentialHelper {
+        helper: program.clone(),
+        message,
+    };
+    let server = if registry == DOCKER_HUB {
+        DOCKER_HUB_SERVER
+    } else {
+        registry
+    };
+    let mut child = match Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => return failed(err.to_string()).fail(),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(server.as_bytes());
+    }
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let deadline = Instant::now() + HELPER_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return failed(format!("timed out after {}s", HELPER_TIMEOUT.as_secs())).fail();
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(err) => return failed(err.to_string()).fail(),
+        }
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stdout = String::from_utf8_lossy(&stdout);
+    if !status.success() {
+        // Helpers report missing logins on stdout or stderr depending on version
+        let stderr = stderr.join().unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&stderr);
+        if stdout.contains("credentials not found") || stderr.contains("credentials not found") {
+            return Ok(None);
+        }
+        return failed(format!("{} {}", stdout.trim(), stderr.trim())).fail();
+    }
+    match parse_helper_output(&stdout, registry) {
+        Some(credentials) => Ok(Some(credentials)),
+        None => failed("unexpected response".to_owned()).fail(),
+    }
+}
+
+/// Credentials for pulling from a registry as the Docker CLI on this host
+/// would, if it has logged in to the registry. Credential helpers take
+/// precedence over logins stored in the file itself.
+pub fn registry_credentials(registry: &str, opt: &Opt) -> Result<Option<DockerCredentials>> {
+    let config = match load(opt)? {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+    if let Some(helper) = helper_for(&config, registry) {
+        if let Some(credentials) = helper_credentials(helper, registry)? {
+            return Ok(Some(credentials));
+        }
+    }
+    Ok(auth_credentials(&config, registry))
+}
@@ -0,0 +1,126 @@
+use crate::auth::Cache;
+use crate::events::Event;
+use crate::markers::Report;
+use crate::reference::{EcrImage, ImageRef};
+use crate::{aws, running_image, DescribingImages, Opt, Result};
+use bollard::service::Service;
+use log::{info, warn};
+use rusoto_core::{Region, RusotoError};
+use rusoto_ecr::{DescribeImagesError, DescribeImagesRequest, Ecr, ImageIdentifier};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+pub fn due(last_report: Option<Instant>, interval_hours: u64) -> bool {
+    last_report.map_or(true, |last| {
+        last.elapsed() >= Duration::from_secs(interval_hours * 3600)
+    })
+}
+
+pub fn latest_digest(image: &EcrImage, cache: &mut Cache, opt: &Opt) -> Result<Option<String>> {
+    let ecr = cache.ecr_clients.get(
+        opt,
+        aws::ecr_region(opt, image.region.clone()),
+        aws::ecr_role(opt, &image.account_id),
+    )?;
+    let req = DescribeImagesRequest {
+        registry_id: Some(image.account_id.clone()),
+        repository_name: image.repository_name.clone(),
+        image_ids: Some(vec![ImageIdentifier {
+            image_tag: Some(image.image_tag.clone()),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    };
+    let digest = ecr
+        .describe_images(req)
+        .sync()
+        .with_context(|| DescribingImages {
+            repository_name: image.repository_name.clone(),
+        })?
+        .image_details
+        .and_then(|mut details| details.pop())
+        .and_then(|detail| detail.image_digest);
+    Ok(digest)
+}
+
+/// Whether the pushed digest is present in the event's repository, as it
+/// is only some time after the push in regions the image is replicated to.
+pub fn has_digest(event: &Event, cache: &mut Cache, opt: &Opt) -> Result<bool> {
+    let region = match Region::from_str(&event.region) {
+        Ok(region) => region,
+        Err(_) => return Ok(true),
+    };
+    let ecr = cache.ecr_clients.get(
+        opt,
+        aws::ecr_region(opt, region),
+        aws::ecr_role(opt, &event.account_id),
+    )?;
+    let req = DescribeImagesRequest {
+        registry_id: Some(event.account_id.clone()),
+        repository_name: event.repository_name.clone(),
+        image_ids: Some(vec![ImageIdentifier {
+            image_digest: Some(event.image_digest.clone()),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    };
+    match ecr.describe_images(req).sync() {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(DescribeImagesError::ImageNotFound(_))) => Ok(false),
+        Err(err) => Err(err).with_context(|| DescribingImages {
+            repository_name: event.repository_name.clone(),
+        }),
+    }
+}
+
+/// Compare the digest each tracked service runs with the digest its tag
+/// currently points to in ECR and report services that have fallen behind.
+pub fn report(
+    services_by_image: &HashMap<String, Service<String>>,
+    cache: &mut Cache,
+    opt: &Opt,
+) -> Report {
+    let mut findings = Vec::new();
+    let mut drifting = 0;
+    for (image, service) in services_by_image.iter() {
+        let ecr_image = match ImageRef::parse(image).and_then(|image| image.ecr()) {
+            Some(ecr_image) => ecr_image,
+            None => continue,
+        };
+        match latest_digest(&ecr_image, cache, opt) {
+            Ok(Some(latest)) => {
+                let running = running_image(service).and_then(|image| image.digest);
+                if running.as_deref() != Some(latest.as_str()) {
+                    drifting += 1;
+                    findings.push(format!(
+                        "Service {} ({}) runs {} but {} is now {}",
+                        &service.spec.name,
+                        &service.id,
+                        running.as_deref().unwrap_or("an unpinned image"),
+                        image,
+                        latest
+                    ));
+                }
+            }
+            Ok(None) => findings.push(format!("Tag of {} no longer exists in ECR", image)),
+            Err(err) => warn!("Drift: could not check {}: {}", image, err),
+        }
+    }
+    findings.sort();
+    for finding in findings.iter() {
+        warn!("Drift: {}", finding);
+    }
+    let summary = format!(
+        "{} of {} tracked services behind",
+        drifting,
+        services_by_image.len()
+    );
+    info!("Drift report complete, {}", summary);
+    Report {
+        title: "drift".to_owned(),
+        summary,
+        findings,
+    }
+}
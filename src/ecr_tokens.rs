@@ -0,0 +1,135 @@
+use crate::auth::Cache;
+use crate::events::Event;
+use crate::{aws, docker_credentials_from_auth_token, ecr_authorizations, Opt, Result};
+use bollard::auth::DockerCredentials;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rusoto_core::Region;
+use rusoto_ecr::AuthorizationData;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// How long before expiry a cached token is replaced.
+const REFRESH_MARGIN_MINUTES: i64 = 5;
+
+/// ECR authorization tokens per region and registry id. Tokens are valid
+/// for 12 hours, so one token serves many events.
+#[derive(Default)]
+pub struct Tokens {
+    tokens: HashMap<(String, String), (DockerCredentials, DateTime<Utc>)>,
+}
+
+impl Tokens {
+    fn cached(&self, key: &(String, String)) -> Option<DockerCredentials> {
+        self.tokens
+            .get(key)
+            .filter(|(_, expires_at)| usable(*expires_at, Utc::now()))
+            .map(|(credentials, _)| credentials.clone())
+    }
+
+    /// Forget the tokens for a registry id in every region, as when the
+    /// registry has refused one of them.
+    pub fn evict(&mut self, account_id: &str) {
+        self.tokens.retain(|(_, account), _| account != account_id);
+    }
+}
+
+/// Whether a token expiring at this time can still be handed out.
+pub fn usable(expires_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    expires_at > now + Duration::minutes(REFRESH_MARGIN_MINUTES)
+}
+
+/// The registry id an authorization is for, taken from its proxy endpoint,
+/// e.g. https://123456789012.dkr.ecr.eu-west-1.amazonaws.com.
+pub fn endpoint_account(proxy_endpoint: &str) -> Option<&str> {
+    let host = proxy_endpoint
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let account_id = &host[..host.find('.')?];
+    if account_id.is_empty() {
+        None
+    } else {
+        Some(account_id)
+    }
+}
+
+/// Fetch tokens for several registries in one call, caching each of them
+/// under the registry its proxy endpoint names.
+fn fetch(
+    cache: &mut Cache,
+    opt: &Opt,
+    region: Region,
+    role_arn: Option<&str>,
+    account_ids: Vec<String>,
+) -> Result<HashMap<String, DockerCredentials>> {
+    let region_name = region.name().to_owned();
+    let ecr = cache.ecr_clients.get(opt, region, role_arn)?;
+    let mut fetched = HashMap::new();
+    for auth in ecr_authorizations(&ecr, account_ids)? {
+        let AuthorizationData {
+            authorization_token,
+            expires_at,
+            proxy_endpoint,
+        } = auth;
+        let account_id = match proxy_endpoint.as_deref().and_then(endpoint_account) {
+            Some(account_id) => account_id.to_owned(),
+            None => continue,
+        };
+        let credentials = match authorization_token {
+            Some(token) => docker_credentials_from_auth_token(token),
+            None => continue,
+        };
+        if let Some(expires_at) = expires_at {
+            let key = (region_name.clone(), account_id.clone());
+            let expires_at = Utc.timestamp(expires_at as i64, 0);
+            cache
+                .ecr_tokens
+                .tokens
+                .insert(key, (credentials.clone(), expires_at));
+        }
+        fetched.insert(account_id, credentials);
+    }
+    Ok(fetched)
+}
+
+/// Credentials for pulling from an ECR registry, reusing the previous token
+/// until shortly before it expires.
+pub fn credentials(
+    cache: &mut Cache,
+    opt: &Opt,
+    region: Region,
+    account_id: &str,
+) -> Result<Option<DockerCredentials>> {
+    let key = (region.name().to_owned(), account_id.to_owned());
+    if let Some(credentials) = cache.ecr_tokens.cached(&key) {
+        return Ok(Some(credentials));
+    }
+    let role_arn = aws::ecr_role(opt, account_id);
+    Ok(fetch(cache, opt, region, role_arn, vec![account_id.to_owned()])?.remove(account_id))
+}
+
+/// Fetch tokens for the registries of a batch of events up front, with one
+/// call per region and role rather than one per event.
+pub fn prefetch(events: &[&Event], cache: &mut Cache, opt: &Opt) -> Result<()> {
+    let mut missing: HashMap<(String, Option<&str>), (Region, Vec<String>)> = HashMap::new();
+    for event in events.iter().filter(|event| event.is_ecr()) {
+        let region = match Region::from_str(&event.region) {
+            Ok(region) => aws::ecr_region(opt, region),
+            Err(_) => continue,
+        };
+        let key = (region.name().to_owned(), event.account_id.clone());
+        if cache.ecr_tokens.cached(&key).is_some() {
+            continue;
+        }
+        let role_arn = aws::ecr_role(opt, &event.account_id);
+        let (_, account_ids) = missing
+            .entry((key.0, role_arn))
+            .or_insert_with(|| (region, Vec::new()));
+        if !account_ids.contains(&key.1) {
+            account_ids.push(key.1);
+        }
+    }
+    for ((_, role_arn), (region, account_ids)) in missing {
+        fetch(cache, opt, region, role_arn, account_ids)?;
+    }
+    Ok(())
+}
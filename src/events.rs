@@ -1,60 +1,847 @@
-use serde_json;
+use crate::github::GHCR;
+use crate::reference::ImageRef;
+use crate::{PullThroughCacheFormat, SeedyError};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use log::warn;
+use serde::Deserialize;
+use serde_json::{self, json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
 
+/// Registry name used for Docker Hub events. Images on Docker Hub are
+/// referred to without registry host, as the Docker CLI does.
+pub const DOCKER_HUB: &str = "docker.io";
+
+#[derive(Clone, Debug)]
 pub struct Event {
     pub account_id: String,
     pub region: String,
     pub repository_name: String,
     pub image_digest: String,
     pub image_tag: String,
+    pub pushed_at: Option<DateTime<Utc>>,
+    /// Registry host for events from registries other than ECR, whose host
+    /// is derived from account and region.
+    pub registry: Option<String>,
+}
+
+/// The registry host of an ECR account in a region. Registries in the China
+/// regions are under amazonaws.com.cn.
+pub fn ecr_host(account_id: &str, region: &str) -> String {
+    let suffix = if region.starts_with("cn-") { ".cn" } else { "" };
+    format!("{}.dkr.ecr.{}.amazonaws.com{}", account_id, region, suffix)
 }
 
 impl Event {
+    pub fn registry_host(&self) -> String {
+        match &self.registry {
+            Some(registry) => registry.clone(),
+            None => ecr_host(&self.account_id, &self.region),
+        }
+    }
+
+    /// The image reference the event is about, without digest.
+    pub fn reference(&self) -> ImageRef {
+        ImageRef {
+            registry: self.registry_host(),
+            repository: self.repository_name.clone(),
+            tag: self.image_tag.clone(),
+            digest: None,
+        }
+    }
+
+    pub fn repository(&self) -> String {
+        self.reference().name()
+    }
+
+    pub fn is_ecr(&self) -> bool {
+        self.registry.is_none()
+    }
+
+    /// Whether an ECR event comes from an allowed account and region. Empty
+    /// lists allow any. Events from other registries carry neither.
+    pub fn origin_allowed(&self, accounts: &[String], regions: &[String]) -> bool {
+        !self.is_ecr()
+            || ((accounts.is_empty() || accounts.contains(&self.account_id))
+                && (regions.is_empty() || regions.contains(&self.region)))
+    }
+
     pub fn image(&self) -> String {
-        format!(
-            "{}.dkr.ecr.{}.amazonaws.com/{}:{}",
-            self.account_id, self.region, self.repository_name, self.image_tag
-        )
-    }
-}
-
-fn extract_string_value(
-    object: &serde_json::Map<String, serde_json::Value>,
-    field: &str,
-) -> String {
-    object
-        .get(field)
-        .unwrap_or_else(|| panic!("event to contain {}", field))
-        .as_str()
-        .unwrap_or_else(|| panic!("{} to be a string", field))
-        .to_owned()
-}
-
-pub fn parse_ecr_event(event_str: &str) -> Option<Event> {
-    let parsed: serde_json::Map<String, serde_json::Value> =
-        serde_json::from_str(event_str).expect("event to be json");
-
-    let detail = parsed
-        .get("detail")
-        .expect("event to contain detail object")
-        .as_object()
-        .expect("a detail object");
-    if detail.get("action-type")?.as_str() == Some("PUSH")
-        && detail.get("result")?.as_str() == Some("SUCCESS")
-    {
-        let account_id = extract_string_value(&parsed, "account");
-        let region = extract_string_value(&parsed, "region");
-        let repository_name = extract_string_value(detail, "repository-name");
-        let image_digest = extract_string_value(detail, "image-digest");
-        let image_tag = extract_string_value(detail, "image-tag");
+        self.reference().unpinned()
+    }
+
+    pub fn pinned_image(&self) -> String {
+        format!("{}@{}", self.image(), self.image_digest)
+    }
 
+    pub fn lead_time(&self, deployed_at: DateTime<Utc>) -> Option<Duration> {
+        self.pushed_at.map(|pushed_at| deployed_at - pushed_at)
+    }
+
+    /// The normalized form exchanged with plugins.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "account_id": self.account_id,
+            "region": self.region,
+            "repository_name": self.repository_name,
+            "image_digest": self.image_digest,
+            "image_tag": self.image_tag,
+            "pushed_at": self.pushed_at.map(|pushed_at| pushed_at.to_rfc3339()),
+            "registry": self.registry,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Event> {
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_owned);
         Some(Event {
-            account_id,
-            region,
-            repository_name,
-            image_digest,
+            account_id: field("account_id")?,
+            region: field("region")?,
+            repository_name: field("repository_name")?,
+            image_digest: field("image_digest")?,
+            image_tag: field("image_tag")?,
+            pushed_at: field("pushed_at").and_then(|time| time.parse().ok()),
+            registry: field("registry"),
+        })
+    }
+}
+
+/// A message recognized as an event of some kind, but whose content does
+/// not make sense.
+#[derive(Debug, PartialEq)]
+pub enum EventParseError {
+    Invalid(String),
+}
+
+impl std::fmt::Display for EventParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EventParseError::Invalid(message) => write!(f, "invalid event: {}", message),
+        }
+    }
+}
+
+/// EventBridge event envelope. Unknown fields are ignored.
+#[derive(Deserialize)]
+struct Envelope<D> {
+    account: String,
+    region: String,
+    time: Option<String>,
+    detail: D,
+}
+
+/// Just enough of an event to tell what kind it is.
+#[derive(Deserialize)]
+struct Kind<D> {
+    /// EventBridge envelope schema version
+    version: Option<String>,
+    #[serde(rename = "detail-type")]
+    detail_type: Option<String>,
+    detail: D,
+}
+
+/// The event schema revisions that announce ECR pushes.
+#[derive(Debug, PartialEq)]
+pub enum EcrSchema {
+    /// "ECR Image Action" events emitted by ECR itself
+    ImageAction,
+    /// PutImage calls recorded by CloudTrail, for accounts that route
+    /// CloudTrail rather than ECR events to the deployer
+    CloudTrailPutImage,
+}
+
+/// Envelope schema versions this deployer was written against. Events of
+/// other versions are parsed as the latest known version, since fields are
+/// only expected to be added.
+pub const KNOWN_SCHEMA_VERSIONS: &[&str] = &["0"];
+
+/// Tell which ECR schema an event follows, if any.
+pub fn ecr_schema(event_str: &str) -> Option<EcrSchema> {
+    let kind: Kind<Value> = serde_json::from_str(event_str).ok()?;
+    let schema = match kind.detail_type.as_deref() {
+        Some("AWS API Call via CloudTrail")
+            if kind.detail.get("eventSource").and_then(Value::as_str)
+                == Some("ecr.amazonaws.com")
+                && kind.detail.get("eventName").and_then(Value::as_str) == Some("PutImage") =>
+        {
+            EcrSchema::CloudTrailPutImage
+        }
+        Some("ECR Image Action") | None => EcrSchema::ImageAction,
+        _ => return None,
+    };
+    if let Some(version) = kind.version.as_deref() {
+        if !KNOWN_SCHEMA_VERSIONS.contains(&version) {
+            warn!(
+                "Event schema version {} is unknown, parsing as version {}",
+                version,
+                KNOWN_SCHEMA_VERSIONS[KNOWN_SCHEMA_VERSIONS.len() - 1]
+            );
+        }
+    }
+    Some(schema)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct EcrAction {
+    action_type: Option<String>,
+    result: Option<String>,
+}
+
+/// Detail of an "ECR Image Action" event for a successful push. Some relays
+/// strip the digest, which is then looked up before deploying.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct EcrImagePush {
+    repository_name: String,
+    #[serde(default)]
+    image_digest: String,
+    image_tag: String,
+}
+
+/// Detail of a CloudTrail event for an ECR PutImage call. Failed calls
+/// carry an error code and no response.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CloudTrailPutImage {
+    error_code: Option<String>,
+    response_elements: Option<PutImageResponse>,
+}
+
+#[derive(Deserialize)]
+struct PutImageResponse {
+    image: EcrImage,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EcrImage {
+    registry_id: String,
+    repository_name: String,
+    image_id: EcrImageId,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EcrImageId {
+    image_digest: String,
+    image_tag: Option<String>,
+}
+
+/// Detail of an "ECR Image Action" event for a deletion. Images deleted by
+/// digest only carry no tag.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct EcrImageDelete {
+    repository_name: String,
+    image_digest: String,
+    #[serde(default)]
+    image_tag: String,
+}
+
+/// Detail of an "ECR Image Scan" event.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct EcrImageScan {
+    scan_status: String,
+    repository_name: String,
+    image_digest: String,
+    #[serde(default)]
+    image_tags: Vec<String>,
+    #[serde(default)]
+    finding_severity_counts: HashMap<String, u64>,
+}
+
+/// A completed ECR image scan, with the image as it would appear in a push
+/// event for each of its tags.
+#[derive(Debug)]
+pub struct ScanCompleted {
+    pub events: Vec<Event>,
+    /// Number of findings per severity, e.g. "CRITICAL"
+    pub severity_counts: HashMap<String, u64>,
+}
+
+pub fn parse_ecr_scan_event(event_str: &str) -> Option<ScanCompleted> {
+    let kind: Kind<Value> = serde_json::from_str(event_str).ok()?;
+    if kind.detail_type.as_deref() != Some("ECR Image Scan") {
+        return None;
+    }
+    let envelope: Envelope<EcrImageScan> = serde_json::from_str(event_str).ok()?;
+    let detail = envelope.detail;
+    if detail.scan_status != "COMPLETE" {
+        return None;
+    }
+    let events = detail
+        .image_tags
+        .into_iter()
+        .map(|image_tag| Event {
+            account_id: envelope.account.clone(),
+            region: envelope.region.clone(),
+            repository_name: detail.repository_name.clone(),
+            image_digest: detail.image_digest.clone(),
             image_tag,
+            pushed_at: None,
+            registry: None,
         })
+        .collect();
+    Some(ScanCompleted {
+        events,
+        severity_counts: detail.finding_severity_counts,
+    })
+}
+
+/// Keep only the newest push of each image in a batch of messages, so that
+/// busy CI does not trigger a service update per intermediate digest. Later
+/// messages win ties. Returns the superseded events.
+pub fn coalesce(batch: Vec<&mut Vec<Event>>) -> Vec<Event> {
+    let mut newest: HashMap<String, (usize, Option<DateTime<Utc>>)> = HashMap::new();
+    for (pos, events) in batch.iter().enumerate() {
+        for event in events.iter() {
+            let newer = match newest.get(&event.image()) {
+                Some((_, pushed_at)) => event.pushed_at >= *pushed_at,
+                None => true,
+            };
+            if newer {
+                newest.insert(event.image(), (pos, event.pushed_at));
+            }
+        }
+    }
+    let mut superseded = Vec::new();
+    for (pos, events) in batch.into_iter().enumerate() {
+        let (keep, drop): (Vec<Event>, Vec<Event>) = events
+            .drain(..)
+            .partition(|event| newest.get(&event.image()).map(|(p, _)| *p == pos) != Some(false));
+        *events = keep;
+        superseded.extend(drop);
+    }
+    superseded
+}
+
+/// The same push as seen in the regions ECR replicates the event's region to,
+/// given as pairs of source and destination region.
+pub fn replicas(event: &Event, replication: &[(String, String)]) -> Vec<Event> {
+    if !event.is_ecr() {
+        return Vec::new();
+    }
+    replication
+        .iter()
+        .filter(|(source, _)| *source == event.region)
+        .map(|(_, destination)| Event {
+            region: destination.clone(),
+            ..event.clone()
+        })
+        .collect()
+}
+
+/// An ECR pull-through cache rule, under which images of the upstream
+/// registry are pulled as <account>.dkr.ecr.<region>.amazonaws.com/<prefix>/<repository>.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PullThroughCache {
+    pub account_id: String,
+    pub region: String,
+    pub prefix: String,
+    pub upstream: String,
+}
+
+impl FromStr for PullThroughCache {
+    type Err = SeedyError;
+
+    fn from_str(input: &str) -> Result<PullThroughCache, SeedyError> {
+        let parts: Vec<&str> = input.splitn(2, '=').collect();
+        let (cache, upstream) = match parts.as_slice() {
+            [cache, upstream] if !upstream.is_empty() => (cache, upstream),
+            _ => return PullThroughCacheFormat { value: input }.fail(),
+        };
+        let parts: Vec<&str> = cache.splitn(2, '/').collect();
+        let (host, prefix) = match parts.as_slice() {
+            [host, prefix] if !prefix.is_empty() => (host, prefix),
+            _ => return PullThroughCacheFormat { value: input }.fail(),
+        };
+        match host.split('.').collect::<Vec<&str>>().as_slice() {
+            [account_id, "dkr", "ecr", region, "amazonaws", "com"] => Ok(PullThroughCache {
+                account_id: (*account_id).to_owned(),
+                region: (*region).to_owned(),
+                prefix: prefix.trim_end_matches('/').to_owned(),
+                upstream: (*upstream).to_owned(),
+            }),
+            _ => PullThroughCacheFormat { value: input }.fail(),
+        }
+    }
+}
+
+/// The same push as seen through the pull-through caches of the event's
+/// registry, so that services pulling the cached copy are updated too.
+pub fn cached(event: &Event, caches: &[PullThroughCache]) -> Vec<Event> {
+    let registry = match &event.registry {
+        Some(registry) => registry,
+        None => return Vec::new(),
+    };
+    caches
+        .iter()
+        .filter(|cache| cache.upstream == *registry)
+        .map(|cache| Event {
+            account_id: cache.account_id.clone(),
+            region: cache.region.clone(),
+            repository_name: format!("{}/{}", cache.prefix, event.repository_name),
+            registry: None,
+            ..event.clone()
+        })
+        .collect()
+}
+
+/// EventBridge marks events replayed from an archive with the replay name.
+pub fn replay_name(event_str: &str) -> Option<String> {
+    let parsed: Value = serde_json::from_str(event_str).ok()?;
+    parsed.get("replay-name")?.as_str().map(str::to_owned)
+}
+
+/// Parse an ECR push event. Messages that are not ECR push events yield
+/// None, while push events lacking the image details yield an error.
+pub fn parse_ecr_event(event_str: &str) -> Result<Option<Event>, EventParseError> {
+    match ecr_schema(event_str) {
+        Some(EcrSchema::ImageAction) => parse_ecr_image_action(event_str),
+        Some(EcrSchema::CloudTrailPutImage) => parse_cloudtrail_put_image(event_str),
+        None => Ok(None),
+    }
+}
+
+fn parse_ecr_image_action(event_str: &str) -> Result<Option<Event>, EventParseError> {
+    let action = match serde_json::from_str::<Kind<EcrAction>>(event_str) {
+        Ok(kind) => kind.detail,
+        Err(_) => return Ok(None),
+    };
+    if action.action_type.as_deref() != Some("PUSH") || action.result.as_deref() != Some("SUCCESS")
+    {
+        return Ok(None);
+    }
+    let envelope: Envelope<EcrImagePush> =
+        serde_json::from_str(event_str).map_err(|err| EventParseError::Invalid(err.to_string()))?;
+    let pushed_at = envelope
+        .time
+        .and_then(|time| time.parse::<DateTime<Utc>>().ok());
+    Ok(Some(Event {
+        account_id: envelope.account,
+        region: envelope.region,
+        repository_name: envelope.detail.repository_name,
+        image_digest: envelope.detail.image_digest,
+        image_tag: envelope.detail.image_tag,
+        pushed_at,
+        registry: None,
+    }))
+}
+
+/// Images pushed by digest only have no tag to match services on.
+fn parse_cloudtrail_put_image(event_str: &str) -> Result<Option<Event>, EventParseError> {
+    let envelope: Envelope<CloudTrailPutImage> =
+        serde_json::from_str(event_str).map_err(|err| EventParseError::Invalid(err.to_string()))?;
+    if envelope.detail.error_code.is_some() {
+        return Ok(None);
+    }
+    let image = match envelope.detail.response_elements {
+        Some(response) => response.image,
+        None => return Err(EventParseError::Invalid("no response elements".to_owned())),
+    };
+    let image_tag = match image.image_id.image_tag {
+        Some(image_tag) => image_tag,
+        None => return Ok(None),
+    };
+    Ok(Some(Event {
+        account_id: image.registry_id,
+        region: envelope.region,
+        repository_name: image.repository_name,
+        image_digest: image.image_id.image_digest,
+        image_tag,
+        pushed_at: envelope.time.and_then(|time| time.parse().ok()),
+        registry: None,
+    }))
+}
+
+/// Parse an ECR event for an image deleted from a repository.
+pub fn parse_ecr_delete_event(event_str: &str) -> Option<Event> {
+    let action = serde_json::from_str::<Kind<EcrAction>>(event_str)
+        .ok()?
+        .detail;
+    if action.action_type.as_deref() != Some("DELETE")
+        || action.result.as_deref() != Some("SUCCESS")
+    {
+        return None;
+    }
+    let envelope: Envelope<EcrImageDelete> = serde_json::from_str(event_str).ok()?;
+    Some(Event {
+        account_id: envelope.account,
+        region: envelope.region,
+        repository_name: envelope.detail.repository_name,
+        image_digest: envelope.detail.image_digest,
+        image_tag: envelope.detail.image_tag,
+        pushed_at: None,
+        registry: None,
+    })
+}
+
+/// Parse an Artifactory Docker "pushed" webhook. Images are addressed with
+/// the repository path method, i.e. <host>/<repo key>/<image>.
+pub fn parse_artifactory_event(event_str: &str) -> Option<Event> {
+    let parsed: Value = serde_json::from_str(event_str).ok()?;
+    if parsed.get("domain")?.as_str() != Some("docker")
+        || parsed.get("event_type")?.as_str() != Some("pushed")
+    {
+        return None;
+    }
+    let data = parsed.get("data")?;
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .filter_map(|name| data.get(*name).and_then(Value::as_str))
+            .next()
+            .map(str::to_owned)
+    };
+    let origin = parsed.get("jpd_origin")?.as_str()?;
+    let registry = origin
+        .splitn(2, "://")
+        .last()?
+        .trim_end_matches('/')
+        .to_owned();
+    let repo_key = field(&["repo_key", "repoKey"])?;
+    let image_name = field(&["image_name", "imageName"])?;
+    Some(Event {
+        account_id: String::new(),
+        region: String::new(),
+        repository_name: format!("{}/{}", repo_key, image_name),
+        image_digest: format!("sha256:{}", field(&["sha256"])?),
+        image_tag: field(&["tag"])?,
+        pushed_at: None,
+        registry: Some(registry),
+    })
+}
+
+/// Parse a Nexus Repository Manager component or asset webhook for a Docker
+/// repository. Nexus serves each Docker repository on its own connector, so
+/// the registry host is looked up from the repository name. The payload
+/// carries no digest; it is left empty to be resolved from the registry.
+pub fn parse_nexus_event(event_str: &str, registries: &[(String, String)]) -> Option<Event> {
+    let parsed: Value = serde_json::from_str(event_str).ok()?;
+    match parsed.get("action")?.as_str()? {
+        "CREATED" | "UPDATED" => (),
+        _ => return None,
+    }
+    let repository = parsed.get("repositoryName")?.as_str()?;
+    let registry = registries
+        .iter()
+        .find(|(name, _)| name == repository)
+        .map(|(_, host)| host.clone())?;
+    let (repository_name, image_tag) = if let Some(component) = parsed.get("component") {
+        if component.get("format")?.as_str() != Some("docker") {
+            return None;
+        }
+        let name = component.get("name")?.as_str()?;
+        let name = match component.get("group").and_then(Value::as_str) {
+            Some(group) if !group.is_empty() => format!("{}/{}", group, name),
+            _ => name.to_owned(),
+        };
+        (name, component.get("version")?.as_str()?.to_owned())
     } else {
-        None
+        let asset = parsed.get("asset")?;
+        if asset.get("format")?.as_str() != Some("docker") {
+            return None;
+        }
+        // Asset names look like v2/<repository>/manifests/<tag>
+        let path = asset.get("name")?.as_str()?.trim_start_matches('/');
+        if !path.starts_with("v2/") {
+            return None;
+        }
+        let manifests_pos = path.rfind("/manifests/")?;
+        let tag = &path[manifests_pos + "/manifests/".len()..];
+        if tag.starts_with("sha256:") {
+            return None;
+        }
+        (path[3..manifests_pos].to_owned(), tag.to_owned())
+    };
+    Some(Event {
+        account_id: String::new(),
+        region: String::new(),
+        repository_name,
+        image_digest: String::new(),
+        image_tag,
+        pushed_at: None,
+        registry: Some(registry),
+    })
+}
+
+/// Parse a Docker Hub repository webhook. These carry no digest, which is
+/// left empty to be resolved from the registry.
+pub fn parse_docker_hub_event(event_str: &str) -> Option<Event> {
+    let parsed: Value = serde_json::from_str(event_str).ok()?;
+    let push_data = parsed.get("push_data")?;
+    let repository_name = parsed.get("repository")?.get("repo_name")?.as_str()?;
+    let repository_name = if repository_name.contains('/') {
+        repository_name.to_owned()
+    } else {
+        format!("library/{}", repository_name)
+    };
+    Some(Event {
+        account_id: String::new(),
+        region: String::new(),
+        repository_name,
+        image_digest: String::new(),
+        image_tag: push_data.get("tag")?.as_str()?.to_owned(),
+        pushed_at: push_data
+            .get("pushed_at")
+            .and_then(Value::as_i64)
+            .map(|pushed_at| Utc.timestamp(pushed_at, 0)),
+        registry: Some(DOCKER_HUB.to_owned()),
+    })
+}
+
+/// Parse a Harbor PUSH_ARTIFACT webhook, with one event per tagged
+/// resource. The registry host is taken from the resource URL.
+pub fn parse_harbor_events(event_str: &str) -> Vec<Event> {
+    let parsed: Value = match serde_json::from_str(event_str) {
+        Ok(parsed) => parsed,
+        Err(_) => return Vec::new(),
+    };
+    if parsed.get("type").and_then(Value::as_str) != Some("PUSH_ARTIFACT") {
+        return Vec::new();
+    }
+    let event_data = match parsed.get("event_data") {
+        Some(event_data) => event_data,
+        None => return Vec::new(),
+    };
+    let repository_name = match event_data
+        .get("repository")
+        .and_then(|repository| repository.get("repo_full_name"))
+        .and_then(Value::as_str)
+    {
+        Some(repository_name) => repository_name,
+        None => return Vec::new(),
+    };
+    let pushed_at = parsed
+        .get("occur_at")
+        .and_then(Value::as_i64)
+        .map(|occur_at| Utc.timestamp(occur_at, 0));
+    event_data
+        .get("resources")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|resource| {
+            let resource_url = resource.get("resource_url")?.as_str()?;
+            let registry = &resource_url[..resource_url.find('/')?];
+            Some(Event {
+                account_id: String::new(),
+                region: String::new(),
+                repository_name: repository_name.to_owned(),
+                image_digest: resource.get("digest")?.as_str()?.to_owned(),
+                image_tag: resource.get("tag")?.as_str()?.to_owned(),
+                pushed_at,
+                registry: Some(registry.to_owned()),
+            })
+        })
+        .collect()
+}
+
+/// Parse a GitLab container registry notification, which uses the Docker
+/// distribution notification envelope. Each manifest push with a tag gives
+/// an event; layer pushes carry no tag.
+pub fn parse_gitlab_registry_events(event_str: &str) -> Vec<Event> {
+    let parsed: Value = match serde_json::from_str(event_str) {
+        Ok(parsed) => parsed,
+        Err(_) => return Vec::new(),
+    };
+    parsed
+        .get("events")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|event| event.get("action").and_then(Value::as_str) == Some("push"))
+        .filter_map(|event| {
+            let target = event.get("target")?;
+            Some(Event {
+                account_id: String::new(),
+                region: String::new(),
+                repository_name: target.get("repository")?.as_str()?.to_owned(),
+                image_digest: target.get("digest")?.as_str()?.to_owned(),
+                image_tag: target.get("tag")?.as_str()?.to_owned(),
+                pushed_at: None,
+                registry: Some(event.get("request")?.get("host")?.as_str()?.to_owned()),
+            })
+        })
+        .collect()
+}
+
+/// Parse a GitHub package or registry_package "published" webhook for a
+/// container image on ghcr.io.
+pub fn parse_github_package_event(event_str: &str) -> Option<Event> {
+    let parsed: Value = serde_json::from_str(event_str).ok()?;
+    if parsed.get("action")?.as_str() != Some("published") {
+        return None;
+    }
+    let package = parsed
+        .get("registry_package")
+        .or_else(|| parsed.get("package"))?;
+    let version = package.get("package_version")?;
+    let tag = version.get("container_metadata")?.get("tag")?;
+    let image_tag = tag.get("name")?.as_str().filter(|name| !name.is_empty())?;
+    // package_url looks like ghcr.io/<owner>/<name>:<tag>
+    let package_url = version.get("package_url")?.as_str()?;
+    let without_tag = &package_url[..package_url.rfind(':')?];
+    let slash_pos = without_tag.find('/')?;
+    if &without_tag[..slash_pos] != GHCR {
+        return None;
+    }
+    Some(Event {
+        account_id: String::new(),
+        region: String::new(),
+        repository_name: without_tag[slash_pos + 1..].to_lowercase(),
+        image_digest: tag.get("digest")?.as_str()?.to_owned(),
+        image_tag: image_tag.to_owned(),
+        pushed_at: version
+            .get("updated_at")
+            .and_then(Value::as_str)
+            .and_then(|time| time.parse().ok()),
+        registry: Some(GHCR.to_owned()),
+    })
+}
+
+/// Parse a Quay repository push notification, which lists every tag the
+/// push updated. It carries no digests; they are left empty to be resolved
+/// from the registry.
+pub fn parse_quay_events(event_str: &str) -> Vec<Event> {
+    let parsed: Value = match serde_json::from_str(event_str) {
+        Ok(parsed) => parsed,
+        Err(_) => return Vec::new(),
+    };
+    let docker_url = match parsed.get("docker_url").and_then(Value::as_str) {
+        Some(docker_url) => docker_url,
+        None => return Vec::new(),
+    };
+    let (registry, repository_name) = match docker_url.find('/') {
+        Some(slash_pos) => (&docker_url[..slash_pos], &docker_url[slash_pos + 1..]),
+        None => return Vec::new(),
+    };
+    parsed
+        .get("updated_tags")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|tag| Event {
+            account_id: String::new(),
+            region: String::new(),
+            repository_name: repository_name.to_owned(),
+            image_digest: String::new(),
+            image_tag: tag.to_owned(),
+            pushed_at: None,
+            registry: Some(registry.to_owned()),
+        })
+        .collect()
+}
+
+fn event_grid_entries(event_str: &str) -> Vec<Value> {
+    match serde_json::from_str(event_str) {
+        Ok(Value::Array(entries)) => entries,
+        Ok(entry @ Value::Object(_)) => vec![entry],
+        _ => Vec::new(),
+    }
+}
+
+fn event_grid_type(entry: &Value) -> Option<&str> {
+    entry
+        .get("eventType")
+        .or_else(|| entry.get("type"))
+        .and_then(Value::as_str)
+}
+
+/// The code to echo back when Event Grid validates a new webhook
+/// subscription, if this is such a request.
+pub fn event_grid_validation_code(event_str: &str) -> Option<String> {
+    event_grid_entries(event_str)
+        .iter()
+        .filter(|entry| {
+            event_grid_type(entry) == Some("Microsoft.EventGrid.SubscriptionValidationEvent")
+        })
+        .filter_map(|entry| entry.get("data")?.get("validationCode")?.as_str())
+        .map(str::to_owned)
+        .next()
+}
+
+/// Parse Azure Container Registry ImagePushed events as delivered by Event
+/// Grid, in either the Event Grid or the CloudEvents schema.
+pub fn parse_acr_events(event_str: &str) -> Vec<Event> {
+    event_grid_entries(event_str)
+        .iter()
+        .filter(|entry| event_grid_type(entry) == Some("Microsoft.ContainerRegistry.ImagePushed"))
+        .filter_map(|entry| {
+            let data = entry.get("data")?;
+            let target = data.get("target")?;
+            let field = |value: &Value, name: &str| value.get(name)?.as_str().map(str::to_owned);
+            Some(Event {
+                account_id: String::new(),
+                region: String::new(),
+                repository_name: field(target, "repository")?,
+                image_digest: field(target, "digest")?,
+                image_tag: field(target, "tag")?,
+                pushed_at: entry
+                    .get("eventTime")
+                    .or_else(|| entry.get("time"))
+                    .and_then(Value::as_str)
+                    .and_then(|time| time.parse().ok()),
+                registry: Some(field(data.get("request")?, "host")?),
+            })
+        })
+        .collect()
+}
+
+/// Parse an Artifact Registry or Container Registry notification from the
+/// gcr Pub/Sub topic. Tag deletions and untagged pushes are ignored.
+pub fn parse_gcr_event(event_str: &str) -> Option<Event> {
+    let parsed: Value = serde_json::from_str(event_str).ok()?;
+    if parsed.get("action")?.as_str() != Some("INSERT") {
+        return None;
+    }
+    let digest_ref = parsed.get("digest")?.as_str()?;
+    let tag_ref = parsed.get("tag")?.as_str()?;
+    let image_digest = &digest_ref[digest_ref.find('@')? + 1..];
+    let slash_pos = tag_ref.find('/')?;
+    let colon_pos = tag_ref.rfind(':').filter(|pos| *pos > slash_pos)?;
+    Some(Event {
+        account_id: String::new(),
+        region: String::new(),
+        repository_name: tag_ref[slash_pos + 1..colon_pos].to_owned(),
+        image_digest: image_digest.to_owned(),
+        image_tag: tag_ref[colon_pos + 1..].to_owned(),
+        pushed_at: None,
+        registry: Some(tag_ref[..slash_pos].to_owned()),
+    })
+}
+
+/// Try each known event format in turn. Malformed ECR events are logged
+/// and treated as unrecognized.
+pub fn parse_event(event_str: &str, nexus_registries: &[(String, String)]) -> Option<Event> {
+    let ecr_event = match parse_ecr_event(event_str) {
+        Ok(event) => event,
+        Err(err) => {
+            warn!("Malformed ECR event: {}", err);
+            None
+        }
+    };
+    ecr_event
+        .or_else(|| parse_artifactory_event(event_str))
+        .or_else(|| parse_docker_hub_event(event_str))
+        .or_else(|| parse_github_package_event(event_str))
+        .or_else(|| parse_gcr_event(event_str))
+        .or_else(|| parse_nexus_event(event_str, nexus_registries))
+}
+
+/// Like parse_event, but also accepts formats that describe several pushes
+/// in one message, or one push of several tags.
+pub fn parse_events(event_str: &str, nexus_registries: &[(String, String)]) -> Vec<Event> {
+    match parse_event(event_str, nexus_registries) {
+        Some(event) => vec![event],
+        None => {
+            let mut events = parse_harbor_events(event_str);
+            events.extend(parse_gitlab_registry_events(event_str));
+            events.extend(parse_quay_events(event_str));
+            events.extend(parse_acr_events(event_str));
+            events
+        }
     }
 }
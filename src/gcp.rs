@@ -0,0 +1,261 @@
+use crate::source::EventSource;
+use crate::{
+    GcpCredentialsFormat, GcpCredentialsIo, GcpKey, GcpRequest, MissingCredentialOption,
+    MissingDeadLetterPolicy, Opt, Result,
+};
+use bollard::auth::DockerCredentials;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::blocking::Client;
+use rusoto_sqs::Message;
+use serde_json::{json, Value};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+const PUBSUB_API: &str = "https://pubsub.googleapis.com/v1";
+/// Pub/Sub caps ack deadlines at ten minutes.
+const MAX_ACK_DEADLINE: i64 = 600;
+
+pub fn is_gcp_registry(registry: &str) -> bool {
+    registry.ends_with("-docker.pkg.dev") || registry == "gcr.io" || registry.ends_with(".gcr.io")
+}
+
+/// Claims for exchanging a service account key for an access token.
+pub fn service_account_claims(key: &Value, now: i64) -> Option<Value> {
+    Some(json!({
+        "iss": key.get("client_email")?.as_str()?,
+        "scope": SCOPE,
+        "aud": key.get("token_uri")?.as_str()?,
+        "iat": now,
+        "exp": now + 3600,
+    }))
+}
+
+/// Access tokens from a service account key file, or from the metadata
+/// server when running on GCP. Tokens are reused until shortly before they
+/// expire.
+pub struct TokenProvider {
+    client: Client,
+    key_file: Option<std::path::PathBuf>,
+    cached: Option<(String, DateTime<Utc>)>,
+}
+
+impl TokenProvider {
+    pub fn new(opt: &Opt) -> TokenProvider {
+        TokenProvider {
+            client: Client::new(),
+            key_file: opt.gcp_credentials.clone(),
+            cached: None,
+        }
+    }
+
+    pub fn token(&mut self) -> Result<String> {
+        if let Some((token, expires_at)) = &self.cached {
+            if *expires_at > Utc::now() + Duration::minutes(5) {
+                return Ok(token.clone());
+            }
+        }
+        let response = match &self.key_file {
+            Some(key_file) => self.service_account_token(key_file)?,
+            None => self.metadata_token()?,
+        };
+        let token = response
+            .get("access_token")
+            .and_then(Value::as_str)
+            .context(MissingCredentialOption {
+                option: "access_token in GCP token response",
+            })?
+            .to_owned();
+        let expires_in = response
+            .get("expires_in")
+            .and_then(Value::as_i64)
+            .unwrap_or(0);
+        self.cached = Some((token.clone(), Utc::now() + Duration::seconds(expires_in)));
+        Ok(token)
+    }
+
+    fn service_account_token(&self, key_file: &Path) -> Result<Value> {
+        let content = fs::read_to_string(key_file).with_context(|| GcpCredentialsIo {
+            path: key_file.to_owned(),
+        })?;
+        let key: Value = serde_json::from_str(&content).with_context(|| GcpCredentialsFormat {
+            path: key_file.to_owned(),
+        })?;
+        let claims = service_account_claims(&key, Utc::now().timestamp()).context(
+            MissingCredentialOption {
+                option: "client_email and token_uri in GCP credentials",
+            },
+        )?;
+        let private_key =
+            key.get("private_key")
+                .and_then(Value::as_str)
+                .context(MissingCredentialOption {
+                    option: "private_key in GCP credentials",
+                })?;
+        let encoding_key =
+            EncodingKey::from_rsa_pem(private_key.as_bytes()).with_context(|| GcpKey)?;
+        let assertion = encode(&Header::new(Algorithm::RS256), &claims, &encoding_key)
+            .with_context(|| GcpKey)?;
+        let token_uri = claims["aud"].as_str().unwrap_or_default().to_owned();
+        self.client
+            .post(&token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", &assertion),
+            ])
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .with_context(|| GcpRequest { url: token_uri })
+    }
+
+    fn metadata_token(&self) -> Result<Value> {
+        self.client
+            .get(METADATA_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .with_context(|| GcpRequest {
+                url: METADATA_TOKEN_URL,
+            })
+    }
+}
+
+/// Pull credentials for Artifact Registry and Container Registry.
+pub fn registry_credentials(registry: &str, opt: &Opt) -> Result<Option<DockerCredentials>> {
+    let token = TokenProvider::new(opt).token()?;
+    Ok(Some(DockerCredentials {
+        username: Some("oauth2accesstoken".to_owned()),
+        password: Some(token),
+        serveraddress: Some(registry.to_owned()),
+        ..Default::default()
+    }))
+}
+
+/// Convert a Pub/Sub pull response into messages. The ack id serves as
+/// receipt handle.
+pub fn messages_from_pull(response: &Value) -> Vec<Message> {
+    response
+        .get("receivedMessages")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|received| {
+            let message = received.get("message")?;
+            let data = message
+                .get("data")
+                .and_then(Value::as_str)
+                .and_then(|data| base64::decode(data).ok())
+                .and_then(|data| String::from_utf8(data).ok());
+            let mut attributes = HashMap::new();
+            if let Some(attempt) = received.get("deliveryAttempt").and_then(Value::as_u64) {
+                attributes.insert("ApproximateReceiveCount".to_owned(), attempt.to_string());
+            }
+            Some(Message {
+                message_id: message
+                    .get("messageId")
+                    .and_then(Value::as_str)
+                    .map(str::to_owned),
+                receipt_handle: Some(received.get("ackId")?.as_str()?.to_owned()),
+                body: data,
+                attributes: Some(attributes),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Pub/Sub only reports delivery attempts for subscriptions with a
+/// dead-letter policy.
+pub fn has_dead_letter_policy(subscription: &Value) -> bool {
+    subscription
+        .get("deadLetterPolicy")
+        .and_then(|policy| policy.get("deadLetterTopic"))
+        .and_then(Value::as_str)
+        .map_or(false, |topic| !topic.is_empty())
+}
+
+/// Consumes a Pub/Sub subscription, e.g. one on the gcr topic that Artifact
+/// Registry publishes pushes to.
+pub struct PubSubSource {
+    client: Client,
+    subscription: String,
+    tokens: TokenProvider,
+}
+
+impl PubSubSource {
+    /// Retries are counted by delivery attempt, so subscriptions without a
+    /// dead-letter policy are refused.
+    pub fn connect(subscription: &str, opt: &Opt) -> Result<PubSubSource> {
+        let mut source = PubSubSource {
+            client: Client::new(),
+            subscription: subscription.to_owned(),
+            tokens: TokenProvider::new(opt),
+        };
+        let url = format!("{}/{}", PUBSUB_API, subscription);
+        let token = source.tokens.token()?;
+        let details: Value = source
+            .client
+            .get(&url)
+            .bearer_auth(token)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .with_context(|| GcpRequest { url: url.clone() })?;
+        ensure!(
+            has_dead_letter_policy(&details),
+            MissingDeadLetterPolicy { subscription }
+        );
+        Ok(source)
+    }
+
+    fn call(&mut self, method: &str, body: Value) -> Result<Value> {
+        let url = format!("{}/{}:{}", PUBSUB_API, &self.subscription, method);
+        let token = self.tokens.token()?;
+        self.client
+            .post(&url)
+            .bearer_auth(token)
+            .json(&body)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .with_context(|| GcpRequest { url: url.clone() })
+    }
+}
+
+impl EventSource for PubSubSource {
+    fn name(&self) -> String {
+        format!("Pub/Sub subscription {}", &self.subscription)
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        let response = self.call("pull", json!({ "maxMessages": 10 }))?;
+        Ok(messages_from_pull(&response))
+    }
+
+    fn ack(&mut self, message: &Message) -> Result<()> {
+        self.call("acknowledge", json!({ "ackIds": [message.receipt_handle] }))?;
+        Ok(())
+    }
+
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()> {
+        self.call(
+            "modifyAckDeadline",
+            json!({
+                "ackIds": [message.receipt_handle],
+                "ackDeadlineSeconds": delay_seconds.max(0).min(MAX_ACK_DEADLINE),
+            }),
+        )?;
+        Ok(())
+    }
+
+    fn receive_count(&self, message: &Message) -> u32 {
+        crate::sqs::receive_count(message)
+    }
+}
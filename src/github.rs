@@ -0,0 +1,140 @@
+use crate::{
+    ecr_tokens, GithubAppKey, GithubAppKeyIo, GithubRequest, MissingCredentialOption, Opt, Result,
+};
+use bollard::auth::DockerCredentials;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use snafu::{OptionExt, ResultExt};
+use std::fs;
+
+pub const GHCR: &str = "ghcr.io";
+
+/// The last installation token, with when it expires. Installation tokens
+/// are valid for an hour.
+#[derive(Default)]
+pub struct InstallationToken {
+    token: Option<(String, DateTime<Utc>)>,
+}
+
+impl InstallationToken {
+    /// Forget the token, as when ghcr.io has refused it.
+    pub fn clear(&mut self) {
+        self.token = None;
+    }
+}
+
+/// Claims for authenticating as a GitHub App. Issued slightly in the past
+/// to allow for clock drift, and valid for the maximum ten minutes.
+pub fn app_claims(app_id: u64, now: i64) -> Value {
+    json!({
+        "iat": now - 60,
+        "exp": now + 540,
+        "iss": app_id.to_string(),
+    })
+}
+
+fn installation_token(
+    client: &Client,
+    app_id: u64,
+    installation_id: u64,
+    opt: &Opt,
+) -> Result<(String, DateTime<Utc>)> {
+    let key_path = opt
+        .github_app_key
+        .as_ref()
+        .context(MissingCredentialOption {
+            option: "--github-app-key",
+        })?;
+    let pem = fs::read(key_path).with_context(|| GithubAppKeyIo {
+        path: key_path.clone(),
+    })?;
+    let key = EncodingKey::from_rsa_pem(&pem).with_context(|| GithubAppKey)?;
+    let jwt = encode(
+        &Header::new(Algorithm::RS256),
+        &app_claims(app_id, chrono::Utc::now().timestamp()),
+        &key,
+    )
+    .with_context(|| GithubAppKey)?;
+    let url = format!(
+        "https://api.github.com/app/installations/{}/access_tokens",
+        installation_id
+    );
+    let response: Value = client
+        .post(&url)
+        .bearer_auth(jwt)
+        .header("Accept", "application/vnd.github.v3+json")
+        .header("User-Agent", "swarm-ecr-deployer")
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json())
+        .with_context(|| GithubRequest { url: url.clone() })?;
+    let token = response
+        .get("token")
+        .and_then(Value::as_str)
+        .context(MissingCredentialOption {
+            option: "token in installation access token response",
+        })?;
+    let expires_at = response
+        .get("expires_at")
+        .and_then(Value::as_str)
+        .and_then(|expires_at| DateTime::parse_from_rfc3339(expires_at).ok())
+        .context(MissingCredentialOption {
+            option: "expires_at in installation access token response",
+        })?;
+    Ok((token.to_owned(), expires_at.with_timezone(&Utc)))
+}
+
+/// The installation token, reusing the previous one until shortly before it
+/// expires.
+fn cached_installation_token(
+    cache: &mut InstallationToken,
+    app_id: u64,
+    installation_id: u64,
+    opt: &Opt,
+) -> Result<String> {
+    if let Some((token, expires_at)) = &cache.token {
+        if ecr_tokens::usable(*expires_at, Utc::now()) {
+            return Ok(token.clone());
+        }
+    }
+    let (token, expires_at) = installation_token(&Client::new(), app_id, installation_id, opt)?;
+    cache.token = Some((token.clone(), expires_at));
+    Ok(token)
+}
+
+/// Credentials for pulling from ghcr.io, from a personal access token or by
+/// exchanging a GitHub App key for an installation token.
+pub fn ghcr_credentials(
+    cache: &mut InstallationToken,
+    opt: &Opt,
+) -> Result<Option<DockerCredentials>> {
+    let (username, password) = match (&opt.ghcr_token, opt.github_app_id) {
+        (Some(token), _) => {
+            let username = opt
+                .ghcr_username
+                .as_ref()
+                .context(MissingCredentialOption {
+                    option: "--ghcr-username",
+                })?;
+            (username.clone(), token.clone())
+        }
+        (None, Some(app_id)) => {
+            let installation_id = opt
+                .github_installation_id
+                .context(MissingCredentialOption {
+                    option: "--github-installation-id",
+                })?;
+            let token = cached_installation_token(cache, app_id, installation_id, opt)?;
+            ("x-access-token".to_owned(), token)
+        }
+        (None, None) => return Ok(None),
+    };
+    Ok(Some(DockerCredentials {
+        username: Some(username),
+        password: Some(password),
+        serveraddress: Some(GHCR.to_owned()),
+        ..Default::default()
+    }))
+}
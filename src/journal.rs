@@ -0,0 +1,168 @@
+use crate::{JournalFormat, JournalIo, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Entry {
+    pub service_id: String,
+    pub image: String,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Record of deployments that have been started but whose message has not
+/// yet been acked. Entries are keyed on SQS message id and service id, as
+/// one message may update several services, so that a redelivered message
+/// can be matched against each interrupted deployment.
+pub struct Journal {
+    path: Option<PathBuf>,
+    entries: HashMap<String, HashMap<String, Entry>>,
+}
+
+fn entry_from_value(value: &Value) -> Option<Entry> {
+    let service_id = value.get("service_id")?.as_str()?.to_owned();
+    let image = value.get("image")?.as_str()?.to_owned();
+    let started_at = value
+        .get("started_at")?
+        .as_str()?
+        .parse::<DateTime<Utc>>()
+        .ok()?;
+    Some(Entry {
+        service_id,
+        image,
+        started_at,
+    })
+}
+
+/// The entries of a message, which journals written before entries were
+/// kept per service hold as a single object.
+fn entries_from_value(value: &Value) -> HashMap<String, Entry> {
+    let entries: Vec<Entry> = match value.as_array() {
+        Some(values) => values.iter().filter_map(entry_from_value).collect(),
+        None => entry_from_value(value).into_iter().collect(),
+    };
+    entries
+        .into_iter()
+        .map(|entry| (entry.service_id.clone(), entry))
+        .collect()
+}
+
+impl Journal {
+    pub fn open(path: Option<&Path>) -> Result<Journal> {
+        let mut journal = Journal {
+            path: path.map(|p| p.to_owned()),
+            entries: HashMap::new(),
+        };
+        if let Some(path) = path.filter(|p| p.exists()) {
+            let content = fs::read_to_string(path).with_context(|| JournalIo {
+                path: path.to_owned(),
+            })?;
+            let parsed: serde_json::Map<String, Value> = serde_json::from_str(&content)
+                .with_context(|| JournalFormat {
+                    path: path.to_owned(),
+                })?;
+            journal.entries = parsed
+                .iter()
+                .map(|(id, value)| (id.clone(), entries_from_value(value)))
+                .filter(|(_, entries)| !entries.is_empty())
+                .collect();
+        }
+        Ok(journal)
+    }
+
+    pub fn get(&self, message_id: &str, service_id: &str) -> Option<&Entry> {
+        self.entries
+            .get(message_id)
+            .and_then(|entries| entries.get(service_id))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &Entry)> {
+        self.entries.iter().flat_map(|(message_id, entries)| {
+            entries.values().map(move |entry| (message_id, entry))
+        })
+    }
+
+    pub fn begin(&mut self, message_id: &str, service_id: &str, image: &str) -> Result<()> {
+        self.entries
+            .entry(message_id.to_owned())
+            .or_default()
+            .insert(
+                service_id.to_owned(),
+                Entry {
+                    service_id: service_id.to_owned(),
+                    image: image.to_owned(),
+                    started_at: Utc::now(),
+                },
+            );
+        self.persist()
+    }
+
+    pub fn clear(&mut self, message_id: &str) -> Result<()> {
+        if self.entries.remove(message_id).is_some() {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    /// Drop entries whose message can no longer be redelivered because it
+    /// has outlived the queue's retention period.
+    pub fn sweep(&mut self, retention: Duration) -> Result<Vec<(String, Entry)>> {
+        let cutoff = Utc::now() - retention;
+        let expired: Vec<(String, String)> = self
+            .entries()
+            .filter(|(_, entry)| entry.started_at < cutoff)
+            .map(|(id, entry)| (id.clone(), entry.service_id.clone()))
+            .collect();
+        if expired.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut removed = Vec::new();
+        for (id, service_id) in expired {
+            if let Some(entries) = self.entries.get_mut(&id) {
+                if let Some(entry) = entries.remove(&service_id) {
+                    removed.push((id.clone(), entry));
+                }
+                if entries.is_empty() {
+                    self.entries.remove(&id);
+                }
+            }
+        }
+        self.persist()?;
+        Ok(removed)
+    }
+
+    fn persist(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let content: serde_json::Map<String, Value> = self
+            .entries
+            .iter()
+            .map(|(id, entries)| {
+                let entries = entries
+                    .values()
+                    .map(|entry| {
+                        json!({
+                            "service_id": entry.service_id,
+                            "image": entry.image,
+                            "started_at": entry.started_at.to_rfc3339(),
+                        })
+                    })
+                    .collect();
+                (id.clone(), Value::Array(entries))
+            })
+            .collect();
+        // Write-then-rename so that a crash never leaves a truncated journal
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, Value::Object(content).to_string()).with_context(|| JournalIo {
+            path: tmp_path.clone(),
+        })?;
+        fs::rename(&tmp_path, path).with_context(|| JournalIo {
+            path: path.to_owned(),
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use crate::source::{Backlog, EventSource};
+use crate::{KafkaConsume, KafkaSetup, Result};
+use ::kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
+use log::warn;
+use rusoto_sqs::Message;
+use snafu::ResultExt;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// Offsets handed out but not yet acked, per partition. Kafka commits a
+/// position rather than individual messages, so the committed offset may
+/// not pass a message that is still waiting to be retried.
+#[derive(Default)]
+pub struct Outstanding {
+    pending: HashMap<i32, BTreeSet<i64>>,
+    highest: HashMap<i32, i64>,
+}
+
+impl Outstanding {
+    pub fn track(&mut self, partition: i32, offset: i64) {
+        self.pending.entry(partition).or_default().insert(offset);
+        let highest = self.highest.entry(partition).or_insert(offset);
+        *highest = (*highest).max(offset);
+    }
+
+    /// Mark an offset as done and return the last offset in the partition
+    /// that can now safely be committed, if any.
+    pub fn settle(&mut self, partition: i32, offset: i64) -> Option<i64> {
+        let pending = self.pending.get_mut(&partition)?;
+        pending.remove(&offset);
+        match pending.iter().next() {
+            Some(lowest) if *lowest > 0 => Some(lowest - 1),
+            Some(_) => None,
+            None => self.highest.get(&partition).cloned(),
+        }
+    }
+}
+
+fn receipt_handle(partition: i32, offset: i64) -> String {
+    format!("{}:{}", partition, offset)
+}
+
+pub fn parse_receipt_handle(handle: &str) -> Option<(i32, i64)> {
+    let colon_pos = handle.find(':')?;
+    Some((
+        handle[..colon_pos].parse().ok()?,
+        handle[colon_pos + 1..].parse().ok()?,
+    ))
+}
+
+/// Consumes every partition of a Kafka topic, committing offsets under a
+/// group name as messages are acked. The kafka crate does not balance
+/// partitions between members of a group, so deployers sharing a topic
+/// would each read all of it and must use groups of their own.
+pub struct KafkaSource {
+    consumer: Consumer,
+    topic: String,
+    outstanding: Outstanding,
+    backlog: Backlog,
+}
+
+impl KafkaSource {
+    pub fn connect(brokers: &[String], topic: &str, group: &str) -> Result<KafkaSource> {
+        let consumer = Consumer::from_hosts(brokers.to_vec())
+            .with_topic(topic.to_owned())
+            .with_group(group.to_owned())
+            .with_fallback_offset(FetchOffset::Earliest)
+            .with_offset_storage(GroupOffsetStorage::Kafka)
+            .with_fetch_max_wait_time(Duration::from_secs(10))
+            .create()
+            .with_context(|| KafkaSetup { topic })?;
+        Ok(KafkaSource {
+            consumer,
+            topic: topic.to_owned(),
+            outstanding: Outstanding::default(),
+            backlog: Backlog::new(),
+        })
+    }
+}
+
+impl EventSource for KafkaSource {
+    fn name(&self) -> String {
+        format!("Kafka topic {}", &self.topic)
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        let mut messages = self.backlog.take_due();
+        if !messages.is_empty() {
+            return Ok(messages);
+        }
+        let topic = self.topic.clone();
+        let message_sets = self
+            .consumer
+            .poll()
+            .with_context(|| KafkaConsume { topic: &topic })?;
+        for message_set in message_sets.iter() {
+            let partition = message_set.partition();
+            for message in message_set.messages() {
+                self.outstanding.track(partition, message.offset);
+                match String::from_utf8(message.value.to_vec()) {
+                    Ok(body) => messages.push(Message {
+                        message_id: Some(format!("{}-{}-{}", &topic, partition, message.offset)),
+                        receipt_handle: Some(receipt_handle(partition, message.offset)),
+                        body: Some(body),
+                        ..Default::default()
+                    }),
+                    Err(err) => {
+                        warn!(
+                            "Ignoring non-UTF-8 message at {}/{}/{}: {}",
+                            &topic, partition, message.offset, err
+                        );
+                        self.outstanding.settle(partition, message.offset);
+                    }
+                }
+            }
+        }
+        Ok(messages)
+    }
+
+    fn ack(&mut self, message: &Message) -> Result<()> {
+        let (partition, offset) = match message
+            .receipt_handle
+            .as_ref()
+            .and_then(|handle| parse_receipt_handle(handle))
+        {
+            Some(position) => position,
+            None => return Ok(()),
+        };
+        if let Some(commit) = self.outstanding.settle(partition, offset) {
+            let topic = self.topic.clone();
+            self.consumer
+                .consume_message(&topic, partition, commit)
+                .and_then(|_| self.consumer.commit_consumed())
+                .with_context(|| KafkaConsume { topic: &topic })?;
+        }
+        Ok(())
+    }
+
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()> {
+        self.backlog.delay(message, delay_seconds);
+        Ok(())
+    }
+
+    fn receive_count(&self, message: &Message) -> u32 {
+        crate::sqs::receive_count(message)
+    }
+}
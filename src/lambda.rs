@@ -0,0 +1,81 @@
+#[cfg(not(feature = "lambda"))]
+use crate::FeatureDisabled;
+use crate::{build_service_index, process_one, Deployer, Opt, Result};
+use rusoto_sqs::Message;
+use serde_json::Value;
+
+/// Convert the records of an SQS-triggered Lambda invocation into the
+/// messages a poll would have returned.
+pub fn messages_from_event(event: &Value) -> Vec<Message> {
+    let records = match event.get("Records").and_then(Value::as_array) {
+        Some(records) => records,
+        None => return Vec::new(),
+    };
+    records
+        .iter()
+        .filter(|record| record.get("eventSource").and_then(Value::as_str) == Some("aws:sqs"))
+        .map(|record| {
+            let field = |name: &str| record.get(name).and_then(Value::as_str).map(str::to_owned);
+            let attributes =
+                record
+                    .get("attributes")
+                    .and_then(Value::as_object)
+                    .map(|attributes| {
+                        attributes
+                            .iter()
+                            .filter_map(|(key, value)| {
+                                value.as_str().map(|value| (key.clone(), value.to_owned()))
+                            })
+                            .collect()
+                    });
+            Message {
+                message_id: field("messageId"),
+                receipt_handle: field("receiptHandle"),
+                body: field("body"),
+                md5_of_body: field("md5OfBody"),
+                attributes,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Process one invocation. Any failure fails the whole batch, leaving
+/// retries and dead-lettering to the event source mapping.
+fn handle(deployer: &mut Deployer, event: &Value, opt: &Opt) -> Result<()> {
+    let messages = messages_from_event(event);
+    if messages.is_empty() {
+        return Ok(());
+    }
+    let services_by_image = build_service_index(deployer.services()?, opt);
+    for message in messages.iter() {
+        process_one(message, &services_by_image, deployer, opt)?;
+        if let Some(message_id) = &message.message_id {
+            deployer.journal.clear(message_id)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "lambda")]
+pub fn run(mut deployer: Deployer, opt: Opt) -> Result<()> {
+    use lambda_runtime::error::HandlerError;
+    lambda_runtime::start(
+        move |event: Value, _context: lambda_runtime::Context| {
+            handle(&mut deployer, &event, &opt)
+                .map(|_| Value::Null)
+                .map_err(|err| HandlerError::from(err.to_string().as_str()))
+        },
+        None,
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "lambda"))]
+pub fn run(_deployer: Deployer, _opt: Opt) -> Result<()> {
+    FeatureDisabled {
+        feature: "lambda",
+        option: "lambda",
+    }
+    .fail()
+}
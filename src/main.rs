@@ -2,50 +2,905 @@ use base64;
 use bollard::errors::Error as BollardError;
 use bollard::service::{ListServicesOptions, Service, ServiceSpec, UpdateServiceOptions};
 use bollard::{auth::DockerCredentials, Docker};
-use log::{debug, info, warn};
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use rusoto_core::request::TlsError;
 use rusoto_core::Region;
 use rusoto_core::RusotoError;
-use rusoto_ecr::{Ecr, EcrClient, GetAuthorizationTokenError, GetAuthorizationTokenRequest};
-use rusoto_sqs::{DeleteMessageError, GetQueueUrlError, Message, ReceiveMessageError, SqsClient};
-use snafu::{ensure, ResultExt, Snafu};
+use rusoto_credential::CredentialsError;
+use rusoto_ecr::{
+    AuthorizationData, DescribeImagesError, DescribeRepositoriesError, Ecr, EcrClient,
+    GetAuthorizationTokenError, GetAuthorizationTokenRequest,
+};
+use rusoto_logs::{CreateLogStreamError, PutLogEventsError};
+use rusoto_s3::GetObjectError;
+use rusoto_secretsmanager::GetSecretValueError;
+use rusoto_sns::SetSubscriptionAttributesError;
+use rusoto_sqs::{
+    ChangeMessageVisibilityError, DeleteMessageBatchError, DeleteMessageError,
+    GetQueueAttributesError, GetQueueUrlError, Message, ReceiveMessageError, SendMessageError,
+};
+use rusoto_stepfunctions::{SendTaskFailureError, SendTaskSuccessError, StepFunctionsClient};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use source::EventSource;
 use std::collections::HashMap;
-use std::str::FromStr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use stderrlog;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 use tokio::runtime::Runtime;
 
+mod auth;
+mod aws;
+mod azure;
+mod build_info;
+mod codebuild;
+mod containers;
+mod convergence;
+mod dedupe;
+mod deletions;
+mod docker_config;
+mod drift;
+mod ecr_tokens;
 mod events;
+mod gcp;
+mod github;
+mod journal;
+mod kafka;
+mod lambda;
+mod managers;
+mod mapping;
+mod markers;
+mod metrics;
+mod mqtt;
+mod nats;
+mod platform;
+mod plugins;
+mod policy;
+mod polling;
+mod quarantine;
+mod redis;
+mod reference;
+mod registry;
+mod replay;
+mod s3;
+mod scan;
+mod schedule;
+mod secrets;
+mod sns;
+mod source;
 mod sqs;
+mod sso;
+mod stepfunctions;
 #[cfg(test)]
 mod tests;
+mod vault;
+mod verify;
+mod watch;
+mod webhook;
 
 const STACK_IMAGE_LABEL: &str = "com.docker.stack.image";
+const UPDATE_ORDER_LABEL: &str = "seedy.update-order";
+/// Push time of the image the deployer last updated the service to
+const PUSHED_AT_LABEL: &str = "seedy.pushed-at";
 
 #[derive(StructOpt, Debug)]
-#[structopt()]
+#[structopt(
+    long_version = build_info::LONG_VERSION,
+    setting = AppSettings::SubcommandsNegateReqs
+)]
 pub struct Opt {
     /// Update only labelled services (default is to consider all services)
     #[structopt(long = "filter-label", env = "DEPLOYER_FILTER_LABEL", parse(try_from_str = split_label))]
     filter_label: Option<(String, String)>,
-    /// SQS queue name to receive ECR events
-    #[structopt(short = "q", long = "queue", env = "DEPLOYER_QUEUE")]
-    queue_name: String,
+    /// SQS queue name to receive ECR events (repeatable, polled in turn)
+    #[structopt(
+        short = "q",
+        long = "queue",
+        env = "DEPLOYER_QUEUE",
+        use_delimiter = true,
+        required_unless_one = &["listen", "pubsub-subscription", "nats-url", "kafka-brokers", "redis-url", "mqtt-broker", "from-file", "poll-registry-seconds"]
+    )]
+    queue_names: Vec<String>,
+    /// Region of the queue, if not the one the deployer runs in
+    #[structopt(long = "sqs-region", env = "DEPLOYER_SQS_REGION")]
+    sqs_region: Option<Region>,
+    /// Region to call ECR in for registry credentials and digests, instead of the image's region
+    #[structopt(long = "ecr-region-override", env = "DEPLOYER_ECR_REGION_OVERRIDE")]
+    ecr_region_override: Option<Region>,
+    /// Only handle SQS messages with this message attribute, e.g. environment=production (repeatable); others are left on the queue for other deployers
+    #[structopt(
+        long = "attribute-filter",
+        env = "DEPLOYER_ATTRIBUTE_FILTERS",
+        parse(try_from_str = parse_attribute_filter),
+        use_delimiter = true
+    )]
+    attribute_filters: Vec<(String, String)>,
+    /// SNS subscription of the queue, whose filter policy is set from --attribute-filter so that SNS only delivers matching messages
+    #[structopt(
+        long = "sns-subscription-arn",
+        env = "DEPLOYER_SNS_SUBSCRIPTION_ARN",
+        requires = "attribute-filters"
+    )]
+    sns_subscription_arn: Option<String>,
+    /// Role to assume for reading the queue, e.g. in the account where ECR events are collected
+    #[structopt(long = "sqs-role-arn", env = "DEPLOYER_SQS_ROLE_ARN")]
+    sqs_role_arn: Option<String>,
+    /// External id the role given by --sqs-role-arn requires, if any
+    #[structopt(
+        long = "sqs-external-id",
+        env = "DEPLOYER_SQS_EXTERNAL_ID",
+        hide_env_values = true
+    )]
+    sqs_external_id: Option<String>,
+    /// Role to assume for ECR calls, e.g. in a central build account holding the registries
+    #[structopt(long = "ecr-role-arn", env = "DEPLOYER_ECR_ROLE_ARN")]
+    ecr_role_arn: Option<String>,
+    /// Role to assume for ECR calls to one account's registries, e.g. 123456789012=arn:aws:iam::123456789012:role/ze-role (repeatable, overrides --ecr-role-arn)
+    #[structopt(
+        long = "ecr-account-role",
+        env = "DEPLOYER_ECR_ACCOUNT_ROLES",
+        parse(try_from_str = parse_ecr_account_role),
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    ecr_account_roles: Vec<(String, String)>,
+    /// External id the ECR roles require, if any
+    #[structopt(
+        long = "ecr-external-id",
+        env = "DEPLOYER_ECR_EXTERNAL_ID",
+        hide_env_values = true
+    )]
+    ecr_external_id: Option<String>,
+    /// SQS queue, e.g. for hotfixes, whose messages are always handled before those on --queue
+    #[structopt(
+        long = "priority-queue",
+        env = "DEPLOYER_PRIORITY_QUEUE",
+        requires = "queue-names"
+    )]
+    priority_queue: Option<String>,
+    /// ARN of a queue in another region to fail over to when SQS in the primary region cannot be reached or answers with server errors (repeatable, tried in order)
+    #[structopt(
+        long = "failover-queue",
+        env = "DEPLOYER_FAILOVER_QUEUES",
+        use_delimiter = true,
+        requires = "queue-names"
+    )]
+    failover_queues: Vec<sqs::QueueArn>,
+    /// Receive registry webhooks over HTTP on this address instead of polling SQS, e.g. 0.0.0.0:8080 (requires --webhook-token unless a loopback address)
+    #[structopt(long = "listen", env = "DEPLOYER_LISTEN")]
+    listen: Option<String>,
+    /// Consume a Pub/Sub subscription instead of polling SQS, e.g. projects/ze-project/subscriptions/gcr (needs a dead-letter policy)
+    #[structopt(long = "pubsub-subscription", env = "DEPLOYER_PUBSUB_SUBSCRIPTION")]
+    pubsub_subscription: Option<String>,
+    /// Service account key file for Pub/Sub and registry access (default is the GCP metadata server)
+    #[structopt(
+        long = "gcp-credentials",
+        env = "GOOGLE_APPLICATION_CREDENTIALS",
+        parse(from_os_str)
+    )]
+    gcp_credentials: Option<PathBuf>,
+    /// Receive events published on NATS instead of polling SQS, e.g. nats://nats-1:4222
+    #[structopt(
+        long = "nats-url",
+        env = "DEPLOYER_NATS_URL",
+        requires = "nats-subject"
+    )]
+    nats_url: Option<String>,
+    /// NATS subject to subscribe to
+    #[structopt(long = "nats-subject", env = "DEPLOYER_NATS_SUBJECT")]
+    nats_subject: Option<String>,
+    /// NATS queue group, so that only one of several deployers handles each event
+    #[structopt(long = "nats-queue-group", env = "DEPLOYER_NATS_QUEUE_GROUP")]
+    nats_queue_group: Option<String>,
+    /// Consume a Kafka topic instead of polling SQS, from these brokers, e.g. kafka-1:9092
+    #[structopt(
+        long = "kafka-brokers",
+        env = "DEPLOYER_KAFKA_BROKERS",
+        use_delimiter = true,
+        requires = "kafka-topic"
+    )]
+    kafka_brokers: Vec<String>,
+    /// Kafka topic to consume
+    #[structopt(long = "kafka-topic", env = "DEPLOYER_KAFKA_TOPIC")]
+    kafka_topic: Option<String>,
+    /// Group name Kafka offsets are committed under as messages are acked; every deployer reads all partitions, so each needs its own
+    #[structopt(
+        long = "kafka-group",
+        env = "DEPLOYER_KAFKA_GROUP",
+        default_value = "swarm-deployer"
+    )]
+    kafka_group: String,
+    /// Read events from a Redis stream instead of polling SQS, e.g. redis://redis:6379
+    #[structopt(
+        long = "redis-url",
+        env = "DEPLOYER_REDIS_URL",
+        hide_env_values = true,
+        requires = "redis-stream"
+    )]
+    redis_url: Option<String>,
+    /// Redis stream to read; entries carry the event in their body field
+    #[structopt(long = "redis-stream", env = "DEPLOYER_REDIS_STREAM")]
+    redis_stream: Option<String>,
+    /// Redis consumer group, created on the stream if missing
+    #[structopt(
+        long = "redis-group",
+        env = "DEPLOYER_REDIS_GROUP",
+        default_value = "swarm-deployer"
+    )]
+    redis_group: String,
+    /// Name of this deployer within the consumer group [default: $HOSTNAME]
+    #[structopt(long = "redis-consumer", env = "DEPLOYER_REDIS_CONSUMER")]
+    redis_consumer: Option<String>,
+    /// Subscribe to an MQTT broker instead of polling SQS, as host[:port]
+    #[structopt(
+        long = "mqtt-broker",
+        env = "DEPLOYER_MQTT_BROKER",
+        requires = "mqtt-topic"
+    )]
+    mqtt_broker: Option<String>,
+    /// MQTT topic to subscribe to with QoS 1
+    #[structopt(long = "mqtt-topic", env = "DEPLOYER_MQTT_TOPIC")]
+    mqtt_topic: Option<String>,
+    /// MQTT client id, which identifies the persistent session [default: $HOSTNAME]
+    #[structopt(long = "mqtt-client-id", env = "DEPLOYER_MQTT_CLIENT_ID")]
+    mqtt_client_id: Option<String>,
+    /// Replay events from a JSON-lines file, or stdin if -, instead of polling SQS, then exit
+    #[structopt(long = "from-file")]
+    from_file: Option<String>,
+    /// Token webhook senders must present, as X-Gitlab-Token or Authorization: Bearer,
+    /// or the secret GitHub webhooks are signed with
+    #[structopt(
+        long = "webhook-token",
+        env = "DEPLOYER_WEBHOOK_TOKEN",
+        hide_env_values = true
+    )]
+    webhook_token: Option<String>,
+    /// Only accept webhooks from peers in this network, e.g. 192.0.2.0/24 (repeatable; default any)
+    #[structopt(
+        long = "webhook-allow-cidr",
+        env = "DEPLOYER_WEBHOOK_ALLOW_CIDRS",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    webhook_allow_cidrs: Vec<webhook::Cidr>,
+    /// Swarm manager endpoints to try in order, e.g. tcp://manager-1:2375 (default is local Docker)
+    #[structopt(long = "manager", env = "DEPLOYER_MANAGERS", use_delimiter = true)]
+    managers: Vec<String>,
+    /// File in which to record in-flight deployments so they can be verified after a crash
+    #[structopt(long = "journal", env = "DEPLOYER_JOURNAL", parse(from_os_str))]
+    journal: Option<PathBuf>,
+    /// Hours to keep journal entries whose message was never redelivered (SQS retention is at most 14 days)
+    #[structopt(
+        long = "journal-retention-hours",
+        default_value = "336",
+        env = "DEPLOYER_JOURNAL_RETENTION_HOURS"
+    )]
+    journal_retention_hours: i64,
+    /// How long to wait for an update to complete before rolling back, e.g. 10m (default is not to wait)
+    #[structopt(long = "deadline", env = "DEPLOYER_DEADLINE", parse(try_from_str = convergence::parse_duration))]
+    deadline: Option<Duration>,
+    /// Seconds a completed update must stay healthy before it is considered successful
+    #[structopt(
+        long = "min-healthy-seconds",
+        default_value = "0",
+        env = "DEPLOYER_MIN_HEALTHY_SECONDS"
+    )]
+    min_healthy_seconds: u64,
+    /// Times to retry a failed event before giving up on it; SQS messages are left to the queue's redrive policy instead, where it has one
+    #[structopt(
+        long = "max-retries",
+        default_value = "3",
+        env = "DEPLOYER_MAX_RETRIES"
+    )]
+    max_retries: u32,
+    /// Seconds before the first retry of a failed event, doubling for each attempt
+    #[structopt(
+        long = "retry-delay-seconds",
+        default_value = "60",
+        env = "DEPLOYER_RETRY_DELAY_SECONDS"
+    )]
+    retry_delay_seconds: i64,
+    /// Messages to receive from SQS per poll, 1-10
+    #[structopt(
+        long = "max-messages",
+        default_value = "10",
+        env = "DEPLOYER_MAX_MESSAGES",
+        parse(try_from_str = parse_max_messages)
+    )]
+    max_messages: i64,
+    /// Seconds each SQS poll waits for messages to arrive, 0-20, split between queues
+    #[structopt(
+        long = "wait-seconds",
+        default_value = "20",
+        env = "DEPLOYER_WAIT_SECONDS",
+        parse(try_from_str = parse_wait_seconds)
+    )]
+    wait_seconds: i64,
+    /// Consecutive empty polls after which polling starts backing off
+    #[structopt(long = "idle-polls", default_value = "3", env = "DEPLOYER_IDLE_POLLS")]
+    idle_polls: u32,
+    /// Longest pause between polls when the queue is idle (default is not to back off)
+    #[structopt(
+        long = "max-idle-delay-seconds",
+        default_value = "0",
+        env = "DEPLOYER_MAX_IDLE_DELAY_SECONDS"
+    )]
+    max_idle_delay_seconds: u64,
+    /// Hours during which not to poll at all, in UTC, e.g. 22-06
+    #[structopt(long = "quiet-hours", env = "DEPLOYER_QUIET_HOURS")]
+    quiet_hours: Option<polling::QuietHours>,
+    /// WebAssembly plugin to parse unrecognized events and veto updates (repeatable, requires the wasm feature)
+    #[structopt(
+        long = "wasm-plugin",
+        env = "DEPLOYER_WASM_PLUGINS",
+        use_delimiter = true,
+        parse(from_os_str)
+    )]
+    wasm_plugins: Vec<PathBuf>,
+    /// Directory to load every .wasm file in as a plugin, in file name order (requires the wasm feature)
+    #[structopt(
+        long = "wasm-plugin-dir",
+        env = "DEPLOYER_WASM_PLUGIN_DIR",
+        parse(from_os_str)
+    )]
+    wasm_plugin_dir: Option<PathBuf>,
+    /// Rhai script that can veto updates, rewrite events and pick the image to deploy, reloaded on change (requires the scripting feature)
+    #[structopt(long = "script", env = "DEPLOYER_SCRIPT", parse(from_os_str))]
+    script: Option<PathBuf>,
+    /// Shell command that reads unrecognized message bodies on stdin and prints an event as JSON, or nothing; exiting non-zero also means it does not recognize the body
+    #[structopt(long = "event-parser-cmd", env = "DEPLOYER_EVENT_PARSER_CMD")]
+    event_parser_cmd: Option<String>,
+    /// Seconds to let --event-parser-cmd run before killing it and retrying the message later
+    #[structopt(
+        long = "event-parser-timeout-seconds",
+        default_value = "30",
+        env = "DEPLOYER_EVENT_PARSER_TIMEOUT_SECONDS"
+    )]
+    event_parser_timeout_seconds: u64,
+    /// Map a Nexus repository to the host:port of its Docker connector, e.g. docker-hosted=nexus:8082
+    #[structopt(
+        long = "nexus-registry",
+        env = "DEPLOYER_NEXUS_REGISTRIES",
+        parse(try_from_str = parse_nexus_registry),
+        use_delimiter = true
+    )]
+    nexus_registries: Vec<(String, String)>,
+    /// Also deploy ECR pushes to services pulling the copy replicated to another region, e.g. us-east-1=eu-west-1 (repeatable); the message is retried until the copy has arrived
+    #[structopt(
+        long = "ecr-replication",
+        env = "DEPLOYER_ECR_REPLICATION",
+        parse(try_from_str = parse_ecr_replication),
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    ecr_replication: Vec<(String, String)>,
+    /// Also deploy pushes to an upstream registry to services pulling through an ECR pull-through cache, e.g. 123456789012.dkr.ecr.eu-west-1.amazonaws.com/docker-hub=docker.io (repeatable)
+    #[structopt(
+        long = "pull-through-cache",
+        env = "DEPLOYER_PULL_THROUGH_CACHE",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    pull_through_caches: Vec<events::PullThroughCache>,
+    /// Only act on ECR events from this AWS account (repeatable; default any)
+    #[structopt(
+        long = "allow-account",
+        env = "DEPLOYER_ALLOW_ACCOUNT",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    allow_accounts: Vec<String>,
+    /// Only act on ECR events from this AWS region (repeatable; default any)
+    #[structopt(
+        long = "allow-region",
+        env = "DEPLOYER_ALLOW_REGION",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    allow_regions: Vec<String>,
+    /// What to do with services running an image deleted from ECR: warn, scale-to-zero or pin (stop updating it)
+    #[structopt(long = "on-delete", default_value = "warn", env = "DEPLOYER_ON_DELETE")]
+    on_delete: deletions::DeleteAction,
+    /// Stop updating a service after this many updates in a row fail to converge within their deadline; remove its seedy.quarantined label to resume
+    #[structopt(long = "quarantine-after", env = "DEPLOYER_QUARANTINE_AFTER")]
+    quarantine_after: Option<u32>,
+    /// What to do with messages that carry no recognized event: drop, reject (forward to --rejects-queue) or leave on the queue
+    #[structopt(
+        long = "unrecognized",
+        default_value = "drop",
+        env = "DEPLOYER_UNRECOGNIZED"
+    )]
+    unrecognized: source::Unrecognized,
+    /// SQS queue to forward unrecognized messages to
+    #[structopt(
+        long = "rejects-queue",
+        env = "DEPLOYER_REJECTS_QUEUE",
+        required_if("unrecognized", "reject")
+    )]
+    rejects_queue: Option<String>,
+    /// Hold ECR pushes until their image scan completes, and deploy only if it has no findings of this severity or above (tracked repositories must scan on push)
+    #[structopt(long = "scan-gate", env = "DEPLOYER_SCAN_GATE")]
+    scan_gate: Option<scan::Severity>,
+    /// Variables exported by CodeBuild builds that hold the images they produced
+    #[structopt(
+        long = "codebuild-image-variables",
+        env = "DEPLOYER_CODEBUILD_IMAGE_VARIABLES",
+        use_delimiter = true,
+        default_value = "IMAGE_URI"
+    )]
+    codebuild_image_variables: Vec<String>,
+    /// Read events of other shapes by mapping a JMESPath expression to an event field, e.g. repository=detail.repo (repeatable; fields are account, region, registry, repository, tag, digest and pushed-at)
+    #[structopt(
+        long = "field-mapping",
+        env = "DEPLOYER_FIELD_MAPPING",
+        parse(try_from_str = mapping::parse_field_mapping),
+        number_of_values = 1
+    )]
+    field_mappings: Vec<mapping::FieldMapping>,
+    /// User to pull ghcr.io images as, together with --ghcr-token
+    #[structopt(long = "ghcr-username", env = "DEPLOYER_GHCR_USERNAME")]
+    ghcr_username: Option<String>,
+    /// Personal access token with read:packages for pulling ghcr.io images
+    #[structopt(
+        long = "ghcr-token",
+        env = "DEPLOYER_GHCR_TOKEN",
+        hide_env_values = true
+    )]
+    ghcr_token: Option<String>,
+    /// GitHub App to pull ghcr.io images as, instead of a personal access token
+    #[structopt(long = "github-app-id", env = "DEPLOYER_GITHUB_APP_ID")]
+    github_app_id: Option<u64>,
+    /// Installation of the GitHub App whose packages to pull
+    #[structopt(
+        long = "github-installation-id",
+        env = "DEPLOYER_GITHUB_INSTALLATION_ID"
+    )]
+    github_installation_id: Option<u64>,
+    /// PEM private key of the GitHub App
+    #[structopt(
+        long = "github-app-key",
+        env = "DEPLOYER_GITHUB_APP_KEY",
+        parse(from_os_str)
+    )]
+    github_app_key: Option<PathBuf>,
+    /// Token name, service principal id or admin user for pulling from Azure Container Registry
+    #[structopt(long = "acr-username", env = "DEPLOYER_ACR_USERNAME")]
+    acr_username: Option<String>,
+    /// Password for --acr-username
+    #[structopt(
+        long = "acr-password",
+        env = "DEPLOYER_ACR_PASSWORD",
+        hide_env_values = true
+    )]
+    acr_password: Option<String>,
+    /// User to pull images from other registries as, e.g. from Harbor or Nexus, together with --registry-password
+    #[structopt(
+        long = "registry-username",
+        env = "DEPLOYER_REGISTRY_USERNAME",
+        requires = "registry-hosts"
+    )]
+    registry_username: Option<String>,
+    /// Password for --registry-username
+    #[structopt(
+        long = "registry-password",
+        env = "DEPLOYER_REGISTRY_PASSWORD",
+        hide_env_values = true,
+        requires = "registry-hosts"
+    )]
+    registry_password: Option<String>,
+    /// Secrets Manager secret holding JSON with username and password to pull images from other registries as, instead of --registry-username
+    #[structopt(
+        long = "registry-secret",
+        env = "DEPLOYER_REGISTRY_SECRET",
+        conflicts_with = "registry-password",
+        requires = "registry-hosts"
+    )]
+    registry_secret: Option<secrets::SecretArn>,
+    /// Vault server to read registry credentials from, e.g. https://vault.example.com:8200
+    #[structopt(
+        long = "vault-addr",
+        env = "DEPLOYER_VAULT_ADDR",
+        requires = "vault-secret-path"
+    )]
+    vault_addr: Option<String>,
+    /// KV version 2 secret with username and password to pull images from other registries as, e.g. secret/registries/harbor
+    #[structopt(
+        long = "vault-secret-path",
+        env = "DEPLOYER_VAULT_SECRET_PATH",
+        requires = "vault-addr",
+        requires = "registry-hosts"
+    )]
+    vault_secret_path: Option<String>,
+    /// Token to read --vault-secret-path with
+    #[structopt(
+        long = "vault-token",
+        env = "DEPLOYER_VAULT_TOKEN",
+        hide_env_values = true
+    )]
+    vault_token: Option<String>,
+    /// AppRole to log in to Vault as, instead of --vault-token
+    #[structopt(
+        long = "vault-role-id",
+        env = "DEPLOYER_VAULT_ROLE_ID",
+        requires = "vault-secret-id"
+    )]
+    vault_role_id: Option<String>,
+    /// Secret id for --vault-role-id
+    #[structopt(
+        long = "vault-secret-id",
+        env = "DEPLOYER_VAULT_SECRET_ID",
+        hide_env_values = true
+    )]
+    vault_secret_id: Option<String>,
+    /// Registry host to use --registry-username, --registry-secret or --vault-secret-path for (repeatable; required with any of them)
+    #[structopt(
+        long = "registry-host",
+        env = "DEPLOYER_REGISTRY_HOSTS",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    registry_hosts: Vec<String>,
+    /// Pull this repository without credentials, e.g. nginx or ghcr.io/owner/app (repeatable; images on public.ecr.aws always are)
+    #[structopt(
+        long = "public-repository",
+        env = "DEPLOYER_PUBLIC_REPOSITORIES",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    public_repositories: Vec<String>,
+    /// Docker CLI config file with logins and credential helpers for other registries [default: $DOCKER_CONFIG/config.json or ~/.docker/config.json]
+    #[structopt(
+        long = "docker-config",
+        env = "DEPLOYER_DOCKER_CONFIG",
+        parse(from_os_str)
+    )]
+    docker_config: Option<PathBuf>,
+    /// Also recreate plain containers on this node when their image is pushed
+    #[structopt(long = "containers", env = "DEPLOYER_CONTAINERS")]
+    containers: bool,
+    /// Force-update services on the cron schedule in their seedy.redeploy-cron label
+    #[structopt(long = "scheduled-redeploys", env = "DEPLOYER_SCHEDULED_REDEPLOYS")]
+    scheduled_redeploys: bool,
+    /// Skip events replayed from an EventBridge archive instead of deploying them
+    #[structopt(long = "refuse-replays", env = "DEPLOYER_REFUSE_REPLAYS")]
+    refuse_replays: bool,
+    /// Check the signature of SNS notifications before unwrapping them (requires the tls feature)
+    #[structopt(long = "verify-sns-signatures", env = "DEPLOYER_VERIFY_SNS_SIGNATURES")]
+    verify_sns_signatures: bool,
+    /// Hours between reports to the marker sinks on services running an older digest than their tag; 0 disables reports
+    #[structopt(
+        long = "drift-report-hours",
+        default_value = "24",
+        env = "DEPLOYER_DRIFT_REPORT_HOURS"
+    )]
+    drift_report_hours: u64,
+    /// Seconds between checking the registry for new digests of tracked images, for registries that send no events
+    #[structopt(long = "poll-registry-seconds", env = "DEPLOYER_POLL_REGISTRY_SECONDS")]
+    poll_registry_seconds: Option<u64>,
+    /// Serve Prometheus metrics on queue depth and event lag at /metrics on this address, e.g. 0.0.0.0:9090
+    #[structopt(long = "metrics-listen", env = "DEPLOYER_METRICS_LISTEN")]
+    metrics_listen: Option<String>,
+    /// Seconds between checks of queue depth for metrics
+    #[structopt(
+        long = "queue-metrics-seconds",
+        default_value = "60",
+        env = "DEPLOYER_QUEUE_METRICS_SECONDS"
+    )]
+    queue_metrics_seconds: u64,
+    /// Number of recently deployed image digests to remember, skipping duplicate events for them (0 disables)
+    #[structopt(
+        long = "dedupe-capacity",
+        default_value = "1000",
+        env = "DEPLOYER_DEDUPE_CAPACITY"
+    )]
+    dedupe_capacity: usize,
+    /// Grafana base URL to post deployment annotations to
+    #[structopt(long = "grafana-url", env = "DEPLOYER_GRAFANA_URL")]
+    grafana_url: Option<String>,
+    /// API token for posting Grafana annotations
+    #[structopt(
+        long = "grafana-token",
+        env = "DEPLOYER_GRAFANA_TOKEN",
+        hide_env_values = true
+    )]
+    grafana_token: Option<String>,
+    /// Datadog API key to record deployments as Datadog events
+    #[structopt(
+        long = "datadog-api-key",
+        env = "DEPLOYER_DATADOG_API_KEY",
+        hide_env_values = true
+    )]
+    datadog_api_key: Option<String>,
+    /// Datadog site to send events to
+    #[structopt(
+        long = "datadog-site",
+        default_value = "datadoghq.com",
+        env = "DEPLOYER_DATADOG_SITE"
+    )]
+    datadog_site: String,
+    /// New Relic user API key to record deployments with change tracking
+    #[structopt(
+        long = "newrelic-api-key",
+        env = "DEPLOYER_NEWRELIC_API_KEY",
+        hide_env_values = true
+    )]
+    newrelic_api_key: Option<String>,
+    /// New Relic entity for services without a seedy.newrelic-entity-guid label
+    #[structopt(long = "newrelic-entity-guid", env = "DEPLOYER_NEWRELIC_ENTITY_GUID")]
+    newrelic_entity_guid: Option<String>,
+    /// New Relic NerdGraph endpoint (use https://api.eu.newrelic.com/graphql for EU accounts)
+    #[structopt(
+        long = "newrelic-endpoint",
+        default_value = "https://api.newrelic.com/graphql",
+        env = "DEPLOYER_NEWRELIC_ENDPOINT"
+    )]
+    newrelic_endpoint: String,
+    /// CloudWatch Logs group to write one JSON record per deployment to
+    #[structopt(long = "cloudwatch-log-group", env = "DEPLOYER_CLOUDWATCH_LOG_GROUP")]
+    cloudwatch_log_group: Option<String>,
+    /// Environment name to tag deployment markers with
+    #[structopt(long = "environment", env = "DEPLOYER_ENVIRONMENT")]
+    environment: Option<String>,
+    /// Where to get AWS credentials: default, environment, profile, instance-metadata, container, web-identity, static or sso
+    #[structopt(
+        long = "aws-credential-source",
+        default_value = "default",
+        env = "DEPLOYER_AWS_CREDENTIAL_SOURCE"
+    )]
+    aws_credential_source: aws::CredentialSource,
+    /// Endpoint to use for all AWS services instead of the public ones, e.g. http://localstack:4566
+    #[structopt(long = "aws-endpoint-url", env = "DEPLOYER_AWS_ENDPOINT_URL")]
+    aws_endpoint_url: Option<String>,
+    /// Endpoints for individual AWS services, e.g. sqs=https://vpce-123.sqs.eu-west-1.vpce.amazonaws.com (services: sqs, ecr, s3, sts, logs, secretsmanager)
+    #[structopt(
+        long = "aws-service-endpoints",
+        env = "DEPLOYER_AWS_SERVICE_ENDPOINTS",
+        parse(try_from_str = parse_service_endpoint),
+        use_delimiter = true
+    )]
+    aws_service_endpoints: Vec<(String, String)>,
+    /// Access key id when using static AWS credentials
+    #[structopt(long = "aws-access-key-id", env = "DEPLOYER_AWS_ACCESS_KEY_ID")]
+    aws_access_key_id: Option<String>,
+    /// Secret access key when using static AWS credentials
+    #[structopt(
+        long = "aws-secret-access-key",
+        env = "DEPLOYER_AWS_SECRET_ACCESS_KEY",
+        hide_env_values = true
+    )]
+    aws_secret_access_key: Option<String>,
+    /// Token file when using web identity AWS credentials
+    #[structopt(
+        long = "aws-web-identity-token-file",
+        env = "AWS_WEB_IDENTITY_TOKEN_FILE",
+        parse(from_os_str)
+    )]
+    aws_web_identity_token_file: Option<PathBuf>,
+    /// AWS CLI profile to use with SSO credentials
+    #[structopt(long = "aws-profile", env = "AWS_PROFILE")]
+    aws_profile: Option<String>,
+    /// Role to assume when using web identity AWS credentials
+    #[structopt(long = "aws-role-arn", env = "AWS_ROLE_ARN")]
+    aws_role_arn: Option<String>,
     /// Verbose mode (trace, debug, info, warn, err)
     #[structopt(long = "log-level", default_value = "WARN", env = "DEPLOYER_LOG_LEVEL")]
     log_level: log::Level,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug, PartialEq)]
+pub enum Command {
+    /// Check that registry credentials for every tracked service grant access to its image
+    VerifyCredentials,
+    /// Run as an AWS Lambda handler for SQS-triggered batches instead of polling
+    Lambda,
 }
 
 #[derive(Debug, Snafu)]
 pub enum SeedyError {
     #[snafu(display("Filter label {} expected to be on format key=value", label))]
     LabelFilterError { label: String },
+    #[snafu(display("{} expected to be on format {}, got {}", option, format, value))]
+    KeyValueFormat {
+        option: String,
+        format: String,
+        value: String,
+    },
+    #[snafu(display(
+        "{} expected to be a number from {} to {}, got {}",
+        option,
+        min,
+        max,
+        value
+    ))]
+    OutOfRange {
+        option: String,
+        value: String,
+        min: i64,
+        max: i64,
+    },
+    #[snafu(display(
+        "Credential source {} expected to be one of default, environment, profile, \
+         instance-metadata, container, web-identity, static or sso",
+        value
+    ))]
+    CredentialSourceFormat { value: String },
+    #[snafu(display("The selected AWS credential source requires {}", option))]
+    MissingCredentialOption { option: String },
+    #[snafu(display("Could not set up AWS credentials: {}", source))]
+    AwsCredentialProvider { source: CredentialsError },
+    #[snafu(display("Could not set up TLS for AWS clients: {}", source))]
+    HttpClientTls { source: TlsError },
+    #[snafu(display("Quiet hours {} expected to be on format 22-06", value))]
+    QuietHoursFormat { value: String },
+    #[snafu(display(
+        "Severity {} expected to be one of INFORMATIONAL, LOW, MEDIUM, HIGH or CRITICAL",
+        value
+    ))]
+    SeverityFormat { value: String },
+    #[snafu(display(
+        "Unrecognized message policy {} expected to be one of drop, reject or leave",
+        value
+    ))]
+    UnrecognizedPolicyFormat { value: String },
+    #[snafu(display(
+        "Delete action {} expected to be one of warn, scale-to-zero or pin",
+        value
+    ))]
+    DeleteActionFormat { value: String },
+    #[snafu(display(
+        "Field mapping {} expected to be <field>=<JMESPath expression>, with field one of {}",
+        value,
+        mapping::FIELDS.join(", ")
+    ))]
+    FieldMappingFormat { value: String },
+    #[snafu(display("Invalid JMESPath expression for {}: {}", field, message))]
+    FieldMappingExpression { field: String, message: String },
+    #[snafu(display("Failed to forward message to {}: {}", queue_url, source))]
+    ForwardingMessage {
+        queue_url: String,
+        source: RusotoError<SendMessageError>,
+    },
+    #[snafu(display("Duration {} expected to be on format 90s, 10m or 2h", value))]
+    DurationFormat { value: String },
     #[snafu(display("Counld not instantiate a Docker client from environment {}", source))]
     DockerInstantiation { source: BollardError },
+    #[snafu(display("Could not use GitHub App key: {}", source))]
+    GithubAppKey { source: jsonwebtoken::errors::Error },
+    #[snafu(display("Could not read GitHub App key {}: {}", path.display(), source))]
+    GithubAppKeyIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("GitHub request to {} failed: {}", url, source))]
+    GithubRequest { url: String, source: reqwest::Error },
+    #[snafu(display("Could not use GCP service account key: {}", source))]
+    GcpKey { source: jsonwebtoken::errors::Error },
+    #[snafu(display("Could not read GCP credentials {}: {}", path.display(), source))]
+    GcpCredentialsIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("GCP credentials {} are not valid JSON: {}", path.display(), source))]
+    GcpCredentialsFormat {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[snafu(display("Could not read Docker config {}: {}", path.display(), source))]
+    DockerConfigIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not parse Docker config {}: {}", path.display(), source))]
+    DockerConfigFormat {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[snafu(display("Credential helper {} failed: {}", helper, message))]
+    CredentialHelper { helper: String, message: String },
+    #[snafu(display(
+        "Expected a Secrets Manager ARN like arn:aws:secretsmanager:<region>:<account>:secret:<name>, got {}",
+        value
+    ))]
+    SecretArnFormat { value: String },
+    #[snafu(display("Could not fetch secret {}: {}", secret_id, source))]
+    FetchingSecret {
+        secret_id: String,
+        source: RusotoError<GetSecretValueError>,
+    },
+    #[snafu(display("Secret {} is not JSON with username and password", secret_id))]
+    SecretFormat { secret_id: String },
+    #[snafu(display("Vault request to {} failed: {}", url, source))]
+    VaultRequest { url: String, source: reqwest::Error },
+    #[snafu(display("Vault secret {} has no username and password", path))]
+    VaultSecretFormat { path: String },
+    #[snafu(display("GCP request to {} failed: {}", url, source))]
+    GcpRequest { url: String, source: reqwest::Error },
+    #[snafu(display(
+        "Pub/Sub subscription {} needs a dead-letter policy for delivery attempts to be counted",
+        subscription
+    ))]
+    MissingDeadLetterPolicy { subscription: String },
+    #[snafu(display("Could not subscribe on NATS server {}: {}", url, source))]
+    NatsConnect { url: String, source: std::io::Error },
+    #[snafu(display("Failed to receive from NATS subject {}: {}", subject, source))]
+    NatsReceive {
+        subject: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not join consumer group for Kafka topic {}: {}", topic, source))]
+    KafkaSetup {
+        topic: String,
+        source: ::kafka::Error,
+    },
+    #[snafu(display("Failed to consume from Kafka topic {}: {}", topic, source))]
+    KafkaConsume {
+        topic: String,
+        source: ::kafka::Error,
+    },
+    #[snafu(display("Could not set up Redis stream consumer at {}: {}", url, source))]
+    RedisConnect {
+        url: String,
+        source: ::redis::RedisError,
+    },
+    #[snafu(display("Failed to read from Redis stream {}: {}", stream, source))]
+    RedisReceive {
+        stream: String,
+        source: ::redis::RedisError,
+    },
+    #[snafu(display("Lost connection to MQTT broker subscribed to {}", topic))]
+    MqttReceive { topic: String },
+    #[snafu(display("Could not read events from {}: {}", path, source))]
+    ReplayIo {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display(
+        "One of --queue, --listen, --pubsub-subscription, --nats-url, --kafka-brokers, --redis-url, --mqtt-broker or --from-file is required"
+    ))]
+    MissingEventSource,
+    #[snafu(display("Could not listen on {}: {}", address, message))]
+    ListenerSetup { address: String, message: String },
+    #[snafu(display("Network {} expected to be on format 192.0.2.0/24", value))]
+    CidrFormat { value: String },
+    #[snafu(display("Webhook listener on {} stopped", address))]
+    WebhookListener { address: String },
     #[snafu(display("Failed to retrieve URL for queue {}: {}", queue_name, source))]
     SqsUrl {
         queue_name: String,
         source: RusotoError<GetQueueUrlError>,
     },
+    #[snafu(display(
+        "Expected an SQS queue ARN like arn:aws:sqs:<region>:<account>:<queue>, got {}",
+        value
+    ))]
+    QueueArnFormat { value: String },
+    #[snafu(display(
+        "Expected an SNS subscription ARN like arn:aws:sns:<region>:<account>:<topic>:<id>, got {}",
+        value
+    ))]
+    SubscriptionArnFormat { value: String },
+    #[snafu(display("Failed to set filter policy on {}: {}", subscription_arn, source))]
+    SettingFilterPolicy {
+        subscription_arn: String,
+        source: RusotoError<SetSubscriptionAttributesError>,
+    },
+    #[snafu(display(
+        "Expected a pull-through cache like <account>.dkr.ecr.<region>.amazonaws.com/<prefix>=<upstream registry>, got {}",
+        value
+    ))]
+    PullThroughCacheFormat { value: String },
+    #[snafu(display("Failed to report task success to Step Functions: {}", source))]
+    ReportingTaskSuccess {
+        source: RusotoError<SendTaskSuccessError>,
+    },
+    #[snafu(display("Failed to report task failure to Step Functions: {}", source))]
+    ReportingTaskFailure {
+        source: RusotoError<SendTaskFailureError>,
+    },
     #[snafu(display("Polling for ECR events on {} failed: {}", queue_url, source))]
     PollingMessage {
         queue_url: String,
@@ -53,6 +908,13 @@ pub enum SeedyError {
     },
     #[snafu(display("Could not list services: {}", source))]
     ServiceListing { source: BollardError },
+    #[snafu(display("Could not list containers: {}", source))]
+    ContainerListing { source: BollardError },
+    #[snafu(display("Failed to recreate container {}: {}", container_id, source))]
+    RecreatingContainer {
+        container_id: String,
+        source: BollardError,
+    },
     #[snafu(display("Failed to update image for service {}: {}", service_id, source))]
     UpdatingService {
         service_id: String,
@@ -69,6 +931,27 @@ pub enum SeedyError {
         queue_url: String,
         source: RusotoError<DeleteMessageError>,
     },
+    #[snafu(display("Failed to get attributes of queue {}: {}", queue_url, source))]
+    QueueAttributes {
+        queue_url: String,
+        source: RusotoError<GetQueueAttributesError>,
+    },
+    #[snafu(display("Failed to ack messages on {}: {}", queue_url, source))]
+    AckingMessages {
+        queue_url: String,
+        source: RusotoError<DeleteMessageBatchError>,
+    },
+    #[snafu(display(
+        "Failed to delay ECR event {} on queue {}: {}",
+        receipt_handle,
+        queue_url,
+        source
+    ))]
+    DelayingMessage {
+        receipt_handle: String,
+        queue_url: String,
+        source: RusotoError<ChangeMessageVisibilityError>,
+    },
     #[snafu(display(
         "Could not retrieve authentication token for accounts {:?}: {}",
         registry_ids,
@@ -78,6 +961,83 @@ pub enum SeedyError {
         registry_ids: Vec<String>,
         source: RusotoError<GetAuthorizationTokenError>,
     },
+    #[snafu(display(
+        "Could not describe images in repository {}: {}",
+        repository_name,
+        source
+    ))]
+    DescribingImages {
+        repository_name: String,
+        source: RusotoError<DescribeImagesError>,
+    },
+    #[snafu(display("{} has not been replicated yet", image))]
+    ReplicaPending { image: String },
+    #[snafu(display("Could not describe repository {}: {}", repository_name, source))]
+    DescribingRepository {
+        repository_name: String,
+        source: RusotoError<DescribeRepositoriesError>,
+    },
+    #[snafu(display(
+        "--scan-gate would hold pushes forever, as repositories {} are not scanned on push",
+        repositories
+    ))]
+    ScanOnPushDisabled { repositories: String },
+    #[snafu(display("Failed to record deployment in {}: {}", sink, source))]
+    RecordingDeployment {
+        sink: String,
+        source: reqwest::Error,
+    },
+    #[snafu(display("Could not create log stream in {}: {}", log_group, source))]
+    CreatingLogStream {
+        log_group: String,
+        source: RusotoError<CreateLogStreamError>,
+    },
+    #[snafu(display("Failed to write deployment record to {}: {}", log_group, source))]
+    PuttingLogEvents {
+        log_group: String,
+        source: RusotoError<PutLogEventsError>,
+    },
+    #[snafu(display("{} rejected deployment: {}", sink, message))]
+    RejectedDeployment { sink: String, message: String },
+    #[snafu(display("Request to registry {} failed: {}", url, source))]
+    RegistryRequest { url: String, source: reqwest::Error },
+    #[snafu(display("Registry {} refused the credentials", url))]
+    RegistryRefused { url: String },
+    #[snafu(display("No token from registry token endpoint {}", url))]
+    RegistryToken { url: String },
+    #[snafu(display("Credentials could not be verified for {} services", failures))]
+    VerificationFailed { failures: usize },
+    #[snafu(display("Plugin {} failed: {}", plugin, message))]
+    PluginFailed { plugin: String, message: String },
+    #[snafu(display("Could not list plugins in {}: {}", path.display(), source))]
+    PluginDirIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not fetch s3://{}/{}: {}", bucket, key, source))]
+    FetchingObject {
+        bucket: String,
+        key: String,
+        source: RusotoError<GetObjectError>,
+    },
+    #[snafu(display("Could not read S3 object {}: {}", key, source))]
+    ReadingObject { key: String, source: std::io::Error },
+    #[snafu(display("Could not fetch SNS signing certificate {}: {}", url, source))]
+    SnsCertificate { url: String, source: reqwest::Error },
+    #[snafu(display("SNS signature verification failed: {}", message))]
+    SnsSignature { message: String },
+    #[snafu(display("{} requires building with the {} feature", option, feature))]
+    FeatureDisabled { feature: String, option: String },
+    #[snafu(display("Could not access journal {}: {}", path.display(), source))]
+    JournalIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Journal {} is not valid JSON: {}", path.display(), source))]
+    JournalFormat {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
 }
 
 type Result<T, E = SeedyError> = std::result::Result<T, E>;
@@ -93,26 +1053,92 @@ fn split_label(input: &str) -> Result<(String, String)> {
     Ok((parts[0].to_owned(), parts[1].to_owned()))
 }
 
+/// Split an option value on the first =, naming the option and the expected
+/// format when there is no key or value.
+fn parse_key_value(input: &str, option: &str, format: &str) -> Result<(String, String)> {
+    match input.find('=') {
+        Some(pos) if pos > 0 && pos + 1 < input.len() => {
+            Ok((input[..pos].to_owned(), input[pos + 1..].to_owned()))
+        }
+        _ => KeyValueFormat {
+            option,
+            format,
+            value: input,
+        }
+        .fail(),
+    }
+}
+
+fn parse_attribute_filter(input: &str) -> Result<(String, String)> {
+    parse_key_value(input, "--attribute-filter", "<attribute>=<value>")
+}
+
+fn parse_ecr_account_role(input: &str) -> Result<(String, String)> {
+    parse_key_value(input, "--ecr-account-role", "<account id>=<role arn>")
+}
+
+fn parse_nexus_registry(input: &str) -> Result<(String, String)> {
+    parse_key_value(input, "--nexus-registry", "<repository>=<host:port>")
+}
+
+fn parse_ecr_replication(input: &str) -> Result<(String, String)> {
+    let format = "<source region>=<destination region>";
+    let (source, destination) = parse_key_value(input, "--ecr-replication", format)?;
+    ensure!(
+        source.parse::<Region>().is_ok() && destination.parse::<Region>().is_ok(),
+        KeyValueFormat {
+            option: "--ecr-replication",
+            format,
+            value: input,
+        }
+    );
+    Ok((source, destination))
+}
+
+fn parse_service_endpoint(input: &str) -> Result<(String, String)> {
+    parse_key_value(input, "--aws-service-endpoints", "<service>=<url>")
+}
+
+fn parse_in_range(input: &str, option: &str, min: i64, max: i64) -> Result<i64> {
+    match input.parse::<i64>() {
+        Ok(value) if value >= min && value <= max => Ok(value),
+        _ => OutOfRange {
+            option,
+            value: input,
+            min,
+            max,
+        }
+        .fail(),
+    }
+}
+
+fn parse_max_messages(input: &str) -> Result<i64> {
+    parse_in_range(input, "--max-messages", 1, 10)
+}
+
+fn parse_wait_seconds(input: &str) -> Result<i64> {
+    parse_in_range(input, "--wait-seconds", 0, 20)
+}
+
 fn extract_service_image(service: &Service<String>) -> Option<String> {
-    service
+    let stacked = service.spec.labels.get(STACK_IMAGE_LABEL).map(|image| {
+        reference::ImageRef::parse(image)
+            .map_or_else(|| image.to_owned(), |image| image.to_string())
+    });
+    let running = service
         .spec
-        .labels
-        .get(STACK_IMAGE_LABEL)
-        .map(|image| image.to_owned())
-        .or_else(|| {
-            service
-                .spec
-                .task_template
-                .container_spec
-                .as_ref()
-                .and_then(|spec| {
-                    spec.image.clone().map(|mut image| {
-                        let at_pos = image.find('@').unwrap_or(usize::max_value());
-                        image.truncate(at_pos);
-                        image
-                    })
-                })
-        })
+        .task_template
+        .container_spec
+        .as_ref()
+        .and_then(|spec| spec.image.as_deref())
+        .and_then(reference::ImageRef::parse)
+        .map(|image| image.unpinned());
+    // Services moved by policy no longer run the image of their stack file
+    if policy::is_labelled(service) {
+        running.or(stacked)
+    } else {
+        stacked.or(running)
+    }
 }
 
 fn docker_credentials_from_auth_token(auth_token: String) -> DockerCredentials {
@@ -129,79 +1155,666 @@ fn docker_credentials_from_auth_token(auth_token: String) -> DockerCredentials {
     }
 }
 
-fn ecr_auth_for_event(ecr: &EcrClient, event: &events::Event) -> Result<Option<DockerCredentials>> {
+fn ecr_authorizations(ecr: &EcrClient, account_ids: Vec<String>) -> Result<Vec<AuthorizationData>> {
     let req = GetAuthorizationTokenRequest {
-        registry_ids: Some(vec![event.account_id.clone()]),
+        registry_ids: Some(account_ids.clone()),
     };
-    let auth_token = ecr
+    let authorizations = ecr
         .get_authorization_token(req)
         .sync()
         .with_context(|| AuthToken {
-            registry_ids: vec![event.account_id.clone()],
+            registry_ids: account_ids,
         })?
         .authorization_data
-        .and_then(|mut auths| {
-            auths
-                .get_mut(0)
-                .map(|auth| auth.authorization_token.take().unwrap())
-                .map(docker_credentials_from_auth_token)
-        });
-    Ok(auth_token)
+        .unwrap_or_default();
+    Ok(authorizations)
+}
+
+/// Push times come from the registry and event relays, whose clocks may
+/// disagree somewhat.
+const CLOCK_SKEW_SECONDS: i64 = 60;
+
+fn running_pushed_at(service: &Service<String>) -> Option<DateTime<Utc>> {
+    service
+        .spec
+        .labels
+        .get(PUSHED_AT_LABEL)
+        .and_then(|pushed_at| pushed_at.parse().ok())
+}
+
+/// Whether the event was pushed before the image the service runs, as
+/// happens when a delayed or redelivered message arrives after a newer push
+/// has been deployed.
+fn is_stale(service: &Service<String>, event: &events::Event) -> bool {
+    match (event.pushed_at, running_pushed_at(service)) {
+        (Some(pushed_at), Some(running_pushed_at)) => {
+            pushed_at + chrono::Duration::seconds(CLOCK_SKEW_SECONDS) < running_pushed_at
+                && running_image(service)
+                    .and_then(|image| image.digest)
+                    .as_deref()
+                    != Some(event.image_digest.as_str())
+        }
+        _ => false,
+    }
+}
+
+/// Check the pushed image against the platforms the service is placed on.
+/// Images without a manifest list do not declare platforms and are let through.
+fn platform_compatible(
+    service: &Service<String>,
+    event: &events::Event,
+    credentials: Option<&DockerCredentials>,
+) -> Result<bool> {
+    let required = platform::required(service);
+    if required.is_empty() {
+        return Ok(true);
+    }
+    let client = reqwest::blocking::Client::new();
+    let manifest = registry::get_manifest(
+        &client,
+        &event.registry_host(),
+        &event.repository_name,
+        &event.image_digest,
+        credentials,
+    )?;
+    let available = match manifest.as_ref().and_then(platform::from_manifest) {
+        Some(available) => available,
+        None => return Ok(true),
+    };
+    let missing = platform::missing(&required, &available);
+    let describe = |platforms: &[platform::Platform]| {
+        platforms
+            .iter()
+            .map(|platform| platform.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if missing.len() == required.len() {
+        warn!(
+            "Skipping service {}, image {} has none of its platforms {}",
+            &service.id,
+            &event.image(),
+            describe(&required)
+        );
+        return Ok(false);
+    }
+    if !missing.is_empty() {
+        warn!(
+            "Image {} lacks platforms {} that nodes of service {} may require",
+            &event.image(),
+            describe(&missing),
+            &service.id
+        );
+    }
+    Ok(true)
 }
 
 fn update_spec(service: &Service<String>, event: &events::Event) -> ServiceSpec<String> {
+    let mut spec = spec_with_image(service, &event.pinned_image());
+    match event.pushed_at {
+        Some(pushed_at) => spec
+            .labels
+            .insert(PUSHED_AT_LABEL.to_owned(), pushed_at.to_rfc3339()),
+        None => spec.labels.remove(PUSHED_AT_LABEL),
+    };
+    spec
+}
+
+fn spec_with_image(service: &Service<String>, image: &str) -> ServiceSpec<String> {
     let mut spec = service.spec.clone();
     spec.task_template.force_update = Some(service.version.index as isize);
     spec.task_template
         .container_spec
         .as_mut()
         .and_then(|mut spec| {
-            spec.image = Some(format!("{}@{}", event.image(), event.image_digest));
+            spec.image = Some(image.to_owned());
             Some(spec)
         });
+    match service
+        .spec
+        .labels
+        .get(UPDATE_ORDER_LABEL)
+        .map(String::as_str)
+    {
+        Some(order @ "start-first") | Some(order @ "stop-first") => {
+            let mut update_config = spec.update_config.take().unwrap_or_default();
+            update_config.order = Some(order.to_owned());
+            spec.update_config = Some(update_config);
+        }
+        Some(order) => warn!(
+            "Ignoring {}={} on service {}, expected start-first or stop-first",
+            UPDATE_ORDER_LABEL, order, &service.id
+        ),
+        None => (),
+    }
     spec
 }
 
+fn current_image(service: &Service<String>) -> Option<&String> {
+    spec_image(&service.spec)
+}
+
+fn running_image(service: &Service<String>) -> Option<reference::ImageRef> {
+    current_image(service).and_then(|image| reference::ImageRef::parse(image))
+}
+
+fn spec_image(spec: &ServiceSpec<String>) -> Option<&String> {
+    spec.task_template
+        .container_spec
+        .as_ref()
+        .and_then(|spec| spec.image.as_ref())
+}
+
 fn process_one(
     message: &Message,
     services_by_image: &HashMap<String, Service<String>>,
-    docker: &Docker,
-    rt: &mut Runtime,
+    deployer: &mut Deployer,
+    opt: &Opt,
 ) -> Result<()> {
+    match message_events(message, deployer, opt)? {
+        Some(parsed) => process_events(message, parsed, services_by_image, deployer, opt),
+        None => Ok(()),
+    }
+}
+
+/// What a message asks of the deployer.
+#[derive(Default)]
+struct Parsed {
+    events: Vec<events::Event>,
+    /// Images reported removed from the registry
+    deletions: Vec<events::Event>,
+    /// Name of the archive replay the message came from, if any
+    replay: Option<String>,
+}
+
+/// Drop ECR events from accounts or regions not on the allowlists, so that
+/// they are never used to fetch auth tokens or update services.
+fn reject_foreign(events: &mut Vec<events::Event>, opt: &Opt) {
+    events.retain(|event| {
+        let allowed = event.origin_allowed(&opt.allow_accounts, &opt.allow_regions);
+        if !allowed {
+            warn!(
+                "Rejecting {} from account {} in region {}",
+                event.pinned_image(),
+                event.account_id,
+                event.region
+            );
+        }
+        allowed
+    });
+}
+
+/// Unwrap a message and parse the deployment events it carries. Returns
+/// None for messages that carry no recognized event at all.
+fn message_events(message: &Message, deployer: &mut Deployer, opt: &Opt) -> Result<Option<Parsed>> {
     debug!("Processing message {:?}", message);
-    if let Some(event_str) = &message.body {
-        if let Some(event) = events::parse_ecr_event(event_str) {
-            if let Some(service) = services_by_image.get(&event.image()) {
-                let event_region = Region::from_str(&event.region).unwrap();
-                let ecr = EcrClient::new(event_region);
-                let auth_token = ecr_auth_for_event(&ecr, &event)?;
-                let updated_spec = update_spec(&service, &event);
-                let options = UpdateServiceOptions {
-                    version: service.version.index,
-                    ..Default::default()
-                };
-                rt.block_on(docker.update_service(&service.id, updated_spec, options, auth_token))
-                    .with_context(|| UpdatingService {
-                        service_id: service.id.clone(),
-                    })?;
+    let body = match &message.body {
+        Some(body) => body,
+        None => {
+            debug!("Encountered empty message {:?}", &message.body);
+            return Ok(None);
+        }
+    };
+    let region = opt.sqs_region.clone().unwrap_or_default();
+    let body = match s3::payload_pointer(body, &region) {
+        Some(object) => s3::fetch(&object, opt)?,
+        None => body.clone(),
+    };
+    let event_str = match sns::envelope(&body) {
+        Some(envelope) => {
+            if let Some(verifier) = &mut deployer.sns {
+                if let Err(err) = verifier.verify(&envelope) {
+                    warn!("Skipping message {:?}: {}", &message.message_id, err);
+                    return Ok(Some(Parsed::default()));
+                }
+            }
+            sns::message(&envelope).to_owned()
+        }
+        None => body,
+    };
+    let replay = events::replay_name(&event_str);
+    if let Some(replay) = &replay {
+        if opt.refuse_replays {
+            info!(
+                "Skipping message {:?} replayed by {}",
+                &message.message_id, replay
+            );
+            return Ok(Some(Parsed::default()));
+        }
+        info!(
+            "Message {:?} is replayed by {}",
+            &message.message_id, replay
+        );
+    }
+    let mut events = events::parse_events(&event_str, &opt.nexus_registries);
+    if events.is_empty() {
+        events.extend(s3::manifest_events(
+            &event_str,
+            &mut deployer.credentials,
+            opt,
+        )?);
+    }
+    if events.is_empty() {
+        events.extend(codebuild::build_events(
+            &event_str,
+            &mut deployer.credentials,
+            opt,
+        )?);
+    }
+    if events.is_empty() && !opt.field_mappings.is_empty() {
+        events.extend(mapping::mapped_event(&event_str, &opt.field_mappings)?);
+    }
+    let mut deletions: Vec<events::Event> = events::parse_ecr_delete_event(&event_str)
+        .into_iter()
+        .collect();
+    let mut recognized = !events.is_empty() || !deletions.is_empty();
+    if let Some(gate) = opt.scan_gate {
+        events.retain(|event| {
+            if event.is_ecr() {
+                info!("Holding {} until its image scan completes", event.image());
+            }
+            !event.is_ecr()
+        });
+        if let Some(scan) = events::parse_ecr_scan_event(&event_str) {
+            recognized = true;
+            let findings = scan::blocking_findings(&scan, gate);
+            if findings == 0 {
+                events.extend(scan.events);
+            } else {
+                for event in scan.events.iter() {
+                    warn!(
+                        "Not deploying {}: scan found {} findings at or above gate",
+                        event.pinned_image(),
+                        findings
+                    );
+                }
+            }
+        }
+    }
+    if events.is_empty() {
+        events.extend(plugins::parse(&deployer.plugins, &event_str)?);
+        recognized |= !events.is_empty();
+    }
+    reject_foreign(&mut events, opt);
+    reject_foreign(&mut deletions, opt);
+    let replicas: Vec<events::Event> = events
+        .iter()
+        .flat_map(|event| events::replicas(event, &opt.ecr_replication))
+        .collect();
+    for replica in replicas.iter() {
+        // Retry the message until ECR has copied the image over
+        ensure!(
+            drift::has_digest(replica, &mut deployer.credentials, opt)?,
+            ReplicaPending {
+                image: replica.pinned_image()
+            }
+        );
+    }
+    events.extend(replicas);
+    if !opt.pull_through_caches.is_empty() {
+        // Caches only fetch a new digest once pulled, so ask upstream
+        events = watch::resolve_digests(events, &mut deployer.credentials, opt)?;
+    }
+    let cached: Vec<events::Event> = events
+        .iter()
+        .flat_map(|event| events::cached(event, &opt.pull_through_caches))
+        .collect();
+    events.extend(cached);
+    if !recognized {
+        debug!("Skipping message {:?} because invalid type", &message.body);
+        return Ok(None);
+    }
+    Ok(Some(Parsed {
+        events,
+        deletions,
+        replay,
+    }))
+}
+
+fn process_events(
+    message: &Message,
+    parsed: Parsed,
+    services_by_image: &HashMap<String, Service<String>>,
+    deployer: &mut Deployer,
+    opt: &Opt,
+) -> Result<()> {
+    let replay = parsed.replay.as_deref();
+    for event in parsed.events {
+        process_event(event, message, replay, services_by_image, deployer, opt)?;
+    }
+    for deleted in parsed.deletions.iter() {
+        deletions::process_deletion(deleted, services_by_image, deployer, opt)?;
+    }
+    Ok(())
+}
+
+fn process_event(
+    event: events::Event,
+    message: &Message,
+    replay: Option<&str>,
+    services_by_image: &HashMap<String, Service<String>>,
+    deployer: &mut Deployer,
+    opt: &Opt,
+) -> Result<()> {
+    let mut event = plugins::rewrite(&deployer.plugins, event)?;
+    let lag = event.lead_time(Utc::now()).filter(|_| replay.is_none());
+    deployer.metrics.record_event(lag);
+    if event.image_digest.is_empty() {
+        match watch::remote_digest(&event, &mut deployer.credentials, opt)? {
+            Some(digest) => event.image_digest = digest,
+            None => {
+                warn!("Could not resolve digest for {}, skipping", &event.image());
+                return Ok(());
+            }
+        }
+    }
+    let message_id = message.message_id.clone().unwrap_or_default();
+    if replay.is_none() {
+        if let Some(previous) = deployer.dedupe.seen(&event.image(), &event.image_digest) {
+            info!(
+                "Skipping {}: digest {} already deployed by message {}",
+                &message_id, &event.image_digest, previous
+            );
+            return Ok(());
+        }
+    }
+    let services = matching_services(services_by_image, &event);
+    if services.is_empty() && deployer.containers.is_none() {
+        debug!("No service matching image {}", &event.image());
+        return Ok(());
+    }
+    let mut updated = false;
+    for service in services {
+        updated |= update_service(service, &event, message, replay, deployer, opt)?;
+    }
+    // Containers are updated whether or not a service runs the image too
+    if let Deployer {
+        containers: Some(docker),
+        rt,
+        sinks,
+        credentials,
+        ..
+    } = deployer
+    {
+        update_containers(docker, rt, sinks, credentials, &event, replay, opt)?;
+    } else if !updated {
+        return Ok(());
+    }
+    deployer
+        .dedupe
+        .record(&event.image(), &event.image_digest, &message_id);
+    Ok(())
+}
+
+/// The service running the pushed image, unless it has a policy label, and
+/// every service whose policy label accepts the pushed tag, which includes
+/// re-pushes of the tag a labelled service runs only if its policy says so.
+fn matching_services<'a>(
+    services_by_image: &'a HashMap<String, Service<String>>,
+    event: &events::Event,
+) -> Vec<&'a Service<String>> {
+    let mut services: Vec<&Service<String>> = services_by_image
+        .get(&event.image())
+        .filter(|service| !policy::is_labelled(service))
+        .into_iter()
+        .collect();
+    services.extend(policy::find(services_by_image.values(), event));
+    services
+}
+
+/// Update the service to the pushed image, unless it is pinned, the push is
+/// stale or a plugin or the service's platforms refuse it.
+fn update_service(
+    service: &Service<String>,
+    event: &events::Event,
+    message: &Message,
+    replay: Option<&str>,
+    deployer: &mut Deployer,
+    opt: &Opt,
+) -> Result<bool> {
+    let Deployer {
+        managers,
+        journal,
+        rt,
+        sinks,
+        plugins,
+        credentials,
+        failures,
+        ..
+    } = deployer;
+    if deletions::is_pinned(service) {
+        info!(
+            "Service {} is pinned after its image was deleted, not deploying {}",
+            &service.spec.name,
+            event.pinned_image()
+        );
+        return Ok(false);
+    }
+    if quarantine::is_quarantined(service) {
+        info!(
+            "Service {} is quarantined after repeated failed updates, not deploying {}",
+            &service.spec.name,
+            event.pinned_image()
+        );
+        return Ok(false);
+    }
+    if replay.is_none() && is_stale(service, event) {
+        info!(
+            "Skipping {} pushed before the image service {} runs",
+            event.pinned_image(),
+            &service.spec.name
+        );
+        return Ok(false);
+    }
+    if !plugins::accept(plugins, event, service)? {
+        return Ok(false);
+    }
+    let auth_token = auth::event_credentials(event, credentials, opt)?;
+    let compatible = platform_compatible(service, event, auth_token.as_ref());
+    if !auth::evict_refused(&event.registry_host(), compatible, credentials)? {
+        return Ok(false);
+    }
+    // Plugins may pick another image than the event's
+    let updated_spec = plugins::mutate_spec(plugins, event, service, update_spec(&service, event))?;
+    let image = spec_image(&updated_spec)
+        .cloned()
+        .unwrap_or_else(|| event.pinned_image());
+    if let Some(message_id) = &message.message_id {
+        if let Some(entry) = journal.get(message_id, &service.id) {
+            if current_image(service) == Some(&image) {
                 info!(
-                    "Updated service {} with image {}, {}",
-                    &service.id,
-                    &event.image(),
-                    &event.image_digest
+                    "Interrupted update of service {} started {} has already completed",
+                    &service.id, entry.started_at
                 );
+                return Ok(false);
+            }
+            warn!(
+                "Retrying interrupted update of service {} started {}",
+                &service.id, entry.started_at
+            );
+        }
+        journal.begin(message_id, &service.id, &image)?;
+    }
+    managers.run(|docker| {
+        let options = UpdateServiceOptions {
+            version: service.version.index,
+            ..Default::default()
+        };
+        rt.block_on(docker.update_service(
+            &service.id,
+            updated_spec.clone(),
+            options,
+            auth_token.clone(),
+        ))
+        .with_context(|| UpdatingService {
+            service_id: service.id.clone(),
+        })
+    })?;
+    let lead_time = event
+        .lead_time(Utc::now())
+        .filter(|_| replay.is_none())
+        .map(|lead_time| format!(" {}s after push", lead_time.num_seconds()))
+        .unwrap_or_default();
+    info!(
+        "Updated service {} with image {}{}",
+        &service.id, &image, lead_time
+    );
+    let outcome = match convergence::deadline_for(service, opt) {
+        Some(deadline) => {
+            if convergence::verify(managers, rt, service, &image, &deadline)? {
+                markers::Outcome::Converged
             } else {
-                debug!("No service matching image {}", &event.image());
+                markers::Outcome::Failed
             }
-        } else {
-            debug!("Skipping message {:?} because invalid type", &message.body);
         }
-    } else {
-        debug!("Encountered empty message {:?}", &message.body);
+        None => markers::Outcome::Updated,
+    };
+    let mut deployment = markers::Deployment {
+        service_id: service.id.clone(),
+        service_name: service.spec.name.clone(),
+        image: event.image(),
+        digest: event.image_digest.clone(),
+        outcome,
+        labels: service.spec.labels.clone(),
+        replay: replay.map(str::to_owned),
+    };
+    markers::record(sinks, &deployment);
+    let failed = failures.record(&service.id, outcome == markers::Outcome::Failed);
+    if opt.quarantine_after.map_or(false, |limit| failed >= limit) {
+        quarantine::quarantine(managers, rt, &service.id, &image)?;
+        failures.record(&service.id, false);
+        error!(
+            "Quarantined service {} after {} failed updates in a row",
+            &service.spec.name, failed
+        );
+        deployment.outcome = markers::Outcome::Quarantined;
+        markers::record(sinks, &deployment);
+    }
+    Ok(true)
+}
+
+fn update_containers(
+    docker: &Docker,
+    rt: &mut Runtime,
+    sinks: &[Box<dyn markers::Sink>],
+    credentials: &mut auth::Cache,
+    event: &events::Event,
+    replay: Option<&str>,
+    opt: &Opt,
+) -> Result<()> {
+    let matching = containers::matching(docker, rt, &event.image(), opt)?;
+    if matching.is_empty() {
+        debug!("No container matching image {}", &event.image());
+        return Ok(());
+    }
+    let auth_token = auth::event_credentials(event, credentials, opt)?;
+    for container in matching {
+        containers::recreate(
+            docker,
+            rt,
+            &container,
+            &event.pinned_image(),
+            auth_token.clone(),
+        )?;
+        let name = containers::container_name(&container)
+            .unwrap_or(&container.id)
+            .to_owned();
+        info!(
+            "Recreated container {} with image {}, {}",
+            &name,
+            &event.image(),
+            &event.image_digest
+        );
+        let deployment = markers::Deployment {
+            service_id: container.id.clone(),
+            service_name: name,
+            image: event.image(),
+            digest: event.image_digest.clone(),
+            outcome: markers::Outcome::Updated,
+            labels: container.labels.clone(),
+            replay: replay.map(str::to_owned),
+        };
+        markers::record(sinks, &deployment);
     }
     Ok(())
 }
 
+/// Force-update a service whose redeploy schedule has come due. ECR images
+/// are pinned to the digest their tag currently points to, other images are
+/// restarted as they are.
+fn redeploy(service: &Service<String>, deployer: &mut Deployer, opt: &Opt) -> Result<()> {
+    let Deployer {
+        managers,
+        rt,
+        sinks,
+        credentials,
+        ..
+    } = deployer;
+    let image = match extract_service_image(service) {
+        Some(image) => image,
+        None => return Ok(()),
+    };
+    let ecr_image = reference::ImageRef::parse(&image).and_then(|image| image.ecr());
+    let (pinned_image, digest, auth_token) = match ecr_image {
+        Some(ecr_image) => {
+            let digest = match drift::latest_digest(&ecr_image, credentials, opt)? {
+                Some(digest) => digest,
+                None => {
+                    warn!(
+                        "No digest found for {}, skipping scheduled redeploy of service {}",
+                        &image, &service.id
+                    );
+                    return Ok(());
+                }
+            };
+            let region = aws::ecr_region(opt, ecr_image.region.clone());
+            let auth_token =
+                ecr_tokens::credentials(credentials, opt, region, &ecr_image.account_id)?;
+            (format!("{}@{}", &image, &digest), digest, auth_token)
+        }
+        None => (
+            current_image(service)
+                .cloned()
+                .unwrap_or_else(|| image.clone()),
+            running_image(service)
+                .and_then(|image| image.digest)
+                .unwrap_or_default(),
+            None,
+        ),
+    };
+    let updated_spec = spec_with_image(service, &pinned_image);
+    managers.run(|docker| {
+        let options = UpdateServiceOptions {
+            version: service.version.index,
+            ..Default::default()
+        };
+        rt.block_on(docker.update_service(
+            &service.id,
+            updated_spec.clone(),
+            options,
+            auth_token.clone(),
+        ))
+        .with_context(|| UpdatingService {
+            service_id: service.id.clone(),
+        })
+    })?;
+    info!(
+        "Redeployed service {} with image {} on schedule",
+        &service.id, &pinned_image
+    );
+    let deployment = markers::Deployment {
+        service_id: service.id.clone(),
+        service_name: service.spec.name.clone(),
+        image,
+        digest,
+        outcome: markers::Outcome::Updated,
+        labels: service.spec.labels.clone(),
+        replay: None,
+    };
+    markers::record(sinks, &deployment);
+    Ok(())
+}
+
 fn candidate_services(docker: &Docker, rt: &mut Runtime) -> Result<Vec<Service<String>>> {
     let services = rt
         .block_on(docker.list_services::<ListServicesOptions<String>, _>(None))
@@ -209,6 +1822,31 @@ fn candidate_services(docker: &Docker, rt: &mut Runtime) -> Result<Vec<Service<S
     Ok(services)
 }
 
+/// Long-lived state used when processing messages.
+pub struct Deployer {
+    managers: managers::Managers,
+    journal: journal::Journal,
+    rt: Runtime,
+    sinks: Vec<Box<dyn markers::Sink>>,
+    plugins: Vec<Box<dyn plugins::Plugin>>,
+    containers: Option<Docker>,
+    metrics: metrics::Metrics,
+    dedupe: dedupe::Dedupe,
+    rejects: Option<sqs::Rejects>,
+    credentials: auth::Cache,
+    sns: Option<sns::Verifier>,
+    /// Reports to Step Functions tasks waiting on messages
+    states: StepFunctionsClient,
+    failures: quarantine::Failures,
+}
+
+impl Deployer {
+    fn services(&mut self) -> Result<Vec<Service<String>>> {
+        let rt = &mut self.rt;
+        self.managers.run(|docker| candidate_services(docker, rt))
+    }
+}
+
 fn build_service_index(
     services: Vec<Service<String>>,
     opt: &Opt,
@@ -224,10 +1862,178 @@ fn build_service_index(
                 .is_some(),
             None => true,
         })
-        .map(|service| (extract_service_image(&service).unwrap(), service))
+        .filter_map(|service| match extract_service_image(&service) {
+            Some(image) => Some((image, service)),
+            None => {
+                warn!(
+                    "Skipping service {} without a recognizable image",
+                    &service.spec.name
+                );
+                None
+            }
+        })
         .collect()
 }
 
+/// Update services whose tag has moved in the registry as if the push had
+/// been announced by an event.
+fn poll_registry(deployer: &mut Deployer, opt: &Opt) -> Result<()> {
+    let services_by_image = build_service_index(deployer.services()?, opt);
+    for event in watch::changed(&services_by_image, &mut deployer.credentials, opt) {
+        info!("Registry poll found {}", event.pinned_image());
+        let message = Message {
+            message_id: Some(format!("registry-poll-{}", event.pinned_image())),
+            ..Default::default()
+        };
+        // One image failing, e.g. for want of registry credentials, should
+        // not stop the others from being updated
+        if let Err(err) = process_event(event, &message, None, &services_by_image, deployer, opt) {
+            error!("{}; retrying on next registry poll", err);
+            continue;
+        }
+        deployer
+            .journal
+            .clear(message.message_id.as_ref().unwrap())?;
+    }
+    Ok(())
+}
+
+/// Check the services of updates that were interrupted by a restart. Their
+/// messages are acked or retried on redelivery like any other.
+fn verify_interrupted(deployer: &mut Deployer) -> Result<()> {
+    let Deployer {
+        managers,
+        journal,
+        rt,
+        ..
+    } = deployer;
+    for (message_id, entry) in journal.entries() {
+        let service = match convergence::fetch_service(managers, rt, &entry.service_id)? {
+            Some(service) => service,
+            None => {
+                warn!(
+                    "Service {} of interrupted update to {} from message {} no longer exists",
+                    entry.service_id, entry.image, message_id
+                );
+                continue;
+            }
+        };
+        if current_image(&service) == Some(&entry.image) {
+            info!(
+                "Interrupted update of service {} to {} from message {} completed",
+                entry.service_id, entry.image, message_id
+            );
+        } else {
+            warn!(
+                "Interrupted update of service {} to {} from message {} did not complete, will retry on redelivery",
+                entry.service_id, entry.image, message_id
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Identifies this deployer towards brokers; in a container, the hostname
+/// is the container id.
+fn instance_name() -> String {
+    std::env::var("HOSTNAME").unwrap_or_else(|_| "swarm-deployer".to_owned())
+}
+
+/// The first event source configured, falling back to SQS.
+fn event_source(opt: &Opt) -> Result<Box<dyn EventSource>> {
+    if let Some(path) = &opt.from_file {
+        return Ok(Box::new(replay::ReplaySource::open(path)?));
+    }
+    if let Some(address) = &opt.listen {
+        let token = opt.webhook_token.clone();
+        let source = webhook::WebhookSource::bind(address, token)?
+            .with_allowed_networks(&opt.webhook_allow_cidrs);
+        return Ok(Box::new(source));
+    }
+    if let Some(subscription) = &opt.pubsub_subscription {
+        return Ok(Box::new(gcp::PubSubSource::connect(subscription, opt)?));
+    }
+    if let Some(url) = &opt.nats_url {
+        let subject = opt.nats_subject.as_ref().context(MissingEventSource)?;
+        let queue_group = opt.nats_queue_group.as_deref();
+        return Ok(Box::new(nats::NatsSource::connect(
+            url,
+            subject,
+            queue_group,
+        )?));
+    }
+    if !opt.kafka_brokers.is_empty() {
+        let topic = opt.kafka_topic.as_ref().context(MissingEventSource)?;
+        return Ok(Box::new(kafka::KafkaSource::connect(
+            &opt.kafka_brokers,
+            topic,
+            &opt.kafka_group,
+        )?));
+    }
+    if let Some(url) = &opt.redis_url {
+        let stream = opt.redis_stream.as_ref().context(MissingEventSource)?;
+        let consumer = opt.redis_consumer.clone().unwrap_or_else(instance_name);
+        return Ok(Box::new(redis::RedisSource::connect(
+            url,
+            stream,
+            &opt.redis_group,
+            &consumer,
+        )?));
+    }
+    if let Some(broker) = &opt.mqtt_broker {
+        let topic = opt.mqtt_topic.as_ref().context(MissingEventSource)?;
+        let client_id = opt.mqtt_client_id.clone().unwrap_or_else(instance_name);
+        return Ok(Box::new(mqtt::MqttSource::connect(
+            broker, topic, &client_id,
+        )?));
+    }
+    if let (true, Some(interval)) = (opt.queue_names.is_empty(), opt.poll_registry_seconds) {
+        return Ok(Box::new(watch::NoEvents {
+            wait: Duration::from_secs(interval.min(20)),
+        }));
+    }
+    ensure!(!opt.queue_names.is_empty(), MissingEventSource);
+    if let Some(subscription_arn) = &opt.sns_subscription_arn {
+        sns::apply_filter_policy(subscription_arn, &opt.attribute_filters, opt)?;
+    }
+    let source = sqs::SqsSource::connect(
+        sqs_client(opt, opt.sqs_region.clone().unwrap_or_default())?,
+        &opt.queue_names,
+        opt.max_messages,
+        opt.wait_seconds,
+        &opt.attribute_filters,
+    )?;
+    let source = match &opt.priority_queue {
+        Some(priority_queue) => source.with_priority_queue(priority_queue)?,
+        None => source,
+    };
+    if opt.failover_queues.is_empty() {
+        return Ok(Box::new(source));
+    }
+    let mut sources = vec![source];
+    for failover_queue in opt.failover_queues.iter() {
+        sources.push(sqs::SqsSource::connect(
+            sqs_client(opt, failover_queue.region.clone())?,
+            &[failover_queue.queue_name.clone()],
+            opt.max_messages,
+            opt.wait_seconds,
+            &opt.attribute_filters,
+        )?);
+    }
+    Ok(Box::new(sqs::SqsFailover::new(sources)))
+}
+
+/// Client for the account of the queue, where Step Functions task tokens in
+/// messages also come from.
+fn sqs_client<C: aws::FromProvider>(opt: &Opt, region: Region) -> Result<C> {
+    match &opt.sqs_role_arn {
+        Some(role_arn) => {
+            aws::assumed_role_client(opt, role_arn, opt.sqs_external_id.clone(), region)
+        }
+        None => aws::client(opt, region),
+    }
+}
+
 fn main() -> Result<()> {
     let opt = Opt::from_args();
     stderrlog::new()
@@ -237,18 +2043,146 @@ fn main() -> Result<()> {
         .init()
         .unwrap();
 
+    info!("{}", build_info::banner());
     let mut rt = Runtime::new().unwrap();
-    let docker = Docker::connect_with_local_defaults().with_context(|| DockerInstantiation)?;
-    let sqs = SqsClient::new(Region::default());
-    warn!("Listening for ECR events on {}", &opt.queue_name);
+    let mut managers = managers::Managers::connect(&opt.managers)?;
+    if let Some(Command::VerifyCredentials) = opt.command {
+        return verify::run(&mut managers, &mut rt, &opt);
+    }
+    let journal = journal::Journal::open(opt.journal.as_deref())?;
+    let sinks = markers::sinks_from_opt(&opt)?;
+    let plugins = plugins::load(&opt)?;
+    let containers = if opt.containers {
+        Some(Docker::connect_with_local_defaults().with_context(|| DockerInstantiation)?)
+    } else {
+        None
+    };
+    let rejects = match &opt.rejects_queue {
+        Some(queue_name) => Some(sqs::Rejects::new(
+            sqs_client(&opt, opt.sqs_region.clone().unwrap_or_default())?,
+            queue_name,
+        )),
+        None => None,
+    };
+    let sns = if opt.verify_sns_signatures {
+        Some(sns::Verifier::new()?)
+    } else {
+        None
+    };
+    let mut deployer = Deployer {
+        managers,
+        journal,
+        rt,
+        sinks,
+        plugins,
+        containers,
+        metrics: metrics::Metrics::default(),
+        dedupe: dedupe::Dedupe::new(opt.dedupe_capacity),
+        rejects,
+        credentials: auth::Cache::default(),
+        sns,
+        states: sqs_client(&opt, opt.sqs_region.clone().unwrap_or_default())?,
+        failures: quarantine::Failures::new(),
+    };
+    verify_interrupted(&mut deployer)?;
+    if opt.scan_gate.is_some() {
+        let services_by_image = build_service_index(deployer.services()?, &opt);
+        let unscanned =
+            scan::unscanned_repositories(&services_by_image, &mut deployer.credentials, &opt)?;
+        ensure!(
+            unscanned.is_empty(),
+            ScanOnPushDisabled {
+                repositories: unscanned.join(", ")
+            }
+        );
+    }
+    if let Some(Command::Lambda) = opt.command {
+        return lambda::run(deployer, opt);
+    }
+    let mut source = event_source(&opt)?;
+    warn!("Listening for events on {}", source.name());
+    if let Some(address) = &opt.metrics_listen {
+        deployer.metrics.serve(address)?;
+        info!("Serving metrics on {}", address);
+    }
+    let mut last_depth_check: Option<Instant> = None;
+    let mut last_drift_report: Option<Instant> = None;
+    let mut empty_polls = 0;
+    let mut redeploys = schedule::Redeploys::new();
+    let mut last_registry_poll: Option<Instant> = None;
     loop {
-        let messages = sqs::poll_messages(&sqs, &opt)?;
-        // TODO: Messages may be empty
-        let services = candidate_services(&docker, &mut rt)?;
-        let services_by_image = build_service_index(services, &opt);
-        for message in messages.iter() {
-            process_one(message, &services_by_image, &docker, &mut rt)?;
-            sqs::delete_message(&sqs, &message, &opt)?;
+        let retention = chrono::Duration::hours(opt.journal_retention_hours);
+        for (message_id, entry) in deployer.journal.sweep(retention)? {
+            warn!(
+                "Dropping journal entry for message {} updating service {} to {}, started {}",
+                message_id, entry.service_id, entry.image, entry.started_at
+            );
+        }
+        if opt.drift_report_hours > 0 && drift::due(last_drift_report, opt.drift_report_hours) {
+            let services = deployer.services()?;
+            let services_by_image = build_service_index(services, &opt);
+            let report = drift::report(&services_by_image, &mut deployer.credentials, &opt);
+            markers::report(&deployer.sinks, &report);
+            last_drift_report = Some(Instant::now());
+        }
+        if let Some(remaining) = opt
+            .quiet_hours
+            .and_then(|quiet_hours| quiet_hours.remaining(Utc::now()))
+        {
+            warn!(
+                "Quiet hours, suspending polling for {}s",
+                remaining.as_secs()
+            );
+            std::thread::sleep(remaining);
+            continue;
+        }
+        if opt.scheduled_redeploys && redeploys.pending(Utc::now()) {
+            let services_by_image = build_service_index(deployer.services()?, &opt);
+            for service in redeploys.due(services_by_image.values(), Utc::now()) {
+                match redeploy(service, &mut deployer, &opt) {
+                    Err(err @ SeedyError::UpdatingService { .. }) => {
+                        error!("{}; scheduled redeploy skipped", err)
+                    }
+                    result => result?,
+                }
+            }
+        }
+        if let Some(interval) = opt.poll_registry_seconds.map(Duration::from_secs) {
+            if watch::due(last_registry_poll, interval) {
+                poll_registry(&mut deployer, &opt)?;
+                last_registry_poll = Some(Instant::now());
+            }
+        }
+        if opt.metrics_listen.is_some() {
+            let interval = Duration::from_secs(opt.queue_metrics_seconds);
+            if watch::due(last_depth_check, interval) {
+                match source.depth() {
+                    Ok(depths) => {
+                        for (queue, visible, in_flight) in depths {
+                            deployer.metrics.set_queue_depth(&queue, visible, in_flight);
+                        }
+                    }
+                    Err(err) => warn!("Could not check queue depth: {}", err),
+                }
+                last_depth_check = Some(Instant::now());
+            }
+        }
+        if source.exhausted() {
+            warn!("No more events from {}", source.name());
+            return Ok(());
+        }
+        let messages = source.poll()?;
+        if messages.is_empty() {
+            empty_polls += 1;
+            let delay =
+                polling::idle_delay(empty_polls, opt.idle_polls, opt.max_idle_delay_seconds);
+            if delay.as_secs() > 0 {
+                debug!("Queue idle for {} polls, pausing {:?}", empty_polls, delay);
+                std::thread::sleep(delay);
+            }
+            continue;
         }
+        empty_polls = 0;
+        source::dispatch(source.as_mut(), &messages, &mut deployer, &opt)?;
     }
 }
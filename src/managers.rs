@@ -0,0 +1,130 @@
+#[cfg(not(feature = "tls"))]
+use crate::FeatureDisabled;
+use crate::{DockerInstantiation, Result, SeedyError};
+use bollard::errors::ErrorKind;
+use bollard::{Docker, API_DEFAULT_VERSION};
+use log::warn;
+use snafu::ResultExt;
+#[cfg(feature = "tls")]
+use std::env;
+#[cfg(feature = "tls")]
+use std::path::PathBuf;
+
+const DOCKER_TIMEOUT: u64 = 120;
+
+/// A list of swarm manager endpoints where only one is in use at any one
+/// time. When the current manager cannot be reached, the next one is tried.
+pub struct Managers {
+    endpoints: Vec<String>,
+    clients: Vec<Docker>,
+    current: usize,
+}
+
+fn connect(endpoint: &str) -> Result<Docker> {
+    if endpoint.starts_with("https://") {
+        return connect_tls(endpoint);
+    }
+    if endpoint.starts_with("unix://") {
+        Docker::connect_with_unix(
+            endpoint.trim_start_matches("unix://"),
+            DOCKER_TIMEOUT,
+            API_DEFAULT_VERSION,
+        )
+    } else {
+        Docker::connect_with_http(endpoint, DOCKER_TIMEOUT, API_DEFAULT_VERSION)
+    }
+    .with_context(|| DockerInstantiation)
+}
+
+/// Client certificates are read from $DOCKER_CERT_PATH as key.pem, cert.pem
+/// and ca.pem, like the Docker CLI does.
+#[cfg(feature = "tls")]
+fn connect_tls(endpoint: &str) -> Result<Docker> {
+    let cert_path = env::var("DOCKER_CERT_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| dirs::home_dir().unwrap_or_default().join(".docker"));
+    Docker::connect_with_ssl(
+        endpoint,
+        &cert_path.join("key.pem"),
+        &cert_path.join("cert.pem"),
+        &cert_path.join("ca.pem"),
+        DOCKER_TIMEOUT,
+        API_DEFAULT_VERSION,
+    )
+    .with_context(|| DockerInstantiation)
+}
+
+#[cfg(not(feature = "tls"))]
+fn connect_tls(_endpoint: &str) -> Result<Docker> {
+    FeatureDisabled {
+        feature: "tls",
+        option: "--manager https://",
+    }
+    .fail()
+}
+
+/// Whether the manager could not be reached or cannot act for the swarm,
+/// as opposed to refusing the request, which any other manager would too.
+fn is_connection_error(err: &SeedyError) -> bool {
+    let source = match err {
+        SeedyError::ServiceListing { source } | SeedyError::UpdatingService { source, .. } => {
+            source
+        }
+        _ => return false,
+    };
+    match source.kind() {
+        ErrorKind::HyperResponseError { .. }
+        | ErrorKind::IOError { .. }
+        | ErrorKind::RequestTimeoutError => true,
+        // A manager that has lost quorum answers 503
+        ErrorKind::DockerResponseServerError { status_code, .. } => *status_code == 503,
+        _ => false,
+    }
+}
+
+impl Managers {
+    pub fn connect(endpoints: &[String]) -> Result<Managers> {
+        if endpoints.is_empty() {
+            let docker =
+                Docker::connect_with_local_defaults().with_context(|| DockerInstantiation)?;
+            return Ok(Managers {
+                endpoints: vec!["local".to_owned()],
+                clients: vec![docker],
+                current: 0,
+            });
+        }
+        let clients = endpoints
+            .iter()
+            .map(|endpoint| connect(endpoint))
+            .collect::<Result<Vec<Docker>>>()?;
+        Ok(Managers {
+            endpoints: endpoints.to_vec(),
+            clients,
+            current: 0,
+        })
+    }
+
+    /// Run a Docker operation against the current manager, moving on to the
+    /// next manager if it cannot be reached. Errors from a manager that did
+    /// answer are returned as is. Gives up when every manager has been tried.
+    pub fn run<T, F>(&mut self, mut operation: F) -> Result<T>
+    where
+        F: FnMut(&Docker) -> Result<T>,
+    {
+        let mut attempts = 1;
+        loop {
+            match operation(&self.clients[self.current]) {
+                Err(err) if is_connection_error(&err) && attempts < self.clients.len() => {
+                    let failed = self.current;
+                    self.current = (self.current + 1) % self.clients.len();
+                    attempts += 1;
+                    warn!(
+                        "Manager {} failed: {}; failing over to {}",
+                        self.endpoints[failed], err, self.endpoints[self.current]
+                    );
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::events::Event;
+use crate::{FieldMappingExpression, FieldMappingFormat, Result};
+use jmespath::{Expression, Rcvar, Variable};
+
+/// Event fields that can be mapped from a payload.
+pub const FIELDS: &[&str] = &[
+    "account",
+    "region",
+    "registry",
+    "repository",
+    "tag",
+    "digest",
+    "pushed-at",
+];
+
+/// A field mapping, compiled up front so that a bad expression is refused
+/// at startup rather than on every message.
+pub type FieldMapping = (String, Expression<'static>);
+
+pub fn parse_field_mapping(input: &str) -> Result<FieldMapping> {
+    let parts: Vec<&str> = input.splitn(2, '=').collect();
+    match parts.as_slice() {
+        [field, expression] if FIELDS.contains(field) && !expression.is_empty() => {
+            match jmespath::compile(expression) {
+                Ok(expression) => Ok(((*field).to_owned(), expression)),
+                Err(err) => FieldMappingExpression {
+                    field: *field,
+                    message: err.to_string(),
+                }
+                .fail(),
+            }
+        }
+        _ => FieldMappingFormat { value: input }.fail(),
+    }
+}
+
+fn search(data: &Rcvar, field: &str, mappings: &[FieldMapping]) -> Result<Option<String>> {
+    let expression = match mappings.iter().find(|(name, _)| name == field) {
+        Some((_, expression)) => expression,
+        None => return Ok(None),
+    };
+    let result = match expression.search(data.clone()) {
+        Ok(result) => result,
+        Err(err) => {
+            return FieldMappingExpression {
+                field,
+                message: err.to_string(),
+            }
+            .fail()
+        }
+    };
+    Ok(result
+        .as_string()
+        .cloned()
+        .or_else(|| result.as_number().map(|number| format!("{:.0}", number)))
+        .filter(|value| !value.is_empty()))
+}
+
+/// Build an event from an arbitrary JSON payload, such as one produced by a
+/// custom relay. An image needs a repository, a tag and either a registry
+/// or an ECR account and region. Without a digest, it is resolved from the
+/// registry.
+pub fn mapped_event(event_str: &str, mappings: &[FieldMapping]) -> Result<Option<Event>> {
+    let data: Rcvar = match Variable::from_json(event_str) {
+        Ok(data) => Rcvar::new(data),
+        Err(_) => return Ok(None),
+    };
+    let field = |name: &str| search(&data, name, mappings);
+    let (repository_name, image_tag) = match (field("repository")?, field("tag")?) {
+        (Some(repository_name), Some(image_tag)) => (repository_name, image_tag),
+        _ => return Ok(None),
+    };
+    let registry = field("registry")?;
+    let account_id = field("account")?.unwrap_or_default();
+    let region = field("region")?.unwrap_or_default();
+    if registry.is_none() && (account_id.is_empty() || region.is_empty()) {
+        return Ok(None);
+    }
+    Ok(Some(Event {
+        account_id,
+        region,
+        repository_name,
+        image_digest: field("digest")?.unwrap_or_default(),
+        image_tag,
+        pushed_at: field("pushed-at")?.and_then(|time| time.parse().ok()),
+        registry,
+    }))
+}
@@ -0,0 +1,434 @@
+use crate::{
+    aws, CreatingLogStream, Opt, PuttingLogEvents, RecordingDeployment, RejectedDeployment, Result,
+};
+use chrono::Utc;
+use log::warn;
+use reqwest::blocking::Client;
+use rusoto_core::{Region, RusotoError};
+use rusoto_logs::{
+    CloudWatchLogs, CloudWatchLogsClient, CreateLogStreamError, CreateLogStreamRequest,
+    InputLogEvent, PutLogEventsRequest,
+};
+use serde_json::{json, Value};
+use snafu::{ensure, ResultExt};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+pub const NEW_RELIC_ENTITY_LABEL: &str = "seedy.newrelic-entity-guid";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Updated,
+    Converged,
+    Failed,
+    /// Updates failed repeatedly and the deployer stopped updating the service
+    Quarantined,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Updated => "updated",
+            Outcome::Converged => "converged",
+            Outcome::Failed => "failed",
+            Outcome::Quarantined => "quarantined",
+        }
+    }
+}
+
+pub struct Deployment {
+    pub service_id: String,
+    pub service_name: String,
+    pub image: String,
+    pub digest: String,
+    pub outcome: Outcome,
+    pub labels: HashMap<String, String>,
+    /// Name of the EventBridge archive replay the triggering event came from
+    pub replay: Option<String>,
+}
+
+impl Deployment {
+    pub fn to_json(&self, environment: Option<&String>) -> Value {
+        json!({
+            "service_id": self.service_id,
+            "service_name": self.service_name,
+            "image": self.image,
+            "digest": self.digest,
+            "outcome": self.outcome.as_str(),
+            "environment": environment,
+            "replay": self.replay,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let replay = self
+            .replay
+            .as_ref()
+            .map(|replay| format!(" (replay {})", replay))
+            .unwrap_or_default();
+        format!(
+            "Service {} ({}) {} with {}@{}{}",
+            self.service_name,
+            self.service_id,
+            self.outcome.as_str(),
+            self.image,
+            self.digest,
+            replay
+        )
+    }
+}
+
+/// A periodic report on the swarm, such as which services have fallen
+/// behind their tag.
+pub struct Report {
+    pub title: String,
+    pub summary: String,
+    /// One line per finding; a report without findings is good news
+    pub findings: Vec<String>,
+}
+
+impl Report {
+    pub fn to_json(&self, environment: Option<&String>) -> Value {
+        json!({
+            "report": self.title,
+            "summary": self.summary,
+            "findings": self.findings,
+            "environment": environment,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = self.summary.clone();
+        for finding in self.findings.iter() {
+            text.push_str("\n");
+            text.push_str(finding);
+        }
+        text
+    }
+}
+
+/// A destination for deployment markers, typically an observability tool
+/// which can show deployments alongside metrics.
+pub trait Sink {
+    fn name(&self) -> &str;
+    fn record(&self, deployment: &Deployment) -> Result<()>;
+    /// Sinks that only know of deployments ignore reports.
+    fn report(&self, _report: &Report) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Grafana {
+    client: Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl Grafana {
+    fn annotate(&self, annotation: &Value) -> Result<()> {
+        let mut request = self
+            .client
+            .post(&format!(
+                "{}/api/annotations",
+                self.url.trim_end_matches('/')
+            ))
+            .json(annotation);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| RecordingDeployment { sink: self.name() })?;
+        Ok(())
+    }
+}
+
+impl Sink for Grafana {
+    fn name(&self) -> &str {
+        "grafana"
+    }
+
+    fn record(&self, deployment: &Deployment) -> Result<()> {
+        self.annotate(&json!({
+            "time": Utc::now().timestamp_millis(),
+            "tags": ["deployment", deployment.service_name, deployment.outcome.as_str()],
+            "text": deployment.describe(),
+        }))
+    }
+
+    fn report(&self, report: &Report) -> Result<()> {
+        self.annotate(&json!({
+            "time": Utc::now().timestamp_millis(),
+            "tags": ["report", report.title],
+            "text": report.describe(),
+        }))
+    }
+}
+
+pub struct Datadog {
+    client: Client,
+    site: String,
+    api_key: String,
+    environment: Option<String>,
+}
+
+impl Datadog {
+    fn post_event(&self, event: &Value) -> Result<()> {
+        self.client
+            .post(&format!("https://api.{}/api/v1/events", self.site))
+            .header("DD-API-KEY", self.api_key.as_str())
+            .json(event)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| RecordingDeployment { sink: self.name() })?;
+        Ok(())
+    }
+
+    pub fn tags(&self, deployment: &Deployment) -> Vec<String> {
+        let mut tags = vec![
+            format!("service:{}", deployment.service_name),
+            format!("outcome:{}", deployment.outcome.as_str()),
+        ];
+        if let Some(environment) = &self.environment {
+            tags.push(format!("env:{}", environment));
+        }
+        tags
+    }
+}
+
+impl Sink for Datadog {
+    fn name(&self) -> &str {
+        "datadog"
+    }
+
+    fn record(&self, deployment: &Deployment) -> Result<()> {
+        let alert_type = match deployment.outcome {
+            Outcome::Failed | Outcome::Quarantined => "error",
+            _ => "info",
+        };
+        let event = json!({
+            "title": format!("Deployment of {}", deployment.service_name),
+            "text": deployment.describe(),
+            "tags": self.tags(deployment),
+            "alert_type": alert_type,
+            "source_type_name": "swarm-ecr-deployer",
+        });
+        self.post_event(&event)
+    }
+
+    fn report(&self, report: &Report) -> Result<()> {
+        let mut tags = vec![format!("report:{}", report.title)];
+        if let Some(environment) = &self.environment {
+            tags.push(format!("env:{}", environment));
+        }
+        let alert_type = if report.findings.is_empty() {
+            "info"
+        } else {
+            "warning"
+        };
+        let event = json!({
+            "title": report.title,
+            "text": report.describe(),
+            "tags": tags,
+            "alert_type": alert_type,
+            "source_type_name": "swarm-ecr-deployer",
+        });
+        self.post_event(&event)
+    }
+}
+
+const NEW_RELIC_MUTATION: &str = "mutation($deployment: ChangeTrackingDeploymentInput!) { \
+    changeTrackingCreateDeployment(deployment: $deployment) { deploymentId } }";
+
+pub struct NewRelic {
+    client: Client,
+    endpoint: String,
+    api_key: String,
+    entity_guid: Option<String>,
+    environment: Option<String>,
+}
+
+impl Sink for NewRelic {
+    fn name(&self) -> &str {
+        "newrelic"
+    }
+
+    /// Services are mapped to New Relic entities by label, falling back on
+    /// the deployer-wide entity. Services without an entity are skipped.
+    fn record(&self, deployment: &Deployment) -> Result<()> {
+        let entity_guid = match deployment
+            .labels
+            .get(NEW_RELIC_ENTITY_LABEL)
+            .or_else(|| self.entity_guid.as_ref())
+        {
+            Some(entity_guid) => entity_guid,
+            None => return Ok(()),
+        };
+        let description = match &self.environment {
+            Some(environment) => format!("{} in {}", deployment.describe(), environment),
+            None => deployment.describe(),
+        };
+        let request = json!({
+            "query": NEW_RELIC_MUTATION,
+            "variables": {
+                "deployment": {
+                    "entityGuid": entity_guid,
+                    "version": deployment.digest,
+                    "description": description,
+                    "user": "swarm-ecr-deployer",
+                }
+            }
+        });
+        let response: Value = self
+            .client
+            .post(&self.endpoint)
+            .header("API-Key", self.api_key.as_str())
+            .json(&request)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .with_context(|| RecordingDeployment { sink: self.name() })?;
+        let errors = response.get("errors").filter(|errors| !errors.is_null());
+        ensure!(
+            errors.is_none(),
+            RejectedDeployment {
+                sink: self.name(),
+                message: errors.map(|e| e.to_string()).unwrap_or_default(),
+            }
+        );
+        Ok(())
+    }
+}
+
+struct LogStreamState {
+    created: bool,
+    sequence_token: Option<String>,
+}
+
+/// Writes one JSON record per deployment to a CloudWatch Logs stream named
+/// after the host, creating the stream on first use.
+pub struct CloudWatchLogsSink {
+    client: CloudWatchLogsClient,
+    log_group: String,
+    log_stream: String,
+    environment: Option<String>,
+    state: Mutex<LogStreamState>,
+}
+
+impl CloudWatchLogsSink {
+    fn create_stream(&self) -> Result<()> {
+        let req = CreateLogStreamRequest {
+            log_group_name: self.log_group.clone(),
+            log_stream_name: self.log_stream.clone(),
+        };
+        match self.client.create_log_stream(req).sync() {
+            Ok(()) => Ok(()),
+            Err(RusotoError::Service(CreateLogStreamError::ResourceAlreadyExists(_))) => Ok(()),
+            Err(err) => Err(err).with_context(|| CreatingLogStream {
+                log_group: self.log_group.clone(),
+            }),
+        }
+    }
+
+    fn put(&self, record: Value) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if !state.created {
+            self.create_stream()?;
+            state.created = true;
+        }
+        let req = PutLogEventsRequest {
+            log_group_name: self.log_group.clone(),
+            log_stream_name: self.log_stream.clone(),
+            log_events: vec![InputLogEvent {
+                message: record.to_string(),
+                timestamp: Utc::now().timestamp_millis(),
+            }],
+            sequence_token: state.sequence_token.take(),
+        };
+        let response =
+            self.client
+                .put_log_events(req)
+                .sync()
+                .with_context(|| PuttingLogEvents {
+                    log_group: self.log_group.clone(),
+                })?;
+        state.sequence_token = response.next_sequence_token;
+        Ok(())
+    }
+}
+
+impl Sink for CloudWatchLogsSink {
+    fn name(&self) -> &str {
+        "cloudwatch-logs"
+    }
+
+    fn record(&self, deployment: &Deployment) -> Result<()> {
+        self.put(deployment.to_json(self.environment.as_ref()))
+    }
+
+    fn report(&self, report: &Report) -> Result<()> {
+        self.put(report.to_json(self.environment.as_ref()))
+    }
+}
+
+pub fn sinks_from_opt(opt: &Opt) -> Result<Vec<Box<dyn Sink>>> {
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if let Some(url) = &opt.grafana_url {
+        sinks.push(Box::new(Grafana {
+            client: Client::new(),
+            url: url.clone(),
+            token: opt.grafana_token.clone(),
+        }));
+    }
+    if let Some(api_key) = &opt.datadog_api_key {
+        sinks.push(Box::new(Datadog {
+            client: Client::new(),
+            site: opt.datadog_site.clone(),
+            api_key: api_key.clone(),
+            environment: opt.environment.clone(),
+        }));
+    }
+    if let Some(api_key) = &opt.newrelic_api_key {
+        sinks.push(Box::new(NewRelic {
+            client: Client::new(),
+            endpoint: opt.newrelic_endpoint.clone(),
+            api_key: api_key.clone(),
+            entity_guid: opt.newrelic_entity_guid.clone(),
+            environment: opt.environment.clone(),
+        }));
+    }
+    if let Some(log_group) = &opt.cloudwatch_log_group {
+        let log_stream =
+            std::env::var("HOSTNAME").unwrap_or_else(|_| "swarm-ecr-deployer".to_owned());
+        sinks.push(Box::new(CloudWatchLogsSink {
+            client: aws::client(opt, Region::default())?,
+            log_group: log_group.clone(),
+            log_stream,
+            environment: opt.environment.clone(),
+            state: Mutex::new(LogStreamState {
+                created: false,
+                sequence_token: None,
+            }),
+        }));
+    }
+    Ok(sinks)
+}
+
+/// Reports are informational, so failing to send one is only logged.
+pub fn report(sinks: &[Box<dyn Sink>], report: &Report) {
+    for sink in sinks {
+        if let Err(err) = sink.report(report) {
+            warn!("{}", err);
+        }
+    }
+}
+
+/// Failing to record a marker should never fail the deployment itself.
+pub fn record(sinks: &[Box<dyn Sink>], deployment: &Deployment) {
+    for sink in sinks {
+        if let Err(err) = sink.record(deployment) {
+            warn!("{}", err);
+        }
+    }
+}
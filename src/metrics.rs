@@ -0,0 +1,124 @@
+use crate::{build_info, ListenerSetup, Result};
+use log::{debug, warn};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Response, Server};
+
+#[derive(Default)]
+struct Values {
+    /// Visible and in-flight messages per queue
+    queue_depth: BTreeMap<String, (u64, u64)>,
+    last_lag_seconds: Option<i64>,
+    events: u64,
+    /// Unrecognized messages per policy outcome
+    unrecognized: BTreeMap<&'static str, u64>,
+}
+
+/// Gauges on how far behind registry pushes the deployer is, served in
+/// Prometheus text format.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    values: Arc<Mutex<Values>>,
+}
+
+impl Metrics {
+    pub fn set_queue_depth(&self, queue: &str, visible: u64, in_flight: u64) {
+        debug!(
+            "Queue {} holds {} messages, {} in flight",
+            queue, visible, in_flight
+        );
+        let mut values = self.values.lock().unwrap();
+        values
+            .queue_depth
+            .insert(queue.to_owned(), (visible, in_flight));
+    }
+
+    /// Count an event, with the time from push to the deployer picking it
+    /// up where that is known.
+    pub fn record_event(&self, lag: Option<chrono::Duration>) {
+        let mut values = self.values.lock().unwrap();
+        if let Some(lag) = lag {
+            values.last_lag_seconds = Some(lag.num_seconds());
+        }
+        values.events += 1;
+    }
+
+    pub fn record_unrecognized(&self, outcome: &'static str) {
+        let mut values = self.values.lock().unwrap();
+        *values.unrecognized.entry(outcome).or_insert(0) += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let values = self.values.lock().unwrap();
+        let mut out = String::new();
+        out.push_str("# TYPE seedy_build_info gauge\n");
+        let _ = writeln!(
+            out,
+            "seedy_build_info{{version=\"{}\",git_sha=\"{}\",features=\"{}\"}} 1",
+            build_info::VERSION,
+            build_info::GIT_SHA,
+            build_info::FEATURES
+        );
+        out.push_str("# TYPE seedy_queue_messages gauge\n");
+        for (queue, (visible, _)) in values.queue_depth.iter() {
+            let _ = writeln!(
+                out,
+                "seedy_queue_messages{{queue=\"{}\"}} {}",
+                queue, visible
+            );
+        }
+        out.push_str("# TYPE seedy_queue_messages_in_flight gauge\n");
+        for (queue, (_, in_flight)) in values.queue_depth.iter() {
+            let _ = writeln!(
+                out,
+                "seedy_queue_messages_in_flight{{queue=\"{}\"}} {}",
+                queue, in_flight
+            );
+        }
+        if let Some(lag) = values.last_lag_seconds {
+            out.push_str("# TYPE seedy_event_lag_seconds gauge\n");
+            let _ = writeln!(out, "seedy_event_lag_seconds {}", lag);
+        }
+        out.push_str("# TYPE seedy_events_total counter\n");
+        let _ = writeln!(out, "seedy_events_total {}", values.events);
+        out.push_str("# TYPE seedy_unrecognized_messages_total counter\n");
+        for (outcome, count) in values.unrecognized.iter() {
+            let _ = writeln!(
+                out,
+                "seedy_unrecognized_messages_total{{outcome=\"{}\"}} {}",
+                outcome, count
+            );
+        }
+        out
+    }
+
+    /// Serve /metrics on the address from a background thread.
+    pub fn serve(&self, address: &str) -> Result<()> {
+        let server = match Server::http(address) {
+            Ok(server) => server,
+            Err(err) => {
+                return ListenerSetup {
+                    address,
+                    message: err.to_string(),
+                }
+                .fail()
+            }
+        };
+        let metrics = self.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = if request.url() == "/metrics" {
+                    Response::from_string(metrics.render())
+                } else {
+                    Response::from_string("").with_status_code(404)
+                };
+                if let Err(err) = request.respond(response) {
+                    warn!("Failed to serve metrics: {}", err);
+                }
+            }
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use crate::source::{Backlog, EventSource};
+use crate::{ListenerSetup, MqttReceive, Result};
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use log::{info, warn};
+use rumqtt::{MqttClient, MqttOptions, Notification, QoS};
+use rusoto_sqs::Message;
+use std::time::Duration;
+
+const POLL_WAIT: Duration = Duration::from_secs(20);
+const DEFAULT_PORT: u16 = 1883;
+
+/// Split host[:port], defaulting to the standard MQTT port.
+pub fn broker_address(broker: &str) -> Option<(String, u16)> {
+    match broker.rfind(':') {
+        Some(colon_pos) => Some((
+            broker[..colon_pos].to_owned(),
+            broker[colon_pos + 1..].parse().ok()?,
+        )),
+        None => Some((broker.to_owned(), DEFAULT_PORT)),
+    }
+}
+
+/// Subscribes to an MQTT topic with QoS 1. The session is persistent so
+/// the broker holds on to events published while the deployer is away.
+/// The client acknowledges deliveries on receipt, so nacked messages are
+/// retried from an in-memory backlog.
+pub struct MqttSource {
+    // Kept alive to hold the connection open
+    _client: MqttClient,
+    notifications: Receiver<Notification>,
+    topic: String,
+    prefix: String,
+    received: u64,
+    backlog: Backlog,
+}
+
+impl MqttSource {
+    pub fn connect(broker: &str, topic: &str, client_id: &str) -> Result<MqttSource> {
+        let setup_failed = |message: String| ListenerSetup {
+            address: broker.to_owned(),
+            message,
+        };
+        let (host, port) = match broker_address(broker) {
+            Some(address) => address,
+            None => return setup_failed("not a host[:port]".to_owned()).fail(),
+        };
+        let options = MqttOptions::new(client_id, host, port)
+            .set_clean_session(false)
+            .set_keep_alive(30);
+        let (mut client, notifications) = match MqttClient::start(options) {
+            Ok(started) => started,
+            Err(err) => return setup_failed(format!("{:?}", err)).fail(),
+        };
+        if let Err(err) = client.subscribe(topic, QoS::AtLeastOnce) {
+            return setup_failed(format!("{:?}", err)).fail();
+        }
+        Ok(MqttSource {
+            _client: client,
+            notifications,
+            topic: topic.to_owned(),
+            prefix: chrono::Utc::now().timestamp_millis().to_string(),
+            received: 0,
+            backlog: Backlog::new(),
+        })
+    }
+
+    fn to_message(&mut self, notification: Notification) -> Option<Message> {
+        let publish = match notification {
+            Notification::Publish(publish) => publish,
+            Notification::Reconnection => {
+                info!("Reconnected to MQTT broker");
+                return None;
+            }
+            Notification::Disconnection => {
+                warn!("Disconnected from MQTT broker, reconnecting");
+                return None;
+            }
+            _ => return None,
+        };
+        let body = match String::from_utf8(publish.payload.to_vec()) {
+            Ok(body) => body,
+            Err(err) => {
+                warn!(
+                    "Ignoring non-UTF-8 message on {}: {}",
+                    &publish.topic_name, err
+                );
+                return None;
+            }
+        };
+        self.received += 1;
+        Some(Message {
+            message_id: Some(format!("{}-{}", self.prefix, self.received)),
+            body: Some(body),
+            ..Default::default()
+        })
+    }
+}
+
+impl EventSource for MqttSource {
+    fn name(&self) -> String {
+        format!("MQTT topic {}", &self.topic)
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        let mut messages = self.backlog.take_due();
+        if messages.is_empty() {
+            match self
+                .notifications
+                .recv_timeout(self.backlog.wait(POLL_WAIT))
+            {
+                Ok(notification) => messages.extend(self.to_message(notification)),
+                Err(RecvTimeoutError::Timeout) => return Ok(messages),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return MqttReceive {
+                        topic: self.topic.clone(),
+                    }
+                    .fail()
+                }
+            }
+        }
+        while let Ok(notification) = self.notifications.try_recv() {
+            messages.extend(self.to_message(notification));
+        }
+        Ok(messages)
+    }
+
+    fn ack(&mut self, _message: &Message) -> Result<()> {
+        Ok(())
+    }
+
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()> {
+        self.backlog.delay(message, delay_seconds);
+        Ok(())
+    }
+
+    fn receive_count(&self, message: &Message) -> u32 {
+        crate::sqs::receive_count(message)
+    }
+}
@@ -0,0 +1,96 @@
+use crate::source::{Backlog, EventSource};
+use crate::{NatsConnect, NatsReceive, Result};
+use log::warn;
+use rusoto_sqs::Message;
+use snafu::ResultExt;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+const POLL_WAIT: Duration = Duration::from_secs(20);
+
+/// Receives events published to a NATS subject. Core NATS does not
+/// redeliver, so nacked messages are retried from an in-memory backlog.
+pub struct NatsSource {
+    subscription: ::nats::Subscription,
+    subject: String,
+    prefix: String,
+    received: u64,
+    backlog: Backlog,
+}
+
+impl NatsSource {
+    /// With a queue group, each event is delivered to only one of the
+    /// deployers subscribing in that group.
+    pub fn connect(url: &str, subject: &str, queue_group: Option<&str>) -> Result<NatsSource> {
+        let connection = ::nats::connect(url).with_context(|| NatsConnect { url })?;
+        let subscription = match queue_group {
+            Some(queue_group) => connection.queue_subscribe(subject, queue_group),
+            None => connection.subscribe(subject),
+        }
+        .with_context(|| NatsConnect { url })?;
+        Ok(NatsSource {
+            subscription,
+            subject: subject.to_owned(),
+            prefix: chrono::Utc::now().timestamp_millis().to_string(),
+            received: 0,
+            backlog: Backlog::new(),
+        })
+    }
+
+    fn to_message(&mut self, message: ::nats::Message) -> Option<Message> {
+        let body = match String::from_utf8(message.data) {
+            Ok(body) => body,
+            Err(err) => {
+                warn!(
+                    "Ignoring non-UTF-8 message on {}: {}",
+                    &message.subject, err
+                );
+                return None;
+            }
+        };
+        self.received += 1;
+        Some(Message {
+            message_id: Some(format!("{}-{}", self.prefix, self.received)),
+            body: Some(body),
+            ..Default::default()
+        })
+    }
+}
+
+impl EventSource for NatsSource {
+    fn name(&self) -> String {
+        format!("NATS subject {}", &self.subject)
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        let mut messages = self.backlog.take_due();
+        if messages.is_empty() {
+            match self.subscription.next_timeout(self.backlog.wait(POLL_WAIT)) {
+                Ok(message) => messages.extend(self.to_message(message)),
+                Err(ref err) if err.kind() == ErrorKind::TimedOut => return Ok(messages),
+                Err(err) => {
+                    return Err(err).with_context(|| NatsReceive {
+                        subject: self.subject.clone(),
+                    })
+                }
+            }
+        }
+        while let Some(message) = self.subscription.try_next() {
+            messages.extend(self.to_message(message));
+        }
+        Ok(messages)
+    }
+
+    fn ack(&mut self, _message: &Message) -> Result<()> {
+        Ok(())
+    }
+
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()> {
+        self.backlog.delay(message, delay_seconds);
+        Ok(())
+    }
+
+    fn receive_count(&self, message: &Message) -> u32 {
+        crate::sqs::receive_count(message)
+    }
+}
@@ -0,0 +1,120 @@
+use bollard::service::Service;
+use serde_json::Value;
+
+/// An os/architecture pair. An empty field matches any value, which is what
+/// a placement constraint on only one of them means.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+}
+
+fn normalize_architecture(architecture: &str) -> String {
+    match architecture.to_lowercase().as_str() {
+        "x86_64" | "x86-64" => "amd64".to_owned(),
+        "aarch64" => "arm64".to_owned(),
+        other => other.to_owned(),
+    }
+}
+
+impl Platform {
+    pub fn new(os: &str, architecture: &str) -> Platform {
+        Platform {
+            os: os.to_lowercase(),
+            architecture: normalize_architecture(architecture),
+        }
+    }
+
+    pub fn satisfied_by(&self, other: &Platform) -> bool {
+        (self.os.is_empty() || self.os == other.os)
+            && (self.architecture.is_empty() || self.architecture == other.architecture)
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let or_any = |value: &str| if value.is_empty() { "*" } else { value }.to_owned();
+        write!(f, "{}/{}", or_any(&self.os), or_any(&self.architecture))
+    }
+}
+
+/// Platforms implied by node.platform.os and node.platform.arch placement
+/// constraints. Negated constraints are not considered.
+pub fn from_constraints(constraints: &[String]) -> Option<Platform> {
+    let mut platform = Platform::new("", "");
+    let mut found = false;
+    for constraint in constraints {
+        let parts: Vec<&str> = constraint.splitn(2, "==").collect();
+        if parts.len() != 2 {
+            continue;
+        }
+        match parts[0].trim() {
+            "node.platform.os" => platform.os = parts[1].trim().to_lowercase(),
+            "node.platform.arch" => platform.architecture = normalize_architecture(parts[1].trim()),
+            _ => continue,
+        }
+        found = true;
+    }
+    if found {
+        Some(platform)
+    } else {
+        None
+    }
+}
+
+/// The platforms a service's tasks may be scheduled on. Empty means any.
+pub fn required(service: &Service<String>) -> Vec<Platform> {
+    let placement = match &service.spec.task_template.placement {
+        Some(placement) => placement,
+        None => return Vec::new(),
+    };
+    let mut platforms: Vec<Platform> = placement
+        .platforms
+        .iter()
+        .flatten()
+        .map(|platform| {
+            Platform::new(
+                platform.os.as_deref().unwrap_or(""),
+                platform.architecture.as_deref().unwrap_or(""),
+            )
+        })
+        .collect();
+    if let Some(constrained) = placement
+        .constraints
+        .as_ref()
+        .and_then(|constraints| from_constraints(constraints))
+    {
+        if platforms.is_empty() {
+            platforms.push(constrained);
+        } else {
+            platforms.retain(|platform| constrained.satisfied_by(platform));
+        }
+    }
+    platforms
+}
+
+/// Platforms listed in a manifest list or OCI index. A plain manifest does
+/// not declare its platform, so None is returned.
+pub fn from_manifest(manifest: &Value) -> Option<Vec<Platform>> {
+    let manifests = manifest.get("manifests")?.as_array()?;
+    Some(
+        manifests
+            .iter()
+            .filter_map(|entry| entry.get("platform"))
+            .filter_map(|platform| {
+                let os = platform.get("os")?.as_str()?;
+                let architecture = platform.get("architecture")?.as_str()?;
+                Some(Platform::new(os, architecture))
+            })
+            .collect(),
+    )
+}
+
+/// Required platforms the image does not provide.
+pub fn missing(required: &[Platform], available: &[Platform]) -> Vec<Platform> {
+    required
+        .iter()
+        .filter(|platform| !available.iter().any(|a| platform.satisfied_by(a)))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,106 @@
+use super::Plugin;
+use crate::events::Event;
+use crate::{Result, SeedyError};
+use bollard::service::Service;
+use log::debug;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to check whether the command has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Hands message bodies the deployer did not recognize to a shell command on
+/// stdin. The command prints an event in the form plugins exchange, or
+/// nothing if the body is not an event it knows either.
+pub struct CommandPlugin {
+    command: String,
+    timeout: Duration,
+}
+
+impl CommandPlugin {
+    pub fn new(command: &str, timeout: Duration) -> CommandPlugin {
+        CommandPlugin {
+            command: command.to_owned(),
+            timeout,
+        }
+    }
+
+    fn failed<E: std::fmt::Display>(&self, err: E) -> SeedyError {
+        SeedyError::PluginFailed {
+            plugin: self.command.clone(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl Plugin for CommandPlugin {
+    fn name(&self) -> &str {
+        &self.command
+    }
+
+    fn parse(&self, body: &str) -> Result<Option<Event>> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| self.failed(err))?;
+        // Feed and drain the command from threads, so that neither side
+        // blocks on a full pipe while the other waits for it.
+        let stdin = child.stdin.take();
+        let body = body.to_owned();
+        thread::spawn(move || {
+            if let Some(mut stdin) = stdin {
+                // The command may not care to read the body
+                let _ = stdin.write_all(body.as_bytes());
+            }
+        });
+        let stdout = child.stdout.take();
+        let reader = thread::spawn(move || {
+            let mut output = Vec::new();
+            if let Some(mut stdout) = stdout {
+                stdout.read_to_end(&mut output).map(|_| output)
+            } else {
+                Ok(output)
+            }
+        });
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            match child.try_wait().map_err(|err| self.failed(err))? {
+                Some(status) => break status,
+                None if Instant::now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    let message = format!("timed out after {}s", self.timeout.as_secs());
+                    return Err(self.failed(message));
+                }
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        };
+        if !status.success() {
+            debug!("{} does not recognize the body: {}", &self.command, status);
+            return Ok(None);
+        }
+        let output = match reader.join() {
+            Ok(output) => output.map_err(|err| self.failed(err))?,
+            Err(_) => return Err(self.failed("could not read output")),
+        };
+        let stdout = String::from_utf8_lossy(&output);
+        if stdout.trim().is_empty() {
+            return Ok(None);
+        }
+        let value: Value = serde_json::from_str(&stdout).map_err(|err| self.failed(err))?;
+        match Event::from_json(&value) {
+            Some(event) => Ok(Some(event)),
+            None => Err(self.failed("output is not an event")),
+        }
+    }
+
+    fn accept(&self, _event: &Event, _service: &Service<String>) -> Result<bool> {
+        Ok(true)
+    }
+}
@@ -0,0 +1,160 @@
+use crate::events::Event;
+#[cfg(any(not(feature = "wasm"), not(feature = "scripting")))]
+use crate::FeatureDisabled;
+use crate::{current_image, Opt, PluginDirIo, Result};
+use bollard::service::{Service, ServiceSpec};
+use log::info;
+use serde_json::{json, Value};
+use snafu::ResultExt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+mod command;
+#[cfg(feature = "scripting")]
+mod script;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+/// Extension point for event formats and matching policies that do not
+/// belong in the deployer itself.
+pub trait Plugin {
+    fn name(&self) -> &str;
+    /// Turn a message body the deployer did not recognize into an event.
+    fn parse(&self, body: &str) -> Result<Option<Event>>;
+    /// Veto the update of a service matching an event.
+    fn accept(&self, event: &Event, service: &Service<String>) -> Result<bool>;
+    /// Alter an event before it is matched against services, e.g. to
+    /// redirect it to another tag.
+    fn rewrite(&self, event: Event) -> Result<Event> {
+        Ok(event)
+    }
+    /// Alter the spec a matching service is about to be updated with.
+    fn mutate_spec(
+        &self,
+        _event: &Event,
+        _service: &Service<String>,
+        spec: ServiceSpec<String>,
+    ) -> Result<ServiceSpec<String>> {
+        Ok(spec)
+    }
+}
+
+/// The service metadata handed to plugins.
+pub fn service_to_json(service: &Service<String>) -> Value {
+    json!({
+        "id": service.id,
+        "name": service.spec.name,
+        "labels": service.spec.labels,
+        "image": current_image(service),
+    })
+}
+
+#[cfg(feature = "wasm")]
+fn wasm_plugin(path: &Path) -> Result<Box<dyn Plugin>> {
+    Ok(Box::new(wasm::WasmPlugin::load(path)?))
+}
+
+#[cfg(not(feature = "wasm"))]
+fn wasm_plugin(_path: &Path) -> Result<Box<dyn Plugin>> {
+    FeatureDisabled {
+        feature: "wasm",
+        option: "--wasm-plugin",
+    }
+    .fail()
+}
+
+#[cfg(feature = "scripting")]
+fn script_plugin(path: &Path) -> Result<Box<dyn Plugin>> {
+    Ok(Box::new(script::ScriptPlugin::load(path)?))
+}
+
+#[cfg(not(feature = "scripting"))]
+fn script_plugin(_path: &Path) -> Result<Box<dyn Plugin>> {
+    FeatureDisabled {
+        feature: "scripting",
+        option: "--script",
+    }
+    .fail()
+}
+
+/// The .wasm files in a plugin directory, in file name order.
+pub fn wasm_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).context(PluginDirIo { path: dir })? {
+        let path = entry.context(PluginDirIo { path: dir })?.path();
+        if path.is_file() && path.extension().map_or(false, |ext| ext == "wasm") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+pub fn load(opt: &Opt) -> Result<Vec<Box<dyn Plugin>>> {
+    let mut plugins = Vec::new();
+    for path in &opt.wasm_plugins {
+        plugins.push(wasm_plugin(path)?);
+    }
+    if let Some(dir) = &opt.wasm_plugin_dir {
+        for path in wasm_files(dir)? {
+            plugins.push(wasm_plugin(&path)?);
+        }
+    }
+    if let Some(path) = &opt.script {
+        plugins.push(script_plugin(path)?);
+    }
+    if let Some(command) = &opt.event_parser_cmd {
+        let timeout = Duration::from_secs(opt.event_parser_timeout_seconds);
+        plugins.push(Box::new(command::CommandPlugin::new(command, timeout)));
+    }
+    Ok(plugins)
+}
+
+pub fn parse(plugins: &[Box<dyn Plugin>], body: &str) -> Result<Option<Event>> {
+    for plugin in plugins {
+        if let Some(event) = plugin.parse(body)? {
+            return Ok(Some(event));
+        }
+    }
+    Ok(None)
+}
+
+pub fn accept(
+    plugins: &[Box<dyn Plugin>],
+    event: &Event,
+    service: &Service<String>,
+) -> Result<bool> {
+    for plugin in plugins {
+        if !plugin.accept(event, service)? {
+            info!(
+                "Plugin {} vetoed update of service {}",
+                plugin.name(),
+                &service.id
+            );
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+pub fn rewrite(plugins: &[Box<dyn Plugin>], event: Event) -> Result<Event> {
+    let mut event = event;
+    for plugin in plugins {
+        event = plugin.rewrite(event)?;
+    }
+    Ok(event)
+}
+
+pub fn mutate_spec(
+    plugins: &[Box<dyn Plugin>],
+    event: &Event,
+    service: &Service<String>,
+    spec: ServiceSpec<String>,
+) -> Result<ServiceSpec<String>> {
+    let mut spec = spec;
+    for plugin in plugins {
+        spec = plugin.mutate_spec(event, service, spec)?;
+    }
+    Ok(spec)
+}
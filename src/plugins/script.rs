@@ -0,0 +1,263 @@
+use super::{service_to_json, Plugin};
+use crate::events::Event;
+use crate::{Result, SeedyError};
+use bollard::service::{Service, ServiceSpec};
+use log::info;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde_json::{Map as JsonMap, Value};
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A Rhai script defining any of `accept(event, service) -> bool`,
+/// `rewrite(event) -> event` and `target(event, service) -> #{image, deploy}`.
+/// `target` picks the image a matching service is updated to and whether to
+/// deploy at all; either field may be left out, and it is called once per
+/// service and event. Events and services are passed as object maps with
+/// the same fields plugins receive as JSON. The script is recompiled
+/// whenever the file changes.
+pub struct ScriptPlugin {
+    path: PathBuf,
+    name: String,
+    engine: Engine,
+    compiled: RefCell<(SystemTime, AST)>,
+    /// Target decided when accepting, keyed on service id and event image
+    decided: RefCell<Option<(String, String, Option<Target>)>>,
+}
+
+fn script_error<E: std::fmt::Display>(plugin: &str, err: E) -> SeedyError {
+    SeedyError::PluginFailed {
+        plugin: plugin.to_owned(),
+        message: err.to_string(),
+    }
+}
+
+pub fn json_to_dynamic(value: &Value) -> Dynamic {
+    match value {
+        Value::Null => Dynamic::from(()),
+        Value::Bool(b) => Dynamic::from(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Dynamic::from(i),
+            None => Dynamic::from(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => Dynamic::from(s.clone()),
+        Value::Array(items) => Dynamic::from(items.iter().map(json_to_dynamic).collect::<Array>()),
+        Value::Object(fields) => Dynamic::from(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone().into(), json_to_dynamic(value)))
+                .collect::<Map>(),
+        ),
+    }
+}
+
+pub fn dynamic_to_json(value: Dynamic) -> Value {
+    if value.is::<()>() {
+        Value::Null
+    } else if value.is::<bool>() {
+        Value::Bool(value.cast::<bool>())
+    } else if value.is::<i64>() {
+        Value::from(value.cast::<i64>())
+    } else if value.is::<f64>() {
+        Value::from(value.cast::<f64>())
+    } else if value.is::<Array>() {
+        Value::Array(
+            value
+                .cast::<Array>()
+                .into_iter()
+                .map(dynamic_to_json)
+                .collect(),
+        )
+    } else if value.is::<Map>() {
+        Value::Object(
+            value
+                .cast::<Map>()
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), dynamic_to_json(value)))
+                .collect::<JsonMap<String, Value>>(),
+        )
+    } else {
+        Value::String(value.to_string())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Limits on a single call into the script. Generous for anything that
+/// inspects an event, but bounded.
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_VALUE_SIZE: usize = 1024 * 1024;
+
+/// What the script's `target` function decided for a service.
+#[derive(Clone)]
+struct Target {
+    image: Option<String>,
+    deploy: bool,
+}
+
+impl ScriptPlugin {
+    pub fn load(path: &Path) -> Result<ScriptPlugin> {
+        let name = path.display().to_string();
+        let mut engine = Engine::new();
+        // A script stuck in a loop or recursion fails instead of hanging the deployer
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(MAX_CALL_LEVELS);
+        engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
+        engine.set_max_string_size(MAX_VALUE_SIZE);
+        engine.set_max_array_size(MAX_VALUE_SIZE);
+        engine.set_max_map_size(MAX_VALUE_SIZE);
+        let ast = engine
+            .compile_file(path.to_owned())
+            .map_err(|err| script_error(&name, err))?;
+        Ok(ScriptPlugin {
+            path: path.to_owned(),
+            name,
+            engine,
+            compiled: RefCell::new((modified(path).unwrap_or(SystemTime::UNIX_EPOCH), ast)),
+            decided: RefCell::new(None),
+        })
+    }
+
+    fn reload_if_changed(&self) -> Result<()> {
+        let current = match modified(&self.path) {
+            Some(current) => current,
+            None => return Ok(()),
+        };
+        if current != self.compiled.borrow().0 {
+            let ast = self
+                .engine
+                .compile_file(self.path.clone())
+                .map_err(|err| script_error(&self.name, err))?;
+            info!("Reloaded script {}", &self.name);
+            *self.compiled.borrow_mut() = (current, ast);
+        }
+        Ok(())
+    }
+
+    /// Call a script function, returning None if the script does not define it.
+    fn call(&self, function: &str, args: Vec<Dynamic>) -> Result<Option<Dynamic>> {
+        self.reload_if_changed()?;
+        let compiled = self.compiled.borrow();
+        let mut scope = Scope::new();
+        let mut args = args;
+        let result = self
+            .engine
+            .call_fn_dynamic(&mut scope, &compiled.1, function, &mut args);
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => match *err {
+                EvalAltResult::ErrorFunctionNotFound(ref signature, _)
+                    if signature.starts_with(function) =>
+                {
+                    Ok(None)
+                }
+                _ => Err(script_error(&self.name, err)),
+            },
+        }
+    }
+
+    fn target(&self, event: &Event, service: &Service<String>) -> Result<Option<Target>> {
+        let args = vec![
+            json_to_dynamic(&event.to_json()),
+            json_to_dynamic(&service_to_json(service)),
+        ];
+        let target = match self.call("target", args)? {
+            Some(target) => dynamic_to_json(target),
+            None => return Ok(None),
+        };
+        if !target.is_object() {
+            return Err(script_error(&self.name, "target did not return a map"));
+        }
+        let image = match target.get("image") {
+            Some(Value::String(image)) => Some(image.clone()),
+            None | Some(Value::Null) => None,
+            Some(_) => return Err(script_error(&self.name, "target image is not a string")),
+        };
+        let deploy = match target.get("deploy") {
+            Some(Value::Bool(deploy)) => *deploy,
+            None | Some(Value::Null) => true,
+            Some(_) => return Err(script_error(&self.name, "target deploy is not a bool")),
+        };
+        Ok(Some(Target { image, deploy }))
+    }
+
+    /// The target decided when the event was accepted for the service, or
+    /// a fresh one if it was not.
+    fn decided_target(&self, event: &Event, service: &Service<String>) -> Result<Option<Target>> {
+        let image = event.pinned_image();
+        let decided = self.decided.borrow_mut().take();
+        match decided {
+            Some((service_id, decided_image, target))
+                if service_id == service.id && decided_image == image =>
+            {
+                Ok(target)
+            }
+            _ => self.target(event, service),
+        }
+    }
+}
+
+impl Plugin for ScriptPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn parse(&self, _body: &str) -> Result<Option<Event>> {
+        Ok(None)
+    }
+
+    fn accept(&self, event: &Event, service: &Service<String>) -> Result<bool> {
+        let args = vec![
+            json_to_dynamic(&event.to_json()),
+            json_to_dynamic(&service_to_json(service)),
+        ];
+        let accepted = match self.call("accept", args)? {
+            Some(accepted) if accepted.is::<bool>() => accepted.cast::<bool>(),
+            Some(_) => return Err(script_error(&self.name, "accept did not return a bool")),
+            None => true,
+        };
+        if !accepted {
+            return Ok(false);
+        }
+        let target = self.target(event, service)?;
+        let deploy = target.as_ref().map_or(true, |target| target.deploy);
+        *self.decided.borrow_mut() = Some((service.id.clone(), event.pinned_image(), target));
+        Ok(deploy)
+    }
+
+    fn rewrite(&self, event: Event) -> Result<Event> {
+        match self.call("rewrite", vec![json_to_dynamic(&event.to_json())])? {
+            Some(rewritten) => Event::from_json(&dynamic_to_json(rewritten))
+                .ok_or_else(|| script_error(&self.name, "rewrite did not return an event")),
+            None => Ok(event),
+        }
+    }
+
+    fn mutate_spec(
+        &self,
+        event: &Event,
+        service: &Service<String>,
+        spec: ServiceSpec<String>,
+    ) -> Result<ServiceSpec<String>> {
+        let image = match self.decided_target(event, service)? {
+            Some(Target {
+                image: Some(image), ..
+            }) => image,
+            _ => return Ok(spec),
+        };
+        let mut spec = spec;
+        if let Some(container_spec) = spec.task_template.container_spec.as_mut() {
+            info!(
+                "Script {} targets service {} at {}",
+                &self.name, &service.spec.name, image
+            );
+            container_spec.image = Some(image);
+        }
+        Ok(spec)
+    }
+}
@@ -0,0 +1,198 @@
+use super::{service_to_json, Plugin};
+use crate::events::Event;
+use crate::{PluginFailed, Result, SeedyError};
+use bollard::service::{Service, ServiceSpec};
+use serde_json::json;
+use snafu::{OptionExt, ResultExt};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use wasmtime::{Config, Engine, Instance, InterruptHandle, Memory, Module, Store};
+
+/// How long a call into a plugin may run before it is interrupted, so that
+/// a plugin stuck in a loop cannot hold up the deployer.
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A WebAssembly module exporting `memory`, `alloc(len) -> ptr` and any of
+/// `parse(ptr, len) -> i64`, `accept(ptr, len) -> i32` and
+/// `mutate_spec(ptr, len) -> i64`. Input is passed as JSON written into
+/// memory obtained from `alloc`; `parse` and `mutate_spec` return the location
+/// of their JSON output packed as `ptr << 32 | len`, or 0 for no event or an
+/// unchanged spec.
+pub struct WasmPlugin {
+    name: String,
+    instance: Instance,
+    interrupts: Arc<InterruptHandle>,
+}
+
+fn plugin_error<E: std::fmt::Display>(plugin: &str, err: E) -> SeedyError {
+    SeedyError::PluginFailed {
+        plugin: plugin.to_owned(),
+        message: err.to_string(),
+    }
+}
+
+impl WasmPlugin {
+    pub fn load(path: &Path) -> Result<WasmPlugin> {
+        let plugin = path.display().to_string();
+        let mut config = Config::new();
+        config.interruptable(true);
+        let engine = Engine::new(&config);
+        let store = Store::new(&engine);
+        let interrupts = store
+            .interrupt_handle()
+            .map_err(|err| plugin_error(&plugin, err))?;
+        let module = Module::from_file(&engine, path).map_err(|err| plugin_error(&plugin, err))?;
+        let instance =
+            Instance::new(&store, &module, &[]).map_err(|err| plugin_error(&plugin, err))?;
+        Ok(WasmPlugin {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            instance,
+            interrupts: Arc::new(interrupts),
+        })
+    }
+
+    /// Run a call into the module, interrupting it if it overruns. The
+    /// interrupted call fails with a trap.
+    fn with_deadline<T, F: FnOnce() -> T>(&self, call: F) -> T {
+        let (done, finished) = mpsc::channel::<()>();
+        let interrupts = self.interrupts.clone();
+        let watchdog = thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(CALL_TIMEOUT) {
+                interrupts.interrupt();
+            }
+        });
+        let result = call();
+        drop(done);
+        let _ = watchdog.join();
+        result
+    }
+
+    fn memory(&self) -> Result<Memory> {
+        self.instance
+            .get_memory("memory")
+            .with_context(|| PluginFailed {
+                plugin: self.name.clone(),
+                message: "module does not export memory",
+            })
+    }
+
+    fn write_input(&self, input: &[u8]) -> Result<i32> {
+        let alloc = self
+            .instance
+            .get_func("alloc")
+            .with_context(|| PluginFailed {
+                plugin: self.name.clone(),
+                message: "module does not export alloc",
+            })?
+            .get1::<i32, i32>()
+            .map_err(|err| plugin_error(&self.name, err))?;
+        let ptr = self
+            .with_deadline(|| alloc(input.len() as i32))
+            .map_err(|err| plugin_error(&self.name, err))?;
+        let memory = self.memory()?;
+        let start = ptr as u32 as usize;
+        let end = start
+            .checked_add(input.len())
+            .filter(|end| *end <= memory.data_size())
+            .ok_or_else(|| plugin_error(&self.name, "alloc returned memory out of bounds"))?;
+        unsafe { memory.data_unchecked_mut()[start..end].copy_from_slice(input) };
+        Ok(ptr)
+    }
+
+    /// Pointer and length are unsigned 32-bit values, whatever their sign
+    /// as a wasm i64.
+    fn read_output(&self, packed: i64) -> Result<Vec<u8>> {
+        let memory = self.memory()?;
+        let start = (packed >> 32) as u32 as usize;
+        let end = start
+            .checked_add(packed as u32 as usize)
+            .filter(|end| *end <= memory.data_size())
+            .ok_or_else(|| plugin_error(&self.name, "output out of bounds"))?;
+        Ok(unsafe { memory.data_unchecked()[start..end].to_vec() })
+    }
+}
+
+impl Plugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn parse(&self, body: &str) -> Result<Option<Event>> {
+        let parse = match self.instance.get_func("parse") {
+            Some(parse) => parse
+                .get2::<i32, i32, i64>()
+                .map_err(|err| plugin_error(&self.name, err))?,
+            None => return Ok(None),
+        };
+        let ptr = self.write_input(body.as_bytes())?;
+        let packed = self
+            .with_deadline(|| parse(ptr, body.len() as i32))
+            .map_err(|err| plugin_error(&self.name, err))?;
+        if packed == 0 {
+            return Ok(None);
+        }
+        let output = self.read_output(packed)?;
+        let event = serde_json::from_slice(&output)
+            .ok()
+            .and_then(|value| Event::from_json(&value));
+        match event {
+            Some(event) => Ok(Some(event)),
+            None => Err(plugin_error(&self.name, "parse returned an invalid event")),
+        }
+    }
+
+    fn accept(&self, event: &Event, service: &Service<String>) -> Result<bool> {
+        let accept = match self.instance.get_func("accept") {
+            Some(accept) => accept
+                .get2::<i32, i32, i32>()
+                .map_err(|err| plugin_error(&self.name, err))?,
+            None => return Ok(true),
+        };
+        let input = json!({
+            "event": event.to_json(),
+            "service": service_to_json(service),
+        })
+        .to_string();
+        let ptr = self.write_input(input.as_bytes())?;
+        let accepted = self
+            .with_deadline(|| accept(ptr, input.len() as i32))
+            .map_err(|err| plugin_error(&self.name, err))?;
+        Ok(accepted != 0)
+    }
+
+    fn mutate_spec(
+        &self,
+        event: &Event,
+        service: &Service<String>,
+        spec: ServiceSpec<String>,
+    ) -> Result<ServiceSpec<String>> {
+        let mutate_spec = match self.instance.get_func("mutate_spec") {
+            Some(mutate_spec) => mutate_spec
+                .get2::<i32, i32, i64>()
+                .map_err(|err| plugin_error(&self.name, err))?,
+            None => return Ok(spec),
+        };
+        let input = json!({
+            "event": event.to_json(),
+            "service": service_to_json(service),
+            "spec": spec,
+        })
+        .to_string();
+        let ptr = self.write_input(input.as_bytes())?;
+        let packed = self
+            .with_deadline(|| mutate_spec(ptr, input.len() as i32))
+            .map_err(|err| plugin_error(&self.name, err))?;
+        if packed == 0 {
+            return Ok(spec);
+        }
+        let output = self.read_output(packed)?;
+        serde_json::from_slice(&output)
+            .map_err(|err| plugin_error(&self.name, format!("mutate_spec returned {}", err)))
+    }
+}
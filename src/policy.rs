@@ -0,0 +1,139 @@
+use crate::{events::Event, running_image};
+use bollard::service::Service;
+use log::warn;
+use semver::Version;
+use std::str::FromStr;
+
+/// Label used by Keel on Kubernetes, honoured so stack files can be reused.
+pub const KEEL_POLICY_LABEL: &str = "keel.sh/policy";
+
+/// Which newly pushed tags a service may move to, following Keel semantics.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Policy {
+    /// Any higher version, including pre-releases
+    All,
+    /// Any higher release version
+    Major,
+    /// Higher release versions with the same major version
+    Minor,
+    /// Higher release versions with the same major and minor version
+    Patch,
+    /// Any tag pushed to the repository
+    Force,
+    /// Tags matching a pattern where * matches any sequence of characters
+    Glob(String),
+    Never,
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "all" => Ok(Policy::All),
+            "major" => Ok(Policy::Major),
+            "minor" => Ok(Policy::Minor),
+            "patch" => Ok(Policy::Patch),
+            "force" => Ok(Policy::Force),
+            "never" => Ok(Policy::Never),
+            other if other.starts_with("glob:") => Ok(Policy::Glob(other[5..].to_owned())),
+            other => Err(format!(
+                "expected all, major, minor, patch, force, never or glob:<pattern>, got {}",
+                other
+            )),
+        }
+    }
+}
+
+fn parse_version(tag: &str) -> Option<Version> {
+    let tag = if tag.starts_with('v') { &tag[1..] } else { tag };
+    Version::parse(tag).ok()
+}
+
+pub fn glob_matches(pattern: &str, candidate: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == candidate;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !candidate.starts_with(first) || candidate.len() < first.len() + last.len() {
+        return false;
+    }
+    let mut rest = &candidate[first.len()..candidate.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    candidate.ends_with(last)
+}
+
+impl Policy {
+    /// Whether a service running current_tag should move to new_tag.
+    pub fn accepts(&self, current_tag: &str, new_tag: &str) -> bool {
+        match self {
+            Policy::Force => true,
+            Policy::Never => false,
+            Policy::Glob(pattern) => glob_matches(pattern, new_tag),
+            semver_policy => {
+                let (current, new) = match (parse_version(current_tag), parse_version(new_tag)) {
+                    (Some(current), Some(new)) => (current, new),
+                    _ => return false,
+                };
+                if new <= current {
+                    return false;
+                }
+                if new.is_prerelease() && *semver_policy != Policy::All {
+                    return false;
+                }
+                match semver_policy {
+                    Policy::Minor => new.major == current.major,
+                    Policy::Patch => new.major == current.major && new.minor == current.minor,
+                    _ => true,
+                }
+            }
+        }
+    }
+}
+
+pub fn is_labelled(service: &Service<String>) -> bool {
+    service.spec.labels.contains_key(KEEL_POLICY_LABEL)
+}
+
+/// Whether the service's policy label allows it to move to the tag.
+fn accepts(service: &Service<String>, repository: &str, tag: &str) -> bool {
+    let label = match service.spec.labels.get(KEEL_POLICY_LABEL) {
+        Some(label) => label,
+        None => return false,
+    };
+    let policy = match Policy::from_str(label) {
+        Ok(policy) => policy,
+        Err(err) => {
+            warn!(
+                "Ignoring {} on service {}: {}",
+                KEEL_POLICY_LABEL, &service.id, err
+            );
+            return false;
+        }
+    };
+    match running_image(service) {
+        Some(current) => current.name() == repository && policy.accepts(&current.tag, tag),
+        None => false,
+    }
+}
+
+/// Find the services whose policy label allows them to move to the pushed
+/// tag. As with Keel, every accepting service is updated, in order of name.
+pub fn find<'a, I>(services: I, event: &Event) -> Vec<&'a Service<String>>
+where
+    I: IntoIterator<Item = &'a Service<String>>,
+{
+    let repository = event.repository();
+    let mut accepting: Vec<&Service<String>> = services
+        .into_iter()
+        .filter(|service| accepts(service, &repository, &event.image_tag))
+        .collect();
+    accepting.sort_by(|a, b| a.spec.name.cmp(&b.spec.name));
+    accepting
+}
@@ -0,0 +1,68 @@
+use crate::{QuietHoursFormat, Result, SeedyError};
+use chrono::{DateTime, Timelike, Utc};
+use std::str::FromStr;
+use std::time::Duration;
+
+const BASE_IDLE_DELAY_SECONDS: u64 = 20;
+
+/// How long to pause before the next receive after a number of consecutive
+/// empty polls. Doubles once the queue has been idle for `threshold` polls,
+/// capped at `max_seconds`.
+pub fn idle_delay(empty_polls: u32, threshold: u32, max_seconds: u64) -> Duration {
+    if max_seconds == 0 || empty_polls < threshold {
+        return Duration::from_secs(0);
+    }
+    let doublings = (empty_polls - threshold).min(16);
+    let delay = BASE_IDLE_DELAY_SECONDS.saturating_mul(1 << doublings);
+    Duration::from_secs(delay.min(max_seconds))
+}
+
+/// A daily window, in UTC, during which the deployer does not poll at all.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuietHours {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl FromStr for QuietHours {
+    type Err = SeedyError;
+
+    fn from_str(input: &str) -> Result<QuietHours> {
+        let hours: Vec<Option<u32>> = input
+            .splitn(2, '-')
+            .map(|hour| hour.trim().parse::<u32>().ok().filter(|hour| *hour < 24))
+            .collect();
+        match hours.as_slice() {
+            [Some(start), Some(end)] if start != end => Ok(QuietHours {
+                start: *start,
+                end: *end,
+            }),
+            _ => QuietHoursFormat {
+                value: input.to_owned(),
+            }
+            .fail(),
+        }
+    }
+}
+
+impl QuietHours {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start < self.end {
+            hour >= self.start && hour < self.end
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+
+    /// Time left of the quiet window, or none if `now` is outside it.
+    pub fn remaining(&self, now: DateTime<Utc>) -> Option<Duration> {
+        if !self.contains(now.hour()) {
+            return None;
+        }
+        let mut end = now.date().and_hms(self.end, 0, 0);
+        if end <= now {
+            end = end + chrono::Duration::days(1);
+        }
+        (end - now).to_std().ok()
+    }
+}
@@ -0,0 +1,70 @@
+use crate::convergence::fetch_service;
+use crate::managers::Managers;
+use crate::{Result, UpdatingService};
+use bollard::service::{Service, UpdateServiceOptions};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use tokio::runtime::Runtime;
+
+/// Services carrying this label are left alone by the deployer until it is
+/// removed, e.g. with docker service update --label-rm seedy.quarantined.
+/// Its value is the image the last failed update was to.
+pub const QUARANTINE_LABEL: &str = "seedy.quarantined";
+
+pub fn is_quarantined(service: &Service<String>) -> bool {
+    service.spec.labels.contains_key(QUARANTINE_LABEL)
+}
+
+/// Consecutive failed updates per service.
+#[derive(Default)]
+pub struct Failures {
+    counts: HashMap<String, u32>,
+}
+
+impl Failures {
+    pub fn new() -> Failures {
+        Failures::default()
+    }
+
+    /// Record the outcome of an update, returning how many updates of the
+    /// service have failed in a row.
+    pub fn record(&mut self, service_id: &str, failed: bool) -> u32 {
+        if failed {
+            let count = self.counts.entry(service_id.to_owned()).or_insert(0);
+            *count += 1;
+            *count
+        } else {
+            self.counts.remove(service_id);
+            0
+        }
+    }
+}
+
+/// Label the service so that later pushes are not deployed to it. The
+/// service has been updated since it was listed, so its current version is
+/// fetched first.
+pub fn quarantine(
+    managers: &mut Managers,
+    rt: &mut Runtime,
+    service_id: &str,
+    image: &str,
+) -> Result<()> {
+    let service = match fetch_service(managers, rt, service_id)? {
+        Some(service) => service,
+        None => return Ok(()),
+    };
+    let mut spec = service.spec.clone();
+    spec.labels
+        .insert(QUARANTINE_LABEL.to_owned(), image.to_owned());
+    managers.run(|docker| {
+        let options = UpdateServiceOptions {
+            version: service.version.index,
+            ..Default::default()
+        };
+        rt.block_on(docker.update_service(&service.id, spec.clone(), options, None))
+            .with_context(|| UpdatingService {
+                service_id: service.id.clone(),
+            })
+    })?;
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use crate::source::{Backlog, EventSource};
+use crate::{RedisConnect, RedisReceive, Result};
+use ::redis::streams::{StreamId, StreamReadReply};
+use ::redis::{Connection, RedisResult, Value};
+use log::warn;
+use rusoto_sqs::Message;
+use snafu::ResultExt;
+use std::time::{Duration, Instant};
+
+const POLL_WAIT: Duration = Duration::from_secs(20);
+/// Entries left pending this long are taken over from their consumer, as
+/// when a deployer has stopped for good.
+const CLAIM_IDLE: Duration = Duration::from_secs(900);
+/// How often to look for idle entries to take over.
+const CLAIM_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Stream entries are expected to carry the event in this field.
+pub const BODY_FIELD: &str = "body";
+
+/// Reads a Redis stream as a member of a consumer group. Entries stay in
+/// the group's pending list until acked, so events that were in flight
+/// when the deployer stopped are read again on the next start, or taken
+/// over by another member once they have been idle for long.
+pub struct RedisSource {
+    connection: Connection,
+    stream: String,
+    group: String,
+    consumer: String,
+    pending_from: Option<String>,
+    claim_from: String,
+    last_claim: Option<Instant>,
+    backlog: Backlog,
+}
+
+impl RedisSource {
+    pub fn connect(url: &str, stream: &str, group: &str, consumer: &str) -> Result<RedisSource> {
+        let mut connection = ::redis::Client::open(url)
+            .and_then(|client| client.get_connection())
+            .with_context(|| RedisConnect { url })?;
+        let created: RedisResult<()> = ::redis::cmd("XGROUP")
+            .arg("CREATE")
+            .arg(stream)
+            .arg(group)
+            .arg("$")
+            .arg("MKSTREAM")
+            .query(&mut connection);
+        if let Err(err) = created {
+            // The group surviving from an earlier run is the normal case
+            if err.code() != Some("BUSYGROUP") {
+                return Err(err).with_context(|| RedisConnect { url });
+            }
+        }
+        Ok(RedisSource {
+            connection,
+            stream: stream.to_owned(),
+            group: group.to_owned(),
+            consumer: consumer.to_owned(),
+            pending_from: Some("0".to_owned()),
+            claim_from: "0".to_owned(),
+            last_claim: None,
+            backlog: Backlog::new(),
+        })
+    }
+
+    /// Reading from an id returns this consumer's pending entries after it,
+    /// reading from > returns entries never delivered to the group.
+    fn read(&mut self, from: &str, block: Duration) -> Result<Vec<Message>> {
+        let mut command = ::redis::cmd("XREADGROUP");
+        command
+            .arg("GROUP")
+            .arg(&self.group)
+            .arg(&self.consumer)
+            .arg("COUNT")
+            .arg(10);
+        if from == ">" {
+            command.arg("BLOCK").arg(block.as_millis() as u64);
+        }
+        let reply: Option<StreamReadReply> = command
+            .arg("STREAMS")
+            .arg(&self.stream)
+            .arg(from)
+            .query(&mut self.connection)
+            .with_context(|| RedisReceive {
+                stream: self.stream.clone(),
+            })?;
+        Ok(reply
+            .map(|reply| reply.keys)
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|key| key.ids)
+            .map(|entry| to_message(&self.stream, entry))
+            .collect())
+    }
+
+    /// Take over entries that other consumers have left idle. Entries this
+    /// consumer is waiting to retry are not handed out twice.
+    fn claim(&mut self) -> Result<Vec<Message>> {
+        let reply: Value = ::redis::cmd("XAUTOCLAIM")
+            .arg(&self.stream)
+            .arg(&self.group)
+            .arg(&self.consumer)
+            .arg(CLAIM_IDLE.as_millis() as u64)
+            .arg(&self.claim_from)
+            .arg("COUNT")
+            .arg(10)
+            .query(&mut self.connection)
+            .with_context(|| RedisReceive {
+                stream: self.stream.clone(),
+            })?;
+        let (next, entries) = parse_autoclaim(&reply).unwrap_or_default();
+        // The scan starts over once it has been through the pending list
+        self.claim_from = match next.as_str() {
+            "" | "0-0" => "0".to_owned(),
+            _ => next,
+        };
+        Ok(entries
+            .into_iter()
+            .filter(|entry| !self.backlog.contains(&entry.id))
+            .map(|entry| to_message(&self.stream, entry))
+            .collect())
+    }
+}
+
+fn as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Data(data) => String::from_utf8(data.clone()).ok(),
+        Value::Status(status) => Some(status.clone()),
+        _ => None,
+    }
+}
+
+/// Split an XAUTOCLAIM reply into the id to continue the scan from and the
+/// claimed entries. Entries deleted from the stream while pending come back
+/// as nil and are left out.
+pub fn parse_autoclaim(reply: &Value) -> Option<(String, Vec<StreamId>)> {
+    let parts = match reply {
+        Value::Bulk(parts) => parts,
+        _ => return None,
+    };
+    let next = as_string(parts.get(0)?)?;
+    let entries = match parts.get(1)? {
+        Value::Bulk(entries) => entries,
+        _ => return None,
+    };
+    let entries = entries
+        .iter()
+        .filter_map(|entry| {
+            let (id, fields) = match entry {
+                Value::Bulk(entry) => (as_string(entry.get(0)?)?, entry.get(1)?),
+                _ => return None,
+            };
+            let fields = match fields {
+                Value::Bulk(fields) => fields,
+                _ => return None,
+            };
+            let map = fields
+                .chunks(2)
+                .filter_map(|pair| match pair {
+                    [key, value] => Some((as_string(key)?, value.clone())),
+                    _ => None,
+                })
+                .collect();
+            Some(StreamId { id, map })
+        })
+        .collect();
+    Some((next, entries))
+}
+
+/// Entries without a usable body are passed on empty so that they get
+/// acked rather than left pending forever.
+pub fn to_message(stream: &str, entry: StreamId) -> Message {
+    let body = match entry.map.get(BODY_FIELD) {
+        Some(Value::Data(data)) => String::from_utf8(data.clone()).ok(),
+        _ => None,
+    };
+    if body.is_none() {
+        warn!(
+            "Ignoring entry {} on {} without a UTF-8 {} field",
+            &entry.id, stream, BODY_FIELD
+        );
+    }
+    Message {
+        message_id: Some(entry.id.clone()),
+        receipt_handle: Some(entry.id),
+        body,
+        ..Default::default()
+    }
+}
+
+impl EventSource for RedisSource {
+    fn name(&self) -> String {
+        format!("Redis stream {}", &self.stream)
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        if let Some(from) = self.pending_from.take() {
+            let pending = self.read(&from, POLL_WAIT)?;
+            if let Some(last) = pending.last() {
+                self.pending_from = last.message_id.clone();
+                return Ok(pending);
+            }
+        }
+        if self
+            .last_claim
+            .map_or(true, |last_claim| last_claim.elapsed() >= CLAIM_INTERVAL)
+        {
+            self.last_claim = Some(Instant::now());
+            match self.claim() {
+                Ok(claimed) if !claimed.is_empty() => return Ok(claimed),
+                Ok(_) => (),
+                Err(err) => warn!("Could not take over idle entries: {}", err),
+            }
+        }
+        let messages = self.backlog.take_due();
+        if !messages.is_empty() {
+            return Ok(messages);
+        }
+        let wait = self.backlog.wait(POLL_WAIT);
+        self.read(">", wait)
+    }
+
+    fn ack(&mut self, message: &Message) -> Result<()> {
+        if let Some(id) = &message.receipt_handle {
+            let _: i64 = ::redis::cmd("XACK")
+                .arg(&self.stream)
+                .arg(&self.group)
+                .arg(id)
+                .query(&mut self.connection)
+                .with_context(|| RedisReceive {
+                    stream: self.stream.clone(),
+                })?;
+        }
+        Ok(())
+    }
+
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()> {
+        self.backlog.delay(message, delay_seconds);
+        Ok(())
+    }
+
+    fn receive_count(&self, message: &Message) -> u32 {
+        crate::sqs::receive_count(message)
+    }
+}
@@ -0,0 +1,125 @@
+use crate::events::DOCKER_HUB;
+use rusoto_core::Region;
+use std::fmt;
+use std::str::FromStr;
+
+/// Tag Docker assumes when an image reference has none.
+pub const DEFAULT_TAG: &str = "latest";
+
+/// Hosts that refer to Docker Hub when written out in a reference.
+const DOCKER_HUB_ALIASES: &[&str] = &[DOCKER_HUB, "index.docker.io", "registry-1.docker.io"];
+
+/// An image reference on the form [registry/]repository[:tag][@digest],
+/// normalized the way Docker reads it. Docker Hub images are written without
+/// registry and official images without the library/ namespace, so that
+/// "nginx", "docker.io/library/nginx:latest" and "nginx:latest" are all
+/// written as "nginx:latest".
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageRef {
+    pub registry: String,
+    pub repository: String,
+    pub tag: String,
+    pub digest: Option<String>,
+}
+
+/// An image in ECR, addressed the way the ECR API takes it.
+pub struct EcrImage {
+    pub account_id: String,
+    pub region: Region,
+    pub repository_name: String,
+    pub image_tag: String,
+}
+
+/// Whether the first path component of a reference is a registry host
+/// rather than a Docker Hub user, which Docker decides the same way.
+fn is_registry(component: &str) -> bool {
+    component.contains('.') || component.contains(':') || component == "localhost"
+}
+
+impl ImageRef {
+    pub fn parse(input: &str) -> Option<ImageRef> {
+        let (name, digest) = match input.find('@') {
+            Some(at_pos) => (&input[..at_pos], Some(input[at_pos + 1..].to_owned())),
+            None => (input, None),
+        };
+        let (name, tag) = match name.rfind(':') {
+            Some(colon_pos) if !name[colon_pos..].contains('/') => {
+                (&name[..colon_pos], &name[colon_pos + 1..])
+            }
+            _ => (name, DEFAULT_TAG),
+        };
+        let (registry, repository) = match name.find('/') {
+            Some(slash_pos) if is_registry(&name[..slash_pos]) => {
+                (&name[..slash_pos], &name[slash_pos + 1..])
+            }
+            _ => (DOCKER_HUB, name),
+        };
+        if repository.is_empty() || tag.is_empty() || digest.as_deref() == Some("") {
+            return None;
+        }
+        let registry = if DOCKER_HUB_ALIASES.contains(&registry) {
+            DOCKER_HUB
+        } else {
+            registry
+        };
+        let repository = if registry == DOCKER_HUB && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository.to_owned()
+        };
+        Some(ImageRef {
+            registry: registry.to_owned(),
+            repository,
+            tag: tag.to_owned(),
+            digest,
+        })
+    }
+
+    /// The repository as written in a reference, with registry host.
+    pub fn name(&self) -> String {
+        if self.registry == DOCKER_HUB {
+            self.repository.trim_start_matches("library/").to_owned()
+        } else {
+            format!("{}/{}", self.registry, self.repository)
+        }
+    }
+
+    /// The reference without digest.
+    pub fn unpinned(&self) -> String {
+        format!("{}:{}", self.name(), self.tag)
+    }
+
+    /// Whether both refer to the same tag of the same repository, whatever
+    /// digest they are pinned to.
+    pub fn same_tag(&self, other: &ImageRef) -> bool {
+        self.registry == other.registry
+            && self.repository == other.repository
+            && self.tag == other.tag
+    }
+
+    /// The ECR repository of references to registries on the form
+    /// <account>.dkr.ecr.<region>.amazonaws.com[.cn].
+    pub fn ecr(&self) -> Option<EcrImage> {
+        let host_parts: Vec<&str> = self.registry.split('.').collect();
+        match host_parts.as_slice() {
+            [account_id, "dkr", "ecr", region, "amazonaws", "com"]
+            | [account_id, "dkr", "ecr", region, "amazonaws", "com", "cn"] => Some(EcrImage {
+                account_id: (*account_id).to_owned(),
+                region: Region::from_str(region).ok()?,
+                repository_name: self.repository.clone(),
+                image_tag: self.tag.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ImageRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.unpinned())?;
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
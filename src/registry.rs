@@ -0,0 +1,335 @@
+use crate::auth::Cache;
+use crate::events::DOCKER_HUB;
+use crate::{
+    secrets, vault, MissingCredentialOption, Opt, RegistryRefused, RegistryRequest, RegistryToken,
+    Result,
+};
+use bollard::auth::DockerCredentials;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::WWW_AUTHENTICATE;
+use reqwest::{Method, StatusCode, Url};
+use serde_json::Value;
+use snafu::{OptionExt, ResultExt};
+use std::collections::HashMap;
+
+const MANIFEST_MEDIA_TYPES: &str = "application/vnd.docker.distribution.manifest.v2+json, \
+     application/vnd.docker.distribution.manifest.list.v2+json, \
+     application/vnd.oci.image.manifest.v1+json, \
+     application/vnd.oci.image.index.v1+json";
+
+/// Client id token endpoints are told when exchanging identity tokens.
+const CLIENT_ID: &str = "swarm-deployer";
+
+/// Docker Hub is served from a different host than its registry name.
+fn api_host(registry: &str) -> &str {
+    match registry {
+        DOCKER_HUB => "registry-1.docker.io",
+        other => other,
+    }
+}
+
+pub fn manifest_url(registry: &str, repository: &str, reference: &str) -> String {
+    format!(
+        "https://{}/v2/{}/manifests/{}",
+        api_host(registry),
+        repository,
+        reference
+    )
+}
+
+/// Basic auth credentials given on the command line or kept in Secrets
+/// Manager or Vault, for the registries they are scoped to. They are never
+/// sent to other hosts.
+pub fn static_credentials(
+    registry: &str,
+    cache: &mut Cache,
+    opt: &Opt,
+) -> Result<Option<DockerCredentials>> {
+    if opt.registry_password.is_none()
+        && opt.registry_secret.is_none()
+        && opt.vault_secret_path.is_none()
+    {
+        return Ok(None);
+    }
+    if !opt.registry_hosts.iter().any(|host| host == registry) {
+        return Ok(None);
+    }
+    let vault = (&opt.vault_addr, &opt.vault_secret_path);
+    let (username, password) = match (&opt.registry_secret, vault, &opt.registry_password) {
+        (Some(secret), _, _) => secrets::registry_login(secret, &mut cache.secrets, opt)?,
+        (None, (Some(addr), Some(path)), _) => {
+            vault::registry_login(addr, path, &mut cache.vault, opt)?
+        }
+        (None, _, password) => {
+            let username = opt
+                .registry_username
+                .as_ref()
+                .context(MissingCredentialOption {
+                    option: "--registry-username",
+                })?;
+            (username.clone(), password.clone().unwrap_or_default())
+        }
+    };
+    Ok(Some(DockerCredentials {
+        username: Some(username),
+        password: Some(password),
+        serveraddress: Some(registry.to_owned()),
+        ..Default::default()
+    }))
+}
+
+/// A bearer token challenge from a WWW-Authenticate header, telling where
+/// to get a token for the request that was refused.
+#[derive(Debug, PartialEq)]
+pub struct Challenge {
+    pub realm: String,
+    pub service: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// Read a challenge on the form Bearer realm="...",service="...",scope="...".
+/// Values are quoted, and scopes may contain commas.
+pub fn parse_challenge(header: &str) -> Option<Challenge> {
+    let header = header.trim();
+    if !header.get(..7)?.eq_ignore_ascii_case("bearer ") {
+        return None;
+    }
+    let mut params = HashMap::new();
+    let mut rest = header[7..].trim_start();
+    while let Some(eq_pos) = rest.find('=') {
+        let key = rest[..eq_pos].trim().to_ascii_lowercase();
+        rest = &rest[eq_pos + 1..];
+        let value = if rest.starts_with('"') {
+            let end_pos = rest[1..].find('"')? + 1;
+            let value = &rest[1..end_pos];
+            rest = &rest[end_pos + 1..];
+            value
+        } else {
+            let end_pos = rest.find(',').unwrap_or_else(|| rest.len());
+            let value = &rest[..end_pos];
+            rest = &rest[end_pos..];
+            value
+        };
+        params.insert(key, value.to_owned());
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    }
+    Some(Challenge {
+        realm: params.remove("realm")?,
+        service: params.remove("service"),
+        scope: params.remove("scope"),
+    })
+}
+
+/// Form for exchanging an identity token, the refresh token Docker logins
+/// store for registries that support OAuth2, for an access token.
+fn refresh_form<'a>(challenge: &'a Challenge, identity_token: &'a str) -> Vec<(&'a str, &'a str)> {
+    let mut form = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", identity_token),
+        ("client_id", CLIENT_ID),
+    ];
+    if let Some(service) = &challenge.service {
+        form.push(("service", service));
+    }
+    if let Some(scope) = &challenge.scope {
+        form.push(("scope", scope));
+    }
+    form
+}
+
+/// The URL to get a token from for a challenge, with service and scope as
+/// query parameters.
+pub fn token_url(challenge: &Challenge) -> Option<Url> {
+    let mut params = Vec::new();
+    if let Some(service) = &challenge.service {
+        params.push(("service", service.as_str()));
+    }
+    if let Some(scope) = &challenge.scope {
+        params.push(("scope", scope.as_str()));
+    }
+    Url::parse_with_params(&challenge.realm, &params).ok()
+}
+
+/// Answer a challenge with a bearer token. Identity tokens are exchanged
+/// through OAuth2, names and passwords are sent to the token endpoint as
+/// basic auth and without credentials, the token is for anonymous access.
+/// Credentials the token endpoint refuses are reported as RegistryRefused.
+pub fn bearer_token(
+    client: &Client,
+    challenge: &Challenge,
+    credentials: Option<&DockerCredentials>,
+) -> Result<DockerCredentials> {
+    let url = token_url(challenge).context(RegistryToken {
+        url: challenge.realm.clone(),
+    })?;
+    let identity_token = credentials.and_then(|c| c.identitytoken.as_ref());
+    let username = credentials.and_then(|c| c.username.as_ref());
+    let request = match (identity_token, username) {
+        (Some(identity_token), _) => client
+            .post(url.clone())
+            .form(&refresh_form(challenge, identity_token)),
+        (None, Some(username)) => client
+            .get(url.clone())
+            .basic_auth(username, credentials.and_then(|c| c.password.as_ref())),
+        (None, None) => client.get(url.clone()),
+    };
+    let response = request.send().with_context(|| RegistryRequest {
+        url: url.to_string(),
+    })?;
+    if response.status() == StatusCode::UNAUTHORIZED && credentials.is_some() {
+        return RegistryRefused {
+            url: url.to_string(),
+        }
+        .fail();
+    }
+    let response: Value = response
+        .error_for_status()
+        .and_then(|response| response.json())
+        .with_context(|| RegistryRequest {
+            url: url.to_string(),
+        })?;
+    let token = response
+        .get("token")
+        .or_else(|| response.get("access_token"))
+        .and_then(Value::as_str)
+        .context(RegistryToken {
+            url: url.to_string(),
+        })?;
+    Ok(DockerCredentials {
+        registrytoken: Some(token.to_owned()),
+        ..Default::default()
+    })
+}
+
+fn send(
+    client: &Client,
+    method: Method,
+    registry: &str,
+    repository: &str,
+    reference: &str,
+    credentials: Option<&DockerCredentials>,
+) -> Result<Response> {
+    let url = manifest_url(registry, repository, reference);
+    let request = |credentials: Option<&DockerCredentials>| {
+        let mut request = client
+            .request(method.clone(), &url)
+            .header("Accept", MANIFEST_MEDIA_TYPES);
+        if let Some(token) = credentials.and_then(|c| c.registrytoken.as_ref()) {
+            request = request.bearer_auth(token);
+        } else if let Some(username) = credentials.and_then(|c| c.username.as_ref()) {
+            request = request.basic_auth(username, credentials.and_then(|c| c.password.as_ref()));
+        }
+        request
+            .send()
+            .with_context(|| RegistryRequest { url: url.clone() })
+    };
+    let response = request(credentials)?;
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return Ok(response);
+    }
+    if credentials.map_or(false, |c| c.registrytoken.is_some()) {
+        return refused(response, credentials);
+    }
+    // Registries that only speak bearer tokens refuse the first request and
+    // say where to get a token for it
+    let challenge = response
+        .headers()
+        .get(WWW_AUTHENTICATE)
+        .and_then(|header| header.to_str().ok())
+        .and_then(parse_challenge);
+    let challenge = match challenge {
+        Some(challenge) => challenge,
+        None => return refused(response, credentials),
+    };
+    let token = bearer_token(client, &challenge, credentials)?;
+    let response = request(Some(&token))?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return refused(response, credentials);
+    }
+    Ok(response)
+}
+
+/// A 401 in answer to credentials means they are wrong or expired, which
+/// callers need to tell from the image not being there. Without credentials
+/// it only means the image is private.
+fn refused(response: Response, credentials: Option<&DockerCredentials>) -> Result<Response> {
+    match credentials {
+        Some(_) => RegistryRefused {
+            url: response.url().to_string(),
+        }
+        .fail(),
+        None => Ok(response),
+    }
+}
+
+/// Ask the registry whether a manifest exists without downloading it.
+pub fn head_manifest(
+    client: &Client,
+    registry: &str,
+    repository: &str,
+    reference: &str,
+    credentials: Option<&DockerCredentials>,
+) -> Result<StatusCode> {
+    send(
+        client,
+        Method::HEAD,
+        registry,
+        repository,
+        reference,
+        credentials,
+    )
+    .map(|r| r.status())
+}
+
+/// Resolve a tag to the digest the registry currently serves for it.
+pub fn manifest_digest(
+    client: &Client,
+    registry: &str,
+    repository: &str,
+    reference: &str,
+    credentials: Option<&DockerCredentials>,
+) -> Result<Option<String>> {
+    let response = send(
+        client,
+        Method::HEAD,
+        registry,
+        repository,
+        reference,
+        credentials,
+    )?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    Ok(response
+        .headers()
+        .get("Docker-Content-Digest")
+        .and_then(|digest| digest.to_str().ok())
+        .map(str::to_owned))
+}
+
+/// Fetch a manifest or manifest list. Returns None if the registry does not
+/// have it.
+pub fn get_manifest(
+    client: &Client,
+    registry: &str,
+    repository: &str,
+    reference: &str,
+    credentials: Option<&DockerCredentials>,
+) -> Result<Option<Value>> {
+    let url = manifest_url(registry, repository, reference);
+    let response = send(
+        client,
+        Method::GET,
+        registry,
+        repository,
+        reference,
+        credentials,
+    )?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let manifest = response
+        .json()
+        .with_context(|| RegistryRequest { url: url.clone() })?;
+    Ok(Some(manifest))
+}
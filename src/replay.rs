@@ -0,0 +1,90 @@
+use crate::source::{Backlog, EventSource};
+use crate::{ReplayIo, Result};
+use rusoto_sqs::Message;
+use snafu::ResultExt;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::time::Duration;
+
+const BATCH_SIZE: usize = 10;
+const POLL_WAIT: Duration = Duration::from_secs(20);
+
+/// Feeds archived events, one JSON document per line, through the normal
+/// pipeline. The source is exhausted once every line has been handled,
+/// including retries.
+pub struct ReplaySource {
+    name: String,
+    lines: VecDeque<Message>,
+    backlog: Backlog,
+    retrying: usize,
+}
+
+impl ReplaySource {
+    /// Read events from a file, or from stdin when the path is -.
+    pub fn open(path: &str) -> Result<ReplaySource> {
+        if path == "-" {
+            ReplaySource::from_reader("stdin", io::stdin())
+        } else {
+            let file = File::open(path).with_context(|| ReplayIo { path })?;
+            ReplaySource::from_reader(path, file)
+        }
+    }
+
+    pub fn from_reader<R: Read>(name: &str, reader: R) -> Result<ReplaySource> {
+        let mut lines = VecDeque::new();
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line.with_context(|| ReplayIo { path: name })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            lines.push_back(Message {
+                message_id: Some(format!("replay-{}", index + 1)),
+                body: Some(line),
+                ..Default::default()
+            });
+        }
+        Ok(ReplaySource {
+            name: name.to_owned(),
+            lines,
+            backlog: Backlog::new(),
+            retrying: 0,
+        })
+    }
+}
+
+impl EventSource for ReplaySource {
+    fn name(&self) -> String {
+        format!("replay of {}", &self.name)
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        if self.lines.is_empty() && self.retrying > 0 {
+            std::thread::sleep(self.backlog.wait(POLL_WAIT));
+        }
+        let mut messages = self.backlog.take_due();
+        self.retrying -= messages.len();
+        let remaining = BATCH_SIZE.saturating_sub(messages.len());
+        let count = remaining.min(self.lines.len());
+        messages.extend(self.lines.drain(..count));
+        Ok(messages)
+    }
+
+    fn ack(&mut self, _message: &Message) -> Result<()> {
+        Ok(())
+    }
+
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()> {
+        self.backlog.delay(message, delay_seconds);
+        self.retrying += 1;
+        Ok(())
+    }
+
+    fn receive_count(&self, message: &Message) -> u32 {
+        crate::sqs::receive_count(message)
+    }
+
+    fn exhausted(&self) -> bool {
+        self.lines.is_empty() && self.retrying == 0
+    }
+}
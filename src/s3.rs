@@ -0,0 +1,164 @@
+use crate::auth::Cache;
+use crate::events::Event;
+use crate::{aws, watch, FetchingObject, Opt, ReadingObject, Result};
+use log::warn;
+use rusoto_core::Region;
+use rusoto_s3::{GetObjectRequest, S3Client, S3};
+use serde_json::Value;
+use snafu::ResultExt;
+use std::io::Read;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq)]
+pub struct S3Object {
+    pub region: String,
+    pub bucket: String,
+    pub key: String,
+}
+
+/// Object keys in S3 notifications are form-encoded.
+fn decode_key(key: &str) -> String {
+    let bytes = key.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'+' => decoded.push(b' '),
+            b'%' if pos + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[pos + 1..pos + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        pos += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        pos += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Objects created according to an S3 event notification.
+pub fn created_objects(event_str: &str) -> Vec<S3Object> {
+    let parsed: Value = match serde_json::from_str(event_str) {
+        Ok(parsed) => parsed,
+        Err(_) => return Vec::new(),
+    };
+    let records = match parsed.get("Records").and_then(Value::as_array) {
+        Some(records) => records,
+        None => return Vec::new(),
+    };
+    records
+        .iter()
+        .filter(|record| record.get("eventSource").and_then(Value::as_str) == Some("aws:s3"))
+        .filter(|record| {
+            record
+                .get("eventName")
+                .and_then(Value::as_str)
+                .map_or(false, |name| name.starts_with("ObjectCreated:"))
+        })
+        .filter_map(|record| {
+            let s3 = record.get("s3")?;
+            Some(S3Object {
+                region: record.get("awsRegion")?.as_str()?.to_owned(),
+                bucket: s3.get("bucket")?.get("name")?.as_str()?.to_owned(),
+                key: decode_key(s3.get("object")?.get("key")?.as_str()?),
+            })
+        })
+        .collect()
+}
+
+/// Class names the SQS extended client libraries tag their payload
+/// pointers with, current and legacy.
+const POINTER_CLASSES: &[&str] = &[
+    "software.amazon.payloadoffloading.PayloadS3Pointer",
+    "com.amazon.sqs.javamessaging.MessageS3Pointer",
+];
+
+/// Recognise a message body that the SQS extended client replaced with a
+/// pointer to the real payload in S3.
+pub fn payload_pointer(body: &str, region: &Region) -> Option<S3Object> {
+    let parsed: Value = serde_json::from_str(body).ok()?;
+    let parts = parsed.as_array()?;
+    if parts.len() != 2 || !POINTER_CLASSES.contains(&parts[0].as_str()?) {
+        return None;
+    }
+    Some(S3Object {
+        region: region.name().to_owned(),
+        bucket: parts[1].get("s3BucketName")?.as_str()?.to_owned(),
+        key: parts[1].get("s3Key")?.as_str()?.to_owned(),
+    })
+}
+
+/// A deployment manifest lists image references, either one per line with
+/// # comments, or as a JSON array. References may be pinned with a digest;
+/// the digest of unpinned references is looked up in the registry.
+pub fn parse_manifest(manifest: &str) -> Vec<Event> {
+    let images: Vec<String> = if manifest.trim_start().starts_with('[') {
+        match serde_json::from_str::<Vec<String>>(manifest) {
+            Ok(images) => images,
+            Err(err) => {
+                warn!("Ignoring malformed JSON manifest: {}", err);
+                return Vec::new();
+            }
+        }
+    } else {
+        manifest
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect()
+    };
+    images
+        .iter()
+        .filter_map(|image| {
+            let event = watch::event_for_image(image);
+            if event.is_none() {
+                warn!("Ignoring manifest entry {} without a tag", image);
+            }
+            event
+        })
+        .collect()
+}
+
+/// Fetch an object as text, such as a manifest or an offloaded payload.
+pub fn fetch(object: &S3Object, opt: &Opt) -> Result<String> {
+    let region = Region::from_str(&object.region).unwrap_or_default();
+    let s3: S3Client = aws::client(opt, region)?;
+    let req = GetObjectRequest {
+        bucket: object.bucket.clone(),
+        key: object.key.clone(),
+        ..Default::default()
+    };
+    let output = s3.get_object(req).sync().with_context(|| FetchingObject {
+        bucket: object.bucket.clone(),
+        key: object.key.clone(),
+    })?;
+    let mut manifest = String::new();
+    if let Some(body) = output.body {
+        body.into_blocking_read()
+            .read_to_string(&mut manifest)
+            .with_context(|| ReadingObject {
+                key: object.key.clone(),
+            })?;
+    }
+    Ok(manifest)
+}
+
+/// Events for every image in the manifests an S3 notification announces.
+pub fn manifest_events(event_str: &str, cache: &mut Cache, opt: &Opt) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for object in created_objects(event_str) {
+        let manifest = fetch(&object, opt)?;
+        events.extend(watch::resolve_digests(
+            parse_manifest(&manifest),
+            cache,
+            opt,
+        )?);
+    }
+    Ok(events)
+}
@@ -0,0 +1,101 @@
+use crate::auth::Cache;
+use crate::events::ScanCompleted;
+use crate::reference::ImageRef;
+use crate::{aws, DescribingRepository, Opt, Result, SeedyError, SeverityFormat};
+use bollard::service::Service;
+use rusoto_ecr::{DescribeRepositoriesRequest, Ecr, Repository};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// ECR finding severities, in increasing order of gravity. UNDEFINED
+/// findings are treated as critical.
+const SEVERITIES: &[&str] = &[
+    "INFORMATIONAL",
+    "LOW",
+    "MEDIUM",
+    "HIGH",
+    "CRITICAL",
+    "UNDEFINED",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Severity(usize);
+
+impl FromStr for Severity {
+    type Err = SeedyError;
+
+    fn from_str(input: &str) -> Result<Severity> {
+        let input = input.to_uppercase();
+        match SEVERITIES.iter().position(|severity| *severity == input) {
+            Some(position) => Ok(Severity(position.min(SEVERITIES.len() - 2))),
+            None => SeverityFormat { value: input }.fail(),
+        }
+    }
+}
+
+fn rank(severity: &str) -> usize {
+    SEVERITIES
+        .iter()
+        .position(|known| *known == severity)
+        .unwrap_or(SEVERITIES.len() - 1)
+        .min(SEVERITIES.len() - 2)
+}
+
+/// Findings in the scan at or above the gate severity.
+pub fn blocking_findings(scan: &ScanCompleted, gate: Severity) -> u64 {
+    scan.severity_counts
+        .iter()
+        .filter(|(severity, _)| rank(severity) >= gate.0)
+        .map(|(_, count)| count)
+        .sum()
+}
+
+pub fn scans_on_push(repository: &Repository) -> bool {
+    repository
+        .image_scanning_configuration
+        .as_ref()
+        .and_then(|configuration| configuration.scan_on_push)
+        .unwrap_or(false)
+}
+
+/// Repositories of tracked ECR images that are not scanned on push. The
+/// gate would hold pushes to them forever, waiting for a scan that never
+/// comes.
+pub fn unscanned_repositories(
+    services_by_image: &HashMap<String, Service<String>>,
+    cache: &mut Cache,
+    opt: &Opt,
+) -> Result<Vec<String>> {
+    let mut unscanned = Vec::new();
+    for image in services_by_image.keys() {
+        let ecr_image = match ImageRef::parse(image).and_then(|image| image.ecr()) {
+            Some(ecr_image) => ecr_image,
+            None => continue,
+        };
+        let ecr = cache.ecr_clients.get(
+            opt,
+            aws::ecr_region(opt, ecr_image.region.clone()),
+            aws::ecr_role(opt, &ecr_image.account_id),
+        )?;
+        let req = DescribeRepositoriesRequest {
+            registry_id: Some(ecr_image.account_id.clone()),
+            repository_names: Some(vec![ecr_image.repository_name.clone()]),
+            ..Default::default()
+        };
+        let repositories = ecr
+            .describe_repositories(req)
+            .sync()
+            .with_context(|| DescribingRepository {
+                repository_name: ecr_image.repository_name.clone(),
+            })?
+            .repositories
+            .unwrap_or_default();
+        if !repositories.iter().all(scans_on_push) {
+            unscanned.push(ecr_image.repository_name);
+        }
+    }
+    unscanned.sort();
+    unscanned.dedup();
+    Ok(unscanned)
+}
@@ -0,0 +1,91 @@
+use bollard::service::Service;
+use chrono::{DateTime, Duration, Utc};
+use cron::Schedule;
+use log::warn;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub const REDEPLOY_CRON_LABEL: &str = "seedy.redeploy-cron";
+
+/// How often services are listed to pick up schedules that were added or
+/// changed since the last listing.
+const RESCAN_MINUTES: i64 = 5;
+
+/// Parse a standard five-field cron expression. The cron crate expects a
+/// leading seconds field, so one is supplied.
+pub fn parse_cron(expression: &str) -> Option<Schedule> {
+    Schedule::from_str(&format!("0 {}", expression.trim())).ok()
+}
+
+/// Tracks when each labelled service is next due for a forced redeploy.
+#[derive(Default)]
+pub struct Redeploys {
+    next: HashMap<String, (String, DateTime<Utc>)>,
+    scanned_at: Option<DateTime<Utc>>,
+}
+
+impl Redeploys {
+    pub fn new() -> Redeploys {
+        Redeploys::default()
+    }
+
+    /// Whether services need listing, because a schedule has come due or
+    /// their labels have not been read for a while.
+    pub fn pending(&self, now: DateTime<Utc>) -> bool {
+        match self.scanned_at {
+            Some(scanned_at) => {
+                now >= scanned_at + Duration::minutes(RESCAN_MINUTES)
+                    || self.next.values().any(|(_, at)| *at <= now)
+            }
+            None => true,
+        }
+    }
+
+    /// Return the services whose schedule has come due. A service seen for
+    /// the first time, or whose expression has changed, is only scheduled.
+    pub fn due<'a, I>(&mut self, services: I, now: DateTime<Utc>) -> Vec<&'a Service<String>>
+    where
+        I: IntoIterator<Item = &'a Service<String>>,
+    {
+        let mut due = Vec::new();
+        let mut next = HashMap::new();
+        for service in services {
+            let expression = match service.spec.labels.get(REDEPLOY_CRON_LABEL) {
+                Some(expression) => expression,
+                None => continue,
+            };
+            let schedule = match parse_cron(expression) {
+                Some(schedule) => schedule,
+                None => {
+                    warn!(
+                        "Ignoring {}={} on service {}, not a valid cron expression",
+                        REDEPLOY_CRON_LABEL, expression, &service.id
+                    );
+                    continue;
+                }
+            };
+            let previous = self
+                .next
+                .get(&service.id)
+                .filter(|(previous, _)| previous == expression);
+            let upcoming = match previous {
+                Some((_, at)) if *at > now => *at,
+                Some(_) => {
+                    due.push(service);
+                    match schedule.after(&now).next() {
+                        Some(at) => at,
+                        None => continue,
+                    }
+                }
+                None => match schedule.after(&now).next() {
+                    Some(at) => at,
+                    None => continue,
+                },
+            };
+            next.insert(service.id.clone(), (expression.clone(), upcoming));
+        }
+        self.next = next;
+        self.scanned_at = Some(now);
+        due
+    }
+}
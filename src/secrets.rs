@@ -0,0 +1,102 @@
+use crate::{aws, FetchingSecret, Opt, Result, SecretArnFormat, SecretFormat, SeedyError};
+use rusoto_core::Region;
+use rusoto_secretsmanager::{GetSecretValueRequest, SecretsManager, SecretsManagerClient};
+use serde_json::Value;
+use snafu::{OptionExt, ResultExt};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How long a fetched secret is used before it is fetched again, so that
+/// rotated credentials are picked up without a restart.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(900);
+
+/// Logins fetched per secret, with when they were fetched.
+#[derive(Default)]
+pub struct Logins {
+    logins: HashMap<String, (String, String, Instant)>,
+}
+
+impl Logins {
+    /// Forget all fetched logins, as when a registry has refused one.
+    pub fn clear(&mut self) {
+        self.logins.clear();
+    }
+}
+
+/// A Secrets Manager secret ARN, with the region to fetch it from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecretArn {
+    pub arn: String,
+    pub region: Region,
+}
+
+impl FromStr for SecretArn {
+    type Err = SeedyError;
+
+    fn from_str(input: &str) -> Result<SecretArn> {
+        let parts: Vec<&str> = input.splitn(7, ':').collect();
+        match parts.as_slice() {
+            ["arn", _, "secretsmanager", region, _, "secret", name] if !name.is_empty() => {
+                match region.parse::<Region>() {
+                    Ok(region) => Ok(SecretArn {
+                        arn: input.to_owned(),
+                        region,
+                    }),
+                    Err(_) => SecretArnFormat { value: input }.fail(),
+                }
+            }
+            _ => SecretArnFormat { value: input }.fail(),
+        }
+    }
+}
+
+/// Username and password from a secret string like {"username": ..,
+/// "password": ..}, the format ECS uses for private registry credentials.
+pub fn parse_login(secret_id: &str, secret: &str) -> Result<(String, String)> {
+    let value: Value = serde_json::from_str(secret)
+        .ok()
+        .context(SecretFormat { secret_id })?;
+    let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_owned);
+    let username = field("username").context(SecretFormat { secret_id })?;
+    let password = field("password").context(SecretFormat { secret_id })?;
+    Ok((username, password))
+}
+
+/// The registry login stored in the secret, fetched again once the previous
+/// fetch is older than the refresh interval.
+pub fn registry_login(
+    secret: &SecretArn,
+    logins: &mut Logins,
+    opt: &Opt,
+) -> Result<(String, String)> {
+    let cached = logins
+        .logins
+        .get(&secret.arn)
+        .filter(|(_, _, fetched_at)| fetched_at.elapsed() < REFRESH_INTERVAL)
+        .map(|(username, password, _)| (username.clone(), password.clone()));
+    if let Some(login) = cached {
+        return Ok(login);
+    }
+    let client: SecretsManagerClient = aws::client(opt, secret.region.clone())?;
+    let req = GetSecretValueRequest {
+        secret_id: secret.arn.clone(),
+        ..Default::default()
+    };
+    let secret_string = client
+        .get_secret_value(req)
+        .sync()
+        .with_context(|| FetchingSecret {
+            secret_id: secret.arn.clone(),
+        })?
+        .secret_string
+        .context(SecretFormat {
+            secret_id: secret.arn.clone(),
+        })?;
+    let (username, password) = parse_login(&secret.arn, &secret_string)?;
+    logins.logins.insert(
+        secret.arn.clone(),
+        (username.clone(), password.clone(), Instant::now()),
+    );
+    Ok((username, password))
+}
@@ -0,0 +1,192 @@
+use crate::aws;
+#[cfg(not(feature = "tls"))]
+use crate::FeatureDisabled;
+use crate::{Opt, Result, SettingFilterPolicy, SubscriptionArnFormat};
+#[cfg(feature = "tls")]
+use crate::{SnsCertificate, SnsSignature};
+use rusoto_core::Region;
+use rusoto_sns::{SetSubscriptionAttributesInput, Sns, SnsClient};
+use serde_json::{Map, Value};
+use snafu::{OptionExt, ResultExt};
+#[cfg(feature = "tls")]
+use std::collections::HashMap;
+
+/// Fields covered by the signature of a notification, in signing order.
+const SIGNED_FIELDS: &[&str] = &[
+    "Message",
+    "MessageId",
+    "Subject",
+    "Timestamp",
+    "TopicArn",
+    "Type",
+];
+
+/// Recognise an SNS notification envelope, as delivered to SQS when raw
+/// message delivery is off.
+pub fn envelope(body: &str) -> Option<Value> {
+    let parsed: Value = serde_json::from_str(body).ok()?;
+    if parsed.get("Type")?.as_str() != Some("Notification") {
+        return None;
+    }
+    parsed.get("TopicArn")?.as_str()?;
+    parsed.get("Message")?.as_str()?;
+    Some(parsed)
+}
+
+pub fn message(envelope: &Value) -> &str {
+    envelope
+        .get("Message")
+        .and_then(Value::as_str)
+        .unwrap_or("")
+}
+
+pub fn string_to_sign(envelope: &Value) -> String {
+    SIGNED_FIELDS
+        .iter()
+        .filter_map(|field| {
+            envelope
+                .get(*field)
+                .and_then(Value::as_str)
+                .map(|value| format!("{}\n{}\n", field, value))
+        })
+        .collect()
+}
+
+/// Only certificates served by SNS itself may vouch for a message.
+pub fn trusted_certificate_url(url: &str) -> bool {
+    if !url.starts_with("https://sns.") || !url.ends_with(".pem") {
+        return false;
+    }
+    let host = url["https://".len()..].split('/').next().unwrap_or("");
+    host.ends_with(".amazonaws.com") && host.split('.').count() == 4
+}
+
+/// Checks notification signatures, keeping the signing certificates it has
+/// fetched by URL. SNS rarely rotates its certificate, and a new one comes
+/// with a new URL.
+#[derive(Default)]
+pub struct Verifier {
+    #[cfg(feature = "tls")]
+    certificates: HashMap<String, openssl::x509::X509>,
+}
+
+impl Verifier {
+    #[cfg(feature = "tls")]
+    pub fn new() -> Result<Verifier> {
+        Ok(Verifier::default())
+    }
+
+    /// Without the tls feature, asking for signatures to be checked is
+    /// refused at startup rather than failing every message.
+    #[cfg(not(feature = "tls"))]
+    pub fn new() -> Result<Verifier> {
+        FeatureDisabled {
+            feature: "tls",
+            option: "--verify-sns-signatures",
+        }
+        .fail()
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn verify(&mut self, envelope: &Value) -> Result<()> {
+        use openssl::hash::MessageDigest;
+        use openssl::sign::Verifier as SignatureVerifier;
+        use openssl::x509::X509;
+
+        let field = |name: &str| envelope.get(name).and_then(Value::as_str).unwrap_or("");
+        let invalid = |message: String| SnsSignature { message };
+        let url = field("SigningCertURL");
+        if !trusted_certificate_url(url) {
+            return invalid(format!("untrusted signing certificate {}", url)).fail();
+        }
+        let digest = match field("SignatureVersion") {
+            "1" => MessageDigest::sha1(),
+            "2" => MessageDigest::sha256(),
+            other => return invalid(format!("unknown signature version {}", other)).fail(),
+        };
+        let signature = match base64::decode(field("Signature")) {
+            Ok(signature) => signature,
+            Err(err) => return invalid(format!("signature is not base64: {}", err)).fail(),
+        };
+        let certificate = match self.certificates.get(url) {
+            Some(certificate) => certificate.clone(),
+            None => {
+                let pem = reqwest::blocking::get(url)
+                    .and_then(|response| response.error_for_status())
+                    .and_then(|response| response.bytes())
+                    .with_context(|| SnsCertificate { url })?;
+                let certificate = match X509::from_pem(&pem) {
+                    Ok(certificate) => certificate,
+                    Err(err) => return invalid(err.to_string()).fail(),
+                };
+                self.certificates
+                    .insert(url.to_owned(), certificate.clone());
+                certificate
+            }
+        };
+        let verified = certificate.public_key().and_then(|key| {
+            let mut verifier = SignatureVerifier::new(digest, &key)?;
+            verifier.update(string_to_sign(envelope).as_bytes())?;
+            verifier.verify(&signature)
+        });
+        match verified {
+            Ok(true) => Ok(()),
+            Ok(false) => invalid("signature does not match".to_owned()).fail(),
+            Err(err) => invalid(err.to_string()).fail(),
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub fn verify(&mut self, _envelope: &Value) -> Result<()> {
+        FeatureDisabled {
+            feature: "tls",
+            option: "--verify-sns-signatures",
+        }
+        .fail()
+    }
+}
+
+/// SNS filter policy matching the given message attributes. As with
+/// --attribute-filter, values for the same key are alternatives.
+pub fn filter_policy(filters: &[(String, String)]) -> String {
+    let mut policy = Map::new();
+    for (key, value) in filters.iter() {
+        policy
+            .entry(key.clone())
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+            .unwrap()
+            .push(Value::String(value.clone()));
+    }
+    Value::Object(policy).to_string()
+}
+
+pub fn subscription_region(subscription_arn: &str) -> Option<Region> {
+    let parts: Vec<&str> = subscription_arn.split(':').collect();
+    match parts.as_slice() {
+        ["arn", _, "sns", region, _, _, _] => region.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Have SNS deliver only messages for this deployer to its queue, rather
+/// than having every deployer receive and discard the others' messages.
+pub fn apply_filter_policy(
+    subscription_arn: &str,
+    filters: &[(String, String)],
+    opt: &Opt,
+) -> Result<()> {
+    let region = subscription_region(subscription_arn).context(SubscriptionArnFormat {
+        value: subscription_arn,
+    })?;
+    let sns: SnsClient = aws::client(opt, region)?;
+    let req = SetSubscriptionAttributesInput {
+        subscription_arn: subscription_arn.to_owned(),
+        attribute_name: "FilterPolicy".to_owned(),
+        attribute_value: Some(filter_policy(filters)),
+    };
+    sns.set_subscription_attributes(req)
+        .sync()
+        .with_context(|| SettingFilterPolicy { subscription_arn })?;
+    Ok(())
+}
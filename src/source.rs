@@ -0,0 +1,339 @@
+use crate::{
+    build_service_index, ecr_tokens, events, message_events, process_events, stepfunctions,
+    Deployer, Opt, Parsed, Result, SeedyError, UnrecognizedPolicyFormat,
+};
+use log::{debug, error, info, warn};
+use rusoto_sqs::Message;
+use rusoto_stepfunctions::StepFunctionsClient;
+use serde_json::json;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Somewhere deployment events are received from. Messages that are neither
+/// acked nor nacked are expected to be redelivered by the source.
+pub trait EventSource {
+    fn name(&self) -> String;
+    fn poll(&mut self) -> Result<Vec<Message>>;
+    /// The message has been handled and should not be delivered again
+    fn ack(&mut self, message: &Message) -> Result<()>;
+    /// The message could not be handled and should be redelivered after delay
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()>;
+    /// Times this message has been delivered, including this delivery
+    fn receive_count(&self, message: &Message) -> u32;
+    /// Messages waiting and in flight per queue, where the source can tell
+    fn depth(&self) -> Result<Vec<(String, u64, u64)>> {
+        Ok(Vec::new())
+    }
+    /// Keep the message from being redelivered while it is processed
+    fn lease(&self, _message: &Message) -> Option<Lease> {
+        None
+    }
+    /// Receive count at which the source moves a failing message aside
+    /// itself, like SQS does with a redrive policy, rather than it being
+    /// dropped
+    fn max_receive_count(&self, _message: &Message) -> Option<u32> {
+        None
+    }
+    /// Ack several messages at once, for sources where that saves requests
+    fn ack_all(&mut self, messages: &[&Message]) -> Result<()> {
+        for message in messages.iter() {
+            self.ack(message)?;
+        }
+        Ok(())
+    }
+    /// A finite source has nothing more to deliver and the deployer can exit
+    fn exhausted(&self) -> bool {
+        false
+    }
+}
+
+/// Renews a hold on a message from a background thread until dropped.
+pub struct Lease {
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Lease {
+    pub fn spawn<F>(interval: Duration, mut renew: F) -> Lease
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                renew();
+            }
+        });
+        Lease {
+            stop: Some(stop),
+            worker: Some(worker),
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Nacked messages waiting for redelivery, for sources that cannot ask the
+/// sender to redeliver.
+#[derive(Default)]
+pub struct Backlog {
+    delayed: Vec<(Instant, Message)>,
+}
+
+impl Backlog {
+    pub fn new() -> Backlog {
+        Backlog::default()
+    }
+
+    /// Queue the message for redelivery with its receive count bumped.
+    pub fn delay(&mut self, message: &Message, delay_seconds: i64) {
+        let receive_count = crate::sqs::receive_count(message) + 1;
+        let mut message = message.clone();
+        let mut attributes = message.attributes.take().unwrap_or_else(HashMap::new);
+        attributes.insert(
+            "ApproximateReceiveCount".to_owned(),
+            receive_count.to_string(),
+        );
+        message.attributes = Some(attributes);
+        let at = Instant::now() + Duration::from_secs(delay_seconds.max(0) as u64);
+        self.delayed.push((at, message));
+    }
+
+    /// Whether a message with this id is waiting to be redelivered.
+    pub fn contains(&self, message_id: &str) -> bool {
+        self.delayed
+            .iter()
+            .any(|(_, message)| message.message_id.as_deref() == Some(message_id))
+    }
+
+    pub fn take_due(&mut self) -> Vec<Message> {
+        let now = Instant::now();
+        let (due, waiting) = self.delayed.drain(..).partition(|(at, _)| *at <= now);
+        self.delayed = waiting;
+        due.into_iter().map(|(_, message)| message).collect()
+    }
+
+    /// How long to wait for new messages before the next one is due.
+    pub fn wait(&self, max_wait: Duration) -> Duration {
+        self.delayed
+            .iter()
+            .map(|(at, _)| at.saturating_duration_since(Instant::now()))
+            .min()
+            .unwrap_or(max_wait)
+            .min(max_wait)
+    }
+}
+
+/// What to do with messages that carry no event the deployer recognizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unrecognized {
+    /// Ack the message, so that it is not delivered again
+    Drop,
+    /// Forward the message to the rejects queue, then ack it
+    Reject,
+    /// Leave the message for the source to redeliver or redrive
+    Leave,
+}
+
+impl Unrecognized {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unrecognized::Drop => "drop",
+            Unrecognized::Reject => "reject",
+            Unrecognized::Leave => "leave",
+        }
+    }
+}
+
+impl FromStr for Unrecognized {
+    type Err = SeedyError;
+
+    fn from_str(input: &str) -> Result<Unrecognized> {
+        match input {
+            "drop" => Ok(Unrecognized::Drop),
+            "reject" => Ok(Unrecognized::Reject),
+            "leave" => Ok(Unrecognized::Leave),
+            _ => UnrecognizedPolicyFormat { value: input }.fail(),
+        }
+    }
+}
+
+/// Apply the unrecognized message policy, returning whether to ack.
+fn handle_unrecognized(message: &Message, deployer: &Deployer, opt: &Opt) -> bool {
+    let outcome = match (opt.unrecognized, &deployer.rejects) {
+        (Unrecognized::Reject, Some(rejects)) => match rejects.forward(message) {
+            Ok(()) => {
+                info!("Forwarded unrecognized message {:?}", &message.message_id);
+                Unrecognized::Reject
+            }
+            Err(err) => {
+                warn!("{}; leaving message on the queue", err);
+                Unrecognized::Leave
+            }
+        },
+        (Unrecognized::Reject, None) | (Unrecognized::Leave, _) => Unrecognized::Leave,
+        (Unrecognized::Drop, _) => Unrecognized::Drop,
+    };
+    deployer.metrics.record_unrecognized(outcome.as_str());
+    outcome != Unrecognized::Leave
+}
+
+/// Tell a Step Functions workflow waiting on the message how it went.
+/// Failing to report is logged, as the workflow will eventually time out.
+fn report_success(
+    states: &StepFunctionsClient,
+    task_token: &str,
+    message: &Message,
+    images: &[String],
+) {
+    let output = json!({
+        "message_id": message.message_id,
+        "images": images,
+    });
+    if let Err(err) = stepfunctions::report_success(states, task_token, &output) {
+        warn!("{}", err);
+    }
+}
+
+/// Only reported once the message is given up on, by the deployer or by
+/// the redrive policy, as the workflow may not wait for a retry.
+fn report_failure(states: &StepFunctionsClient, task_token: &str, err: &SeedyError) {
+    if let Err(err) = stepfunctions::report_failure(states, task_token, &err.to_string()) {
+        warn!("{}", err);
+    }
+}
+
+/// Process a batch of messages, acking those that are done with and nacking
+/// those that failed, so that one bad message does not hold up the rest.
+/// Acks are sent together once the batch is through. Once a message of a
+/// FIFO group is nacked, later messages of that group in the batch are put
+/// back unprocessed so that they are not applied ahead of the retry.
+pub fn dispatch(
+    source: &mut dyn EventSource,
+    messages: &[Message],
+    deployer: &mut Deployer,
+    opt: &Opt,
+) -> Result<()> {
+    let mut held_groups: HashMap<String, i64> = HashMap::new();
+    let mut done: Vec<&Message> = Vec::new();
+    let mut leases: Vec<Option<Lease>> = messages
+        .iter()
+        .map(|message| source.lease(message))
+        .collect();
+    let mut parsed: Vec<Result<Option<Parsed>>> = messages
+        .iter()
+        .map(|message| message_events(message, deployer, opt))
+        .collect();
+    let batch = parsed
+        .iter_mut()
+        .filter_map(|res| res.as_mut().ok().and_then(Option::as_mut))
+        .map(|parsed| &mut parsed.events)
+        .collect();
+    for event in events::coalesce(batch) {
+        info!(
+            "Skipping {}, superseded by a newer push in the same batch",
+            event.pinned_image()
+        );
+    }
+    let parseable = parsed.iter().any(|res| match res {
+        Ok(Some(parsed)) => !parsed.events.is_empty() || !parsed.deletions.is_empty(),
+        _ => false,
+    });
+    let services_by_image = if parseable {
+        build_service_index(deployer.services()?, opt)
+    } else {
+        HashMap::new()
+    };
+    // Only pushes to images some service runs will need a token
+    let pushes: Vec<&events::Event> = parsed
+        .iter()
+        .filter_map(|res| res.as_ref().ok().and_then(Option::as_ref))
+        .flat_map(|parsed| parsed.events.iter())
+        .filter(|event| services_by_image.contains_key(&event.image()))
+        .collect();
+    if let Err(err) = ecr_tokens::prefetch(&pushes, &mut deployer.credentials, opt) {
+        warn!("{}; fetching tokens per event instead", err);
+    }
+    for (index, (message, parsed)) in messages.iter().zip(parsed).enumerate() {
+        let group = crate::sqs::message_group(message);
+        if let Some(delay) = group.and_then(|group| held_groups.get(group)) {
+            debug!(
+                "Holding back message {:?} behind retry in its group",
+                &message.message_id
+            );
+            leases[index].take();
+            source.nack(message, *delay)?;
+            continue;
+        }
+        let task_token = stepfunctions::task_token(message);
+        let mut images: Vec<String> = Vec::new();
+        let res = match parsed {
+            Ok(Some(parsed)) => {
+                images.extend(parsed.events.iter().map(events::Event::image));
+                process_events(message, parsed, &services_by_image, deployer, opt)
+            }
+            Ok(None) => {
+                if handle_unrecognized(message, deployer, opt) {
+                    done.push(message);
+                } else {
+                    leases[index].take();
+                }
+                continue;
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = &res {
+            let attempt = source.receive_count(message);
+            let redrive_at = source.max_receive_count(message);
+            let last_attempt = match redrive_at {
+                Some(limit) => attempt >= limit,
+                None => attempt > opt.max_retries,
+            };
+            if let (true, Some(task_token)) = (last_attempt, &task_token) {
+                report_failure(&deployer.states, task_token, err);
+            }
+            if attempt > opt.max_retries && redrive_at.is_none() {
+                error!("{}; giving up after {} retries", err, opt.max_retries);
+            } else {
+                let delay = crate::sqs::retry_delay(attempt, opt.retry_delay_seconds);
+                if attempt <= opt.max_retries {
+                    warn!("{}; retry {} in {}s", err, attempt, delay);
+                } else {
+                    error!(
+                        "{}; failed {} times, leaving message to the redrive policy",
+                        err, attempt
+                    );
+                }
+                leases[index].take();
+                source.nack(message, delay)?;
+                if let Some(group) = group {
+                    held_groups.insert(group.to_owned(), delay);
+                }
+                continue;
+            }
+        }
+        if let (Ok(()), Some(task_token)) = (&res, &task_token) {
+            report_success(&deployer.states, task_token, message, &images);
+        }
+        done.push(message);
+    }
+    // Stop heartbeats first, so none extends a message after its delete.
+    drop(leases);
+    source.ack_all(&done)?;
+    for message in done {
+        if let Some(message_id) = &message.message_id {
+            deployer.journal.clear(message_id)?;
+        }
+    }
+    Ok(())
+}
@@ -1,28 +1,51 @@
-use crate::{AckingMessage, Opt, PollingMessage, Result, SqsUrl};
-use rusoto_sqs::{DeleteMessageRequest, GetQueueUrlRequest, Message, ReceiveMessageRequest, Sqs};
+use crate::source::{EventSource, Lease};
+use crate::{
+    AckingMessage, AckingMessages, DelayingMessage, ForwardingMessage, PollingMessage,
+    QueueArnFormat, QueueAttributes, Result, SeedyError, SqsUrl,
+};
+use log::{debug, info, warn};
+use rusoto_core::{Region, RusotoError};
+use rusoto_sqs::{
+    ChangeMessageVisibilityRequest, DeleteMessageBatchRequest, DeleteMessageBatchRequestEntry,
+    DeleteMessageRequest, GetQueueAttributesRequest, GetQueueUrlRequest, Message,
+    ReceiveMessageRequest, SendMessageRequest, Sqs, SqsClient,
+};
+use serde_json::Value;
 use snafu::ResultExt;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-fn resolve_queue_url(sqs: &dyn Sqs, opt: &Opt) -> Result<String> {
+fn resolve_queue_url(sqs: &dyn Sqs, queue_name: &str) -> Result<String> {
     let req = GetQueueUrlRequest {
-        queue_name: opt.queue_name.clone(),
+        queue_name: queue_name.to_owned(),
         ..Default::default()
     };
     let queue_url = sqs
         .get_queue_url(req)
         .sync()
-        .with_context(|| SqsUrl {
-            queue_name: opt.queue_name.clone(),
-        })?
+        .with_context(|| SqsUrl { queue_name })?
         .queue_url
         .unwrap();
     Ok(queue_url)
 }
 
-pub fn poll_messages(sqs: &dyn Sqs, opt: &Opt) -> Result<Vec<Message>> {
-    let queue_url = resolve_queue_url(sqs, opt)?;
+pub fn poll_messages(
+    sqs: &dyn Sqs,
+    queue_name: &str,
+    max_messages: i64,
+    wait_seconds: i64,
+) -> Result<Vec<Message>> {
+    let queue_url = resolve_queue_url(sqs, queue_name)?;
     let request = ReceiveMessageRequest {
         queue_url: queue_url.clone(),
-        wait_time_seconds: Some(20),
+        attribute_names: Some(vec![
+            "ApproximateReceiveCount".to_owned(),
+            "MessageGroupId".to_owned(),
+        ]),
+        max_number_of_messages: Some(max_messages),
+        message_attribute_names: Some(vec!["All".to_owned()]),
+        wait_time_seconds: Some(wait_seconds),
         ..Default::default()
     };
     let messages = sqs
@@ -36,8 +59,8 @@ pub fn poll_messages(sqs: &dyn Sqs, opt: &Opt) -> Result<Vec<Message>> {
     Ok(messages)
 }
 
-pub fn delete_message(sqs: &dyn Sqs, message: &Message, opt: &Opt) -> Result<()> {
-    let queue_url = resolve_queue_url(sqs, opt)?;
+pub fn delete_message(sqs: &dyn Sqs, message: &Message, queue_name: &str) -> Result<()> {
+    let queue_url = resolve_queue_url(sqs, queue_name)?;
     let receipt_handle = message.receipt_handle.as_ref().expect("No handle");
     let req = DeleteMessageRequest {
         queue_url: queue_url.clone(),
@@ -51,3 +74,550 @@ pub fn delete_message(sqs: &dyn Sqs, message: &Message, opt: &Opt) -> Result<()>
         })?;
     Ok(())
 }
+
+/// DeleteMessageBatch takes at most this many entries.
+const MAX_BATCH_SIZE: usize = 10;
+
+/// Delete messages in batches. Entries SQS fails to delete are logged and
+/// will be redelivered.
+pub fn delete_messages(sqs: &dyn Sqs, messages: &[&Message], queue_name: &str) -> Result<()> {
+    let queue_url = resolve_queue_url(sqs, queue_name)?;
+    for batch in messages.chunks(MAX_BATCH_SIZE) {
+        let entries = batch
+            .iter()
+            .enumerate()
+            .map(|(index, message)| DeleteMessageBatchRequestEntry {
+                id: index.to_string(),
+                receipt_handle: message.receipt_handle.clone().expect("No handle"),
+            })
+            .collect();
+        let req = DeleteMessageBatchRequest {
+            queue_url: queue_url.clone(),
+            entries,
+        };
+        let result = sqs
+            .delete_message_batch(req)
+            .sync()
+            .with_context(|| AckingMessages {
+                queue_url: queue_url.clone(),
+            })?;
+        for failed in result.failed {
+            let message_id = failed
+                .id
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| batch.get(index))
+                .and_then(|message| message.message_id.as_ref());
+            warn!(
+                "Failed to ack message {:?} on {}: {} {}",
+                message_id,
+                &queue_url,
+                failed.code,
+                failed.message.unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Approximate number of visible and in-flight messages on the queue.
+pub fn queue_depth(sqs: &dyn Sqs, queue_name: &str) -> Result<(u64, u64)> {
+    let queue_url = resolve_queue_url(sqs, queue_name)?;
+    let req = GetQueueAttributesRequest {
+        queue_url: queue_url.clone(),
+        attribute_names: Some(vec![
+            "ApproximateNumberOfMessages".to_owned(),
+            "ApproximateNumberOfMessagesNotVisible".to_owned(),
+        ]),
+    };
+    let attributes = sqs
+        .get_queue_attributes(req)
+        .sync()
+        .with_context(|| QueueAttributes {
+            queue_url: queue_url.clone(),
+        })?
+        .attributes
+        .unwrap_or_default();
+    let count = |name: &str| {
+        attributes
+            .get(name)
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0)
+    };
+    Ok((
+        count("ApproximateNumberOfMessages"),
+        count("ApproximateNumberOfMessagesNotVisible"),
+    ))
+}
+
+/// The receive count at which SQS gives up on a message, per the
+/// maxReceiveCount of a redrive policy.
+pub fn redrive_limit(policy: &str) -> Option<u32> {
+    let policy: Value = serde_json::from_str(policy).ok()?;
+    match policy.get("maxReceiveCount")? {
+        Value::Number(count) => count.as_u64().map(|count| count as u32),
+        Value::String(count) => count.parse().ok(),
+        _ => None,
+    }
+}
+
+/// How many receives SQS allows messages on the queue before moving them
+/// to a dead-letter queue, if it has a redrive policy.
+pub fn max_receive_count(sqs: &dyn Sqs, queue_name: &str) -> Result<Option<u32>> {
+    let queue_url = resolve_queue_url(sqs, queue_name)?;
+    let req = GetQueueAttributesRequest {
+        queue_url: queue_url.clone(),
+        attribute_names: Some(vec!["RedrivePolicy".to_owned()]),
+    };
+    let attributes = sqs
+        .get_queue_attributes(req)
+        .sync()
+        .with_context(|| QueueAttributes { queue_url })?
+        .attributes
+        .unwrap_or_default();
+    Ok(attributes
+        .get("RedrivePolicy")
+        .and_then(|policy| redrive_limit(policy)))
+}
+
+/// Visibility timeouts are capped at 12 hours by SQS.
+const MAX_VISIBILITY_TIMEOUT: i64 = 43200;
+
+pub fn receive_count(message: &Message) -> u32 {
+    message
+        .attributes
+        .as_ref()
+        .and_then(|attributes| attributes.get("ApproximateReceiveCount"))
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1)
+}
+
+/// FIFO queues deliver the messages of a group strictly in order. Pushes
+/// are expected to be grouped per repository.
+pub fn message_group(message: &Message) -> Option<&str> {
+    message
+        .attributes
+        .as_ref()
+        .and_then(|attributes| attributes.get("MessageGroupId"))
+        .map(String::as_str)
+}
+
+/// Whether the message carries the attributes given as key=value filters.
+/// Filters on the same key are alternatives; different keys must all match.
+pub fn matches_attributes(message: &Message, filters: &[(String, String)]) -> bool {
+    filters.iter().all(|(key, _)| {
+        let value = message
+            .message_attributes
+            .as_ref()
+            .and_then(|attributes| attributes.get(key))
+            .and_then(|attribute| attribute.string_value.as_ref());
+        filters
+            .iter()
+            .filter(|(other, _)| other == key)
+            .any(|(_, expected)| value == Some(expected))
+    })
+}
+
+pub fn retry_delay(attempt: u32, base_seconds: i64) -> i64 {
+    let factor = 1i64 << attempt.saturating_sub(1).min(32);
+    base_seconds
+        .saturating_mul(factor)
+        .min(MAX_VISIBILITY_TIMEOUT)
+}
+
+/// Hide a message from consumers for a while, after which SQS redelivers it.
+pub fn delay_message(
+    sqs: &dyn Sqs,
+    message: &Message,
+    seconds: i64,
+    queue_name: &str,
+) -> Result<()> {
+    let queue_url = resolve_queue_url(sqs, queue_name)?;
+    change_visibility(sqs, message, seconds, &queue_url)
+}
+
+fn change_visibility(
+    sqs: &dyn Sqs,
+    message: &Message,
+    seconds: i64,
+    queue_url: &str,
+) -> Result<()> {
+    let receipt_handle = message.receipt_handle.as_ref().expect("No handle");
+    let req = ChangeMessageVisibilityRequest {
+        queue_url: queue_url.to_owned(),
+        receipt_handle: receipt_handle.clone(),
+        visibility_timeout: seconds,
+    };
+    sqs.change_message_visibility(req)
+        .sync()
+        .with_context(|| DelayingMessage {
+            queue_url,
+            receipt_handle,
+        })?;
+    Ok(())
+}
+
+/// How often the visibility of messages being processed is extended, and
+/// by how much. Generous, so that a slow SQS call does not lose the message.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+const HEARTBEAT_VISIBILITY_SECONDS: i64 = 60;
+
+/// Long poll wait per queue, so that a round over all queues takes about
+/// as long as a single long poll. No wait means short polling throughout.
+pub fn wait_seconds(total_seconds: i64, queue_count: usize) -> i64 {
+    if total_seconds == 0 {
+        return 0;
+    }
+    (total_seconds / queue_count.max(1) as i64).max(1)
+}
+
+/// Queue that messages the deployer does not recognize are forwarded to,
+/// so that they can be inspected without clogging the event queue.
+pub struct Rejects {
+    client: SqsClient,
+    queue_name: String,
+}
+
+impl Rejects {
+    pub fn new(client: SqsClient, queue_name: &str) -> Rejects {
+        Rejects {
+            client,
+            queue_name: queue_name.to_owned(),
+        }
+    }
+
+    pub fn forward(&self, message: &Message) -> Result<()> {
+        let queue_url = resolve_queue_url(&self.client, &self.queue_name)?;
+        let req = SendMessageRequest {
+            queue_url: queue_url.clone(),
+            message_body: message.body.clone().unwrap_or_default(),
+            message_attributes: message.message_attributes.clone(),
+            ..Default::default()
+        };
+        self.client
+            .send_message(req)
+            .sync()
+            .with_context(|| ForwardingMessage { queue_url })?;
+        Ok(())
+    }
+}
+
+/// Polls one or more queues in turn, remembering which queue each message
+/// came from so that it is acked there.
+pub struct SqsSource {
+    client: SqsClient,
+    queue_names: Vec<String>,
+    max_messages: i64,
+    wait_seconds: i64,
+    attribute_filters: Vec<(String, String)>,
+    priority_queue: Option<String>,
+    next: usize,
+    origins: HashMap<String, String>,
+    redrive_limits: HashMap<String, u32>,
+}
+
+impl SqsSource {
+    pub fn connect(
+        client: SqsClient,
+        queue_names: &[String],
+        max_messages: i64,
+        wait_seconds: i64,
+        attribute_filters: &[(String, String)],
+    ) -> Result<SqsSource> {
+        let mut redrive_limits = HashMap::new();
+        for queue_name in queue_names.iter() {
+            if let Some(limit) = max_receive_count(&client, queue_name)? {
+                redrive_limits.insert(queue_name.clone(), limit);
+            }
+        }
+        Ok(SqsSource {
+            client,
+            queue_names: queue_names.to_vec(),
+            max_messages,
+            wait_seconds,
+            attribute_filters: attribute_filters.to_vec(),
+            priority_queue: None,
+            next: 0,
+            origins: HashMap::new(),
+            redrive_limits,
+        })
+    }
+
+    /// Messages on the priority queue are taken before any others.
+    pub fn with_priority_queue(mut self, queue_name: &str) -> Result<SqsSource> {
+        if let Some(limit) = max_receive_count(&self.client, queue_name)? {
+            self.redrive_limits.insert(queue_name.to_owned(), limit);
+        }
+        self.priority_queue = Some(queue_name.to_owned());
+        Ok(self)
+    }
+
+    /// Receive from one queue, releasing messages not meant for us. The
+    /// queue may be shared with other deployers, so they must not be
+    /// deleted; use a subscription filter policy to keep them off the queue.
+    fn receive(&mut self, queue_name: &str, wait: i64) -> Result<Vec<Message>> {
+        let (messages, others): (Vec<Message>, Vec<Message>) =
+            poll_messages(&self.client, queue_name, self.max_messages, wait)?
+                .into_iter()
+                .partition(|message| matches_attributes(message, &self.attribute_filters));
+        for message in others.iter() {
+            debug!(
+                "Releasing message {:?} not matching attribute filters",
+                &message.message_id
+            );
+            delay_message(&self.client, message, 0, queue_name)?;
+        }
+        if !messages.is_empty() {
+            debug!("Received {} messages from {}", messages.len(), queue_name);
+        }
+        for message in messages.iter() {
+            if let Some(handle) = &message.receipt_handle {
+                self.origins.insert(handle.clone(), queue_name.to_owned());
+            }
+        }
+        Ok(messages)
+    }
+
+    fn origin(&self, message: &Message) -> &str {
+        message
+            .receipt_handle
+            .as_ref()
+            .and_then(|handle| self.origins.get(handle))
+            .unwrap_or(&self.queue_names[0])
+    }
+
+    fn forget(&mut self, message: &Message) {
+        if let Some(handle) = &message.receipt_handle {
+            self.origins.remove(handle);
+        }
+    }
+}
+
+impl EventSource for SqsSource {
+    fn name(&self) -> String {
+        let queues = if self.queue_names.len() == 1 {
+            format!("SQS queue {}", &self.queue_names[0])
+        } else {
+            format!("SQS queues {}", self.queue_names.join(", "))
+        };
+        match &self.priority_queue {
+            Some(priority_queue) => format!("{} with priority queue {}", queues, priority_queue),
+            None => queues,
+        }
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        if let Some(priority_queue) = self.priority_queue.clone() {
+            let messages = self.receive(&priority_queue, 0)?;
+            if !messages.is_empty() {
+                return Ok(messages);
+            }
+        }
+        let queue_name = self.queue_names[self.next].clone();
+        self.next = (self.next + 1) % self.queue_names.len();
+        let wait = wait_seconds(self.wait_seconds, self.queue_names.len());
+        self.receive(&queue_name, wait)
+    }
+
+    fn ack(&mut self, message: &Message) -> Result<()> {
+        let queue_name = self.origin(message).to_owned();
+        debug!(
+            "Acking message {:?} on {}",
+            &message.message_id, &queue_name
+        );
+        self.forget(message);
+        delete_message(&self.client, message, &queue_name)
+    }
+
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()> {
+        let queue_name = self.origin(message).to_owned();
+        self.forget(message);
+        delay_message(&self.client, message, delay_seconds, &queue_name)
+    }
+
+    fn receive_count(&self, message: &Message) -> u32 {
+        receive_count(message)
+    }
+
+    fn max_receive_count(&self, message: &Message) -> Option<u32> {
+        self.redrive_limits.get(self.origin(message)).cloned()
+    }
+
+    fn depth(&self) -> Result<Vec<(String, u64, u64)>> {
+        let mut depths = Vec::new();
+        for queue_name in self.priority_queue.iter().chain(self.queue_names.iter()) {
+            let (visible, in_flight) = queue_depth(&self.client, queue_name)?;
+            depths.push((queue_name.clone(), visible, in_flight));
+        }
+        Ok(depths)
+    }
+
+    fn lease(&self, message: &Message) -> Option<Lease> {
+        let client = self.client.clone();
+        let queue_name = self.origin(message).to_owned();
+        let message = message.clone();
+        let mut queue_url: Option<String> = None;
+        Some(Lease::spawn(HEARTBEAT_INTERVAL, move || {
+            if queue_url.is_none() {
+                match resolve_queue_url(&client, &queue_name) {
+                    Ok(url) => queue_url = Some(url),
+                    Err(err) => {
+                        warn!("Could not extend visibility of message: {}", err);
+                        return;
+                    }
+                }
+            }
+            let queue_url = queue_url.as_ref().unwrap();
+            let extended =
+                change_visibility(&client, &message, HEARTBEAT_VISIBILITY_SECONDS, queue_url);
+            if let Err(err) = extended {
+                warn!("Could not extend visibility of message: {}", err);
+            }
+        }))
+    }
+
+    fn ack_all(&mut self, messages: &[&Message]) -> Result<()> {
+        let mut by_queue: HashMap<String, Vec<&Message>> = HashMap::new();
+        for message in messages.iter() {
+            let queue_name = self.origin(message).to_owned();
+            by_queue.entry(queue_name).or_default().push(*message);
+        }
+        for (queue_name, messages) in by_queue.iter() {
+            debug!("Acking {} messages on {}", messages.len(), queue_name);
+            delete_messages(&self.client, messages, queue_name)?;
+        }
+        for message in messages.iter() {
+            self.forget(message);
+        }
+        Ok(())
+    }
+}
+
+/// How long to stay on a failover queue before trying the primary again.
+pub const FAILBACK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// A queue identified by ARN, e.g. arn:aws:sqs:eu-west-1:123456789012:deploys
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueueArn {
+    pub region: Region,
+    pub queue_name: String,
+}
+
+impl FromStr for QueueArn {
+    type Err = SeedyError;
+
+    fn from_str(input: &str) -> Result<QueueArn> {
+        let parts: Vec<&str> = input.split(':').collect();
+        match parts.as_slice() {
+            ["arn", _, "sqs", region, _, queue_name] if !queue_name.is_empty() => {
+                match region.parse::<Region>() {
+                    Ok(region) => Ok(QueueArn {
+                        region,
+                        queue_name: (*queue_name).to_owned(),
+                    }),
+                    Err(_) => QueueArnFormat { value: input }.fail(),
+                }
+            }
+            _ => QueueArnFormat { value: input }.fail(),
+        }
+    }
+}
+
+fn outage<E>(err: &RusotoError<E>) -> bool {
+    match err {
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(response) => response.status.is_server_error(),
+        _ => false,
+    }
+}
+
+/// Whether an error means SQS could not be reached or failed on its side,
+/// as opposed to refusing the request. Throttling, missing permissions and
+/// missing queues are not failed over from.
+pub fn unreachable(err: &SeedyError) -> bool {
+    match err {
+        SeedyError::SqsUrl { source, .. } => outage(source),
+        SeedyError::PollingMessage { source, .. } => outage(source),
+        _ => false,
+    }
+}
+
+/// Polls the first source that is reachable, falling back on queues in
+/// other regions during a regional outage, as told by `unreachable`.
+/// Messages are acked before the next poll, so they always belong to the
+/// active source.
+pub struct SqsFailover {
+    sources: Vec<SqsSource>,
+    active: usize,
+    failed_over_at: Option<Instant>,
+}
+
+impl SqsFailover {
+    pub fn new(sources: Vec<SqsSource>) -> SqsFailover {
+        SqsFailover {
+            sources,
+            active: 0,
+            failed_over_at: None,
+        }
+    }
+}
+
+impl EventSource for SqsFailover {
+    fn name(&self) -> String {
+        let names: Vec<String> = self.sources.iter().map(|source| source.name()).collect();
+        names.join(", failing over to ")
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        let failback_due = self
+            .failed_over_at
+            .map(|at| at.elapsed() >= FAILBACK_INTERVAL)
+            .unwrap_or(false);
+        if self.active != 0 && failback_due {
+            info!("Trying {} again", self.sources[0].name());
+            self.active = 0;
+        }
+        loop {
+            match self.sources[self.active].poll() {
+                Err(ref err) if unreachable(err) && self.active + 1 < self.sources.len() => {
+                    self.active += 1;
+                    self.failed_over_at = Some(Instant::now());
+                    warn!(
+                        "{}; failing over to {}",
+                        err,
+                        self.sources[self.active].name()
+                    );
+                }
+                res => return res,
+            }
+        }
+    }
+
+    fn ack(&mut self, message: &Message) -> Result<()> {
+        self.sources[self.active].ack(message)
+    }
+
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()> {
+        self.sources[self.active].nack(message, delay_seconds)
+    }
+
+    fn receive_count(&self, message: &Message) -> u32 {
+        self.sources[self.active].receive_count(message)
+    }
+
+    fn max_receive_count(&self, message: &Message) -> Option<u32> {
+        self.sources[self.active].max_receive_count(message)
+    }
+
+    fn depth(&self) -> Result<Vec<(String, u64, u64)>> {
+        self.sources[self.active].depth()
+    }
+
+    fn lease(&self, message: &Message) -> Option<Lease> {
+        self.sources[self.active].lease(message)
+    }
+
+    fn ack_all(&mut self, messages: &[&Message]) -> Result<()> {
+        self.sources[self.active].ack_all(messages)
+    }
+}
@@ -0,0 +1,174 @@
+use chrono::{DateTime, TimeZone, Utc};
+use futures01::future::{self, FutureResult};
+use reqwest::blocking::Client;
+use rusoto_credential::{AwsCredentials, CredentialsError, ProvideAwsCredentials};
+use serde_json::Value;
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+pub struct SsoProfile {
+    pub start_url: String,
+    pub region: String,
+    pub account_id: String,
+    pub role_name: String,
+    /// Name of the sso-session section, which keys the token cache when present
+    pub session_name: Option<String>,
+}
+
+fn parse_sections(config: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut sections = HashMap::new();
+    let mut current: Option<String> = None;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_owned();
+            sections.entry(name.clone()).or_insert_with(HashMap::new);
+            current = Some(name);
+        } else if let (Some(section), Some(eq_pos)) = (&current, line.find('=')) {
+            sections.get_mut(section).unwrap().insert(
+                line[..eq_pos].trim().to_owned(),
+                line[eq_pos + 1..].trim().to_owned(),
+            );
+        }
+    }
+    sections
+}
+
+/// Read the SSO settings of a profile from an AWS CLI config file, in
+/// either the legacy format or the sso-session format.
+pub fn parse_profile(config: &str, profile: &str) -> Option<SsoProfile> {
+    let sections = parse_sections(config);
+    let section_name = if profile == "default" {
+        "default".to_owned()
+    } else {
+        format!("profile {}", profile)
+    };
+    let section = sections.get(&section_name)?;
+    let session_name = section.get("sso_session").cloned();
+    let session = match &session_name {
+        Some(name) => sections.get(&format!("sso-session {}", name))?,
+        None => section,
+    };
+    Some(SsoProfile {
+        start_url: session.get("sso_start_url")?.clone(),
+        region: session.get("sso_region")?.clone(),
+        account_id: section.get("sso_account_id")?.clone(),
+        role_name: section.get("sso_role_name")?.clone(),
+        session_name,
+    })
+}
+
+pub fn cache_file_name(profile: &SsoProfile) -> String {
+    let key = profile.session_name.as_ref().unwrap_or(&profile.start_url);
+    format!("{}.json", Sha1::from(key).hexdigest())
+}
+
+pub fn parse_role_credentials(body: &Value) -> Option<AwsCredentials> {
+    let credentials = body.get("roleCredentials")?;
+    let expires_at = Utc.timestamp_millis(credentials.get("expiration")?.as_i64()?);
+    Some(AwsCredentials::new(
+        credentials.get("accessKeyId")?.as_str()?,
+        credentials.get("secretAccessKey")?.as_str()?,
+        Some(credentials.get("sessionToken")?.as_str()?.to_owned()),
+        Some(expires_at),
+    ))
+}
+
+fn credentials_error(message: String) -> CredentialsError {
+    CredentialsError::new(message)
+}
+
+/// Exchanges the access token cached by `aws sso login` for role
+/// credentials. Operators must log in again when the token expires.
+pub struct SsoProvider {
+    aws_dir: PathBuf,
+    profile: String,
+}
+
+impl SsoProvider {
+    pub fn new(aws_dir: &Path, profile: &str) -> SsoProvider {
+        SsoProvider {
+            aws_dir: aws_dir.to_owned(),
+            profile: profile.to_owned(),
+        }
+    }
+
+    fn access_token(&self, profile: &SsoProfile) -> Result<String, CredentialsError> {
+        let path = self
+            .aws_dir
+            .join("sso")
+            .join("cache")
+            .join(cache_file_name(profile));
+        let cached: Value = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .ok_or_else(|| {
+                credentials_error(format!(
+                    "No cached SSO token in {}, run aws sso login --profile {}",
+                    path.display(),
+                    self.profile
+                ))
+            })?;
+        let expired = cached
+            .get("expiresAt")
+            .and_then(|expires_at| expires_at.as_str())
+            .and_then(|expires_at| expires_at.parse::<DateTime<Utc>>().ok())
+            .map_or(true, |expires_at| expires_at <= Utc::now());
+        if expired {
+            return Err(credentials_error(format!(
+                "SSO token for profile {} has expired, run aws sso login --profile {}",
+                self.profile, self.profile
+            )));
+        }
+        cached
+            .get("accessToken")
+            .and_then(|token| token.as_str())
+            .map(str::to_owned)
+            .ok_or_else(|| credentials_error(format!("No access token in {}", path.display())))
+    }
+
+    fn role_credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+        let config_path = self.aws_dir.join("config");
+        let profile = fs::read_to_string(&config_path)
+            .ok()
+            .and_then(|config| parse_profile(&config, &self.profile))
+            .ok_or_else(|| {
+                credentials_error(format!(
+                    "Profile {} in {} has no SSO configuration",
+                    self.profile,
+                    config_path.display()
+                ))
+            })?;
+        let access_token = self.access_token(&profile)?;
+        let body: Value = Client::new()
+            .get(&format!(
+                "https://portal.sso.{}.amazonaws.com/federation/credentials",
+                profile.region
+            ))
+            .query(&[
+                ("role_name", profile.role_name.as_str()),
+                ("account_id", profile.account_id.as_str()),
+            ])
+            .header("x-amz-sso_bearer_token", access_token.as_str())
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .map_err(|err| credentials_error(format!("SSO GetRoleCredentials failed: {}", err)))?;
+        parse_role_credentials(&body).ok_or_else(|| {
+            credentials_error("SSO GetRoleCredentials returned no credentials".to_owned())
+        })
+    }
+}
+
+impl ProvideAwsCredentials for SsoProvider {
+    type Future = FutureResult<AwsCredentials, CredentialsError>;
+
+    fn credentials(&self) -> Self::Future {
+        future::result(self.role_credentials())
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{ReportingTaskFailure, ReportingTaskSuccess, Result};
+use rusoto_sqs::Message;
+use rusoto_stepfunctions::{
+    SendTaskFailureInput, SendTaskSuccessInput, StepFunctions, StepFunctionsClient,
+};
+use serde_json::Value;
+use snafu::ResultExt;
+
+/// Name of the message attribute or body field carrying the token of a
+/// Step Functions task waiting for the deployment (.waitForTaskToken).
+pub const TASK_TOKEN: &str = "TaskToken";
+
+/// Error name reported to the workflow, which may catch or retry on it.
+pub const DEPLOYMENT_FAILED: &str = "DeploymentFailed";
+
+pub fn task_token(message: &Message) -> Option<String> {
+    let attribute = message
+        .message_attributes
+        .as_ref()
+        .and_then(|attributes| attributes.get(TASK_TOKEN))
+        .and_then(|attribute| attribute.string_value.clone());
+    if attribute.is_some() {
+        return attribute;
+    }
+    let body: Value = serde_json::from_str(message.body.as_ref()?).ok()?;
+    body.get(TASK_TOKEN)?.as_str().map(str::to_owned)
+}
+
+pub fn report_success(
+    states: &StepFunctionsClient,
+    task_token: &str,
+    output: &Value,
+) -> Result<()> {
+    let req = SendTaskSuccessInput {
+        task_token: task_token.to_owned(),
+        output: output.to_string(),
+    };
+    states
+        .send_task_success(req)
+        .sync()
+        .context(ReportingTaskSuccess)?;
+    Ok(())
+}
+
+pub fn report_failure(states: &StepFunctionsClient, task_token: &str, cause: &str) -> Result<()> {
+    let req = SendTaskFailureInput {
+        task_token: task_token.to_owned(),
+        error: Some(DEPLOYMENT_FAILED.to_owned()),
+        cause: Some(cause.to_owned()),
+    };
+    states
+        .send_task_failure(req)
+        .sync()
+        .context(ReportingTaskFailure)?;
+    Ok(())
+}
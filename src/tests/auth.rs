@@ -0,0 +1,61 @@
+use crate::auth::{self, RegistryAuthProvider};
+use rusoto_core::Region;
+use structopt::StructOpt;
+
+#[test]
+fn test_ecr_registry() {
+    assert_eq!(
+        Some(("123456789012", Region::EuWest1)),
+        auth::ecr_registry("123456789012.dkr.ecr.eu-west-1.amazonaws.com")
+    );
+    assert_eq!(
+        Some(("123456789012", Region::CnNorth1)),
+        auth::ecr_registry("123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn")
+    );
+    assert_eq!(
+        None,
+        auth::ecr_registry("123456789012.dkr.ecr.nowhere-1.amazonaws.com")
+    );
+    assert_eq!(None, auth::ecr_registry("ghcr.io"));
+}
+
+#[test]
+fn test_providers_keyed_on_registry_host() {
+    let ecr_host = "123456789012.dkr.ecr.eu-west-1.amazonaws.com";
+    assert!(auth::Ecr.handles(ecr_host));
+    assert!(!auth::Ecr.handles("registry.example.com"));
+    assert!(auth::Ghcr.handles("ghcr.io"));
+    assert!(auth::Acr.handles("example.azurecr.io"));
+    assert!(auth::Gcp.handles("europe-docker.pkg.dev"));
+    assert!(auth::Generic.handles("registry.example.com"));
+    assert!(auth::provider(ecr_host).handles(ecr_host));
+    assert!(!auth::provider("registry.example.com").handles(ecr_host));
+}
+
+#[test]
+fn test_public_images_pulled_anonymously() {
+    let opt = crate::Opt::from_iter(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--public-repository",
+        "docker.io/library/nginx",
+        "--public-repository",
+        "ghcr.io/bittrance/ze-image",
+    ]);
+    let mut event = super::message_event();
+    event.registry = Some("docker.io".to_owned());
+    event.repository_name = "library/nginx".to_owned();
+    assert!(auth::is_public(&event, &opt));
+    let mut cache = auth::Cache::default();
+    assert!(auth::event_credentials(&event, &mut cache, &opt)
+        .unwrap()
+        .is_none());
+    event.registry = Some("ghcr.io".to_owned());
+    event.repository_name = "bittrance/ze-image".to_owned();
+    assert!(auth::is_public(&event, &opt));
+    event.repository_name = "bittrance/other-image".to_owned();
+    assert!(!auth::is_public(&event, &opt));
+    assert!(auth::provider(auth::ECR_PUBLIC).handles(auth::ECR_PUBLIC));
+    assert!(!auth::Ecr.handles(auth::ECR_PUBLIC));
+}
@@ -0,0 +1,151 @@
+use crate::aws::{self, CredentialSource};
+use rusoto_core::Region;
+use rusoto_sqs::SqsClient;
+use structopt::StructOpt;
+
+#[test]
+fn test_parse_credential_source() {
+    assert_eq!(
+        CredentialSource::WebIdentity,
+        "web-identity".parse::<CredentialSource>().unwrap()
+    );
+    assert_eq!(
+        CredentialSource::InstanceMetadata,
+        "instance-metadata".parse::<CredentialSource>().unwrap()
+    );
+    assert!("imds".parse::<CredentialSource>().is_err());
+}
+
+#[test]
+fn test_static_credentials_require_keys() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "some-queue",
+            "--aws-credential-source",
+            "static",
+        ]
+        .iter(),
+    );
+    assert!(aws::client::<SqsClient>(&opt, Region::EuWest1).is_err());
+}
+
+#[test]
+fn test_assumed_role_requires_base_credentials() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "some-queue",
+            "--aws-credential-source",
+            "static",
+            "--sqs-role-arn",
+            "arn:aws:iam::123456789012:role/ze-deployer",
+        ]
+        .iter(),
+    );
+    let role_arn = opt.sqs_role_arn.clone().unwrap();
+    let client = aws::assumed_role_client::<SqsClient>(&opt, &role_arn, None, Region::EuWest1);
+    assert!(client.is_err());
+}
+
+#[test]
+fn test_parse_assume_role_response() {
+    let body = r#"<AssumeRoleWithWebIdentityResponse>
+      <AssumeRoleWithWebIdentityResult>
+        <Credentials>
+          <AccessKeyId>ASIAEXAMPLE</AccessKeyId>
+          <SecretAccessKey>secret</SecretAccessKey>
+          <SessionToken>token</SessionToken>
+          <Expiration>2020-04-08T18:00:00Z</Expiration>
+        </Credentials>
+      </AssumeRoleWithWebIdentityResult>
+    </AssumeRoleWithWebIdentityResponse>"#;
+    let credentials = aws::parse_assume_role_response(body).unwrap();
+    assert_eq!("ASIAEXAMPLE", credentials.aws_access_key_id());
+    assert_eq!("secret", credentials.aws_secret_access_key());
+    assert_eq!(&Some("token".to_owned()), credentials.token());
+}
+
+#[test]
+fn test_service_endpoint_overrides_general_endpoint() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "some-queue",
+            "--aws-endpoint-url",
+            "http://localstack:4566",
+            "--aws-service-endpoints",
+            "sqs=https://vpce-1234.sqs.eu-west-1.vpce.amazonaws.com",
+        ]
+        .iter(),
+    );
+    assert_eq!(
+        Region::Custom {
+            name: "eu-west-1".to_owned(),
+            endpoint: "https://vpce-1234.sqs.eu-west-1.vpce.amazonaws.com".to_owned(),
+        },
+        aws::endpoint_region::<SqsClient>(&opt, Region::EuWest1)
+    );
+    assert_eq!(
+        Region::Custom {
+            name: "eu-west-1".to_owned(),
+            endpoint: "http://localstack:4566".to_owned(),
+        },
+        aws::endpoint_region::<rusoto_ecr::EcrClient>(&opt, Region::EuWest1)
+    );
+}
+
+#[test]
+fn test_public_endpoints_by_default() {
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "--queue", "some-queue"].iter());
+    assert_eq!(
+        Region::EuWest1,
+        aws::endpoint_region::<SqsClient>(&opt, Region::EuWest1)
+    );
+}
+
+#[test]
+fn test_ecr_region_override() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "some-queue",
+            "--ecr-region-override",
+            "us-east-1",
+        ]
+        .iter(),
+    );
+    assert_eq!(Region::UsEast1, aws::ecr_region(&opt, Region::EuWest1));
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "--queue", "some-queue"].iter());
+    assert_eq!(Region::EuWest1, aws::ecr_region(&opt, Region::EuWest1));
+}
+
+#[test]
+fn test_ecr_role() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "some-queue",
+            "--ecr-role-arn",
+            "arn:aws:iam::123456789012:role/central",
+            "--ecr-account-role",
+            "210987654321=arn:aws:iam::210987654321:role/other",
+        ]
+        .iter(),
+    );
+    assert_eq!(
+        Some("arn:aws:iam::210987654321:role/other"),
+        aws::ecr_role(&opt, "210987654321")
+    );
+    assert_eq!(
+        Some("arn:aws:iam::123456789012:role/central"),
+        aws::ecr_role(&opt, "123456789012")
+    );
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "--queue", "some-queue"].iter());
+    assert_eq!(None, aws::ecr_role(&opt, "123456789012"));
+}
@@ -0,0 +1,14 @@
+use crate::build_info;
+
+#[test]
+fn test_banner_includes_version_and_sha() {
+    let banner = build_info::banner();
+    assert!(banner.contains(build_info::VERSION));
+    assert!(banner.contains(build_info::GIT_SHA));
+}
+
+#[test]
+fn test_long_version_names_features() {
+    assert!(!build_info::LONG_VERSION.contains("features: )"));
+    assert!(build_info::LONG_VERSION.contains(build_info::FEATURES));
+}
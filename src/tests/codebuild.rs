@@ -0,0 +1,49 @@
+use crate::codebuild;
+use serde_json::json;
+
+fn build_event(status: &str, images: &str) -> String {
+    json!({
+        "detail-type": "CodeBuild Build State Change",
+        "source": "aws.codebuild",
+        "account": "123456789012",
+        "region": "eu-west-1",
+        "detail": {
+            "build-status": status,
+            "project-name": "ze-app",
+            "additional-information": {
+                "exported-environment-variables": [
+                    {"name": "IMAGE_URI", "value": images},
+                    {"name": "GIT_SHA", "value": "abc123"}
+                ]
+            }
+        }
+    })
+    .to_string()
+}
+
+#[test]
+fn test_image_references_from_succeeded_build() {
+    let event = build_event(
+        "SUCCEEDED",
+        "123456789012.dkr.ecr.eu-west-1.amazonaws.com/ze-app:1.0, bittrance/ze-worker:1.0",
+    );
+    assert_eq!(
+        vec![
+            "123456789012.dkr.ecr.eu-west-1.amazonaws.com/ze-app:1.0".to_owned(),
+            "bittrance/ze-worker:1.0".to_owned()
+        ],
+        codebuild::image_references(&event, &["IMAGE_URI".to_owned()])
+    );
+}
+
+#[test]
+fn test_failed_build_has_no_images() {
+    let event = build_event("FAILED", "bittrance/ze-worker:1.0");
+    assert!(codebuild::image_references(&event, &["IMAGE_URI".to_owned()]).is_empty());
+}
+
+#[test]
+fn test_only_configured_variables_are_used() {
+    let event = build_event("SUCCEEDED", "bittrance/ze-worker:1.0");
+    assert!(codebuild::image_references(&event, &["OTHER".to_owned()]).is_empty());
+}
@@ -0,0 +1,11 @@
+use crate::containers;
+use crate::reference::ImageRef;
+
+#[test]
+fn test_runs_image() {
+    let image = ImageRef::parse("docker.io/library/nginx:latest").unwrap();
+    assert!(containers::runs_image("nginx", &image));
+    assert!(containers::runs_image("nginx:latest@sha256:1234", &image));
+    assert!(!containers::runs_image("nginx:1.17", &image));
+    assert!(!containers::runs_image("bittrance/nginx", &image));
+}
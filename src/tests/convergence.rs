@@ -0,0 +1,67 @@
+use crate::convergence::{self, Deadline};
+use std::collections::HashMap;
+use std::time::Duration;
+use structopt::StructOpt;
+
+fn opt(args: &[&str]) -> crate::Opt {
+    let mut argv = vec!["ze-bin", "--queue", "some-queue"];
+    argv.extend_from_slice(args);
+    crate::Opt::from_iter(argv.iter())
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(
+        Duration::from_secs(90),
+        convergence::parse_duration("90").unwrap()
+    );
+    assert_eq!(
+        Duration::from_secs(90),
+        convergence::parse_duration("90s").unwrap()
+    );
+    assert_eq!(
+        Duration::from_secs(600),
+        convergence::parse_duration("10m").unwrap()
+    );
+    assert_eq!(
+        Duration::from_secs(7200),
+        convergence::parse_duration("2h").unwrap()
+    );
+    assert!(convergence::parse_duration("soon").is_err());
+    assert!(convergence::parse_duration("18446744073709551615h").is_err());
+}
+
+#[test]
+fn test_no_deadline_by_default() {
+    let service = super::service_spec(None, Some("bittrance/ze-image:latest".to_owned()));
+    assert_eq!(None, convergence::deadline_for(&service, &opt(&[])));
+}
+
+#[test]
+fn test_deadline_from_flags() {
+    let service = super::service_spec(None, Some("bittrance/ze-image:latest".to_owned()));
+    let opt = opt(&["--deadline", "5m", "--min-healthy-seconds", "30"]);
+    assert_eq!(
+        Some(Deadline {
+            deadline: Duration::from_secs(300),
+            min_healthy: Duration::from_secs(30),
+        }),
+        convergence::deadline_for(&service, &opt)
+    );
+}
+
+#[test]
+fn test_deadline_labels_override_flags() {
+    let mut labels = HashMap::new();
+    labels.insert(convergence::DEADLINE_LABEL.to_owned(), "10m".to_owned());
+    labels.insert(convergence::MIN_HEALTHY_LABEL.to_owned(), "60".to_owned());
+    let service = super::service_spec(Some(labels), Some("bittrance/ze-image:latest".to_owned()));
+    let opt = opt(&["--deadline", "5m"]);
+    assert_eq!(
+        Some(Deadline {
+            deadline: Duration::from_secs(600),
+            min_healthy: Duration::from_secs(60),
+        }),
+        convergence::deadline_for(&service, &opt)
+    );
+}
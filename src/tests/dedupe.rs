@@ -0,0 +1,40 @@
+use crate::dedupe::Dedupe;
+
+#[test]
+fn test_seen_after_record() {
+    let mut dedupe = Dedupe::new(10);
+    assert_eq!(None, dedupe.seen("ze-repo", "sha256:abc"));
+    dedupe.record("ze-repo", "sha256:abc", "msg-1");
+    assert_eq!(
+        Some("msg-1".to_owned()),
+        dedupe.seen("ze-repo", "sha256:abc")
+    );
+    assert_eq!(None, dedupe.seen("other-repo", "sha256:abc"));
+    assert_eq!(None, dedupe.seen("ze-repo", "sha256:def"));
+}
+
+#[test]
+fn test_least_recently_seen_is_evicted() {
+    let mut dedupe = Dedupe::new(2);
+    dedupe.record("ze-repo", "sha256:1", "msg-1");
+    dedupe.record("ze-repo", "sha256:2", "msg-2");
+    dedupe.seen("ze-repo", "sha256:1");
+    dedupe.record("ze-repo", "sha256:3", "msg-3");
+    assert!(dedupe.seen("ze-repo", "sha256:1").is_some());
+    assert!(dedupe.seen("ze-repo", "sha256:2").is_none());
+    assert!(dedupe.seen("ze-repo", "sha256:3").is_some());
+}
+
+#[test]
+fn test_zero_capacity_disables() {
+    let mut dedupe = Dedupe::new(0);
+    dedupe.record("ze-repo", "sha256:abc", "msg-1");
+    assert_eq!(None, dedupe.seen("ze-repo", "sha256:abc"));
+}
+
+#[test]
+fn test_tags_of_same_digest_are_distinct() {
+    let mut dedupe = Dedupe::new(10);
+    dedupe.record("ze-repo:stable", "sha256:abc", "msg-1");
+    assert_eq!(None, dedupe.seen("ze-repo:v1.2.3", "sha256:abc"));
+}
@@ -0,0 +1,84 @@
+use crate::deletions::{self, DeleteAction};
+use bollard::service::ServiceSpecMode;
+use serde_json::json;
+use std::collections::HashMap;
+
+fn delete_event(digest: &str) -> String {
+    json!({
+        "version": "0",
+        "detail-type": "ECR Image Action",
+        "source": "aws.ecr",
+        "account": "123456789012",
+        "time": "2020-03-30T09:56:58Z",
+        "region": "rp-north-1",
+        "detail": {
+            "result": "SUCCESS",
+            "repository-name": "bittrance/ze-image",
+            "image-digest": digest,
+            "action-type": "DELETE",
+            "image-tag": "latest"
+        }
+    })
+    .to_string()
+}
+
+#[test]
+fn test_parse_ecr_delete_event() {
+    let deleted = crate::events::parse_ecr_delete_event(&delete_event("sha256:1234")).unwrap();
+    assert_eq!("bittrance/ze-image", deleted.repository_name);
+    assert_eq!("sha256:1234", deleted.image_digest);
+    assert!(crate::events::parse_ecr_event(&delete_event("sha256:1234"))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_affected_services_run_deleted_digest() {
+    let image = "123456789012.dkr.ecr.rp-north-1.amazonaws.com/bittrance/ze-image:latest";
+    let mut services_by_image = HashMap::new();
+    services_by_image.insert(
+        image.to_owned(),
+        super::service_spec(None, Some(format!("{}@sha256:1234", image))),
+    );
+    let deleted = crate::events::parse_ecr_delete_event(&delete_event("sha256:1234")).unwrap();
+    assert_eq!(1, deletions::affected(&deleted, &services_by_image).len());
+    let expired = crate::events::parse_ecr_delete_event(&delete_event("sha256:5678")).unwrap();
+    assert!(deletions::affected(&expired, &services_by_image).is_empty());
+    let promoted = "123456789012.dkr.ecr.rp-north-1.amazonaws.com/bittrance/ze-image-prod:latest";
+    let mut services_by_image = HashMap::new();
+    services_by_image.insert(
+        promoted.to_owned(),
+        super::service_spec(None, Some(format!("{}@sha256:1234", promoted))),
+    );
+    assert!(deletions::affected(&deleted, &services_by_image).is_empty());
+}
+
+#[test]
+fn test_is_pinned() {
+    let service = super::service_spec(
+        super::filter_label(deletions::PINNED_LABEL, "sha256:1234"),
+        None,
+    );
+    assert!(deletions::is_pinned(&service));
+    assert!(!deletions::is_pinned(&super::service_spec(None, None)));
+}
+
+#[test]
+fn test_is_global() {
+    let mut service = super::service_spec(None, None);
+    assert!(!deletions::is_global(&service));
+    service.spec.mode = Some(ServiceSpecMode {
+        global: Some(Default::default()),
+        ..Default::default()
+    });
+    assert!(deletions::is_global(&service));
+}
+
+#[test]
+fn test_parse_delete_action() {
+    assert_eq!(
+        DeleteAction::ScaleToZero,
+        "scale-to-zero".parse::<DeleteAction>().unwrap()
+    );
+    assert!("delete".parse::<DeleteAction>().is_err());
+}
@@ -0,0 +1,86 @@
+use crate::docker_config;
+use serde_json::json;
+use structopt::StructOpt;
+
+fn config() -> serde_json::Value {
+    json!({
+        "auths": {
+            "harbor.example.com": {"auth": base64::encode("robot$ze:s3cret")},
+            "https://index.docker.io/v1/": {"username": "bittrance", "password": "hunter2"},
+            "nexus.example.com:8082": {"identitytoken": "ze-token"},
+            "empty.example.com": {}
+        }
+    })
+}
+
+#[test]
+fn test_auth_credentials_from_encoded_auth() {
+    let credentials = docker_config::auth_credentials(&config(), "harbor.example.com").unwrap();
+    assert_eq!(Some("robot$ze".to_owned()), credentials.username);
+    assert_eq!(Some("s3cret".to_owned()), credentials.password);
+    assert_eq!(
+        Some("harbor.example.com".to_owned()),
+        credentials.serveraddress
+    );
+}
+
+#[test]
+fn test_auth_credentials_for_docker_hub() {
+    let credentials = docker_config::auth_credentials(&config(), "docker.io").unwrap();
+    assert_eq!(Some("bittrance".to_owned()), credentials.username);
+    assert_eq!(Some("hunter2".to_owned()), credentials.password);
+}
+
+#[test]
+fn test_auth_credentials_with_identity_token() {
+    let credentials = docker_config::auth_credentials(&config(), "nexus.example.com:8082").unwrap();
+    assert_eq!(Some("ze-token".to_owned()), credentials.identitytoken);
+}
+
+#[test]
+fn test_auth_credentials_without_login() {
+    assert!(docker_config::auth_credentials(&config(), "empty.example.com").is_none());
+    assert!(docker_config::auth_credentials(&config(), "quay.io").is_none());
+}
+
+#[test]
+fn test_explicit_config_must_exist() {
+    let opt = crate::Opt::from_iter(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--docker-config",
+        "/nonexistent/config.json",
+    ]);
+    assert!(docker_config::load(&opt).is_err());
+}
+
+#[test]
+fn test_helper_for_registry() {
+    let config = json!({
+        "credsStore": "desktop",
+        "credHelpers": {
+            "123456789012.dkr.ecr.eu-west-1.amazonaws.com": "ecr-login",
+            "gcr.io": "gcr"
+        }
+    });
+    assert_eq!(Some("gcr"), docker_config::helper_for(&config, "gcr.io"));
+    assert_eq!(
+        Some("desktop"),
+        docker_config::helper_for(&config, "harbor.example.com")
+    );
+    assert_eq!(None, docker_config::helper_for(&config(), "gcr.io"));
+}
+
+#[test]
+fn test_parse_helper_output() {
+    let output = json!({"ServerURL": "gcr.io", "Username": "_json_key", "Secret": "ze-key"});
+    let credentials = docker_config::parse_helper_output(&output.to_string(), "gcr.io").unwrap();
+    assert_eq!(Some("_json_key".to_owned()), credentials.username);
+    assert_eq!(Some("ze-key".to_owned()), credentials.password);
+    let output = json!({"ServerURL": "gcr.io", "Username": "<token>", "Secret": "ze-token"});
+    let credentials = docker_config::parse_helper_output(&output.to_string(), "gcr.io").unwrap();
+    assert_eq!(None, credentials.username);
+    assert_eq!(Some("ze-token".to_owned()), credentials.identitytoken);
+    assert!(docker_config::parse_helper_output("not json", "gcr.io").is_none());
+}
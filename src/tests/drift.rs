@@ -0,0 +1,12 @@
+use crate::drift;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_due() {
+    assert!(drift::due(None, 24));
+    assert!(!drift::due(Some(Instant::now()), 24));
+    assert!(drift::due(
+        Some(Instant::now() - Duration::from_secs(3600)),
+        1
+    ));
+}
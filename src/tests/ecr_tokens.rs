@@ -0,0 +1,20 @@
+use crate::ecr_tokens;
+use chrono::{Duration, Utc};
+
+#[test]
+fn test_token_usable_until_shortly_before_expiry() {
+    let now = Utc::now();
+    assert!(ecr_tokens::usable(now + Duration::hours(12), now));
+    assert!(!ecr_tokens::usable(now + Duration::minutes(4), now));
+    assert!(!ecr_tokens::usable(now - Duration::minutes(1), now));
+}
+
+#[test]
+fn test_endpoint_account() {
+    assert_eq!(
+        Some("123456789012"),
+        ecr_tokens::endpoint_account("https://123456789012.dkr.ecr.eu-west-1.amazonaws.com")
+    );
+    assert_eq!(None, ecr_tokens::endpoint_account("https://.dkr.ecr"));
+    assert_eq!(None, ecr_tokens::endpoint_account("localhost"));
+}
@@ -1,3 +1,4 @@
+use chrono::{Duration, TimeZone, Utc};
 use serde_json::json;
 
 fn message_event() -> String {
@@ -23,7 +24,9 @@ fn message_event() -> String {
 
 #[test]
 fn test_parse_ecr_event() {
-    let event = crate::events::parse_ecr_event(&message_event()).unwrap();
+    let event = crate::events::parse_ecr_event(&message_event())
+        .unwrap()
+        .unwrap();
     assert_eq!(event.account_id, "123456789012");
     assert_eq!(event.repository_name, "bittrance/ze-image");
     assert_eq!(event.image_digest, "sha256:1234");
@@ -32,9 +35,649 @@ fn test_parse_ecr_event() {
 
 #[test]
 fn test_extract_event_image() {
-    let event = crate::events::parse_ecr_event(&message_event()).unwrap();
+    let event = crate::events::parse_ecr_event(&message_event())
+        .unwrap()
+        .unwrap();
     assert_eq!(
         "123456789012.dkr.ecr.rp-north-1.amazonaws.com/bittrance/ze-image:latest",
         event.image()
     );
 }
+
+#[test]
+fn test_parse_ecr_event_push_time() {
+    let event = crate::events::parse_ecr_event(&message_event())
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        Some(Utc.ymd(2020, 3, 30).and_hms(9, 56, 58)),
+        event.pushed_at
+    );
+}
+
+#[test]
+fn test_event_lead_time() {
+    let event = crate::events::parse_ecr_event(&message_event())
+        .unwrap()
+        .unwrap();
+    let deployed_at = Utc.ymd(2020, 3, 30).and_hms(9, 58, 0);
+    assert_eq!(Some(Duration::seconds(62)), event.lead_time(deployed_at));
+}
+
+#[test]
+fn test_event_json_round_trip() {
+    let event = crate::events::parse_ecr_event(&message_event())
+        .unwrap()
+        .unwrap();
+    let parsed = crate::events::Event::from_json(&event.to_json()).unwrap();
+    assert_eq!(event.image(), parsed.image());
+    assert_eq!(event.image_digest, parsed.image_digest);
+    assert_eq!(event.pushed_at, parsed.pushed_at);
+}
+
+#[test]
+fn test_parse_ecr_event_missing_field() {
+    let body = json!({
+        "account": "123456789012",
+        "region": "rp-north-1",
+        "detail": {
+            "action-type": "PUSH",
+            "result": "SUCCESS",
+            "image-digest": "sha256:1234"
+        }
+    })
+    .to_string();
+    let err = crate::events::parse_ecr_event(&body).err().unwrap();
+    assert!(err.to_string().contains("repository-name"));
+    assert!(crate::events::parse_event(&body, &[]).is_none());
+}
+
+#[test]
+fn test_parse_ecr_event_without_digest() {
+    let body = json!({
+        "account": "123456789012",
+        "region": "rp-north-1",
+        "detail": {
+            "action-type": "PUSH",
+            "result": "SUCCESS",
+            "repository-name": "bittrance/ze-image",
+            "image-tag": "latest"
+        }
+    })
+    .to_string();
+    let event = crate::events::parse_ecr_event(&body).unwrap().unwrap();
+    assert_eq!("", event.image_digest);
+    assert_eq!("latest", event.image_tag);
+}
+
+#[test]
+fn test_parse_ecr_event_ignores_other_messages() {
+    assert!(crate::events::parse_ecr_event("not json")
+        .unwrap()
+        .is_none());
+    assert!(
+        crate::events::parse_ecr_event("{\"detail\": {\"action-type\": \"DELETE\"}}")
+            .unwrap()
+            .is_none()
+    );
+}
+
+fn cloudtrail_put_image_event() -> serde_json::Value {
+    json!({
+        "version": "0",
+        "id": "1e5a5f3c-5d1c-7a1e-2b8e-0c6d7f3e2a11",
+        "detail-type": "AWS API Call via CloudTrail",
+        "source": "aws.ecr",
+        "account": "123456789012",
+        "time": "2020-03-30T09:56:58Z",
+        "region": "rp-north-1",
+        "detail": {
+            "eventSource": "ecr.amazonaws.com",
+            "eventName": "PutImage",
+            "awsRegion": "rp-north-1",
+            "requestParameters": {
+                "repositoryName": "bittrance/ze-image",
+                "imageTag": "latest"
+            },
+            "responseElements": {
+                "image": {
+                    "registryId": "123456789012",
+                    "repositoryName": "bittrance/ze-image",
+                    "imageId": {
+                        "imageDigest": "sha256:1234",
+                        "imageTag": "latest"
+                    },
+                    "imageManifest": "{}"
+                }
+            }
+        }
+    })
+}
+
+#[test]
+fn test_ecr_schema() {
+    use crate::events::{ecr_schema, EcrSchema};
+    assert_eq!(Some(EcrSchema::ImageAction), ecr_schema(&message_event()));
+    assert_eq!(
+        Some(EcrSchema::CloudTrailPutImage),
+        ecr_schema(&cloudtrail_put_image_event().to_string())
+    );
+    assert_eq!(
+        None,
+        ecr_schema(&json!({"detail-type": "ECR Image Scan", "detail": {}}).to_string())
+    );
+}
+
+#[test]
+fn test_parse_cloudtrail_put_image_event() {
+    let body = cloudtrail_put_image_event().to_string();
+    let event = crate::events::parse_ecr_event(&body).unwrap().unwrap();
+    assert_eq!(
+        "123456789012.dkr.ecr.rp-north-1.amazonaws.com/bittrance/ze-image:latest",
+        event.image()
+    );
+    assert_eq!("sha256:1234", event.image_digest);
+    assert_eq!(
+        Some(Utc.ymd(2020, 3, 30).and_hms(9, 56, 58)),
+        event.pushed_at
+    );
+}
+
+#[test]
+fn test_parse_failed_cloudtrail_put_image_event() {
+    let mut body = cloudtrail_put_image_event();
+    body["detail"]["errorCode"] = json!("ImageAlreadyExistsException");
+    body["detail"]["responseElements"] = json!(null);
+    assert!(crate::events::parse_ecr_event(&body.to_string())
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_parse_ecr_event_of_unknown_version_and_fields() {
+    let mut body: serde_json::Value = serde_json::from_str(&message_event()).unwrap();
+    body["version"] = json!("1");
+    body["detail"]["artifact-media-type"] = json!("application/vnd.docker.container.image.v1+json");
+    let event = crate::events::parse_ecr_event(&body.to_string())
+        .unwrap()
+        .unwrap();
+    assert_eq!("sha256:1234", event.image_digest);
+}
+
+fn artifactory_event() -> String {
+    json!({
+        "domain": "docker",
+        "event_type": "pushed",
+        "data": {
+            "repo_key": "docker-local",
+            "event_type": "pushed",
+            "path": "ze-image/latest/manifest.json",
+            "name": "manifest.json",
+            "sha256": "1234",
+            "size": 524,
+            "image_name": "ze-image",
+            "tag": "latest"
+        },
+        "subscription_key": "deploys",
+        "jpd_origin": "https://bittrance.jfrog.io",
+        "source": "jfrog/bittrance"
+    })
+    .to_string()
+}
+
+#[test]
+fn test_parse_artifactory_event() {
+    let event = crate::events::parse_artifactory_event(&artifactory_event()).unwrap();
+    assert_eq!(
+        "bittrance.jfrog.io/docker-local/ze-image:latest",
+        event.image()
+    );
+    assert_eq!("sha256:1234", event.image_digest);
+    assert!(!event.is_ecr());
+}
+
+#[test]
+fn test_parse_event_tries_each_format() {
+    assert!(crate::events::parse_event(&message_event(), &[])
+        .unwrap()
+        .is_ecr());
+    assert!(!crate::events::parse_event(&artifactory_event(), &[])
+        .unwrap()
+        .is_ecr());
+    assert!(crate::events::parse_event("{\"detail\": 42}", &[]).is_none());
+}
+
+#[test]
+fn test_origin_allowed() {
+    let event = crate::events::parse_ecr_event(&message_event())
+        .unwrap()
+        .unwrap();
+    let account = vec!["123456789012".to_owned()];
+    let region = vec!["rp-north-1".to_owned()];
+    let other_account = vec!["210987654321".to_owned()];
+    let other_region = vec!["eu-west-1".to_owned()];
+    assert!(event.origin_allowed(&[], &[]));
+    assert!(event.origin_allowed(&account, &region));
+    assert!(!event.origin_allowed(&other_account, &[]));
+    assert!(!event.origin_allowed(&[], &other_region));
+    let event = crate::events::parse_artifactory_event(&artifactory_event()).unwrap();
+    assert!(event.origin_allowed(&other_account, &other_region));
+}
+
+#[test]
+fn test_replicas() {
+    let event = crate::events::parse_ecr_event(&message_event())
+        .unwrap()
+        .unwrap();
+    let replication = vec![
+        ("rp-north-1".to_owned(), "eu-west-1".to_owned()),
+        ("us-east-1".to_owned(), "eu-central-1".to_owned()),
+    ];
+    let replicas = crate::events::replicas(&event, &replication);
+    assert_eq!(1, replicas.len());
+    assert_eq!(
+        "123456789012.dkr.ecr.eu-west-1.amazonaws.com/bittrance/ze-image:latest",
+        replicas[0].image()
+    );
+    assert_eq!(event.image_digest, replicas[0].image_digest);
+    let event = crate::events::parse_artifactory_event(&artifactory_event()).unwrap();
+    assert!(crate::events::replicas(&event, &replication).is_empty());
+}
+
+#[test]
+fn test_cached() {
+    let cache: crate::events::PullThroughCache =
+        "123456789012.dkr.ecr.eu-west-1.amazonaws.com/docker-hub=docker.io"
+            .parse()
+            .unwrap();
+    assert_eq!("docker-hub", cache.prefix);
+    let event = crate::events::parse_event(&docker_hub_event("nginx"), &[]).unwrap();
+    let cached = crate::events::cached(&event, &[cache]);
+    assert_eq!(1, cached.len());
+    assert_eq!(
+        "123456789012.dkr.ecr.eu-west-1.amazonaws.com/docker-hub/library/nginx:latest",
+        cached[0].image()
+    );
+    assert!(cached[0].is_ecr());
+}
+
+#[test]
+fn test_pull_through_cache_format() {
+    assert!("docker-hub=docker.io"
+        .parse::<crate::events::PullThroughCache>()
+        .is_err());
+    assert!("123456789012.dkr.ecr.eu-west-1.amazonaws.com/docker-hub"
+        .parse::<crate::events::PullThroughCache>()
+        .is_err());
+}
+
+#[test]
+fn test_non_ecr_event_json_round_trip() {
+    let event = crate::events::parse_artifactory_event(&artifactory_event()).unwrap();
+    let parsed = crate::events::Event::from_json(&event.to_json()).unwrap();
+    assert_eq!(event.image(), parsed.image());
+}
+
+fn nexus_registries() -> Vec<(String, String)> {
+    vec![(
+        "docker-hosted".to_owned(),
+        "nexus.example.com:8082".to_owned(),
+    )]
+}
+
+#[test]
+fn test_parse_nexus_component_event() {
+    let body = json!({
+        "timestamp": "2020-03-30T09:56:58.000+0000",
+        "nodeId": "52905B51",
+        "initiator": "admin/127.0.0.1",
+        "repositoryName": "docker-hosted",
+        "action": "CREATED",
+        "component": {
+            "id": "08909bf0",
+            "componentId": "ZG9ja2VyLWhvc3RlZDo",
+            "format": "docker",
+            "name": "bittrance/ze-image",
+            "group": null,
+            "version": "1.0.0"
+        }
+    })
+    .to_string();
+    let event = crate::events::parse_event(&body, &nexus_registries()).unwrap();
+    assert_eq!(
+        "nexus.example.com:8082/bittrance/ze-image:1.0.0",
+        event.image()
+    );
+    assert!(event.image_digest.is_empty());
+}
+
+#[test]
+fn test_parse_nexus_asset_event() {
+    let body = json!({
+        "repositoryName": "docker-hosted",
+        "action": "UPDATED",
+        "asset": {
+            "id": "31c950c8",
+            "assetId": "ZG9ja2VyLWhvc3RlZDo",
+            "format": "docker",
+            "name": "v2/bittrance/ze-image/manifests/latest"
+        }
+    })
+    .to_string();
+    let event = crate::events::parse_nexus_event(&body, &nexus_registries()).unwrap();
+    assert_eq!(
+        "nexus.example.com:8082/bittrance/ze-image:latest",
+        event.image()
+    );
+}
+
+#[test]
+fn test_parse_nexus_event_requires_known_repository() {
+    let body = json!({
+        "repositoryName": "docker-proxy",
+        "action": "CREATED",
+        "component": {"format": "docker", "name": "ze-image", "version": "1.0.0"}
+    })
+    .to_string();
+    assert!(crate::events::parse_nexus_event(&body, &nexus_registries()).is_none());
+}
+
+fn docker_hub_event(repo_name: &str) -> String {
+    json!({
+        "callback_url": "https://registry.hub.docker.com/u/bittrance/ze-image/hook/2141b5bi5i5b02bec211i4eeih0242eg11000a/",
+        "push_data": {
+            "pushed_at": 1585562218,
+            "pusher": "bittrance",
+            "tag": "latest"
+        },
+        "repository": {
+            "name": "ze-image",
+            "namespace": "bittrance",
+            "repo_name": repo_name,
+            "repo_url": "https://registry.hub.docker.com/u/bittrance/ze-image/",
+            "status": "Active"
+        }
+    })
+    .to_string()
+}
+
+#[test]
+fn test_parse_docker_hub_event() {
+    let event = crate::events::parse_event(&docker_hub_event("bittrance/ze-image"), &[]).unwrap();
+    assert_eq!("bittrance/ze-image:latest", event.image());
+    assert!(event.image_digest.is_empty());
+    assert_eq!(
+        Some(Utc.ymd(2020, 3, 30).and_hms(9, 56, 58)),
+        event.pushed_at
+    );
+}
+
+#[test]
+fn test_parse_docker_hub_official_image_event() {
+    let event = crate::events::parse_docker_hub_event(&docker_hub_event("nginx")).unwrap();
+    assert_eq!("library/nginx", event.repository_name);
+    assert_eq!("nginx:latest", event.image());
+}
+
+#[test]
+fn test_parse_harbor_event() {
+    let body = json!({
+        "type": "PUSH_ARTIFACT",
+        "occur_at": 1585562218,
+        "operator": "admin",
+        "event_data": {
+            "resources": [{
+                "digest": "sha256:1234",
+                "tag": "latest",
+                "resource_url": "harbor.example.com/bittrance/ze-image:latest"
+            }, {
+                "digest": "sha256:1234",
+                "tag": "v1.2.3",
+                "resource_url": "harbor.example.com/bittrance/ze-image:v1.2.3"
+            }],
+            "repository": {
+                "date_created": 1585562218,
+                "name": "ze-image",
+                "namespace": "bittrance",
+                "repo_full_name": "bittrance/ze-image",
+                "repo_type": "private"
+            }
+        }
+    })
+    .to_string();
+    let events = crate::events::parse_events(&body, &[]);
+    let images: Vec<String> = events.iter().map(|event| event.image()).collect();
+    assert_eq!(
+        vec![
+            "harbor.example.com/bittrance/ze-image:latest",
+            "harbor.example.com/bittrance/ze-image:v1.2.3"
+        ],
+        images
+    );
+    assert!(events
+        .iter()
+        .all(|event| event.image_digest == "sha256:1234"));
+}
+
+#[test]
+fn test_parse_harbor_event_ignores_other_types() {
+    let body = json!({"type": "DELETE_ARTIFACT", "event_data": {}}).to_string();
+    assert!(crate::events::parse_harbor_events(&body).is_empty());
+}
+
+#[test]
+fn test_parse_gitlab_registry_event() {
+    let body = json!({
+        "events": [
+            {
+                "id": "320678d8-ca14-430f-8bb6-4ca139cd83f7",
+                "timestamp": "2020-03-30T09:56:58.000Z",
+                "action": "push",
+                "target": {
+                    "mediaType": "application/octet-stream",
+                    "digest": "sha256:abcd",
+                    "repository": "bittrance/ze-image"
+                },
+                "request": {"host": "registry.gitlab.example.com"}
+            },
+            {
+                "id": "6b4a61bc-93fc-4b4e-8c0f-2ec1a4a3a8a1",
+                "timestamp": "2020-03-30T09:56:58.000Z",
+                "action": "push",
+                "target": {
+                    "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+                    "digest": "sha256:1234",
+                    "repository": "bittrance/ze-image",
+                    "tag": "latest"
+                },
+                "request": {"host": "registry.gitlab.example.com"}
+            }
+        ]
+    })
+    .to_string();
+    let events = crate::events::parse_events(&body, &[]);
+    assert_eq!(1, events.len());
+    assert_eq!(
+        "registry.gitlab.example.com/bittrance/ze-image:latest",
+        events[0].image()
+    );
+    assert_eq!("sha256:1234", events[0].image_digest);
+}
+
+#[test]
+fn test_parse_github_package_event() {
+    let body = json!({
+        "action": "published",
+        "registry_package": {
+            "name": "ze-image",
+            "namespace": "bittrance",
+            "ecosystem": "CONTAINER",
+            "package_type": "CONTAINER",
+            "package_version": {
+                "version": "sha256:1234",
+                "updated_at": "2020-03-30T09:56:58Z",
+                "container_metadata": {
+                    "tag": {"name": "latest", "digest": "sha256:1234"}
+                },
+                "package_url": "ghcr.io/Bittrance/ze-image:latest"
+            },
+            "registry": {"url": "https://ghcr.io", "type": "ghcr.io"}
+        }
+    })
+    .to_string();
+    let event = crate::events::parse_event(&body, &[]).unwrap();
+    assert_eq!("ghcr.io/bittrance/ze-image:latest", event.image());
+    assert_eq!("sha256:1234", event.image_digest);
+    assert_eq!(
+        Some(Utc.ymd(2020, 3, 30).and_hms(9, 56, 58)),
+        event.pushed_at
+    );
+}
+
+#[test]
+fn test_parse_quay_events() {
+    let body = json!({
+        "repository": "bittrance/ze-image",
+        "namespace": "bittrance",
+        "name": "ze-image",
+        "docker_url": "quay.io/bittrance/ze-image",
+        "homepage": "https://quay.io/repository/bittrance/ze-image",
+        "updated_tags": ["latest", "1.0.0"]
+    })
+    .to_string();
+    let images: Vec<String> = crate::events::parse_events(&body, &[])
+        .iter()
+        .map(|event| event.image())
+        .collect();
+    assert_eq!(
+        vec![
+            "quay.io/bittrance/ze-image:latest",
+            "quay.io/bittrance/ze-image:1.0.0"
+        ],
+        images
+    );
+}
+
+#[test]
+fn test_parse_events_single_event() {
+    assert_eq!(1, crate::events::parse_events(&message_event(), &[]).len());
+    assert!(crate::events::parse_events("{}", &[]).is_empty());
+}
+
+#[test]
+fn test_parse_acr_events() {
+    let body = json!([{
+        "id": "831e1650-001e-001b-66ab-eeb76e069631",
+        "topic": "/subscriptions/1234/resourceGroups/ze-group/providers/Microsoft.ContainerRegistry/registries/bittrance",
+        "subject": "ze-image:latest",
+        "eventType": "Microsoft.ContainerRegistry.ImagePushed",
+        "eventTime": "2020-03-30T09:56:58Z",
+        "data": {
+            "id": "831e1650-001e-001b-66ab-eeb76e069631",
+            "timestamp": "2020-03-30T09:56:58Z",
+            "action": "push",
+            "target": {
+                "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+                "size": 524,
+                "digest": "sha256:1234",
+                "length": 524,
+                "repository": "ze-image",
+                "tag": "latest"
+            },
+            "request": {
+                "id": "273a3f5a-4d6b-4b80-b3b0-c0fb6f3a7a23",
+                "host": "bittrance.azurecr.io",
+                "method": "PUT"
+            }
+        },
+        "dataVersion": "1.0",
+        "metadataVersion": "1"
+    }])
+    .to_string();
+    let events = crate::events::parse_events(&body, &[]);
+    assert_eq!(1, events.len());
+    assert_eq!("bittrance.azurecr.io/ze-image:latest", events[0].image());
+    assert_eq!("sha256:1234", events[0].image_digest);
+}
+
+#[test]
+fn test_event_grid_validation_code() {
+    let body = json!([{
+        "id": "2d1781af-3a4c-4d7c-bd0c-e34b19da4e66",
+        "eventType": "Microsoft.EventGrid.SubscriptionValidationEvent",
+        "data": {"validationCode": "512d38b6-c7b8-40c8-89fe-f46f9e9622b6"}
+    }])
+    .to_string();
+    assert_eq!(
+        Some("512d38b6-c7b8-40c8-89fe-f46f9e9622b6".to_owned()),
+        crate::events::event_grid_validation_code(&body)
+    );
+    assert_eq!(
+        None,
+        crate::events::event_grid_validation_code(&message_event())
+    );
+}
+
+#[test]
+fn test_parse_gcr_event() {
+    let body = json!({
+        "action": "INSERT",
+        "digest": "europe-north1-docker.pkg.dev/ze-project/bittrance/ze-image@sha256:1234",
+        "tag": "europe-north1-docker.pkg.dev/ze-project/bittrance/ze-image:latest"
+    })
+    .to_string();
+    let event = crate::events::parse_event(&body, &[]).unwrap();
+    assert_eq!(
+        "europe-north1-docker.pkg.dev/ze-project/bittrance/ze-image:latest",
+        event.image()
+    );
+    assert_eq!("sha256:1234", event.image_digest);
+}
+
+#[test]
+fn test_parse_gcr_untagged_push() {
+    let body = json!({
+        "action": "INSERT",
+        "digest": "europe-north1-docker.pkg.dev/ze-project/bittrance/ze-image@sha256:1234"
+    })
+    .to_string();
+    assert!(crate::events::parse_gcr_event(&body).is_none());
+}
+
+#[test]
+fn test_replay_name() {
+    let replayed = r#"{"replay-name": "ze-replay", "detail": {}}"#;
+    assert_eq!(
+        Some("ze-replay".to_owned()),
+        crate::events::replay_name(replayed)
+    );
+    assert_eq!(None, crate::events::replay_name(r#"{"detail": {}}"#));
+}
+
+#[test]
+fn test_coalesce_keeps_newest_push() {
+    let older = super::message_event();
+    let mut newer = super::message_event();
+    newer.image_digest = String::from("sha256:5678");
+    newer.pushed_at = older.pushed_at.map(|t| t + Duration::minutes(1));
+    let mut other = super::message_event();
+    other.image_tag = String::from("v1");
+    let mut first = vec![newer, other];
+    let mut second = vec![older];
+    let superseded = crate::events::coalesce(vec![&mut first, &mut second]);
+    assert_eq!(1, superseded.len());
+    assert_eq!("sha256:1234", superseded[0].image_digest);
+    assert_eq!(2, first.len());
+    assert!(second.is_empty());
+}
+
+#[test]
+fn test_coalesce_prefers_later_message_on_tie() {
+    let mut first = vec![super::message_event()];
+    let mut later = super::message_event();
+    later.image_digest = String::from("sha256:5678");
+    let mut second = vec![later];
+    crate::events::coalesce(vec![&mut first, &mut second]);
+    assert!(first.is_empty());
+    assert_eq!("sha256:5678", second[0].image_digest);
+}
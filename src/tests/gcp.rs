@@ -0,0 +1,61 @@
+use crate::gcp;
+use serde_json::json;
+
+#[test]
+fn test_is_gcp_registry() {
+    assert!(gcp::is_gcp_registry("europe-north1-docker.pkg.dev"));
+    assert!(gcp::is_gcp_registry("eu.gcr.io"));
+    assert!(!gcp::is_gcp_registry("bittrance.azurecr.io"));
+}
+
+#[test]
+fn test_service_account_claims() {
+    let key = json!({
+        "type": "service_account",
+        "client_email": "deployer@ze-project.iam.gserviceaccount.com",
+        "token_uri": "https://oauth2.googleapis.com/token"
+    });
+    let claims = gcp::service_account_claims(&key, 1585562218).unwrap();
+    assert_eq!("deployer@ze-project.iam.gserviceaccount.com", claims["iss"]);
+    assert_eq!("https://oauth2.googleapis.com/token", claims["aud"]);
+    assert_eq!(1585565818, claims["exp"]);
+}
+
+#[test]
+fn test_messages_from_pull() {
+    let response = json!({
+        "receivedMessages": [{
+            "ackId": "ze-ack-id",
+            "message": {
+                "data": base64::encode("{\"action\":\"INSERT\"}"),
+                "messageId": "2070443601311540",
+                "publishTime": "2020-03-30T09:56:58Z"
+            },
+            "deliveryAttempt": 3
+        }]
+    });
+    let messages = gcp::messages_from_pull(&response);
+    assert_eq!(1, messages.len());
+    assert_eq!(Some("ze-ack-id".to_owned()), messages[0].receipt_handle);
+    assert_eq!(Some("{\"action\":\"INSERT\"}".to_owned()), messages[0].body);
+    assert_eq!(3, crate::sqs::receive_count(&messages[0]));
+}
+
+#[test]
+fn test_messages_from_empty_pull() {
+    assert!(gcp::messages_from_pull(&json!({})).is_empty());
+}
+
+#[test]
+fn test_has_dead_letter_policy() {
+    let subscription = json!({
+        "name": "projects/ze-project/subscriptions/ze-subscription",
+        "deadLetterPolicy": {
+            "deadLetterTopic": "projects/ze-project/topics/ze-dead-letters",
+            "maxDeliveryAttempts": 5
+        }
+    });
+    assert!(gcp::has_dead_letter_policy(&subscription));
+    let subscription = json!({ "name": "projects/ze-project/subscriptions/ze-subscription" });
+    assert!(!gcp::has_dead_letter_policy(&subscription));
+}
@@ -0,0 +1,56 @@
+use crate::github;
+use structopt::StructOpt;
+
+#[test]
+fn test_app_claims() {
+    let claims = github::app_claims(1234, 1585562218);
+    assert_eq!("1234", claims["iss"]);
+    assert_eq!(1585562158, claims["iat"]);
+    assert_eq!(1585562758, claims["exp"]);
+}
+
+#[test]
+fn test_ghcr_credentials_from_token() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "some-queue",
+            "--ghcr-username",
+            "bittrance",
+            "--ghcr-token",
+            "ghp_1234",
+        ]
+        .iter(),
+    );
+    let credentials = github::ghcr_credentials(&mut github::InstallationToken::default(), &opt)
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some("bittrance".to_owned()), credentials.username);
+    assert_eq!(Some("ghp_1234".to_owned()), credentials.password);
+}
+
+#[test]
+fn test_ghcr_token_requires_username() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "some-queue",
+            "--ghcr-token",
+            "ghp_1234",
+        ]
+        .iter(),
+    );
+    assert!(github::ghcr_credentials(&mut github::InstallationToken::default(), &opt).is_err());
+}
+
+#[test]
+fn test_no_ghcr_credentials() {
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "--queue", "some-queue"].iter());
+    assert!(
+        github::ghcr_credentials(&mut github::InstallationToken::default(), &opt)
+            .unwrap()
+            .is_none()
+    );
+}
@@ -0,0 +1,86 @@
+use crate::journal::Journal;
+use chrono::Duration;
+use std::fs;
+use std::path::PathBuf;
+
+fn journal_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("seedy-journal-{}.json", name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn test_journal_survives_reopen() {
+    let path = journal_path("reopen");
+    let mut journal = Journal::open(Some(path.as_path())).unwrap();
+    journal
+        .begin("msg-1", "foo", "ze-image@sha256:1234")
+        .unwrap();
+    let reopened = Journal::open(Some(path.as_path())).unwrap();
+    let entry = reopened.get("msg-1", "foo").unwrap();
+    assert_eq!("foo", entry.service_id);
+    assert_eq!("ze-image@sha256:1234", entry.image);
+}
+
+#[test]
+fn test_journal_clear_removes_entry() {
+    let path = journal_path("clear");
+    let mut journal = Journal::open(Some(path.as_path())).unwrap();
+    journal
+        .begin("msg-1", "foo", "ze-image@sha256:1234")
+        .unwrap();
+    journal.clear("msg-1").unwrap();
+    let reopened = Journal::open(Some(path.as_path())).unwrap();
+    assert!(reopened.get("msg-1", "foo").is_none());
+}
+
+#[test]
+fn test_journal_without_path_is_in_memory() {
+    let mut journal = Journal::open(None).unwrap();
+    journal
+        .begin("msg-1", "foo", "ze-image@sha256:1234")
+        .unwrap();
+    assert!(journal.get("msg-1", "foo").is_some());
+}
+
+#[test]
+fn test_journal_sweep_drops_expired_entries() {
+    let mut journal = Journal::open(None).unwrap();
+    journal
+        .begin("msg-1", "foo", "ze-image@sha256:1234")
+        .unwrap();
+    assert!(journal.sweep(Duration::hours(1)).unwrap().is_empty());
+    let removed = journal.sweep(Duration::hours(-1)).unwrap();
+    assert_eq!(1, removed.len());
+    assert!(journal.get("msg-1", "foo").is_none());
+}
+
+#[test]
+fn test_journal_keeps_entries_per_service() {
+    let path = journal_path("per-service");
+    let mut journal = Journal::open(Some(path.as_path())).unwrap();
+    journal
+        .begin("msg-1", "foo", "ze-image@sha256:1234")
+        .unwrap();
+    journal
+        .begin("msg-1", "bar", "ze-image@sha256:1234")
+        .unwrap();
+    let reopened = Journal::open(Some(path.as_path())).unwrap();
+    assert!(reopened.get("msg-1", "foo").is_some());
+    assert!(reopened.get("msg-1", "bar").is_some());
+    assert_eq!(2, reopened.entries().count());
+    journal.clear("msg-1").unwrap();
+    assert_eq!(0, journal.entries().count());
+}
+
+#[test]
+fn test_journal_reads_entries_without_service_key() {
+    let path = journal_path("legacy");
+    let legacy = r#"{"msg-1": {"service_id": "foo", "image": "ze-image@sha256:1234", "started_at": "2020-03-30T09:56:58Z"}}"#;
+    fs::write(&path, legacy).unwrap();
+    let journal = Journal::open(Some(path.as_path())).unwrap();
+    assert_eq!(
+        "ze-image@sha256:1234",
+        journal.get("msg-1", "foo").unwrap().image
+    );
+}
@@ -0,0 +1,35 @@
+use crate::kafka::{self, Outstanding};
+
+#[test]
+fn test_commit_waits_for_earlier_offsets() {
+    let mut outstanding = Outstanding::default();
+    for offset in 10..13 {
+        outstanding.track(0, offset);
+    }
+    assert_eq!(Some(9), outstanding.settle(0, 11));
+    assert_eq!(Some(11), outstanding.settle(0, 10));
+    assert_eq!(Some(12), outstanding.settle(0, 12));
+}
+
+#[test]
+fn test_partitions_are_independent() {
+    let mut outstanding = Outstanding::default();
+    outstanding.track(0, 5);
+    outstanding.track(1, 7);
+    assert_eq!(Some(7), outstanding.settle(1, 7));
+    assert_eq!(Some(5), outstanding.settle(0, 5));
+}
+
+#[test]
+fn test_nothing_to_commit_before_first_offset() {
+    let mut outstanding = Outstanding::default();
+    outstanding.track(0, 0);
+    outstanding.track(0, 1);
+    assert_eq!(None, outstanding.settle(0, 1));
+}
+
+#[test]
+fn test_parse_receipt_handle() {
+    assert_eq!(Some((3, 1234)), kafka::parse_receipt_handle("3:1234"));
+    assert_eq!(None, kafka::parse_receipt_handle("ze-handle"));
+}
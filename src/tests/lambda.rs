@@ -0,0 +1,31 @@
+use crate::lambda;
+use serde_json::json;
+
+#[test]
+fn test_messages_from_event() {
+    let event = json!({
+        "Records": [{
+            "messageId": "059f36b4-87a3-44ab-83d2-661975830a7d",
+            "receiptHandle": "AQEBwJnKyrHigUMZj6rYigCgxlaS3SLy0a",
+            "body": "{}",
+            "attributes": {
+                "ApproximateReceiveCount": "2",
+                "SentTimestamp": "1545082649183"
+            },
+            "messageAttributes": {},
+            "md5OfBody": "99914b932bd37a50b983c5e7c90ae93b",
+            "eventSource": "aws:sqs",
+            "eventSourceARN": "arn:aws:sqs:rp-north-1:123456789012:ze-queue",
+            "awsRegion": "rp-north-1"
+        }]
+    });
+    let messages = lambda::messages_from_event(&event);
+    assert_eq!(1, messages.len());
+    assert_eq!(Some("{}".to_owned()), messages[0].body);
+    assert_eq!(2, crate::sqs::receive_count(&messages[0]));
+}
+
+#[test]
+fn test_messages_from_other_event() {
+    assert!(lambda::messages_from_event(&json!({"detail": {}})).is_empty());
+}
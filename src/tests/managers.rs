@@ -0,0 +1,68 @@
+use crate::managers::Managers;
+use crate::SeedyError;
+use bollard::errors::ErrorKind;
+
+fn managers() -> Managers {
+    Managers::connect(&[
+        "tcp://manager-1:2375".to_owned(),
+        "tcp://manager-2:2375".to_owned(),
+    ])
+    .unwrap()
+}
+
+#[test]
+fn test_run_returns_first_success() {
+    let mut calls = 0;
+    let result = managers().run(|_| {
+        calls += 1;
+        Ok(42)
+    });
+    assert_eq!(42, result.unwrap());
+    assert_eq!(1, calls);
+}
+
+#[test]
+fn test_run_does_not_fail_over_on_non_docker_errors() {
+    let mut calls = 0;
+    let result: Result<(), SeedyError> = managers().run(|_| {
+        calls += 1;
+        Err(SeedyError::LabelFilterError {
+            label: "foo".to_owned(),
+        })
+    });
+    assert!(result.is_err());
+    assert_eq!(1, calls);
+}
+
+#[test]
+fn test_run_fails_over_on_connection_errors() {
+    let mut calls = 0;
+    let result = managers().run(|_| {
+        calls += 1;
+        if calls == 1 {
+            Err(SeedyError::ServiceListing {
+                source: ErrorKind::RequestTimeoutError.into(),
+            })
+        } else {
+            Ok(42)
+        }
+    });
+    assert_eq!(42, result.unwrap());
+    assert_eq!(2, calls);
+}
+
+#[test]
+fn test_run_does_not_fail_over_on_refused_requests() {
+    let mut calls = 0;
+    let result: Result<(), SeedyError> = managers().run(|_| {
+        calls += 1;
+        Err(SeedyError::ServiceListing {
+            source: ErrorKind::DockerResponseBadParameterError {
+                message: "invalid filter".to_owned(),
+            }
+            .into(),
+        })
+    });
+    assert!(result.is_err());
+    assert_eq!(1, calls);
+}
@@ -0,0 +1,81 @@
+use crate::mapping;
+use serde_json::json;
+
+fn mappings() -> Vec<mapping::FieldMapping> {
+    vec![
+        mapping::parse_field_mapping("registry=image.host").unwrap(),
+        mapping::parse_field_mapping("repository=image.name").unwrap(),
+        mapping::parse_field_mapping("tag=image.tags[0]").unwrap(),
+        mapping::parse_field_mapping("digest=image.digest").unwrap(),
+    ]
+}
+
+#[test]
+fn test_parse_field_mapping() {
+    let (field, expression) = mapping::parse_field_mapping("repository=detail.repo").unwrap();
+    assert_eq!("repository", field);
+    assert_eq!("detail.repo", expression.as_str());
+    assert!(mapping::parse_field_mapping("colour=detail.colour").is_err());
+    assert!(mapping::parse_field_mapping("repository").is_err());
+}
+
+#[test]
+fn test_mapped_event() {
+    let body = json!({
+        "image": {
+            "host": "registry.example.com",
+            "name": "bittrance/ze-image",
+            "tags": ["latest", "v1.2.3"],
+            "digest": "sha256:1234"
+        }
+    })
+    .to_string();
+    let event = mapping::mapped_event(&body, &mappings()).unwrap().unwrap();
+    assert_eq!(
+        "registry.example.com/bittrance/ze-image:latest",
+        event.image()
+    );
+    assert_eq!("sha256:1234", event.image_digest);
+}
+
+#[test]
+fn test_mapped_event_requires_image() {
+    let body = json!({"image": {"host": "registry.example.com"}}).to_string();
+    assert!(mapping::mapped_event(&body, &mappings()).unwrap().is_none());
+    assert!(mapping::mapped_event("not json", &mappings())
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_mapped_ecr_event_from_account_and_region() {
+    let mappings = vec![
+        mapping::parse_field_mapping("account=acct").unwrap(),
+        mapping::parse_field_mapping("region=loc").unwrap(),
+        mapping::parse_field_mapping("repository=repo").unwrap(),
+        mapping::parse_field_mapping("tag=tag").unwrap(),
+    ];
+    let body =
+        json!({"acct": 123456789012u64, "loc": "rp-north-1", "repo": "ze-image", "tag": "latest"})
+            .to_string();
+    let event = mapping::mapped_event(&body, &mappings).unwrap().unwrap();
+    assert_eq!(
+        "123456789012.dkr.ecr.rp-north-1.amazonaws.com/ze-image:latest",
+        event.image()
+    );
+    assert!(event.image_digest.is_empty());
+}
+
+#[test]
+fn test_invalid_expression() {
+    assert!(mapping::parse_field_mapping("repository=image[").is_err());
+}
+
+#[test]
+fn test_failing_expression() {
+    let mappings = vec![
+        mapping::parse_field_mapping("repository=abs(image)").unwrap(),
+        mapping::parse_field_mapping("tag=tag").unwrap(),
+    ];
+    assert!(mapping::mapped_event("{\"image\": \"ze-image\"}", &mappings).is_err());
+}
@@ -0,0 +1,106 @@
+use crate::markers::{self, Deployment, Outcome, Report};
+use std::collections::HashMap;
+use structopt::StructOpt;
+
+fn deployment() -> Deployment {
+    Deployment {
+        service_id: "foo".to_owned(),
+        service_name: "ze-service".to_owned(),
+        image: "bittrance/ze-image:latest".to_owned(),
+        digest: "sha256:1234".to_owned(),
+        outcome: Outcome::Converged,
+        labels: HashMap::new(),
+        replay: None,
+    }
+}
+
+#[test]
+fn test_describe_deployment() {
+    assert_eq!(
+        "Service ze-service (foo) converged with bittrance/ze-image:latest@sha256:1234",
+        deployment().describe()
+    );
+}
+
+#[test]
+fn test_describe_replayed_deployment() {
+    let deployment = Deployment {
+        replay: Some("ze-replay".to_owned()),
+        ..deployment()
+    };
+    assert_eq!(
+        "Service ze-service (foo) converged with bittrance/ze-image:latest@sha256:1234 (replay ze-replay)",
+        deployment.describe()
+    );
+}
+
+#[test]
+fn test_no_sinks_by_default() {
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "--queue", "some-queue"].iter());
+    assert!(markers::sinks_from_opt(&opt).unwrap().is_empty());
+}
+
+#[test]
+fn test_grafana_sink_from_opt() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "some-queue",
+            "--grafana-url",
+            "http://grafana:3000",
+        ]
+        .iter(),
+    );
+    let sinks = markers::sinks_from_opt(&opt).unwrap();
+    assert_eq!(1, sinks.len());
+    assert_eq!("grafana", sinks[0].name());
+}
+
+#[test]
+fn test_datadog_and_newrelic_sinks_from_opt() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "some-queue",
+            "--datadog-api-key",
+            "dd-key",
+            "--newrelic-api-key",
+            "nr-key",
+        ]
+        .iter(),
+    );
+    let names: Vec<String> = markers::sinks_from_opt(&opt)
+        .unwrap()
+        .iter()
+        .map(|sink| sink.name().to_owned())
+        .collect();
+    assert_eq!(vec!["datadog", "newrelic"], names);
+}
+
+#[test]
+fn test_deployment_to_json() {
+    let environment = "production".to_owned();
+    let record = deployment().to_json(Some(&environment));
+    assert_eq!("ze-service", record["service_name"]);
+    assert_eq!("sha256:1234", record["digest"]);
+    assert_eq!("converged", record["outcome"]);
+    assert_eq!("production", record["environment"]);
+}
+
+#[test]
+fn test_report_lists_findings() {
+    let report = Report {
+        title: "drift".to_owned(),
+        summary: "1 of 2 tracked services behind".to_owned(),
+        findings: vec!["Service ze-service (foo) runs sha256:1234".to_owned()],
+    };
+    assert_eq!(
+        "1 of 2 tracked services behind\nService ze-service (foo) runs sha256:1234",
+        report.describe()
+    );
+    let record = report.to_json(None);
+    assert_eq!("drift", record["report"]);
+    assert_eq!(1, record["findings"].as_array().unwrap().len());
+}
@@ -0,0 +1,42 @@
+use crate::build_info;
+use crate::metrics::Metrics;
+
+#[test]
+fn test_render_queue_depth_and_lag() {
+    let metrics = Metrics::default();
+    metrics.set_queue_depth("ze-queue", 12, 3);
+    metrics.record_event(Some(chrono::Duration::seconds(42)));
+    let rendered = metrics.render();
+    assert!(rendered.contains("seedy_queue_messages{queue=\"ze-queue\"} 12\n"));
+    assert!(rendered.contains("seedy_queue_messages_in_flight{queue=\"ze-queue\"} 3\n"));
+    assert!(rendered.contains("seedy_event_lag_seconds 42\n"));
+    assert!(rendered.contains("seedy_events_total 1\n"));
+}
+
+#[test]
+fn test_no_lag_before_first_event() {
+    let rendered = Metrics::default().render();
+    assert!(!rendered.contains("seedy_event_lag_seconds"));
+    assert!(rendered.contains("seedy_events_total 0\n"));
+}
+
+#[test]
+fn test_events_without_lag_are_counted() {
+    let metrics = Metrics::default();
+    metrics.record_event(None);
+    let rendered = metrics.render();
+    assert!(!rendered.contains("seedy_event_lag_seconds"));
+    assert!(rendered.contains("seedy_events_total 1\n"));
+}
+
+#[test]
+fn test_render_build_info() {
+    let rendered = Metrics::default().render();
+    let expected = format!(
+        "seedy_build_info{{version=\"{}\",git_sha=\"{}\",features=\"{}\"}} 1\n",
+        build_info::VERSION,
+        build_info::GIT_SHA,
+        build_info::FEATURES
+    );
+    assert!(rendered.contains(&expected));
+}
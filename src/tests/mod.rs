@@ -6,8 +6,92 @@ use chrono::{TimeZone, Utc};
 use std::collections::HashMap;
 use structopt::StructOpt;
 
+#[cfg(test)]
+mod auth;
+#[cfg(test)]
+mod aws;
+#[cfg(test)]
+mod build_info;
+#[cfg(test)]
+mod codebuild;
+#[cfg(test)]
+mod containers;
+#[cfg(test)]
+mod convergence;
+#[cfg(test)]
+mod dedupe;
+#[cfg(test)]
+mod deletions;
+#[cfg(test)]
+mod docker_config;
+#[cfg(test)]
+mod drift;
+#[cfg(test)]
+mod ecr_tokens;
 #[cfg(test)]
 mod events;
+#[cfg(test)]
+mod gcp;
+#[cfg(test)]
+mod github;
+#[cfg(test)]
+mod journal;
+#[cfg(test)]
+mod kafka;
+#[cfg(test)]
+mod lambda;
+#[cfg(test)]
+mod managers;
+#[cfg(test)]
+mod mapping;
+#[cfg(test)]
+mod markers;
+#[cfg(test)]
+mod metrics;
+#[cfg(test)]
+mod mqtt;
+#[cfg(test)]
+mod platform;
+#[cfg(test)]
+mod plugins;
+#[cfg(test)]
+mod policy;
+#[cfg(test)]
+mod polling;
+#[cfg(test)]
+mod quarantine;
+#[cfg(test)]
+mod redis;
+#[cfg(test)]
+mod reference;
+#[cfg(test)]
+mod registry;
+#[cfg(test)]
+mod replay;
+#[cfg(test)]
+mod s3;
+#[cfg(test)]
+mod scan;
+#[cfg(test)]
+mod schedule;
+#[cfg(test)]
+mod secrets;
+#[cfg(test)]
+mod sns;
+#[cfg(test)]
+mod source;
+#[cfg(test)]
+mod sqs;
+#[cfg(test)]
+mod sso;
+#[cfg(test)]
+mod stepfunctions;
+#[cfg(test)]
+mod vault;
+#[cfg(test)]
+mod watch;
+#[cfg(test)]
+mod webhook;
 
 fn message_event() -> crate::events::Event {
     crate::events::Event {
@@ -16,6 +100,8 @@ fn message_event() -> crate::events::Event {
         repository_name: String::from("bittrance/ze-image"),
         image_tag: String::from("latest"),
         image_digest: String::from("sha256:1234"),
+        pushed_at: Some(Utc.ymd(2020, 3, 30).and_hms(9, 56, 58)),
+        registry: None,
     }
 }
 
@@ -90,6 +176,13 @@ fn test_extract_service_image_from_container_spec_with_label_with_sha() {
     assert_ne!(Some("bittrance/ze-image:latest".to_owned()), image);
 }
 
+#[test]
+fn test_extract_service_image_normalizes_reference() {
+    let service = service_spec(None, Some("docker.io/library/nginx@sha256:1234".to_owned()));
+    let image = crate::extract_service_image(&service);
+    assert_eq!(Some("nginx:latest".to_owned()), image);
+}
+
 #[test]
 fn test_extract_service_image_from_container_with_nothing() {
     let service = service_spec(None, None);
@@ -135,6 +228,14 @@ fn test_build_service_index() {
     assert_eq!(1, index.len());
 }
 
+#[test]
+fn test_build_service_index_skips_unrecognizable_images() {
+    let service = service_spec(None, Some("bittrance/ze-image:".to_owned()));
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "--queue", "some-queue"].iter());
+    let index = crate::build_service_index(vec![service], &opt);
+    assert!(index.is_empty());
+}
+
 #[test]
 fn test_build_service_index_with_label_filter_includes() {
     let service = service_spec(
@@ -174,3 +275,148 @@ fn test_build_service_index_with_label_filter_excludes() {
     let index = crate::build_service_index(vec![service], &opt);
     assert_eq!(0, index.len());
 }
+
+#[test]
+fn test_update_spec_applies_update_order_label() {
+    let service = service_spec(
+        filter_label(crate::UPDATE_ORDER_LABEL, "stop-first"),
+        Some("bittrance/ze-image:latest".to_owned()),
+    );
+    let updated_spec = crate::update_spec(&service, &message_event());
+    assert_eq!(
+        Some("stop-first".to_owned()),
+        updated_spec.update_config.and_then(|config| config.order)
+    );
+}
+
+#[test]
+fn test_update_spec_ignores_invalid_update_order_label() {
+    let service = service_spec(
+        filter_label(crate::UPDATE_ORDER_LABEL, "sideways"),
+        Some("bittrance/ze-image:latest".to_owned()),
+    );
+    let updated_spec = crate::update_spec(&service, &message_event());
+    assert_eq!(
+        None,
+        updated_spec.update_config.and_then(|config| config.order)
+    );
+}
+
+#[test]
+fn test_running_image_digest() {
+    let service = service_spec(
+        None,
+        Some("bittrance/ze-image:latest@sha256:1234".to_owned()),
+    );
+    let running = crate::running_image(&service).unwrap();
+    assert_eq!(Some("sha256:1234".to_owned()), running.digest);
+    let service = service_spec(None, Some("bittrance/ze-image:latest".to_owned()));
+    assert_eq!(None, crate::running_image(&service).unwrap().digest);
+}
+
+#[test]
+fn test_is_stale() {
+    let mut service = service_spec(
+        None,
+        Some("bittrance/ze-image:latest@sha256:5678".to_owned()),
+    );
+    let event = message_event();
+    assert!(!crate::is_stale(&service, &event));
+    service.spec.labels.insert(
+        crate::PUSHED_AT_LABEL.to_owned(),
+        "2020-03-30T09:57:30Z".to_owned(),
+    );
+    assert!(!crate::is_stale(&service, &event));
+    service.spec.labels.insert(
+        crate::PUSHED_AT_LABEL.to_owned(),
+        "2020-03-31T00:00:00Z".to_owned(),
+    );
+    assert!(crate::is_stale(&service, &event));
+    service.spec.task_template.container_spec = Some(TaskSpecContainerSpec {
+        image: Some("bittrance/ze-image:latest@sha256:1234".to_owned()),
+        ..Default::default()
+    });
+    assert!(!crate::is_stale(&service, &event));
+}
+
+#[test]
+fn test_update_records_push_time() {
+    let service = service_spec(None, Some("bittrance/ze-image:latest".to_owned()));
+    let updated_spec = crate::update_spec(&service, &message_event());
+    assert_eq!(
+        Some(&"2020-03-30T09:56:58+00:00".to_owned()),
+        updated_spec.labels.get(crate::PUSHED_AT_LABEL)
+    );
+}
+
+#[test]
+fn test_verify_credentials_command() {
+    let opt =
+        crate::Opt::from_iter(vec!["ze-bin", "--queue", "some-queue", "verify-credentials"].iter());
+    assert_eq!(Some(crate::Command::VerifyCredentials), opt.command);
+}
+
+#[test]
+fn test_ecr_replication_takes_regions() {
+    let args = vec![
+        "ze-bin",
+        "-q",
+        "ze-queue",
+        "--ecr-replication",
+        "us-east-1=eu-west-1",
+    ];
+    let opt = crate::Opt::from_iter(args.iter());
+    assert_eq!(
+        vec![("us-east-1".to_owned(), "eu-west-1".to_owned())],
+        opt.ecr_replication
+    );
+    let args = vec![
+        "ze-bin",
+        "-q",
+        "ze-queue",
+        "--ecr-replication",
+        "us-east-1=europe",
+    ];
+    let err = crate::Opt::from_iter_safe(args.iter()).unwrap_err();
+    assert!(err.message.contains("--ecr-replication"));
+}
+
+#[test]
+fn test_key_value_options_name_the_option() {
+    let args = vec![
+        "ze-bin",
+        "-q",
+        "ze-queue",
+        "--nexus-registry",
+        "docker-hosted",
+    ];
+    let err = crate::Opt::from_iter_safe(args.iter()).unwrap_err();
+    assert!(err.message.contains("--nexus-registry"));
+    let args = vec![
+        "ze-bin",
+        "-q",
+        "ze-queue",
+        "--nexus-registry",
+        "docker-hosted=nexus:8082",
+    ];
+    let opt = crate::Opt::from_iter(args.iter());
+    assert_eq!(
+        vec![("docker-hosted".to_owned(), "nexus:8082".to_owned())],
+        opt.nexus_registries
+    );
+}
+
+#[test]
+fn test_commands_need_no_queue() {
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "verify-credentials"].iter());
+    assert_eq!(Some(crate::Command::VerifyCredentials), opt.command);
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "lambda"].iter());
+    assert_eq!(Some(crate::Command::Lambda), opt.command);
+    assert!(crate::Opt::from_iter_safe(vec!["ze-bin"].iter()).is_err());
+}
+
+#[test]
+fn test_no_command_by_default() {
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "--queue", "some-queue"].iter());
+    assert_eq!(None, opt.command);
+}
@@ -0,0 +1,14 @@
+use crate::mqtt;
+
+#[test]
+fn test_broker_address() {
+    assert_eq!(
+        Some(("mosquitto".to_owned(), 8883)),
+        mqtt::broker_address("mosquitto:8883")
+    );
+    assert_eq!(
+        Some(("mosquitto".to_owned(), 1883)),
+        mqtt::broker_address("mosquitto")
+    );
+    assert_eq!(None, mqtt::broker_address("mosquitto:ze-port"));
+}
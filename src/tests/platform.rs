@@ -0,0 +1,70 @@
+use crate::platform::{self, Platform};
+use serde_json::json;
+
+#[test]
+fn test_from_constraints() {
+    let constraints = vec![
+        "node.role==worker".to_owned(),
+        "node.platform.os == linux".to_owned(),
+        "node.platform.arch==aarch64".to_owned(),
+    ];
+    assert_eq!(
+        Some(Platform::new("linux", "arm64")),
+        platform::from_constraints(&constraints)
+    );
+    assert_eq!(
+        None,
+        platform::from_constraints(&["node.role==manager".to_owned()])
+    );
+}
+
+#[test]
+fn test_partial_platform_matches_any_architecture() {
+    let windows = Platform::new("windows", "");
+    assert!(windows.satisfied_by(&Platform::new("windows", "amd64")));
+    assert!(!windows.satisfied_by(&Platform::new("linux", "amd64")));
+}
+
+#[test]
+fn test_from_manifest_list() {
+    let manifest = json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.docker.distribution.manifest.list.v2+json",
+        "manifests": [
+            {"digest": "sha256:1234", "platform": {"architecture": "amd64", "os": "linux"}},
+            {"digest": "sha256:5678", "platform": {"architecture": "arm64", "os": "linux", "variant": "v8"}}
+        ]
+    });
+    assert_eq!(
+        Some(vec![
+            Platform::new("linux", "amd64"),
+            Platform::new("linux", "arm64")
+        ]),
+        platform::from_manifest(&manifest)
+    );
+}
+
+#[test]
+fn test_from_plain_manifest() {
+    let manifest = json!({"schemaVersion": 2, "config": {}, "layers": []});
+    assert_eq!(None, platform::from_manifest(&manifest));
+}
+
+#[test]
+fn test_missing() {
+    let required = vec![
+        Platform::new("linux", "amd64"),
+        Platform::new("linux", "arm64"),
+    ];
+    let available = vec![Platform::new("linux", "amd64")];
+    assert_eq!(
+        vec![Platform::new("linux", "arm64")],
+        platform::missing(&required, &available)
+    );
+}
+
+#[test]
+fn test_unplaced_service_requires_nothing() {
+    let service = super::service_spec(None, None);
+    assert!(platform::required(&service).is_empty());
+}
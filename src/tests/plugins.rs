@@ -0,0 +1,179 @@
+use crate::events::Event;
+use crate::plugins::{self, Plugin};
+use crate::Result;
+use bollard::service::{Service, ServiceSpec};
+use structopt::StructOpt;
+
+struct Veto;
+
+impl Plugin for Veto {
+    fn name(&self) -> &str {
+        "veto"
+    }
+
+    fn parse(&self, body: &str) -> Result<Option<Event>> {
+        if body == "custom" {
+            Ok(Some(super::message_event()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn accept(&self, _event: &Event, service: &Service<String>) -> Result<bool> {
+        Ok(!service.spec.labels.contains_key("frozen"))
+    }
+}
+
+struct Retag;
+
+impl Plugin for Retag {
+    fn name(&self) -> &str {
+        "retag"
+    }
+
+    fn parse(&self, _body: &str) -> Result<Option<Event>> {
+        Ok(None)
+    }
+
+    fn accept(&self, _event: &Event, _service: &Service<String>) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn rewrite(&self, mut event: Event) -> Result<Event> {
+        event.image_tag = "stable".to_owned();
+        Ok(event)
+    }
+
+    fn mutate_spec(
+        &self,
+        event: &Event,
+        _service: &Service<String>,
+        mut spec: ServiceSpec<String>,
+    ) -> Result<ServiceSpec<String>> {
+        spec.labels
+            .insert("retagged".to_owned(), event.image_tag.clone());
+        Ok(spec)
+    }
+}
+
+fn loaded() -> Vec<Box<dyn Plugin>> {
+    vec![Box::new(Veto)]
+}
+
+#[test]
+fn test_plugin_parses_custom_body() {
+    let event = plugins::parse(&loaded(), "custom").unwrap().unwrap();
+    assert_eq!("bittrance/ze-image", event.repository_name);
+    assert!(plugins::parse(&loaded(), "other").unwrap().is_none());
+}
+
+#[test]
+fn test_plugin_vetoes_service() {
+    let frozen = super::service_spec(
+        super::filter_label("frozen", "true"),
+        Some("bittrance/ze-image:latest".to_owned()),
+    );
+    let thawed = super::service_spec(None, Some("bittrance/ze-image:latest".to_owned()));
+    let event = super::message_event();
+    assert!(!plugins::accept(&loaded(), &event, &frozen).unwrap());
+    assert!(plugins::accept(&loaded(), &event, &thawed).unwrap());
+}
+
+#[test]
+fn test_service_to_json() {
+    let service = super::service_spec(None, Some("bittrance/ze-image:latest".to_owned()));
+    let value = plugins::service_to_json(&service);
+    assert_eq!("ze-service", value["name"]);
+    assert_eq!("bittrance/ze-image:latest", value["image"]);
+}
+
+#[test]
+fn test_rewrite_defaults_to_unchanged_event() {
+    let event = plugins::rewrite(&loaded(), super::message_event()).unwrap();
+    assert_eq!("latest", event.image_tag);
+}
+
+#[test]
+fn test_plugin_rewrites_event() {
+    let plugins: Vec<Box<dyn Plugin>> = vec![Box::new(Veto), Box::new(Retag)];
+    let event = plugins::rewrite(&plugins, super::message_event()).unwrap();
+    assert_eq!("stable", event.image_tag);
+}
+
+#[test]
+fn test_plugin_mutates_spec() {
+    let plugins: Vec<Box<dyn Plugin>> = vec![Box::new(Veto), Box::new(Retag)];
+    let service = super::service_spec(None, Some("bittrance/ze-image:latest".to_owned()));
+    let event = super::message_event();
+    let spec = crate::update_spec(&service, &event);
+    let spec = plugins::mutate_spec(&plugins, &event, &service, spec).unwrap();
+    assert_eq!(Some(&"latest".to_owned()), spec.labels.get("retagged"));
+}
+
+#[test]
+fn test_wasm_files_in_name_order() {
+    let dir = std::env::temp_dir().join("seedy-wasm-plugins");
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in &["b.wasm", "a.wasm", "notes.txt"] {
+        std::fs::write(dir.join(name), b"").unwrap();
+    }
+    let files = plugins::wasm_files(&dir).unwrap();
+    assert_eq!(vec![dir.join("a.wasm"), dir.join("b.wasm")], files);
+    assert!(plugins::wasm_files(&dir.join("missing")).is_err());
+}
+
+fn command_plugins(command: &str) -> Vec<Box<dyn Plugin>> {
+    let opt = crate::Opt::from_iter(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--event-parser-cmd",
+        command,
+    ]);
+    plugins::load(&opt).unwrap()
+}
+
+#[test]
+fn test_event_parser_cmd_parses_body() {
+    let body = super::message_event().to_json().to_string();
+    let plugins = command_plugins("cat");
+    let event = plugins::parse(&plugins, &body).unwrap().unwrap();
+    assert_eq!("bittrance/ze-image", event.repository_name);
+    assert_eq!("sha256:1234", event.image_digest);
+}
+
+#[test]
+fn test_event_parser_cmd_without_output() {
+    let plugins = command_plugins("cat > /dev/null");
+    assert!(plugins::parse(&plugins, "exotic").unwrap().is_none());
+}
+
+#[test]
+fn test_event_parser_cmd_failures() {
+    assert!(plugins::parse(&command_plugins("exit 1"), "exotic")
+        .unwrap()
+        .is_none());
+    assert!(plugins::parse(&command_plugins("echo '{}'"), "exotic").is_err());
+}
+
+#[test]
+fn test_event_parser_cmd_large_body() {
+    let body = "x".repeat(1 << 20);
+    let plugins = command_plugins("cat > /dev/null");
+    assert!(plugins::parse(&plugins, &body).unwrap().is_none());
+}
+
+#[test]
+fn test_event_parser_cmd_timeout() {
+    let opt = crate::Opt::from_iter(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--event-parser-cmd",
+        "sleep 5",
+        "--event-parser-timeout-seconds",
+        "0",
+    ]);
+    let plugins = plugins::load(&opt).unwrap();
+    assert!(plugins::parse(&plugins, "exotic").is_err());
+}
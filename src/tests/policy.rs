@@ -0,0 +1,146 @@
+use crate::policy::{self, Policy};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+fn policy_label(policy: &str) -> Option<HashMap<String, String>> {
+    let mut service_labels = HashMap::new();
+    service_labels.insert(policy::KEEL_POLICY_LABEL.to_owned(), policy.to_owned());
+    Some(service_labels)
+}
+
+#[test]
+fn test_parse_policy() {
+    assert_eq!(Policy::Minor, Policy::from_str("minor").unwrap());
+    assert_eq!(
+        Policy::Glob("build-*".to_owned()),
+        Policy::from_str("glob:build-*").unwrap()
+    );
+    assert!(Policy::from_str("sometimes").is_err());
+}
+
+#[test]
+fn test_semver_policies() {
+    assert!(Policy::Major.accepts("1.2.3", "2.0.0"));
+    assert!(Policy::Minor.accepts("v1.2.3", "v1.3.0"));
+    assert!(!Policy::Minor.accepts("1.2.3", "2.0.0"));
+    assert!(Policy::Patch.accepts("1.2.3", "1.2.4"));
+    assert!(!Policy::Patch.accepts("1.2.3", "1.3.0"));
+    assert!(!Policy::Major.accepts("1.2.3", "1.2.2"));
+}
+
+#[test]
+fn test_prereleases_only_with_all() {
+    assert!(!Policy::Major.accepts("1.2.3", "1.3.0-rc.1"));
+    assert!(Policy::All.accepts("1.2.3", "1.3.0-rc.1"));
+}
+
+#[test]
+fn test_semver_policy_rejects_non_semver_tags() {
+    assert!(!Policy::Major.accepts("latest", "1.0.0"));
+    assert!(Policy::Force.accepts("latest", "1.0.0"));
+}
+
+#[test]
+fn test_glob_matches() {
+    assert!(policy::glob_matches("build-*", "build-1234"));
+    assert!(policy::glob_matches("*-prod", "1.2-prod"));
+    assert!(policy::glob_matches("release-*-*", "release-1-x"));
+    assert!(!policy::glob_matches("build-*", "release-1234"));
+}
+
+#[test]
+fn test_find_service_by_policy() {
+    let mut event = super::message_event();
+    event.image_tag = "1.3.0".to_owned();
+    let service = super::service_spec(
+        policy_label("minor"),
+        Some(format!("{}:1.2.0@sha256:abcd", event.repository())),
+    );
+    assert!(!policy::find(vec![&service], &event).is_empty());
+    event.image_tag = "2.0.0".to_owned();
+    assert!(policy::find(vec![&service], &event).is_empty());
+}
+
+#[test]
+fn test_find_ignores_unlabelled_services() {
+    let mut event = super::message_event();
+    event.image_tag = "1.3.0".to_owned();
+    let service = super::service_spec(None, Some(format!("{}:1.2.0", event.repository())));
+    assert!(policy::find(vec![&service], &event).is_empty());
+}
+
+#[test]
+fn test_find_returns_every_accepting_service() {
+    let mut event = super::message_event();
+    event.image_tag = "1.3.0".to_owned();
+    let image = Some(format!("{}:1.2.0", event.repository()));
+    let mut staging = super::service_spec(policy_label("all"), image.clone());
+    staging.spec.name = "ze-staging".to_owned();
+    let mut production = super::service_spec(policy_label("minor"), image.clone());
+    production.spec.name = "ze-production".to_owned();
+    let mut frozen = super::service_spec(policy_label("never"), image);
+    frozen.spec.name = "ze-frozen".to_owned();
+    let found = policy::find(vec![&staging, &frozen, &production], &event);
+    let names: Vec<&str> = found
+        .iter()
+        .map(|service| service.spec.name.as_str())
+        .collect();
+    assert_eq!(vec!["ze-production", "ze-staging"], names);
+}
+
+#[test]
+fn test_find_normalizes_docker_hub_images() {
+    let mut event = super::message_event();
+    event.registry = Some("docker.io".to_owned());
+    event.repository_name = "library/nginx".to_owned();
+    event.image_tag = "1.3.0".to_owned();
+    let service = super::service_spec(policy_label("minor"), Some("nginx:1.2.0".to_owned()));
+    assert!(!policy::find(vec![&service], &event).is_empty());
+    let service = super::service_spec(
+        policy_label("minor"),
+        Some("docker.io/library/nginx:1.2.0".to_owned()),
+    );
+    assert!(!policy::find(vec![&service], &event).is_empty());
+}
+
+#[test]
+fn test_policy_decides_on_repush_of_running_tag() {
+    let event = super::message_event();
+    let image = format!("{}:latest@sha256:abcd", event.repository());
+    let mut services_by_image = HashMap::new();
+    services_by_image.insert(
+        event.image(),
+        super::service_spec(policy_label("never"), Some(image.clone())),
+    );
+    assert!(crate::matching_services(&services_by_image, &event).is_empty());
+    services_by_image.insert(
+        event.image(),
+        super::service_spec(policy_label("force"), Some(image.clone())),
+    );
+    assert_eq!(
+        1,
+        crate::matching_services(&services_by_image, &event).len()
+    );
+    services_by_image.insert(event.image(), super::service_spec(None, Some(image)));
+    assert_eq!(
+        1,
+        crate::matching_services(&services_by_image, &event).len()
+    );
+}
+
+#[test]
+fn test_services_moved_by_policy_are_indexed_by_running_image() {
+    let mut labels = policy_label("minor").unwrap();
+    labels.insert(
+        crate::STACK_IMAGE_LABEL.to_owned(),
+        "bittrance/ze-image:1.2.0".to_owned(),
+    );
+    let service = super::service_spec(
+        Some(labels),
+        Some("bittrance/ze-image:1.3.0@sha256:abcd".to_owned()),
+    );
+    assert_eq!(
+        Some("bittrance/ze-image:1.3.0".to_owned()),
+        crate::extract_service_image(&service)
+    );
+}
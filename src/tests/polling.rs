@@ -0,0 +1,62 @@
+use crate::polling::{self, QuietHours};
+use chrono::{TimeZone, Utc};
+use std::time::Duration;
+
+#[test]
+fn test_no_idle_delay_below_threshold() {
+    assert_eq!(Duration::from_secs(0), polling::idle_delay(2, 3, 300));
+}
+
+#[test]
+fn test_idle_delay_doubles() {
+    assert_eq!(Duration::from_secs(20), polling::idle_delay(3, 3, 300));
+    assert_eq!(Duration::from_secs(40), polling::idle_delay(4, 3, 300));
+    assert_eq!(Duration::from_secs(80), polling::idle_delay(5, 3, 300));
+}
+
+#[test]
+fn test_idle_delay_is_capped() {
+    assert_eq!(Duration::from_secs(300), polling::idle_delay(100, 3, 300));
+}
+
+#[test]
+fn test_idle_delay_disabled_by_default() {
+    assert_eq!(Duration::from_secs(0), polling::idle_delay(100, 3, 0));
+}
+
+#[test]
+fn test_parse_quiet_hours() {
+    assert_eq!(
+        QuietHours { start: 22, end: 6 },
+        "22-06".parse::<QuietHours>().unwrap()
+    );
+    assert!("22".parse::<QuietHours>().is_err());
+    assert!("22-24".parse::<QuietHours>().is_err());
+    assert!("6-6".parse::<QuietHours>().is_err());
+}
+
+#[test]
+fn test_quiet_hours_spanning_midnight() {
+    let quiet_hours = QuietHours { start: 22, end: 6 };
+    assert!(quiet_hours.contains(23));
+    assert!(quiet_hours.contains(0));
+    assert!(!quiet_hours.contains(6));
+    assert!(!quiet_hours.contains(12));
+}
+
+#[test]
+fn test_quiet_hours_remaining() {
+    let quiet_hours = QuietHours { start: 22, end: 6 };
+    let late = Utc.ymd(2020, 4, 8).and_hms(23, 30, 0);
+    assert_eq!(
+        Some(Duration::from_secs(6 * 3600 + 1800)),
+        quiet_hours.remaining(late)
+    );
+    let early = Utc.ymd(2020, 4, 9).and_hms(5, 0, 0);
+    assert_eq!(
+        Some(Duration::from_secs(3600)),
+        quiet_hours.remaining(early)
+    );
+    let noon = Utc.ymd(2020, 4, 9).and_hms(12, 0, 0);
+    assert_eq!(None, quiet_hours.remaining(noon));
+}
@@ -0,0 +1,23 @@
+use crate::quarantine::{self, Failures};
+
+#[test]
+fn test_failures_count_in_a_row() {
+    let mut failures = Failures::new();
+    assert_eq!(1, failures.record("ze-service", true));
+    assert_eq!(2, failures.record("ze-service", true));
+    assert_eq!(1, failures.record("other-service", true));
+    assert_eq!(0, failures.record("ze-service", false));
+    assert_eq!(1, failures.record("ze-service", true));
+}
+
+#[test]
+fn test_is_quarantined() {
+    let service = super::service_spec(
+        super::filter_label(quarantine::QUARANTINE_LABEL, "bittrance/ze-image:latest"),
+        None,
+    );
+    assert!(quarantine::is_quarantined(&service));
+    assert!(!quarantine::is_quarantined(&super::service_spec(
+        None, None
+    )));
+}
@@ -0,0 +1,50 @@
+use crate::redis::{self, BODY_FIELD};
+use ::redis::streams::StreamId;
+use ::redis::Value;
+use std::collections::HashMap;
+
+fn entry(field: &str, value: &[u8]) -> StreamId {
+    let mut map = HashMap::new();
+    map.insert(field.to_owned(), Value::Data(value.to_vec()));
+    StreamId {
+        id: "1526919030474-55".to_owned(),
+        map,
+    }
+}
+
+#[test]
+fn test_to_message_uses_body_field() {
+    let message = redis::to_message("deploys", entry(BODY_FIELD, b"{\"ze\":\"event\"}"));
+    assert_eq!(Some("{\"ze\":\"event\"}".to_owned()), message.body);
+    assert_eq!(Some("1526919030474-55".to_owned()), message.receipt_handle);
+}
+
+#[test]
+fn test_to_message_without_body_is_empty() {
+    let message = redis::to_message("deploys", entry("payload", b"{}"));
+    assert_eq!(None, message.body);
+    assert_eq!(Some("1526919030474-55".to_owned()), message.message_id);
+}
+
+#[test]
+fn test_parse_autoclaim() {
+    let reply = Value::Bulk(vec![
+        Value::Data(b"1526919030474-58".to_vec()),
+        Value::Bulk(vec![
+            Value::Bulk(vec![
+                Value::Data(b"1526919030474-55".to_vec()),
+                Value::Bulk(vec![
+                    Value::Data(BODY_FIELD.as_bytes().to_vec()),
+                    Value::Data(b"{}".to_vec()),
+                ]),
+            ]),
+            Value::Nil,
+        ]),
+    ]);
+    let (next, entries) = redis::parse_autoclaim(&reply).unwrap();
+    assert_eq!("1526919030474-58", next);
+    assert_eq!(1, entries.len());
+    let message = redis::to_message("deploys", entries.into_iter().next().unwrap());
+    assert_eq!(Some("{}".to_owned()), message.body);
+    assert!(redis::parse_autoclaim(&Value::Nil).is_none());
+}
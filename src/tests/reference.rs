@@ -0,0 +1,79 @@
+use crate::reference::ImageRef;
+use rusoto_core::Region;
+
+#[test]
+fn test_parse_official_image() {
+    let image = ImageRef::parse("nginx").unwrap();
+    assert_eq!("docker.io", image.registry);
+    assert_eq!("library/nginx", image.repository);
+    assert_eq!("latest", image.tag);
+    assert_eq!(None, image.digest);
+    assert_eq!("nginx:latest", image.to_string());
+}
+
+#[test]
+fn test_parse_explicit_docker_hub() {
+    let image = ImageRef::parse("docker.io/library/nginx:1.17").unwrap();
+    assert_eq!("nginx:1.17", image.to_string());
+    let image = ImageRef::parse("index.docker.io/bittrance/ze-image").unwrap();
+    assert_eq!("bittrance/ze-image:latest", image.to_string());
+}
+
+#[test]
+fn test_parse_registry_with_port() {
+    let image = ImageRef::parse("registry.example.com:5000/team/ze-app@sha256:1234").unwrap();
+    assert_eq!("registry.example.com:5000", image.registry);
+    assert_eq!("team/ze-app", image.repository);
+    assert_eq!("latest", image.tag);
+    assert_eq!(Some("sha256:1234".to_owned()), image.digest);
+    assert_eq!(
+        "registry.example.com:5000/team/ze-app:latest",
+        image.unpinned()
+    );
+    let image = ImageRef::parse("localhost/ze-app:1.0").unwrap();
+    assert_eq!("localhost", image.registry);
+}
+
+#[test]
+fn test_parse_pinned_ecr_image() {
+    let input = "123456789012.dkr.ecr.eu-west-1.amazonaws.com/ze-app:1.0@sha256:1234";
+    let image = ImageRef::parse(input).unwrap();
+    assert_eq!("ze-app", image.repository);
+    assert_eq!(input, image.to_string());
+}
+
+#[test]
+fn test_parse_invalid() {
+    assert!(ImageRef::parse("").is_none());
+    assert!(ImageRef::parse("nginx:").is_none());
+    assert!(ImageRef::parse("nginx@").is_none());
+}
+
+#[test]
+fn test_ecr_image() {
+    let image =
+        ImageRef::parse("123456789012.dkr.ecr.eu-west-1.amazonaws.com/bittrance/ze-image:latest")
+            .and_then(|image| image.ecr())
+            .unwrap();
+    assert_eq!("123456789012", image.account_id);
+    assert_eq!(Region::EuWest1, image.region);
+    assert_eq!("bittrance/ze-image", image.repository_name);
+    assert_eq!("latest", image.image_tag);
+    let image = ImageRef::parse("123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn/ze-image")
+        .and_then(|image| image.ecr())
+        .unwrap();
+    assert_eq!(Region::CnNorth1, image.region);
+}
+
+#[test]
+fn test_ecr_image_rejects_other_registries() {
+    let image = ImageRef::parse("docker.io/bittrance/ze-image:latest").unwrap();
+    assert!(image.ecr().is_none());
+}
+
+#[test]
+fn test_same_tag() {
+    let image = ImageRef::parse("nginx").unwrap();
+    assert!(image.same_tag(&ImageRef::parse("docker.io/library/nginx:latest@sha256:1234").unwrap()));
+    assert!(!image.same_tag(&ImageRef::parse("nginx:1.17").unwrap()));
+}
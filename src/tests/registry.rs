@@ -0,0 +1,112 @@
+use crate::{auth, registry};
+use structopt::StructOpt;
+
+#[test]
+fn test_manifest_url() {
+    assert_eq!(
+        "https://123456789012.dkr.ecr.rp-north-1.amazonaws.com/v2/bittrance/ze-image/manifests/latest",
+        registry::manifest_url(
+            "123456789012.dkr.ecr.rp-north-1.amazonaws.com",
+            "bittrance/ze-image",
+            "latest"
+        )
+    );
+}
+
+#[test]
+fn test_docker_hub_manifest_url() {
+    assert_eq!(
+        "https://registry-1.docker.io/v2/library/nginx/manifests/latest",
+        registry::manifest_url("docker.io", "library/nginx", "latest")
+    );
+}
+
+#[test]
+fn test_static_credentials_scoped_to_hosts() {
+    let opt = crate::Opt::from_iter(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--registry-username",
+        "robot",
+        "--registry-password",
+        "s3cret",
+        "--registry-host",
+        "harbor.example.com",
+    ]);
+    let mut cache = auth::Cache::default();
+    let credentials = registry::static_credentials("harbor.example.com", &mut cache, &opt)
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some("robot".to_owned()), credentials.username);
+    assert_eq!(Some("s3cret".to_owned()), credentials.password);
+    assert!(registry::static_credentials("quay.io", &mut cache, &opt)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_static_credentials_require_username() {
+    let opt = crate::Opt::from_iter(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--registry-password",
+        "s3cret",
+        "--registry-host",
+        "quay.io",
+    ]);
+    let mut cache = auth::Cache::default();
+    assert!(registry::static_credentials("quay.io", &mut cache, &opt).is_err());
+}
+
+#[test]
+fn test_parse_challenge() {
+    let challenge = registry::parse_challenge(
+        "Bearer realm=\"https://auth.docker.io/token\",service=\"registry.docker.io\",scope=\"repository:library/nginx:pull,push\"",
+    )
+    .unwrap();
+    assert_eq!(
+        registry::Challenge {
+            realm: "https://auth.docker.io/token".to_owned(),
+            service: Some("registry.docker.io".to_owned()),
+            scope: Some("repository:library/nginx:pull,push".to_owned()),
+        },
+        challenge
+    );
+    let challenge = registry::parse_challenge("bearer realm=https://ghcr.io/token").unwrap();
+    assert_eq!("https://ghcr.io/token", challenge.realm);
+    assert_eq!(None, challenge.service);
+    assert_eq!(None, registry::parse_challenge("Basic realm=\"Registry\""));
+    assert_eq!(
+        None,
+        registry::parse_challenge("Bearer service=\"ghcr.io\"")
+    );
+}
+
+#[test]
+fn test_token_url() {
+    let challenge = registry::Challenge {
+        realm: "https://auth.docker.io/token".to_owned(),
+        service: Some("registry.docker.io".to_owned()),
+        scope: Some("repository:library/nginx:pull".to_owned()),
+    };
+    assert_eq!(
+        "https://auth.docker.io/token?service=registry.docker.io&scope=repository%3Alibrary%2Fnginx%3Apull",
+        registry::token_url(&challenge).unwrap().as_str()
+    );
+}
+
+#[test]
+fn test_static_credentials_require_hosts() {
+    let result = crate::Opt::from_iter_safe(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--registry-username",
+        "robot",
+        "--registry-password",
+        "s3cret",
+    ]);
+    assert!(result.is_err());
+}
@@ -0,0 +1,36 @@
+use crate::replay::ReplaySource;
+use crate::source::EventSource;
+use std::io::Cursor;
+
+#[test]
+fn test_replay_skips_blank_lines() {
+    let input = "{\"first\":1}\n\n{\"second\":2}\n";
+    let mut source = ReplaySource::from_reader("events.jsonl", Cursor::new(input)).unwrap();
+    let messages = source.poll().unwrap();
+    assert_eq!(2, messages.len());
+    assert_eq!(Some("{\"second\":2}".to_owned()), messages[1].body);
+    assert_eq!(Some("replay-3".to_owned()), messages[1].message_id);
+    assert!(source.exhausted());
+}
+
+#[test]
+fn test_replay_delivers_in_batches() {
+    let input = "{}\n".repeat(15);
+    let mut source = ReplaySource::from_reader("events.jsonl", Cursor::new(input)).unwrap();
+    assert_eq!(10, source.poll().unwrap().len());
+    assert!(!source.exhausted());
+    assert_eq!(5, source.poll().unwrap().len());
+    assert!(source.exhausted());
+}
+
+#[test]
+fn test_replay_not_exhausted_until_retries_done() {
+    let mut source = ReplaySource::from_reader("events.jsonl", Cursor::new("{}\n")).unwrap();
+    let messages = source.poll().unwrap();
+    source.nack(&messages[0], 0).unwrap();
+    assert!(!source.exhausted());
+    let retried = source.poll().unwrap();
+    assert_eq!(1, retried.len());
+    assert_eq!(2, source.receive_count(&retried[0]));
+    assert!(source.exhausted());
+}
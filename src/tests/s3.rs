@@ -0,0 +1,87 @@
+use crate::s3::{self, S3Object};
+use rusoto_core::Region;
+use serde_json::json;
+
+fn notification(event_name: &str) -> String {
+    json!({
+        "Records": [{
+            "eventSource": "aws:s3",
+            "awsRegion": "eu-west-1",
+            "eventName": event_name,
+            "s3": {
+                "bucket": {"name": "ze-deployments"},
+                "object": {"key": "production/release+2020-03-01%231.txt"}
+            }
+        }]
+    })
+    .to_string()
+}
+
+#[test]
+fn test_created_objects() {
+    assert_eq!(
+        vec![S3Object {
+            region: "eu-west-1".to_owned(),
+            bucket: "ze-deployments".to_owned(),
+            key: "production/release 2020-03-01#1.txt".to_owned(),
+        }],
+        s3::created_objects(&notification("ObjectCreated:Put"))
+    );
+}
+
+#[test]
+fn test_removed_objects_are_ignored() {
+    assert!(s3::created_objects(&notification("ObjectRemoved:Delete")).is_empty());
+}
+
+#[test]
+fn test_test_event_is_ignored() {
+    let test_event = r#"{"Service":"Amazon S3","Event":"s3:TestEvent","Bucket":"ze-deployments"}"#;
+    assert!(s3::created_objects(test_event).is_empty());
+}
+
+#[test]
+fn test_parse_line_manifest() {
+    let manifest = "# release 1\n\
+                    bittrance/ze-app:1.0@sha256:1234\n\
+                    \n\
+                    registry.example.com/ze-worker:2.0\n";
+    let events = s3::parse_manifest(manifest);
+    assert_eq!(2, events.len());
+    assert_eq!("bittrance/ze-app:1.0", events[0].image());
+    assert_eq!("sha256:1234", events[0].image_digest);
+    assert_eq!("registry.example.com/ze-worker:2.0", events[1].image());
+    assert_eq!("", events[1].image_digest);
+}
+
+#[test]
+fn test_parse_json_manifest() {
+    let manifest = r#"["bittrance/ze-app:1.0", "ze-untagged"]"#;
+    let events = s3::parse_manifest(manifest);
+    assert_eq!(1, events.len());
+    assert_eq!("bittrance/ze-app:1.0", events[0].image());
+}
+
+#[test]
+fn test_payload_pointer() {
+    let body = r#"["software.amazon.payloadoffloading.PayloadS3Pointer",{"s3BucketName":"ze-payloads","s3Key":"0f9a6d3c"}]"#;
+    assert_eq!(
+        Some(S3Object {
+            region: "eu-west-1".to_owned(),
+            bucket: "ze-payloads".to_owned(),
+            key: "0f9a6d3c".to_owned(),
+        }),
+        s3::payload_pointer(body, &Region::EuWest1)
+    );
+}
+
+#[test]
+fn test_legacy_payload_pointer() {
+    let body = r#"["com.amazon.sqs.javamessaging.MessageS3Pointer",{"s3BucketName":"ze-payloads","s3Key":"0f9a6d3c"}]"#;
+    assert!(s3::payload_pointer(body, &Region::EuWest1).is_some());
+}
+
+#[test]
+fn test_plain_array_is_not_a_pointer() {
+    assert!(s3::payload_pointer(r#"["nginx:1.17"]"#, &Region::EuWest1).is_none());
+}
@@ -0,0 +1,79 @@
+use crate::events;
+use crate::scan::{self, Severity};
+use rusoto_ecr::{ImageScanningConfiguration, Repository};
+use serde_json::json;
+use std::str::FromStr;
+
+fn scan_event(counts: serde_json::Value) -> String {
+    json!({
+        "detail-type": "ECR Image Scan",
+        "source": "aws.ecr",
+        "account": "123456789012",
+        "region": "eu-west-1",
+        "detail": {
+            "scan-status": "COMPLETE",
+            "repository-name": "ze-app",
+            "image-digest": "sha256:1234",
+            "image-tags": ["1.0", "latest"],
+            "finding-severity-counts": counts
+        }
+    })
+    .to_string()
+}
+
+#[test]
+fn test_parse_scan_event() {
+    let scan = events::parse_ecr_scan_event(&scan_event(json!({"LOW": 3}))).unwrap();
+    assert_eq!(2, scan.events.len());
+    assert_eq!(
+        "123456789012.dkr.ecr.eu-west-1.amazonaws.com/ze-app:latest@sha256:1234",
+        scan.events[1].pinned_image()
+    );
+    assert_eq!(Some(&3), scan.severity_counts.get("LOW"));
+}
+
+#[test]
+fn test_push_event_is_not_a_scan() {
+    let push = json!({"detail-type": "ECR Image Action", "detail": {}}).to_string();
+    assert!(events::parse_ecr_scan_event(&push).is_none());
+}
+
+#[test]
+fn test_blocking_findings() {
+    let scan = events::parse_ecr_scan_event(&scan_event(json!({
+        "LOW": 3,
+        "HIGH": 1,
+        "UNDEFINED": 2
+    })))
+    .unwrap();
+    assert_eq!(
+        3,
+        scan::blocking_findings(&scan, Severity::from_str("high").unwrap())
+    );
+    assert_eq!(
+        2,
+        scan::blocking_findings(&scan, Severity::from_str("CRITICAL").unwrap())
+    );
+    assert_eq!(
+        6,
+        scan::blocking_findings(&scan, Severity::from_str("LOW").unwrap())
+    );
+}
+
+#[test]
+fn test_unknown_severity() {
+    assert!(Severity::from_str("dire").is_err());
+}
+
+#[test]
+fn test_scans_on_push() {
+    let mut repository = Repository {
+        repository_name: Some("ze-app".to_owned()),
+        ..Default::default()
+    };
+    assert!(!scan::scans_on_push(&repository));
+    repository.image_scanning_configuration = Some(ImageScanningConfiguration {
+        scan_on_push: Some(true),
+    });
+    assert!(scan::scans_on_push(&repository));
+}
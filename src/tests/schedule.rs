@@ -0,0 +1,68 @@
+use crate::schedule::{self, Redeploys};
+use chrono::{TimeZone, Utc};
+use std::collections::HashMap;
+
+fn cron_label(expression: &str) -> Option<HashMap<String, String>> {
+    let mut service_labels = HashMap::new();
+    service_labels.insert(
+        schedule::REDEPLOY_CRON_LABEL.to_owned(),
+        expression.to_owned(),
+    );
+    Some(service_labels)
+}
+
+#[test]
+fn test_parse_cron() {
+    let schedule = schedule::parse_cron("0 4 * * 1").unwrap();
+    let monday = Utc.ymd(2020, 3, 30).and_hms(3, 0, 0);
+    assert_eq!(
+        Some(Utc.ymd(2020, 3, 30).and_hms(4, 0, 0)),
+        schedule.after(&monday).next()
+    );
+}
+
+#[test]
+fn test_parse_cron_rejects_garbage() {
+    assert!(schedule::parse_cron("every monday").is_none());
+}
+
+#[test]
+fn test_first_sighting_is_not_due() {
+    let service = super::service_spec(cron_label("0 4 * * 1"), None);
+    let mut redeploys = Redeploys::new();
+    let now = Utc.ymd(2020, 3, 30).and_hms(5, 0, 0);
+    assert!(redeploys.due(vec![&service], now).is_empty());
+}
+
+#[test]
+fn test_due_once_schedule_passes() {
+    let service = super::service_spec(cron_label("0 4 * * 1"), None);
+    let mut redeploys = Redeploys::new();
+    redeploys.due(vec![&service], Utc.ymd(2020, 3, 30).and_hms(3, 0, 0));
+    let now = Utc.ymd(2020, 3, 30).and_hms(4, 0, 1);
+    assert_eq!(1, redeploys.due(vec![&service], now).len());
+    assert!(redeploys.due(vec![&service], now).is_empty());
+}
+
+#[test]
+fn test_unlabelled_services_are_ignored() {
+    let service = super::service_spec(None, None);
+    let mut redeploys = Redeploys::new();
+    redeploys.due(vec![&service], Utc.ymd(2020, 3, 30).and_hms(3, 0, 0));
+    assert!(redeploys
+        .due(vec![&service], Utc.ymd(2020, 4, 30).and_hms(3, 0, 0))
+        .is_empty());
+}
+
+#[test]
+fn test_services_listed_when_due_or_rescanning() {
+    let service = super::service_spec(cron_label("0 4 * * 1"), None);
+    let mut redeploys = Redeploys::new();
+    assert!(redeploys.pending(Utc.ymd(2020, 3, 30).and_hms(3, 0, 0)));
+    redeploys.due(vec![&service], Utc.ymd(2020, 3, 30).and_hms(3, 0, 0));
+    assert!(!redeploys.pending(Utc.ymd(2020, 3, 30).and_hms(3, 1, 0)));
+    assert!(redeploys.pending(Utc.ymd(2020, 3, 30).and_hms(3, 5, 0)));
+    redeploys.due(vec![&service], Utc.ymd(2020, 3, 30).and_hms(3, 58, 0));
+    assert!(!redeploys.pending(Utc.ymd(2020, 3, 30).and_hms(3, 59, 0)));
+    assert!(redeploys.pending(Utc.ymd(2020, 3, 30).and_hms(4, 0, 0)));
+}
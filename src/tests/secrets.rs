@@ -0,0 +1,27 @@
+use crate::secrets::{self, SecretArn};
+use rusoto_core::Region;
+
+#[test]
+fn test_parse_secret_arn() {
+    let secret: SecretArn = "arn:aws:secretsmanager:eu-west-1:123456789012:secret:harbor-AbCdEf"
+        .parse()
+        .unwrap();
+    assert_eq!(Region::EuWest1, secret.region);
+    assert!("arn:aws:sqs:eu-west-1:123456789012:ze-queue"
+        .parse::<SecretArn>()
+        .is_err());
+    assert!("harbor".parse::<SecretArn>().is_err());
+}
+
+#[test]
+fn test_parse_login() {
+    let (username, password) = secrets::parse_login(
+        "ze-secret",
+        r#"{"username": "robot", "password": "s3cret"}"#,
+    )
+    .unwrap();
+    assert_eq!("robot", username);
+    assert_eq!("s3cret", password);
+    assert!(secrets::parse_login("ze-secret", r#"{"username": "robot"}"#).is_err());
+    assert!(secrets::parse_login("ze-secret", "s3cret").is_err());
+}
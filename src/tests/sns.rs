@@ -0,0 +1,98 @@
+use crate::events;
+use crate::sns;
+use serde_json::json;
+
+fn notification(message: &str) -> String {
+    json!({
+        "Type": "Notification",
+        "MessageId": "22b80b92-fdea-4c2c-8f9d-bdfb0c7bf324",
+        "TopicArn": "arn:aws:sns:eu-west-1:123456789012:ecr-pushes",
+        "Message": message,
+        "Timestamp": "2020-03-01T12:00:00.000Z",
+        "SignatureVersion": "1",
+        "Signature": "ze-signature",
+        "SigningCertURL": "https://sns.eu-west-1.amazonaws.com/SimpleNotificationService-a86cb10b4e1f29c941702d737128f7b6.pem"
+    })
+    .to_string()
+}
+
+#[test]
+fn test_unwrap_ecr_event_from_envelope() {
+    let ecr_event = json!({
+        "account": "123456789012",
+        "region": "eu-west-1",
+        "detail": {
+            "action-type": "PUSH",
+            "result": "SUCCESS",
+            "repository-name": "ze-app",
+            "image-digest": "sha256:ze-digest",
+            "image-tag": "latest"
+        }
+    })
+    .to_string();
+    let envelope = sns::envelope(&notification(&ecr_event)).unwrap();
+    let event = events::parse_ecr_event(sns::message(&envelope))
+        .unwrap()
+        .unwrap();
+    assert_eq!("ze-app", event.repository_name);
+}
+
+#[test]
+fn test_plain_event_is_not_an_envelope() {
+    assert!(sns::envelope("{\"detail\":{}}").is_none());
+    assert!(sns::envelope("not json").is_none());
+}
+
+#[test]
+fn test_string_to_sign_skips_missing_subject() {
+    let envelope = sns::envelope(&notification("ze-message")).unwrap();
+    assert_eq!(
+        "Message\nze-message\n\
+         MessageId\n22b80b92-fdea-4c2c-8f9d-bdfb0c7bf324\n\
+         Timestamp\n2020-03-01T12:00:00.000Z\n\
+         TopicArn\narn:aws:sns:eu-west-1:123456789012:ecr-pushes\n\
+         Type\nNotification\n",
+        sns::string_to_sign(&envelope)
+    );
+}
+
+#[test]
+fn test_trusted_certificate_url() {
+    assert!(sns::trusted_certificate_url(
+        "https://sns.eu-west-1.amazonaws.com/SimpleNotificationService-abc.pem"
+    ));
+    assert!(!sns::trusted_certificate_url(
+        "https://sns.eu-west-1.amazonaws.com.evil.example/cert.pem"
+    ));
+    assert!(!sns::trusted_certificate_url(
+        "http://sns.eu-west-1.amazonaws.com/cert.pem"
+    ));
+}
+
+#[test]
+fn test_filter_policy() {
+    let filters = vec![
+        ("environment".to_owned(), "production".to_owned()),
+        ("environment".to_owned(), "staging".to_owned()),
+        ("team".to_owned(), "ze-team".to_owned()),
+    ];
+    let policy: serde_json::Value = serde_json::from_str(&sns::filter_policy(&filters)).unwrap();
+    assert_eq!(
+        json!({"environment": ["production", "staging"], "team": ["ze-team"]}),
+        policy
+    );
+}
+
+#[test]
+fn test_subscription_region() {
+    assert_eq!(
+        Some(rusoto_core::Region::EuWest1),
+        sns::subscription_region(
+            "arn:aws:sns:eu-west-1:123456789012:ecr-events:8a21d249-4329-4871-acc6-7be709c6ea7f"
+        )
+    );
+    assert_eq!(
+        None,
+        sns::subscription_region("arn:aws:sqs:eu-west-1:123456789012:ecr-events")
+    );
+}
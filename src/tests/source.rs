@@ -0,0 +1,219 @@
+use crate::source::{self, EventSource};
+use crate::{auth, dedupe, journal, managers, metrics, quarantine, Deployer, Opt, Result};
+use rusoto_core::Region;
+use rusoto_sqs::Message;
+use rusoto_stepfunctions::StepFunctionsClient;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use structopt::StructOpt;
+use tokio::runtime::Runtime;
+
+#[derive(Default)]
+struct MockSource {
+    acked: Vec<String>,
+    nacked: Vec<String>,
+    receive_count: u32,
+    max_receive_count: Option<u32>,
+}
+
+impl EventSource for MockSource {
+    fn name(&self) -> String {
+        "mock".to_owned()
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        Ok(Vec::new())
+    }
+
+    fn ack(&mut self, message: &Message) -> Result<()> {
+        self.acked.push(message.message_id.clone().unwrap());
+        Ok(())
+    }
+
+    fn nack(&mut self, message: &Message, _delay_seconds: i64) -> Result<()> {
+        self.nacked.push(message.message_id.clone().unwrap());
+        Ok(())
+    }
+
+    fn receive_count(&self, _message: &Message) -> u32 {
+        self.receive_count.max(1)
+    }
+
+    fn max_receive_count(&self, _message: &Message) -> Option<u32> {
+        self.max_receive_count
+    }
+}
+
+fn deployer() -> Deployer {
+    Deployer {
+        managers: managers::Managers::connect(&["tcp://127.0.0.1:2375".to_owned()]).unwrap(),
+        journal: journal::Journal::open(None).unwrap(),
+        rt: Runtime::new().unwrap(),
+        sinks: Vec::new(),
+        plugins: Vec::new(),
+        containers: None,
+        metrics: metrics::Metrics::default(),
+        dedupe: dedupe::Dedupe::new(10),
+        rejects: None,
+        credentials: auth::Cache::default(),
+        sns: None,
+        states: StepFunctionsClient::new(Region::EuWest1),
+        failures: quarantine::Failures::new(),
+    }
+}
+
+fn message(id: &str, body: &str) -> Message {
+    Message {
+        message_id: Some(id.to_owned()),
+        body: Some(body.to_owned()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_dispatch_drops_unrecognized_messages() {
+    let opt = Opt::from_iter(vec!["swarm-deployer", "-q", "ze-queue"]);
+    let mut source = MockSource::default();
+    let messages = vec![
+        message("1", "{\"detail\": {\"action-type\": \"DELETE\"}}"),
+        message("2", "not an event"),
+    ];
+    let mut deployer = deployer();
+    source::dispatch(&mut source, &messages, &mut deployer, &opt).unwrap();
+    assert_eq!(vec!["1".to_owned(), "2".to_owned()], source.acked);
+    assert!(source.nacked.is_empty());
+    assert!(deployer
+        .metrics
+        .render()
+        .contains("seedy_unrecognized_messages_total{outcome=\"drop\"} 2\n"));
+}
+
+// Dispatch lists the swarm's services for recognized events, so what it
+// acks on success is exercised directly.
+#[test]
+fn test_events_without_matching_service_succeed() {
+    let opt = Opt::from_iter(vec!["swarm-deployer", "-q", "ze-queue"]);
+    let body = json!({
+        "detail-type": "ECR Image Action",
+        "source": "aws.ecr",
+        "account": "123456789012",
+        "time": "2020-03-30T09:56:58Z",
+        "region": "rp-north-1",
+        "detail": {
+            "action-type": "PUSH",
+            "result": "SUCCESS",
+            "repository-name": "bittrance/ze-image",
+            "image-digest": "sha256:1234",
+            "image-tag": "latest"
+        }
+    })
+    .to_string();
+    let mut deployer = deployer();
+    crate::process_one(&message("1", &body), &HashMap::new(), &mut deployer, &opt).unwrap();
+    assert!(deployer.metrics.render().contains("seedy_events_total 1\n"));
+    assert_eq!(0, deployer.journal.entries().count());
+}
+
+#[test]
+fn test_dispatch_leaves_unrecognized_messages() {
+    let opt = Opt::from_iter(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--unrecognized",
+        "leave",
+    ]);
+    let mut source = MockSource::default();
+    let messages = vec![message("1", "not an event")];
+    let mut deployer = deployer();
+    source::dispatch(&mut source, &messages, &mut deployer, &opt).unwrap();
+    assert!(source.acked.is_empty());
+    assert!(source.nacked.is_empty());
+    assert!(deployer
+        .metrics
+        .render()
+        .contains("seedy_unrecognized_messages_total{outcome=\"leave\"} 1\n"));
+}
+
+fn exhausted_failure(max_receive_count: Option<u32>) -> MockSource {
+    let opt = Opt::from_iter(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--field-mapping",
+        "repository=abs(image)",
+    ]);
+    let mut source = MockSource {
+        receive_count: opt.max_retries + 1,
+        max_receive_count,
+        ..Default::default()
+    };
+    let messages = vec![message("1", "{\"image\": \"ze-image\"}")];
+    source::dispatch(&mut source, &messages, &mut deployer(), &opt).unwrap();
+    source
+}
+
+#[test]
+fn test_dispatch_leaves_exhausted_failures_to_redrive_policy() {
+    let source = exhausted_failure(Some(10));
+    assert!(source.acked.is_empty());
+    assert_eq!(vec!["1".to_owned()], source.nacked);
+}
+
+#[test]
+fn test_dispatch_drops_exhausted_failures_without_redrive_policy() {
+    let source = exhausted_failure(None);
+    assert_eq!(vec!["1".to_owned()], source.acked);
+    assert!(source.nacked.is_empty());
+}
+
+#[test]
+fn test_reject_policy_requires_rejects_queue() {
+    let res = Opt::from_iter_safe(vec![
+        "swarm-deployer",
+        "-q",
+        "ze-queue",
+        "--unrecognized",
+        "reject",
+    ]);
+    assert!(res.is_err());
+    assert!("ignore".parse::<source::Unrecognized>().is_err());
+}
+
+#[test]
+fn test_backlog_redelivers_with_bumped_receive_count() {
+    let mut backlog = source::Backlog::new();
+    backlog.delay(&message("1", "{}"), 0);
+    backlog.delay(&message("2", "{}"), 3600);
+    let due = backlog.take_due();
+    assert_eq!(1, due.len());
+    assert_eq!(2, crate::sqs::receive_count(&due[0]));
+    assert!(backlog.take_due().is_empty());
+}
+
+#[test]
+fn test_backlog_wait() {
+    let mut backlog = source::Backlog::new();
+    let max_wait = std::time::Duration::from_secs(20);
+    assert_eq!(max_wait, backlog.wait(max_wait));
+    backlog.delay(&message("1", "{}"), 5);
+    assert!(backlog.wait(max_wait) <= std::time::Duration::from_secs(5));
+}
+
+#[test]
+fn test_lease_renews_until_dropped() {
+    let renewals = Arc::new(AtomicUsize::new(0));
+    let counter = renewals.clone();
+    let lease = source::Lease::spawn(Duration::from_millis(10), move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    std::thread::sleep(Duration::from_millis(55));
+    drop(lease);
+    let renewed = renewals.load(Ordering::SeqCst);
+    assert!(renewed >= 2);
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(renewed, renewals.load(Ordering::SeqCst));
+}
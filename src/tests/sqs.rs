@@ -0,0 +1,204 @@
+use crate::sqs;
+use rusoto_core::request::HttpDispatchError;
+use rusoto_core::{Region, RusotoError};
+use rusoto_sqs::{Message, MessageAttributeValue};
+use std::collections::HashMap;
+use structopt::StructOpt;
+
+fn message_with_receive_count(count: &str) -> Message {
+    let mut attributes = HashMap::new();
+    attributes.insert("ApproximateReceiveCount".to_owned(), count.to_owned());
+    Message {
+        attributes: Some(attributes),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_receive_count() {
+    assert_eq!(3, sqs::receive_count(&message_with_receive_count("3")));
+}
+
+#[test]
+fn test_receive_count_defaults_to_first_delivery() {
+    assert_eq!(1, sqs::receive_count(&Message::default()));
+}
+
+#[test]
+fn test_retry_delay_doubles() {
+    assert_eq!(60, sqs::retry_delay(1, 60));
+    assert_eq!(120, sqs::retry_delay(2, 60));
+    assert_eq!(240, sqs::retry_delay(3, 60));
+}
+
+#[test]
+fn test_retry_delay_is_capped() {
+    assert_eq!(43200, sqs::retry_delay(20, 60));
+    assert_eq!(43200, sqs::retry_delay(100, 60));
+}
+
+#[test]
+fn test_wait_seconds_shared_between_queues() {
+    assert_eq!(20, sqs::wait_seconds(20, 1));
+    assert_eq!(6, sqs::wait_seconds(20, 3));
+    assert_eq!(1, sqs::wait_seconds(20, 40));
+    assert_eq!(0, sqs::wait_seconds(0, 3));
+}
+
+#[test]
+fn test_repeated_queue_option() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "staging-queue",
+            "--queue",
+            "prod-queue",
+        ]
+        .iter(),
+    );
+    assert_eq!(vec!["staging-queue", "prod-queue"], opt.queue_names);
+}
+
+#[test]
+fn test_priority_queue_requires_queue() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "ze-queue",
+            "--priority-queue",
+            "hotfix-queue",
+        ]
+        .iter(),
+    );
+    assert_eq!(Some("hotfix-queue".to_owned()), opt.priority_queue);
+    let res = crate::Opt::from_iter_safe(
+        vec![
+            "ze-bin",
+            "--listen",
+            "0.0.0.0:8080",
+            "--priority-queue",
+            "hotfix-queue",
+        ]
+        .iter(),
+    );
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_message_group() {
+    let mut attributes = HashMap::new();
+    attributes.insert("MessageGroupId".to_owned(), "ze-repo".to_owned());
+    let message = Message {
+        attributes: Some(attributes),
+        ..Default::default()
+    };
+    assert_eq!(Some("ze-repo"), sqs::message_group(&message));
+    assert_eq!(None, sqs::message_group(&Message::default()));
+}
+
+#[test]
+fn test_receive_tuning_defaults() {
+    let opt = crate::Opt::from_iter(vec!["ze-bin", "--queue", "ze-queue"].iter());
+    assert_eq!(10, opt.max_messages);
+    assert_eq!(20, opt.wait_seconds);
+}
+
+#[test]
+fn test_max_messages_out_of_range() {
+    let result = crate::Opt::from_iter_safe(
+        vec!["ze-bin", "--queue", "ze-queue", "--max-messages", "11"].iter(),
+    );
+    assert!(result.is_err());
+}
+
+fn message_with_attribute(key: &str, value: &str) -> Message {
+    let mut attributes = HashMap::new();
+    attributes.insert(
+        key.to_owned(),
+        MessageAttributeValue {
+            data_type: "String".to_owned(),
+            string_value: Some(value.to_owned()),
+            ..Default::default()
+        },
+    );
+    Message {
+        message_attributes: Some(attributes),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_matches_attributes() {
+    let filters = vec![
+        ("environment".to_owned(), "staging".to_owned()),
+        ("environment".to_owned(), "production".to_owned()),
+    ];
+    assert!(sqs::matches_attributes(
+        &message_with_attribute("environment", "production"),
+        &filters
+    ));
+    assert!(!sqs::matches_attributes(
+        &message_with_attribute("environment", "test"),
+        &filters
+    ));
+    assert!(!sqs::matches_attributes(&Message::default(), &filters));
+    assert!(sqs::matches_attributes(&Message::default(), &[]));
+}
+
+#[test]
+fn test_parse_queue_arn() {
+    let arn = "arn:aws:sqs:eu-west-1:123456789012:deploys"
+        .parse::<sqs::QueueArn>()
+        .unwrap();
+    assert_eq!(Region::EuWest1, arn.region);
+    assert_eq!("deploys", arn.queue_name);
+    assert!("deploys".parse::<sqs::QueueArn>().is_err());
+    assert!("arn:aws:sqs:mars-1:123456789012:deploys"
+        .parse::<sqs::QueueArn>()
+        .is_err());
+    assert!("arn:aws:sns:eu-west-1:123456789012:deploys"
+        .parse::<sqs::QueueArn>()
+        .is_err());
+}
+
+#[test]
+fn test_failover_queues_option() {
+    let opt = crate::Opt::from_iter(
+        vec![
+            "ze-bin",
+            "--queue",
+            "deploys",
+            "--failover-queue",
+            "arn:aws:sqs:eu-north-1:123456789012:deploys",
+        ]
+        .iter(),
+    );
+    assert_eq!(1, opt.failover_queues.len());
+    assert_eq!(Region::EuNorth1, opt.failover_queues[0].region);
+}
+
+#[test]
+fn test_fails_over_only_when_unreachable() {
+    let polling = |source| crate::SeedyError::PollingMessage {
+        queue_url: "https://sqs.eu-west-1.amazonaws.com/123456789012/deploys".to_owned(),
+        source,
+    };
+    let dispatch = HttpDispatchError::new("connection refused".to_owned());
+    assert!(sqs::unreachable(&polling(RusotoError::HttpDispatch(
+        dispatch
+    ))));
+    let refused = RusotoError::Validation("no such queue".to_owned());
+    assert!(!sqs::unreachable(&polling(refused)));
+}
+
+#[test]
+fn test_redrive_limit() {
+    let policy =
+        r#"{"deadLetterTargetArn":"arn:aws:sqs:eu-west-1:123456789012:dead","maxReceiveCount":5}"#;
+    assert_eq!(Some(5), sqs::redrive_limit(policy));
+    let quoted = r#"{"deadLetterTargetArn":"arn:aws:sqs:eu-west-1:123456789012:dead","maxReceiveCount":"7"}"#;
+    assert_eq!(Some(7), sqs::redrive_limit(quoted));
+    assert_eq!(None, sqs::redrive_limit("{}"));
+}
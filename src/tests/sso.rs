@@ -0,0 +1,77 @@
+use crate::sso::{self, SsoProfile};
+use serde_json::json;
+
+const LEGACY_CONFIG: &str = "
+[default]
+region = eu-west-1
+
+[profile ops]
+sso_start_url = https://example.awsapps.com/start
+sso_region = eu-north-1
+sso_account_id = 123456789012
+sso_role_name = Deployer
+";
+
+const SESSION_CONFIG: &str = "
+[profile ops]
+sso_session = corp
+sso_account_id = 123456789012
+sso_role_name = Deployer
+
+[sso-session corp]
+sso_start_url = https://example.awsapps.com/start
+sso_region = eu-north-1
+";
+
+#[test]
+fn test_parse_legacy_sso_profile() {
+    assert_eq!(
+        Some(SsoProfile {
+            start_url: "https://example.awsapps.com/start".to_owned(),
+            region: "eu-north-1".to_owned(),
+            account_id: "123456789012".to_owned(),
+            role_name: "Deployer".to_owned(),
+            session_name: None,
+        }),
+        sso::parse_profile(LEGACY_CONFIG, "ops")
+    );
+}
+
+#[test]
+fn test_parse_sso_session_profile() {
+    let profile = sso::parse_profile(SESSION_CONFIG, "ops").unwrap();
+    assert_eq!("eu-north-1", profile.region);
+    assert_eq!(Some("corp".to_owned()), profile.session_name);
+}
+
+#[test]
+fn test_profile_without_sso() {
+    assert_eq!(None, sso::parse_profile(LEGACY_CONFIG, "default"));
+}
+
+#[test]
+fn test_cache_file_name_is_sha1_of_start_url() {
+    let profile = sso::parse_profile(LEGACY_CONFIG, "ops").unwrap();
+    assert_eq!(
+        format!(
+            "{}.json",
+            sha1::Sha1::from("https://example.awsapps.com/start").hexdigest()
+        ),
+        sso::cache_file_name(&profile)
+    );
+}
+
+#[test]
+fn test_parse_role_credentials() {
+    let body = json!({
+        "roleCredentials": {
+            "accessKeyId": "ASIAEXAMPLE",
+            "secretAccessKey": "secret",
+            "sessionToken": "token",
+            "expiration": 1586361600000i64
+        }
+    });
+    let credentials = sso::parse_role_credentials(&body).unwrap();
+    assert_eq!("ASIAEXAMPLE", credentials.aws_access_key_id());
+    assert_eq!(&Some("token".to_owned()), credentials.token());
+}
@@ -0,0 +1,46 @@
+use crate::stepfunctions;
+use rusoto_sqs::{Message, MessageAttributeValue};
+use std::collections::HashMap;
+
+#[test]
+fn test_task_token_from_body() {
+    let message = Message {
+        body: Some("{\"TaskToken\": \"ze-token\", \"detail\": {}}".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(
+        Some("ze-token".to_owned()),
+        stepfunctions::task_token(&message)
+    );
+}
+
+#[test]
+fn test_task_token_from_attribute() {
+    let mut attributes = HashMap::new();
+    attributes.insert(
+        "TaskToken".to_owned(),
+        MessageAttributeValue {
+            data_type: "String".to_owned(),
+            string_value: Some("ze-token".to_owned()),
+            ..Default::default()
+        },
+    );
+    let message = Message {
+        body: Some("not json".to_owned()),
+        message_attributes: Some(attributes),
+        ..Default::default()
+    };
+    assert_eq!(
+        Some("ze-token".to_owned()),
+        stepfunctions::task_token(&message)
+    );
+}
+
+#[test]
+fn test_no_task_token() {
+    let message = Message {
+        body: Some("{\"detail\": {}}".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(None, stepfunctions::task_token(&message));
+}
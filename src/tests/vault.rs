@@ -0,0 +1,35 @@
+use crate::vault;
+use serde_json::json;
+
+#[test]
+fn test_kv2_url() {
+    assert_eq!(
+        Some("https://vault.example.com:8200/v1/secret/data/registries/harbor".to_owned()),
+        vault::kv2_url(
+            "https://vault.example.com:8200/",
+            "/secret/registries/harbor"
+        )
+    );
+    assert_eq!(
+        None,
+        vault::kv2_url("https://vault.example.com:8200", "secret")
+    );
+}
+
+#[test]
+fn test_login_from_response() {
+    let response = json!({
+        "data": {
+            "data": {"username": "robot", "password": "s3cret"},
+            "metadata": {"version": 2}
+        }
+    });
+    assert_eq!(
+        Some(("robot".to_owned(), "s3cret".to_owned())),
+        vault::login_from_response(&response)
+    );
+    assert_eq!(
+        None,
+        vault::login_from_response(&json!({"data": {"username": "robot"}}))
+    );
+}
@@ -0,0 +1,61 @@
+use crate::watch;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_event_for_docker_hub_official_image() {
+    let event = watch::event_for_image("nginx:1.17").unwrap();
+    assert_eq!(Some("docker.io".to_owned()), event.registry);
+    assert_eq!("library/nginx", event.repository_name);
+    assert_eq!("nginx:1.17", event.image());
+}
+
+#[test]
+fn test_event_for_docker_hub_user_image() {
+    let event = watch::event_for_image("bittrance/ze-app:latest").unwrap();
+    assert_eq!(Some("docker.io".to_owned()), event.registry);
+    assert_eq!("bittrance/ze-app", event.repository_name);
+}
+
+#[test]
+fn test_event_for_private_registry_image() {
+    let event = watch::event_for_image("registry.example.com:5000/team/ze-app:1.0").unwrap();
+    assert_eq!(Some("registry.example.com:5000".to_owned()), event.registry);
+    assert_eq!("team/ze-app", event.repository_name);
+    assert_eq!("1.0", event.image_tag);
+}
+
+#[test]
+fn test_event_for_ecr_image() {
+    let event =
+        watch::event_for_image("123456789012.dkr.ecr.eu-west-1.amazonaws.com/ze-app:1.0").unwrap();
+    assert!(event.is_ecr());
+    assert_eq!("123456789012", event.account_id);
+    assert_eq!("eu-west-1", event.region);
+    assert_eq!(
+        "123456789012.dkr.ecr.eu-west-1.amazonaws.com/ze-app:1.0",
+        event.image()
+    );
+}
+
+#[test]
+fn test_event_for_untagged_image() {
+    let event = watch::event_for_image("nginx").unwrap();
+    assert_eq!("library/nginx", event.repository_name);
+    assert_eq!("latest", event.image_tag);
+}
+
+#[test]
+fn test_due() {
+    assert!(watch::due(None, Duration::from_secs(60)));
+    assert!(!watch::due(Some(Instant::now()), Duration::from_secs(60)));
+}
+
+#[test]
+fn test_event_for_pinned_image() {
+    let event = watch::event_for_image(
+        "123456789012.dkr.ecr.eu-west-1.amazonaws.com/ze-app:1.0@sha256:1234",
+    )
+    .unwrap();
+    assert_eq!("sha256:1234", event.image_digest);
+    assert_eq!("ze-app", event.repository_name);
+}
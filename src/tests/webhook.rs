@@ -0,0 +1,134 @@
+use crate::source::EventSource;
+use crate::webhook::{self, Cidr, WebhookSource};
+use std::thread;
+
+fn post(address: &str, body: &'static str) -> thread::JoinHandle<u16> {
+    post_with_token(address, body, "")
+}
+
+fn post_with_token(address: &str, body: &'static str, token: &str) -> thread::JoinHandle<u16> {
+    let url = format!("http://{}/webhook", address);
+    let token = token.to_owned();
+    thread::spawn(move || {
+        reqwest::blocking::Client::new()
+            .post(&url)
+            .header("X-Gitlab-Token", token)
+            .body(body)
+            .send()
+            .unwrap()
+            .status()
+            .as_u16()
+    })
+}
+
+#[test]
+fn test_webhook_becomes_message() {
+    let mut source = WebhookSource::bind("127.0.0.1:0", None).unwrap();
+    let sender = post(source.address(), "{\"ze\": \"event\"}");
+    let messages = source.poll().unwrap();
+    assert_eq!(202, sender.join().unwrap());
+    assert_eq!(1, messages.len());
+    assert_eq!(Some("{\"ze\": \"event\"}".to_owned()), messages[0].body);
+    assert_eq!(1, source.receive_count(&messages[0]));
+}
+
+#[test]
+fn test_nacked_webhook_is_redelivered() {
+    let mut source = WebhookSource::bind("127.0.0.1:0", None).unwrap();
+    let sender = post(source.address(), "{}");
+    let messages = source.poll().unwrap();
+    sender.join().unwrap();
+    source.nack(&messages[0], 0).unwrap();
+    let redelivered = source.poll().unwrap();
+    assert_eq!(messages[0].message_id, redelivered[0].message_id);
+    assert_eq!(2, source.receive_count(&redelivered[0]));
+}
+
+#[test]
+fn test_webhook_without_token_is_rejected() {
+    let mut source = WebhookSource::bind("127.0.0.1:0", Some("s3cret".to_owned())).unwrap();
+    let rejected = post_with_token(source.address(), "{}", "guess");
+    let accepted = post_with_token(source.address(), "{}", "s3cret");
+    assert_eq!(1, source.poll().unwrap().len());
+    assert_eq!(401, rejected.join().unwrap());
+    assert_eq!(202, accepted.join().unwrap());
+}
+
+#[test]
+fn test_webhook_is_answered_between_polls() {
+    let mut source = WebhookSource::bind("127.0.0.1:0", None).unwrap();
+    let first = post(source.address(), "{}");
+    assert_eq!(1, source.poll().unwrap().len());
+    first.join().unwrap();
+    let second = post(source.address(), "{}");
+    assert_eq!(202, second.join().unwrap());
+    assert_eq!(1, source.poll().unwrap().len());
+}
+
+#[test]
+fn test_oversized_webhook_is_rejected() {
+    let mut source = WebhookSource::bind("127.0.0.1:0", None).unwrap();
+    let url = format!("http://{}/webhook", source.address());
+    let oversized = thread::spawn(move || {
+        reqwest::blocking::Client::new()
+            .post(&url)
+            .body(vec![b' '; webhook::MAX_BODY as usize + 1])
+            .send()
+            .unwrap()
+            .status()
+            .as_u16()
+    });
+    let accepted = post(source.address(), "{}");
+    assert_eq!(1, source.poll().unwrap().len());
+    assert_eq!(413, oversized.join().unwrap());
+    assert_eq!(202, accepted.join().unwrap());
+}
+
+#[test]
+fn test_github_signature() {
+    let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+    let secret = "It's a Secret to Everybody";
+    let body = b"Hello, World!";
+    assert!(webhook::valid_signature(secret, body, signature));
+    assert!(!webhook::valid_signature(secret, b"Hello", signature));
+    assert!(!webhook::valid_signature("guess", body, signature));
+    assert!(!webhook::valid_signature(secret, body, "sha1=757107"));
+}
+
+#[test]
+fn test_constant_time_eq() {
+    assert!(webhook::constant_time_eq(b"s3cret", b"s3cret"));
+    assert!(!webhook::constant_time_eq(b"s3cret", b"s3crex"));
+    assert!(!webhook::constant_time_eq(b"s3cret", b"s3cre"));
+    assert!(webhook::constant_time_eq(b"", b""));
+}
+
+#[test]
+fn test_cidr_contains() {
+    let cidr: Cidr = "192.0.2.0/23".parse().unwrap();
+    assert!(cidr.contains("192.0.3.17".parse().unwrap()));
+    assert!(!cidr.contains("192.0.4.1".parse().unwrap()));
+    assert!(cidr.contains("::ffff:192.0.2.1".parse().unwrap()));
+    let host: Cidr = "2001:db8::1".parse().unwrap();
+    assert!(host.contains("2001:db8::1".parse().unwrap()));
+    assert!(!host.contains("2001:db8::2".parse().unwrap()));
+    assert!("192.0.2.0/33".parse::<Cidr>().is_err());
+    assert!("example.com/24".parse::<Cidr>().is_err());
+}
+
+#[test]
+fn test_webhook_outside_allowed_networks_is_rejected() {
+    let allowed = vec!["192.0.2.0/24".parse().unwrap()];
+    let mut source = WebhookSource::bind("127.0.0.1:0", None)
+        .unwrap()
+        .with_allowed_networks(&allowed);
+    let rejected = post(source.address(), "{}");
+    assert!(source.poll().unwrap().is_empty());
+    assert_eq!(403, rejected.join().unwrap());
+}
+
+#[test]
+fn test_public_listener_requires_token() {
+    assert!(WebhookSource::bind("0.0.0.0:0", None).is_err());
+    assert!(WebhookSource::bind("0.0.0.0:0", Some("s3cret".to_owned())).is_ok());
+}
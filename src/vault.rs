@@ -0,0 +1,108 @@
+use crate::{MissingCredentialOption, Opt, Result, VaultRequest, VaultSecretFormat};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use snafu::{OptionExt, ResultExt};
+use std::time::{Duration, Instant};
+
+/// How long a read secret is used before it is read again, so that rotated
+/// credentials are picked up without a restart.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(900);
+
+/// The last login read, with when it was read.
+#[derive(Default)]
+pub struct Login {
+    login: Option<(String, String, Instant)>,
+}
+
+impl Login {
+    /// Forget the login, as when a registry has refused it.
+    pub fn clear(&mut self) {
+        self.login = None;
+    }
+}
+
+/// URL to read a KV version 2 secret from, where the first component of the
+/// path is the mount, e.g. secret/registries/harbor.
+pub fn kv2_url(addr: &str, path: &str) -> Option<String> {
+    let path = path.trim_matches('/');
+    let slash_pos = path.find('/')?;
+    Some(format!(
+        "{}/v1/{}/data/{}",
+        addr.trim_end_matches('/'),
+        &path[..slash_pos],
+        &path[slash_pos + 1..]
+    ))
+}
+
+/// Username and password from the data of a KV version 2 read response.
+pub fn login_from_response(response: &Value) -> Option<(String, String)> {
+    let data = response.get("data")?.get("data")?;
+    let field = |name: &str| data.get(name).and_then(Value::as_str).map(str::to_owned);
+    Some((field("username")?, field("password")?))
+}
+
+fn send(request: reqwest::blocking::RequestBuilder, url: &str) -> Result<Value> {
+    request
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json())
+        .with_context(|| VaultRequest {
+            url: url.to_owned(),
+        })
+}
+
+/// A token from --vault-token, or from logging in with the AppRole.
+fn token(client: &Client, addr: &str, opt: &Opt) -> Result<String> {
+    if let Some(token) = &opt.vault_token {
+        return Ok(token.clone());
+    }
+    let role_id = opt
+        .vault_role_id
+        .as_ref()
+        .context(MissingCredentialOption {
+            option: "--vault-token or --vault-role-id",
+        })?;
+    let secret_id = opt
+        .vault_secret_id
+        .as_ref()
+        .context(MissingCredentialOption {
+            option: "--vault-secret-id",
+        })?;
+    let url = format!("{}/v1/auth/approle/login", addr.trim_end_matches('/'));
+    let body = json!({"role_id": role_id, "secret_id": secret_id});
+    let response = send(client.post(&url).json(&body), &url)?;
+    response
+        .get("auth")
+        .and_then(|auth| auth.get("client_token"))
+        .and_then(Value::as_str)
+        .map(str::to_owned)
+        .context(MissingCredentialOption {
+            option: "client_token in Vault AppRole login",
+        })
+}
+
+/// The registry login in --vault-secret-path, read again once the previous
+/// read is older than the refresh interval.
+pub fn registry_login(
+    addr: &str,
+    path: &str,
+    login: &mut Login,
+    opt: &Opt,
+) -> Result<(String, String)> {
+    let cached = login
+        .login
+        .as_ref()
+        .filter(|(_, _, read_at)| read_at.elapsed() < REFRESH_INTERVAL)
+        .map(|(username, password, _)| (username.clone(), password.clone()));
+    if let Some(login) = cached {
+        return Ok(login);
+    }
+    let url = kv2_url(addr, path).context(VaultSecretFormat { path })?;
+    let client = Client::new();
+    let token = token(&client, addr, opt)?;
+    let response = send(client.get(&url).header("X-Vault-Token", token), &url)?;
+    let (username, password) =
+        login_from_response(&response).context(VaultSecretFormat { path })?;
+    login.login = Some((username.clone(), password.clone(), Instant::now()));
+    Ok((username, password))
+}
@@ -0,0 +1,61 @@
+use crate::managers::Managers;
+use crate::reference::ImageRef;
+use crate::{
+    auth, build_service_index, candidate_services, registry, Opt, Result, VerificationFailed,
+};
+use reqwest::blocking::Client;
+use snafu::ensure;
+use tokio::runtime::Runtime;
+
+/// Fetch credentials for an image the same way a deployment would and check
+/// that they grant access to its manifest, answering bearer token challenges
+/// as a pull would. Returns the reason on failure.
+fn verify_image(
+    client: &Client,
+    image: &str,
+    cache: &mut auth::Cache,
+    opt: &Opt,
+) -> Result<Option<String>> {
+    let reference = match ImageRef::parse(image) {
+        Some(reference) => reference,
+        None => return Ok(Some("unreadable image reference".to_owned())),
+    };
+    let credentials =
+        auth::provider(&reference.registry).credentials(&reference.registry, cache, opt)?;
+    let status = registry::head_manifest(
+        client,
+        &reference.registry,
+        &reference.repository,
+        reference.digest.as_deref().unwrap_or(&reference.tag),
+        credentials.as_ref(),
+    );
+    let status = auth::evict_refused(&reference.registry, status, cache)?;
+    if status.is_success() {
+        Ok(None)
+    } else {
+        Ok(Some(format!("registry responded {}", status)))
+    }
+}
+
+pub fn run(managers: &mut Managers, rt: &mut Runtime, opt: &Opt) -> Result<()> {
+    let services = managers.run(|docker| candidate_services(docker, rt))?;
+    let services_by_image = build_service_index(services, opt);
+    let client = Client::new();
+    let mut cache = auth::Cache::default();
+    let mut failures = 0;
+    for (image, service) in services_by_image.iter() {
+        let reason = match verify_image(&client, image, &mut cache, opt) {
+            Ok(reason) => reason,
+            Err(err) => Some(err.to_string()),
+        };
+        match reason {
+            None => println!("OK   {} {}", &service.spec.name, image),
+            Some(reason) => {
+                failures += 1;
+                println!("FAIL {} {}: {}", &service.spec.name, image, reason);
+            }
+        }
+    }
+    ensure!(failures == 0, VerificationFailed { failures });
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::auth::Cache;
+use crate::events::Event;
+use crate::reference::ImageRef;
+use crate::source::EventSource;
+use crate::{auth, drift, registry, running_image, Opt, Result};
+use bollard::service::Service;
+use log::warn;
+use rusoto_sqs::Message;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Build the event a push of this image reference would have produced,
+/// with a digest only if the reference is pinned.
+pub fn event_for_image(image: &str) -> Option<Event> {
+    let reference = ImageRef::parse(image)?;
+    let digest = reference.digest.clone().unwrap_or_default();
+    if let Some(ecr_image) = reference.ecr() {
+        return Some(Event {
+            account_id: ecr_image.account_id,
+            region: ecr_image.region.name().to_owned(),
+            repository_name: ecr_image.repository_name,
+            image_digest: digest,
+            image_tag: ecr_image.image_tag,
+            pushed_at: None,
+            registry: None,
+        });
+    }
+    Some(Event {
+        account_id: String::new(),
+        region: String::new(),
+        repository_name: reference.repository,
+        image_digest: digest,
+        image_tag: reference.tag,
+        pushed_at: None,
+        registry: Some(reference.registry),
+    })
+}
+
+/// The digest the event's tag currently points to in its registry.
+pub fn remote_digest(event: &Event, cache: &mut Cache, opt: &Opt) -> Result<Option<String>> {
+    if event.is_ecr() {
+        return match event.reference().ecr() {
+            Some(ecr_image) => drift::latest_digest(&ecr_image, cache, opt),
+            None => Ok(None),
+        };
+    }
+    let registry = event.registry_host();
+    let client = reqwest::blocking::Client::new();
+    let credentials = auth::event_credentials(event, cache, opt)?;
+    let digest = registry::manifest_digest(
+        &client,
+        &registry,
+        &event.repository_name,
+        &event.image_tag,
+        credentials.as_ref(),
+    );
+    auth::evict_refused(&registry, digest, cache)
+}
+
+/// Look up the digest of events for unpinned image references, dropping
+/// those whose tag cannot be found.
+pub fn resolve_digests(events: Vec<Event>, cache: &mut Cache, opt: &Opt) -> Result<Vec<Event>> {
+    let mut resolved = Vec::new();
+    for mut event in events {
+        if event.image_digest.is_empty() {
+            match remote_digest(&event, cache, opt)? {
+                Some(digest) => event.image_digest = digest,
+                None => {
+                    warn!("{} not found in registry, skipping", event.image());
+                    continue;
+                }
+            }
+        }
+        resolved.push(event);
+    }
+    Ok(resolved)
+}
+
+/// Events for the tracked services whose tag has moved to a digest other
+/// than the one they run. Images that cannot be checked are skipped.
+pub fn changed(
+    services_by_image: &HashMap<String, Service<String>>,
+    cache: &mut Cache,
+    opt: &Opt,
+) -> Vec<Event> {
+    let mut events = Vec::new();
+    for (image, service) in services_by_image.iter() {
+        let mut event = match event_for_image(image) {
+            Some(event) => event,
+            None => continue,
+        };
+        match remote_digest(&event, cache, opt) {
+            Ok(Some(digest)) => {
+                let running = running_image(service).and_then(|image| image.digest);
+                if running.as_deref() != Some(digest.as_str()) {
+                    event.image_digest = digest;
+                    events.push(event);
+                }
+            }
+            Ok(None) => warn!("Registry poll: {} not found", image),
+            Err(err) => warn!("Registry poll: could not check {}: {}", image, err),
+        }
+    }
+    events
+}
+
+pub fn due(last_poll: Option<Instant>, interval: Duration) -> bool {
+    last_poll.map_or(true, |last| last.elapsed() >= interval)
+}
+
+/// Stands in for an event source when registries are only polled.
+pub struct NoEvents {
+    pub wait: Duration,
+}
+
+impl EventSource for NoEvents {
+    fn name(&self) -> String {
+        "registry polling only".to_owned()
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        std::thread::sleep(self.wait);
+        Ok(Vec::new())
+    }
+
+    fn ack(&mut self, _message: &Message) -> Result<()> {
+        Ok(())
+    }
+
+    fn nack(&mut self, _message: &Message, _delay_seconds: i64) -> Result<()> {
+        Ok(())
+    }
+
+    fn receive_count(&self, _message: &Message) -> u32 {
+        1
+    }
+}
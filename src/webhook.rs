@@ -0,0 +1,354 @@
+use crate::events;
+use crate::source::{Backlog, EventSource};
+use crate::{CidrFormat, ListenerSetup, Result, SeedyError, WebhookListener};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use hmac::{Hmac, Mac};
+use log::{debug, info, warn};
+use rusoto_sqs::Message;
+use serde_json::json;
+use sha2::Sha256;
+use std::io::Read;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Method, Request, Response, Server};
+
+const POLL_WAIT: Duration = Duration::from_secs(20);
+/// Registry payloads are a few kilobytes; anything much larger is refused
+/// before it is authenticated, let alone parsed.
+pub const MAX_BODY: u64 = 1024 * 1024;
+
+/// An IPv4 or IPv6 network such as 192.0.2.0/24. An address without a
+/// prefix length is a network of that address only.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.network, unmapped(address)) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                prefix_matches(&network.octets(), &address.octets(), self.prefix)
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                prefix_matches(&network.octets(), &address.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = SeedyError;
+
+    fn from_str(input: &str) -> Result<Cidr> {
+        let mut parts = input.splitn(2, '/');
+        let network = parts
+            .next()
+            .and_then(|network| network.parse::<IpAddr>().ok());
+        let max_prefix = match network {
+            Some(IpAddr::V4(_)) => 32,
+            Some(IpAddr::V6(_)) => 128,
+            None => return CidrFormat { value: input }.fail(),
+        };
+        let prefix = match parts.next() {
+            Some(prefix) => prefix.parse::<u8>().ok(),
+            None => Some(max_prefix),
+        };
+        match (network, prefix) {
+            (Some(network), Some(prefix)) if prefix <= max_prefix => Ok(Cidr { network, prefix }),
+            _ => CidrFormat { value: input }.fail(),
+        }
+    }
+}
+
+/// IPv4 peers of a listener bound to an IPv6 address appear as
+/// ::ffff:a.b.c.d, which IPv4 networks should still match.
+fn unmapped(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, _, _] => {
+                let octets = v6.octets();
+                IpAddr::from([octets[12], octets[13], octets[14], octets[15]])
+            }
+            _ => address,
+        },
+        IpAddr::V4(_) => address,
+    }
+}
+
+fn prefix_matches(network: &[u8], address: &[u8], prefix: u8) -> bool {
+    let whole = (prefix / 8) as usize;
+    let rest = prefix % 8;
+    network[..whole] == address[..whole]
+        && (rest == 0 || (network[whole] ^ address[whole]) & (0xff << (8 - rest)) == 0)
+}
+
+/// Receives registry webhooks over HTTP. Deliveries are acknowledged to the
+/// sender as soon as they are read, so a failed update is retried from an
+/// in-memory backlog rather than by the sender. Requests are answered on a
+/// thread of their own, so that senders do not wait for a slow batch.
+pub struct WebhookSource {
+    address: String,
+    listener: Option<Listener>,
+    deliveries: Receiver<Message>,
+    backlog: Backlog,
+}
+
+impl WebhookSource {
+    /// When token is given, deliveries must present it in an X-Gitlab-Token
+    /// header or as an Authorization bearer token, or be signed with it as
+    /// GitHub does. Without a token, only
+    /// peers on the same host can reach the listener, so it must be bound to
+    /// a loopback address.
+    pub fn bind(address: &str, token: Option<String>) -> Result<WebhookSource> {
+        let server = match Server::http(address) {
+            Ok(server) => server,
+            Err(err) => {
+                return ListenerSetup {
+                    address,
+                    message: err.to_string(),
+                }
+                .fail()
+            }
+        };
+        if token.is_none() && !server.server_addr().ip().is_loopback() {
+            return ListenerSetup {
+                address,
+                message: "--webhook-token is required unless listening on localhost",
+            }
+            .fail();
+        }
+        let (sender, deliveries) = crossbeam_channel::unbounded();
+        Ok(WebhookSource {
+            address: server.server_addr().to_string(),
+            listener: Some(Listener {
+                server,
+                token,
+                allowed: Vec::new(),
+                prefix: chrono::Utc::now().timestamp_millis().to_string(),
+                received: 0,
+                deliveries: sender,
+            }),
+            deliveries,
+            backlog: Backlog::new(),
+        })
+    }
+
+    /// Only accept deliveries from peers within these networks, such as the
+    /// egress ranges of a SaaS registry. Any peer is accepted when empty.
+    pub fn with_allowed_networks(mut self, allowed: &[Cidr]) -> WebhookSource {
+        if let Some(listener) = self.listener.as_mut() {
+            listener.allowed = allowed.to_vec();
+        }
+        self
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+/// Answers requests and passes accepted deliveries on to the source.
+struct Listener {
+    server: Server,
+    token: Option<String>,
+    allowed: Vec<Cidr>,
+    prefix: String,
+    received: u64,
+    deliveries: Sender<Message>,
+}
+
+impl Listener {
+    /// Serve until the server fails or the source is dropped.
+    fn spawn(mut self) {
+        thread::spawn(move || loop {
+            let request = match self.server.recv() {
+                Ok(request) => request,
+                Err(err) => {
+                    warn!("Webhook listener failed: {}", err);
+                    return;
+                }
+            };
+            if let Some(message) = self.receive(request) {
+                if self.deliveries.send(message).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Read a delivery, answering the sender. Failing to answer, as when the
+    /// sender has hung up, does not affect a delivery that was accepted.
+    fn receive(&mut self, mut request: Request) -> Option<Message> {
+        let peer = request.remote_addr().ip();
+        if !self.allowed.is_empty() && !self.allowed.iter().any(|cidr| cidr.contains(peer)) {
+            warn!("Rejecting webhook from {} outside allowed networks", peer);
+            respond(request, Response::from_string("").with_status_code(403));
+            return None;
+        }
+        if *request.method() != Method::Post {
+            respond(request, Response::from_string("").with_status_code(405));
+            return None;
+        }
+        let announced = request.body_length().unwrap_or(0) as u64;
+        if announced > MAX_BODY {
+            warn!("Rejecting oversized webhook from {}", peer);
+            respond(request, Response::from_string("").with_status_code(413));
+            return None;
+        }
+        let mut body = String::new();
+        let read = request
+            .as_reader()
+            .take(MAX_BODY + 1)
+            .read_to_string(&mut body);
+        if let Err(err) = read {
+            warn!(
+                "Could not read webhook from {:?}: {}",
+                request.remote_addr(),
+                err
+            );
+            respond(request, Response::from_string("").with_status_code(400));
+            return None;
+        }
+        if body.len() as u64 > MAX_BODY {
+            warn!("Rejecting oversized webhook from {}", peer);
+            respond(request, Response::from_string("").with_status_code(413));
+            return None;
+        }
+        if let Some(token) = &self.token {
+            if !authenticated(&request, &body, token) {
+                warn!(
+                    "Rejecting webhook from {:?} without valid token",
+                    request.remote_addr()
+                );
+                respond(request, Response::from_string("").with_status_code(401));
+                return None;
+            }
+        }
+        if let Some(code) = events::event_grid_validation_code(&body) {
+            info!("Validating Event Grid subscription");
+            let response = json!({ "validationResponse": code }).to_string();
+            respond(request, Response::from_string(response));
+            return None;
+        }
+        debug!("Received webhook on {}", request.url());
+        respond(request, Response::from_string("").with_status_code(202));
+        self.received += 1;
+        Some(Message {
+            message_id: Some(format!("{}-{}", self.prefix, self.received)),
+            body: Some(body),
+            ..Default::default()
+        })
+    }
+}
+
+fn respond<R: Read>(request: Request, response: Response<R>) {
+    let peer = *request.remote_addr();
+    if let Err(err) = request.respond(response) {
+        warn!("Could not respond to webhook from {}: {}", peer, err);
+    }
+}
+
+/// GitHub signs deliveries with the webhook secret rather than sending it,
+/// so its signature is checked against the body. Other senders present the
+/// token itself.
+fn authenticated(request: &Request, body: &str, token: &str) -> bool {
+    let signature = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("X-Hub-Signature-256"));
+    match signature {
+        Some(header) => valid_signature(token, body.as_bytes(), header.value.as_str()),
+        None => presented_token(request).map_or(false, |presented| {
+            constant_time_eq(presented.as_bytes(), token.as_bytes())
+        }),
+    }
+}
+
+/// Compare secrets without stopping at the first difference, so that the
+/// time taken does not reveal how much of a guess was right.
+pub fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right.iter())
+            .fold(0, |diff, (l, r)| diff | (l ^ r))
+            == 0
+}
+
+/// Whether an X-Hub-Signature-256 value is the HMAC-SHA256 of the body
+/// keyed with the secret.
+pub fn valid_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    if !signature.starts_with("sha256=") {
+        return false;
+    }
+    let digest = match hex::decode(&signature["sha256=".len()..]) {
+        Ok(digest) => digest,
+        Err(_) => return false,
+    };
+    let mut mac = match Hmac::<Sha256>::new_varkey(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.input(body);
+    mac.verify(&digest).is_ok()
+}
+
+fn presented_token(request: &Request) -> Option<String> {
+    request.headers().iter().find_map(|header| {
+        let value = header.value.as_str();
+        if header.field.equiv("X-Gitlab-Token") {
+            Some(value.to_owned())
+        } else if header.field.equiv("Authorization") && value.starts_with("Bearer ") {
+            Some(value["Bearer ".len()..].to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+impl EventSource for WebhookSource {
+    fn name(&self) -> String {
+        format!("webhook listener {}", &self.address)
+    }
+
+    fn poll(&mut self) -> Result<Vec<Message>> {
+        if let Some(listener) = self.listener.take() {
+            listener.spawn();
+        }
+        let mut messages = self.backlog.take_due();
+        if messages.is_empty() {
+            match self.deliveries.recv_timeout(self.backlog.wait(POLL_WAIT)) {
+                Ok(message) => messages.push(message),
+                Err(RecvTimeoutError::Timeout) => return Ok(messages),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return WebhookListener {
+                        address: self.address.clone(),
+                    }
+                    .fail()
+                }
+            }
+        }
+        while let Ok(message) = self.deliveries.try_recv() {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    fn ack(&mut self, _message: &Message) -> Result<()> {
+        Ok(())
+    }
+
+    fn nack(&mut self, message: &Message, delay_seconds: i64) -> Result<()> {
+        self.backlog.delay(message, delay_seconds);
+        Ok(())
+    }
+
+    fn receive_count(&self, message: &Message) -> u32 {
+        crate::sqs::receive_count(message)
+    }
+}